@@ -1,31 +1,204 @@
-//! This build script copies the `memory.x` file from the crate root into
-//! a directory where the linker can always find it at build time.
-//! For many projects this is optional, as the linker always searches the
-//! project root directory -- wherever `Cargo.toml` is. However, if you
-//! are using a workspace or have a more complicated build setup, this
-//! build script becomes required. Additionally, by requesting that
-//! Cargo re-run the build script whenever `memory.x` is changed,
-//! updating `memory.x` ensures a rebuild of the application with the
-//! new memory settings.
+//! This build script writes the `memory.x` of the selected board variant into
+//! a directory where the linker can always find it at build time. Each
+//! variant keeps the program out of the sectors `src/storage.rs` erases for
+//! the settings store: the last two on the 512K chips, left out of the FLASH
+//! region, and the small sectors 1 and 2 on the 256K STM32F401CC, which the
+//! program skips by starting `.text` after them.
+//!
+//! It also picks the configurable example's profile out of `profiles.toml`,
+//! named by the `VL6180X_PROFILE` environment variable or else the file's
+//! `default`, and fails the build if any profile in the file is broken.
 
 use std::env;
-use std::fs::File;
-use std::io::Write;
+use std::fs;
 use std::path::PathBuf;
 
+use vl6180x_profile::Profile;
+
+/// Memory layout of a board variant, sizes in K.
+struct Variant {
+    feature: &'static str,
+    chip: &'static str,
+    flash: u32,
+    ram: u32,
+    /// Start of the settings store.
+    store: u32,
+    store_len: u32,
+    /// With the store at the end of flash, the program's FLASH region ends
+    /// where it starts. Otherwise the vector table stays at the start of
+    /// flash, in front of the store, and `.text` starts after it through
+    /// cortex-m-rt's `_stext`.
+    layout: Layout,
+}
+
+enum Layout {
+    StoreAtEnd,
+    TextAfterStore,
+}
+
+const FLASH_ORIGIN: u32 = 0x0800_0000;
+
+const VARIANTS: [Variant; 4] = [
+    // The vector table in sector 0, the store in 1 and 2 (16K each) and the
+    // program from sector 3 on, 208K. The last two sectors would take 192K.
+    Variant {
+        feature: "blackpill-f401cc",
+        chip: "STM32F401CC",
+        flash: 256,
+        ram: 64,
+        store: 0x0800_4000,
+        store_len: 32,
+        layout: Layout::TextAfterStore,
+    },
+    // Sectors 0 to 5, the store takes 6 and 7 (128K each)
+    Variant {
+        feature: "blackpill-f401ce",
+        chip: "STM32F401CE",
+        flash: 512,
+        ram: 96,
+        store: 0x0804_0000,
+        store_len: 256,
+        layout: Layout::StoreAtEnd,
+    },
+    Variant {
+        feature: "blackpill-f411ce",
+        chip: "STM32F411CE",
+        flash: 512,
+        ram: 128,
+        store: 0x0804_0000,
+        store_len: 256,
+        layout: Layout::StoreAtEnd,
+    },
+    Variant {
+        feature: "nucleo-f401re",
+        chip: "STM32F401RE",
+        flash: 512,
+        ram: 96,
+        store: 0x0804_0000,
+        store_len: 256,
+        layout: Layout::StoreAtEnd,
+    },
+];
+
+/// Least flash the program is given, in K. Debug builds of the examples
+/// need more than 64K.
+const MIN_PROGRAM: u32 = 128;
+
+fn is_enabled(feature: &str) -> bool {
+    let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+    env::var_os(var).is_some()
+}
+
+/// Flash left for the program, in K.
+const fn program_len(variant: &Variant) -> u32 {
+    match variant.layout {
+        Layout::StoreAtEnd => (variant.store - FLASH_ORIGIN) / 1024,
+        Layout::TextAfterStore => {
+            variant.flash - ((variant.store - FLASH_ORIGIN) / 1024 + variant.store_len)
+        }
+    }
+}
+
+// Sector 0 and the store come off the F401CC's flash
+const _: () = assert!(program_len(&VARIANTS[0]) == 208);
+
+/// Fails the build on a layout that doesn't fit, rather than leaving it to
+/// a link error or a store that erases the program.
+fn check(variant: &Variant) {
+    let store_end = (variant.store - FLASH_ORIGIN) / 1024 + variant.store_len;
+    let fits = match variant.layout {
+        Layout::StoreAtEnd => store_end == variant.flash,
+        Layout::TextAfterStore => variant.store > FLASH_ORIGIN && store_end < variant.flash,
+    };
+    if !fits {
+        panic!(
+            "{}: the settings store at 0x{:08X} ({}K) doesn't fit its layout",
+            variant.feature, variant.store, variant.store_len
+        );
+    }
+    let program = program_len(variant);
+    if program < MIN_PROGRAM {
+        panic!(
+            "{}: only {}K of flash left for the program next to the settings store, \
+             at least {}K is needed",
+            variant.feature, program, MIN_PROGRAM
+        );
+    }
+}
+
+fn memory_x(variant: &Variant) -> String {
+    let (flash_len, text) = match variant.layout {
+        Layout::StoreAtEnd => (program_len(variant), String::new()),
+        Layout::TextAfterStore => (
+            variant.flash,
+            format!(
+                "
+/* The program skips the settings store, which follows the vector table */
+_stext = 0x{:08X};
+",
+                variant.store + variant.store_len * 1024
+            ),
+        ),
+    };
+    format!(
+        "/* Written by build.rs for the {chip} ({feature}) */
+MEMORY
+{{
+  /* NOTE 1 K = 1 KiBi = 1024 bytes */
+  /* The settings store (src/storage.rs) takes {store_len}K at 0x{store:08X} */
+  FLASH : ORIGIN = 0x{FLASH_ORIGIN:08X}, LENGTH = {flash_len}K
+  RAM : ORIGIN = 0x20000000, LENGTH = {ram}K
+}}
+{text}",
+        chip = variant.chip,
+        feature = variant.feature,
+        store = variant.store,
+        store_len = variant.store_len,
+        ram = variant.ram,
+    )
+}
+
 fn main() {
+    let enabled: Vec<&Variant> = VARIANTS.iter().filter(|v| is_enabled(v.feature)).collect();
+    let variant = match enabled[..] {
+        [variant] => variant,
+        _ => {
+            let features: Vec<&str> = VARIANTS.iter().map(|v| v.feature).collect();
+            panic!(
+                "enable exactly one board feature, one of: {}",
+                features.join(", ")
+            );
+        }
+    };
+    check(variant);
+
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    File::create(out.join("memory.x"))
-        .unwrap()
-        .write_all(include_bytes!("memory.x"))
-        .unwrap();
+    fs::write(out.join("memory.x"), memory_x(variant)).unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
-    // By default, Cargo will re-run a build script whenever
-    // any file in the project changes. By specifying `memory.x`
-    // here, we ensure the build script is only re-run when
-    // `memory.x` is changed.
-    println!("cargo:rerun-if-changed=memory.x");
+    let source = fs::read_to_string("profiles.toml").unwrap();
+    for name in vl6180x_profile::names(&source) {
+        if let Err(e) = Profile::load(&source, name) {
+            panic!("profiles.toml: profile {}: {}", name, e);
+        }
+    }
+    let selected = match env::var("VL6180X_PROFILE") {
+        Ok(name) => name,
+        Err(_) => vl6180x_profile::default_name(&source)
+            .expect("profiles.toml: no default profile")
+            .to_string(),
+    };
+    if Profile::load(&source, &selected).is_err() {
+        let names: Vec<&str> = vl6180x_profile::names(&source).collect();
+        panic!(
+            "no profile {} in profiles.toml, expected one of: {}",
+            selected,
+            names.join(", ")
+        );
+    }
+    println!("cargo:rustc-env=VL6180X_PROFILE_NAME={}", selected);
+    println!("cargo:rerun-if-changed=profiles.toml");
+    println!("cargo:rerun-if-env-changed=VL6180X_PROFILE");
 }
@@ -1,90 +1,86 @@
+//! Continuous ambient light readings in lux, with the analogue gain and
+//! integration time stepped to suit the light level as it changes, from a
+//! dark room to daylight.
+
 #![no_main]
 #![no_std]
 
-use panic_semihosting as _;
 use rtic::app;
 
 #[app(device = hal::pac, peripherals = true)]
 mod app {
-    use cortex_m_semihosting::hprintln;
     use hal::prelude::*;
     use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::ambient::{self, AutoGain, AutoGainConfig};
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::bus;
+    use vl6180x_stm32f401_examples::handlers;
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::new_ceiling_bus;
+    use vl6180x_stm32f401_examples::registers::DEFAULT_ADDRESS;
 
-    type I2cType = hal::i2c::I2c<
-        you_must_enable_the_rt_feature_for_the_pac_in_your_cargo_toml::I2C1,
-        (
-            hal::gpio::gpiob::PB8<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-            hal::gpio::gpiob::PB9<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-        ),
-    >;
+    /// Only the EXTI task uses the bus.
+    const BUS_CEILING: u8 = 1;
+
+    type I2cType = bus::I2cProxy<board::I2c1, BUS_CEILING>;
 
     type Vl6180xType = vl6180x::VL6180X<vl6180x::AmbientContinuousMode, I2cType>;
 
     type Tof1Type = vl6180x::VL6180XwPins<
         vl6180x::AmbientContinuousMode,
         I2cType,
-        hal::gpio::gpiob::PB7<hal::gpio::Output>,
-        hal::gpio::gpiob::PB6<hal::gpio::Input>,
+        board::SensorXshut,
+        board::SensorInterrupt,
     >;
 
     #[shared]
     struct Shared {
-        led: hal::gpio::gpioc::PC13<hal::gpio::Output<hal::gpio::PushPull>>,
+        led: board::Led,
         tof_1: Tof1Type,
     }
 
     #[local]
-    struct Local {}
+    struct Local {
+        auto_gain: AutoGain,
+        /// Writes the gain settings, the driver keeps its own proxy.
+        i2c: I2cType,
+    }
 
     #[init]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
-        let dp = ctx.device;
-        let cp = ctx.core;
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
-        let mut delay = cp.SYST.delay(&clocks);
-        let mut exti = dp.EXTI;
-        let mut syscfg = dp.SYSCFG.constrain();
-
-        // Set up led
-        let gpioc = dp.GPIOC.split();
-        let mut led = gpioc.pc13.into_push_pull_output();
-        led.set_high();
-
-        // Set up I2C
-        let gpiob = dp.GPIOB.split();
-        let scl = gpiob
-            .pb8
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let sda = gpiob
-            .pb9
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let i2c: I2cType = dp.I2C1.i2c((scl, sda), 400.kHz(), &clocks);
+        let Board {
+            mut delay,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            sensor,
+            ..
+        } = Board::new(ctx.device, ctx.core);
 
         // Set up vl6180x
-        let mut x_shutdown_pin = gpiob.pb7.into_push_pull_output();
+        let mut x_shutdown_pin = sensor.xshut;
         x_shutdown_pin.set_high();
 
         // Ensure vl6180x is booted before trying to communicate with it
         delay.delay_ms(2_u8);
 
-        let mut interrupt_pin = gpiob.pb6.into_pull_up_input();
+        let mut interrupt_pin = sensor.interrupt;
         interrupt_pin.make_interrupt_source(&mut syscfg);
         interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
         interrupt_pin.enable_interrupt(&mut exti);
 
+        let bus_manager = new_ceiling_bus!(board::I2c1, BUS_CEILING, i2c).unwrap();
+        let mut i2c = bus_manager.acquire_i2c();
+
+        let auto_gain = AutoGain::new(AutoGainConfig::default());
         let mut tof_config = vl6180x::Config::new();
         tof_config.set_ambient_interrupt_mode(vl6180x::AmbientInterruptMode::NewSampleReady);
-        tof_config.set_ambient_analogue_gain_level(7).expect("saag");
-        tof_config.set_ambient_result_scaler(15).expect("sas");
-        let vl6180x: Vl6180xType = vl6180x::VL6180X::with_config(i2c, &tof_config)
-            .expect("vl")
-            .start_ambient_continuous_mode()
-            .expect("ct");
+        let vl6180x =
+            vl6180x::VL6180X::with_config(bus_manager.acquire_i2c(), &tof_config).expect("vl");
+        // Before starting, the integration time isn't in the driver's config
+        ambient::write_step(&mut i2c, DEFAULT_ADDRESS, auto_gain.step()).expect("step");
+        let vl6180x: Vl6180xType = vl6180x.start_ambient_continuous_mode().expect("ct");
 
         let tof_1: Tof1Type = vl6180x::VL6180XwPins {
             vl6180x,
@@ -92,24 +88,41 @@ mod app {
             interrupt_pin,
         };
 
-        (Shared { led, tof_1 }, Local {}, init::Monotonics())
+        (
+            Shared { led, tof_1 },
+            Local { auto_gain, i2c },
+            init::Monotonics(),
+        )
     }
 
-    #[task(binds=EXTI9_5, shared = [led, tof_1])]
+    #[task(binds=EXTI9_5, shared = [led, tof_1], local = [auto_gain, i2c])]
     fn exti95_event(ctx: exti95_event::Context) {
         let led = ctx.shared.led;
         let tof_1 = ctx.shared.tof_1;
+        let auto_gain = ctx.local.auto_gain;
+        let i2c = ctx.local.i2c;
 
-        hprintln!("-------- Interrupt! --------").unwrap();
+        log!("-------- Interrupt! --------");
         (led, tof_1).lock(|led, tof_1| {
-            led.set_low();
-            match tof_1.vl6180x.read_ambient_lux() {
-                Ok(lux) => hprintln!("Ambient Read: {} lux", lux).unwrap(),
-                Err(e) => hprintln!("Error {:?}", e).unwrap(),
+            // The driver's lux would use the gain it was configured with
+            let handled = handlers::ambient_raw_interrupt(
+                &mut tof_1.vl6180x,
+                &mut tof_1.interrupt_pin,
+                led,
+                handlers::Clear::All,
+            );
+            match handled.reading {
+                Ok(raw) => {
+                    let reading = auto_gain.update(raw);
+                    log!("Ambient Read: {} lux", reading.lux);
+                    if let Some(step) = reading.next {
+                        ambient::write_step(i2c, DEFAULT_ADDRESS, step).expect("step");
+                        log!("Gain {}x, {} ms", step.gain(), step.integration_ms);
+                    }
+                }
+                Err(e) => log!("Error {:?}", e),
             };
-            led.set_high();
-            tof_1.interrupt_pin.clear_interrupt_pending_bit();
-            tof_1.vl6180x.clear_all_interrupts().expect("clrall");
+            handled.cleared.expect("clrall");
         });
     }
 
@@ -0,0 +1,116 @@
+//! One firmware for every threshold interrupt set-up: the interrupt mode,
+//! thresholds, gain and scalers come from a profile in `profiles.toml`,
+//! picked when building, e.g.
+//! `VL6180X_PROFILE=ambient-low cargo run --example configurable`.
+//! Wired as the other interrupt examples, see `board::PIN_MAP`.
+
+#![no_main]
+#![no_std]
+
+use rtic::app;
+
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use hal::prelude::*;
+    use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::handlers;
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::profile::{self, Measurement, Profile};
+    use vl6180x_stm32f401_examples::shell;
+
+    type I2cType = board::I2c1;
+
+    type Tof1Type = vl6180x::VL6180XwPins<
+        vl6180x::DynamicMode,
+        I2cType,
+        board::SensorXshut,
+        board::SensorInterrupt,
+    >;
+
+    #[shared]
+    struct Shared {
+        led: board::Led,
+        tof_1: Tof1Type,
+    }
+
+    #[local]
+    struct Local {
+        profile: Profile<'static>,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let Board {
+            mut delay,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            sensor,
+            ..
+        } = Board::new(ctx.device, ctx.core);
+
+        // Set up vl6180x
+        let mut x_shutdown_pin = sensor.xshut;
+        x_shutdown_pin.set_high();
+
+        // Ensure vl6180x is booted before trying to communicate with it
+        delay.delay_ms(2_u8);
+
+        let mut interrupt_pin = sensor.interrupt;
+        interrupt_pin.make_interrupt_source(&mut syscfg);
+        interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
+        interrupt_pin.enable_interrupt(&mut exti);
+
+        let profile = profile::selected();
+        log!("profile {}", profile.name);
+        // The profile's keys are shell settings, so the shell's mapping to a
+        // driver config applies as is
+        let mut vl6180x = vl6180x::VL6180X::with_config(i2c, &shell::config(&profile.settings))
+            .expect("vl")
+            .into_dynamic_mode();
+        match profile.start {
+            Measurement::Range => vl6180x.try_start_range_continuous_mode(),
+            Measurement::Ambient => vl6180x.try_start_ambient_continuous_mode(),
+        }
+        .expect("ct");
+
+        let tof_1: Tof1Type = vl6180x::VL6180XwPins {
+            vl6180x,
+            x_shutdown_pin,
+            interrupt_pin,
+        };
+
+        (Shared { led, tof_1 }, Local { profile }, init::Monotonics())
+    }
+
+    #[task(binds=EXTI9_5, shared = [led, tof_1], local = [profile])]
+    fn exti95_event(ctx: exti95_event::Context) {
+        let led = ctx.shared.led;
+        let tof_1 = ctx.shared.tof_1;
+        let profile = ctx.local.profile;
+        let clear = profile::clear_mode(profile.clear);
+
+        log!("-------- Interrupt! --------");
+        (led, tof_1).lock(|led, tof_1| {
+            let sensor = &mut tof_1.vl6180x;
+            let pin = &mut tof_1.interrupt_pin;
+            let handled = match profile.start {
+                Measurement::Range => handlers::range_interrupt(sensor, pin, led, clear),
+                Measurement::Ambient => handlers::ambient_raw_interrupt(sensor, pin, led, clear),
+            };
+            match (profile.start, handled.reading) {
+                (Measurement::Range, Ok(range)) => log!("Range Read: {}mm", range),
+                (Measurement::Ambient, Ok(raw)) => log!("Ambient Read: {}", raw),
+                (_, Err(e)) => log!("Error {:?}", e),
+            };
+            handled.cleared.expect("clear");
+        });
+    }
+
+    #[idle()]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {}
+    }
+}
@@ -2,180 +2,205 @@
 #![no_std]
 #![no_main]
 
+use core::cell::RefCell;
+
+use cortex_m::interrupt::{free, Mutex};
 use cortex_m_rt::ExceptionFrame;
 use cortex_m_rt::{entry, exception};
-use cortex_m_semihosting::hprintln;
+use hal::pac::{self, interrupt};
+use heapless::Deque;
 use stm32f4xx_hal as hal;
+use vl6180x_stm32f401_examples::log;
 
-use hal::gpio::{Output, Pin};
-
-use panic_semihosting as _;
 use vl6180x;
+use vl6180x_stm32f401_examples::board::{self, Board};
+use vl6180x_stm32f401_examples::button::{ButtonConfig, ButtonEvent, DebouncedButton};
+use vl6180x_stm32f401_examples::handlers::StatusLed;
+use vl6180x_stm32f401_examples::state_machine::{Actions, StateMachine, Transition};
+use vl6180x_stm32f401_examples::suite::{SuiteAction, DYNAMIC_SUITE, DYNAMIC_SUITE_START};
+use vl6180x_stm32f401_examples::timeout::{TimeSource, TimedReader};
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
 
-use hal::{pac, prelude::*};
+use hal::prelude::*;
 
 #[entry]
 fn main() -> ! {
-    if let (Some(dp), Some(cp)) = (
-        pac::Peripherals::take(),
-        cortex_m::peripheral::Peripherals::take(),
-    ) {
-        // Set up the system clock. We want to run at 48MHz for this one.
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
-
-        // Create a delay abstraction based on SysTick
-        let mut delay = cp.SYST.delay(&clocks);
-
-        // Set up the LED. On the Black Pill it's connected to pin PC13.
-        let gpioc = dp.GPIOC.split();
-        let mut led = gpioc.pc13.into_push_pull_output();
-        led.set_high();
-
-        // Set up I2C - SCL is PB8 and SDA is PB9; they are set to Alternate Function 4
-        let gpiob = dp.GPIOB.split();
-        let scl = gpiob
-            .pb8
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let sda = gpiob
-            .pb9
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let i2c = dp.I2C1.i2c((scl, sda), 400.kHz(), &clocks);
+    if let Some(board) = Board::take() {
+        let Board {
+            clocks,
+            mut delay,
+            clock,
+            cycles,
+            i2c,
+            mut led,
+            button,
+            tim2,
+            #[cfg(feature = "log-uart")]
+            mut console_rx,
+            sensor: sensor_pins,
+            ..
+        } = board;
 
         // Set up TOF distance sensor
         let mut tof_config = vl6180x::Config::new();
         tof_config.set_ambient_analogue_gain_level(7).expect("saag");
-        tof_config.set_ambient_result_scaler(15).expect("sas");
 
-        // To create sensor with default configuration:
-        let mut tof_1 = vl6180x::VL6180X::with_config(i2c, &tof_config)
+        // The driver changes modes, the reads go through a reader that gives
+        // up on a dead sensor instead of hanging the loop
+        let bus = shared_bus::BusManagerSimple::new(i2c);
+        let mut tof_1 = vl6180x::VL6180X::with_config(bus.acquire_i2c(), &tof_config)
             .expect("vl")
             .into_dynamic_mode();
 
-        // Set up button
-        let gpioa = dp.GPIOA.split();
-        let btn = gpioa.pa0.into_pull_up_input();
-
         // Set up XShut pin
-        let mut xshut: Pin<'B', 7, Output> = gpiob.pb7.into_push_pull_output();
+        let mut xshut = sensor_pins.xshut;
 
-        led.set_low();
+        led.on();
         tof_1.try_power_off(&mut xshut).expect("power off");
         delay.delay_ms(2000_u32);
-        led.set_high();
+        led.off();
         tof_1.try_power_on_and_init(&mut xshut).expect("power on");
 
-        tof_1.try_change_i2c_address(20).expect("change address");
+        tof_1
+            .try_change_i2c_address(ADDRESS)
+            .expect("change address");
+        let reader = TimedReader::new(bus.acquire_i2c(), ADDRESS, cycles);
 
         // Set up state for the loop
-        let mut state = State::RangeSinglePoll;
-        let mut was_pressed = btn.is_low();
+        let mut suite = Suite {
+            tof: tof_1,
+            reader,
+            delay,
+        };
+        let mut machine = StateMachine::new(&DYNAMIC_SUITE);
+        machine
+            .start_at(DYNAMIC_SUITE_START, &mut suite)
+            .expect("start");
+
+        // The loop can spend most of a second in a read or a delay, so the
+        // button is sampled from TIM2 and its presses queued for the loop
+        let mut timer = tim2.counter_ms(&clocks);
+        timer.start(BUTTON_POLL_MS.millis()).expect("button timer");
+        timer.listen(hal::timer::Event::Update);
+        free(|cs| {
+            BUTTON_POLL.borrow(cs).replace(Some(ButtonPoll {
+                button,
+                timer,
+                clock,
+                debounced: DebouncedButton::new(ButtonConfig::default()),
+                events: Deque::new(),
+            }))
+        });
+        unsafe { pac::NVIC::unmask(pac::Interrupt::TIM2) };
 
         // This runs continuously, as fast as possible
         loop {
-            let is_pressed = btn.is_low();
-            if !was_pressed && is_pressed {
-                use State::*;
-                // On exiting state
-                match state {
-                    RangeContinuousPoll => tof_1
-                        .try_stop_range_continuous_mode()
-                        .expect("stop range continuous"),
-                    AmbientContinuousPoll => tof_1
-                        .try_stop_ambient_continuous_mode()
-                        .expect("stop ambeint continuous"),
-                    _ => (),
-                };
-
-                state.cycle();
-                was_pressed = true;
-
-                // On first entering state
-                match state {
-                    WelcomeText => hprintln!("{}", WELCOME_TEXT).unwrap(),
-                    RangeContinuousPoll => tof_1
-                        .try_start_range_continuous_mode()
-                        .expect("start range cont"),
-                    AmbientContinuousPoll => tof_1
-                        .try_start_ambient_continuous_mode()
-                        .expect("start ambient cont"),
-                    EndText => hprintln!("{}", END_TEXT).unwrap(),
-                    _ => (),
-                };
-            } else if !is_pressed {
-                was_pressed = false;
-            }
-            // While in state
-            use State::*;
-            match state {
-                RangeContinuousPoll => match tof_1.try_read_range_mm_blocking() {
-                    Ok(range) => hprintln!("Range Continuous Poll: {}mm", range).unwrap(),
-                    Err(e) => {
-                        hprintln!("Error reading TOF sensor Continuous Poll! {:?}", e).unwrap()
-                    }
-                },
-                RangeSinglePoll => match tof_1.try_poll_range_mm_single_blocking() {
-                    Ok(range) => hprintln!("Range Single Poll: {}mm", range).unwrap(),
-                    Err(e) => hprintln!("Error reading TOF sensor Single Poll! {:?}", e).unwrap(),
-                },
-                AmbientContinuousPoll => {
-                    match tof_1.try_read_ambient_lux_blocking() {
-                        Ok(ambient) => {
-                            hprintln!("Ambient Continuous Poll: {:08.4}lux", ambient).unwrap()
-                        }
-                        Err(e) => {
-                            hprintln!("Error reading TOF sensor Ambient Continuous Poll! {:?}", e)
-                                .unwrap()
-                        }
-                    };
-                    delay.delay_ms(500_u32);
+            // Keys typed into the log UART's terminal work too
+            #[cfg(feature = "log-uart")]
+            let key = console_rx.read().ok().and_then(Transition::from_key);
+            #[cfg(not(feature = "log-uart"))]
+            let key = None;
+
+            let presses = core::iter::from_fn(next_button_event).map(Transition::from_button);
+            for transition in presses.chain(key) {
+                if let Err(e) = machine.handle(transition, &mut suite) {
+                    log!("Error changing to {}! {:?}", machine.current().name, e);
                 }
-                AmbientSinglePoll => {
-                    match tof_1.try_poll_ambient_lux_single_blocking() {
-                        Ok(ambient) => {
-                            hprintln!("Ambient Single Poll: {:08.4}lux", ambient).unwrap()
-                        }
-                        Err(e) => {
-                            hprintln!("Error reading TOF sensor Ambient Single Poll! {:?}", e)
-                                .unwrap()
-                        }
-                    };
-                }
-                _ => (),
-            };
+            }
+            if let Err(e) = machine.tick(&mut suite) {
+                log!("Error in {}! {:?}", machine.current().name, e);
+            }
         }
     }
 
     loop {}
 }
 
-const WELCOME_TEXT: &str = "VL6180X\nDynamic Mode Single Sensor Test Suite";
-const END_TEXT: &str = "Goodbye\nSee you soon!";
+const ADDRESS: u8 = 20;
+
+/// How often TIM2 samples the button.
+const BUTTON_POLL_MS: u32 = 5;
+
+/// Presses the loop hasn't got to yet, further ones are dropped.
+const BUTTON_EVENTS: usize = 4;
+
+/// The button's debouncing in the TIM2 interrupt, and the events it has
+/// queued for the loop.
+struct ButtonPoll {
+    button: board::Button,
+    timer: hal::timer::CounterMs<pac::TIM2>,
+    clock: board::Clock,
+    debounced: DebouncedButton,
+    events: Deque<ButtonEvent, BUTTON_EVENTS>,
+}
+
+static BUTTON_POLL: Mutex<RefCell<Option<ButtonPoll>>> = Mutex::new(RefCell::new(None));
+
+#[interrupt]
+fn TIM2() {
+    free(|cs| {
+        if let Some(poll) = BUTTON_POLL.borrow(cs).borrow_mut().as_mut() {
+            poll.timer.clear_interrupt(hal::timer::Event::Update);
+            let events = &mut poll.events;
+            poll.debounced
+                .update(poll.button.is_low(), board::now_ms(&poll.clock), |event| {
+                    let _ = events.push_back(event);
+                });
+        }
+    });
+}
+
+/// Takes the oldest press the TIM2 interrupt has queued.
+fn next_button_event() -> Option<ButtonEvent> {
+    free(|cs| {
+        let mut poll = BUTTON_POLL.borrow(cs).borrow_mut();
+        poll.as_mut()?.events.pop_front()
+    })
+}
 
-enum State {
-    WelcomeText,
-    RangeContinuousPoll,
-    RangeSinglePoll,
-    AmbientContinuousPoll,
-    AmbientSinglePoll,
-    EndText,
+/// Runs the suite's actions on the sensor, logging what it reads.
+struct Suite<I2C, T> {
+    tof: vl6180x::VL6180X<vl6180x::DynamicMode, I2C>,
+    reader: TimedReader<I2C, T>,
+    delay: hal::timer::SysDelay,
 }
 
-impl State {
-    fn cycle(&mut self) {
-        use State::*;
-        *self = match *self {
-            WelcomeText => RangeContinuousPoll,
-            RangeContinuousPoll => RangeSinglePoll,
-            RangeSinglePoll => AmbientContinuousPoll,
-            AmbientContinuousPoll => AmbientSinglePoll,
-            AmbientSinglePoll => EndText,
-            EndText => WelcomeText,
+impl<I2C, T, E> Actions<SuiteAction> for Suite<I2C, T>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    T: TimeSource,
+    E: core::fmt::Debug,
+{
+    type Error = vl6180x::Error<E>;
+
+    fn run(&mut self, action: &SuiteAction) -> Result<(), Self::Error> {
+        use SuiteAction::*;
+        match *action {
+            Print(text) => log!("{}", text),
+            StartRangeContinuous => self.tof.try_start_range_continuous_mode()?,
+            StopRangeContinuous => self.tof.try_stop_range_continuous_mode()?,
+            StartAmbientContinuous => self.tof.try_start_ambient_continuous_mode()?,
+            StopAmbientContinuous => self.tof.try_stop_ambient_continuous_mode()?,
+            ReadRange => match self.reader.read_range_mm() {
+                Ok(range) => log!("Range Continuous Poll: {}mm", range),
+                Err(e) => log!("Error reading TOF sensor Continuous Poll! {:?}", e),
+            },
+            PollRange => match self.reader.poll_range_mm_single() {
+                Ok(range) => log!("Range Single Poll: {}mm", range),
+                Err(e) => log!("Error reading TOF sensor Single Poll! {:?}", e),
+            },
+            ReadAmbient => match self.reader.read_ambient_lux() {
+                Ok(ambient) => log!("Ambient Continuous Poll: {:08.4}lux", ambient),
+                Err(e) => log!("Error reading TOF sensor Ambient Continuous Poll! {:?}", e),
+            },
+            PollAmbient => match self.reader.poll_ambient_lux_single() {
+                Ok(ambient) => log!("Ambient Single Poll: {:08.4}lux", ambient),
+                Err(e) => log!("Error reading TOF sensor Ambient Single Poll! {:?}", e),
+            },
+            DelayMs(ms) => self.delay.delay_ms(ms),
         }
+        Ok(())
     }
 }
 
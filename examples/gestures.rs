@@ -0,0 +1,181 @@
+//! Swipe, tap and hover gestures over two sensors side by side, wired as in
+//! the multiple sensors example: the left one on PB1 (interrupt) and PB2
+//! (XSHUT), the right one on PA2 and PA3. Prints a line per gesture instead
+//! of one per reading.
+
+#![no_main]
+#![no_std]
+
+use rtic::app;
+
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use hal::prelude::*;
+    use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::bus;
+    use vl6180x_stm32f401_examples::dispatch::{Dispatcher, ExtiVector, Route};
+    use vl6180x_stm32f401_examples::gesture::{GestureConfig, GestureRecogniser, Side};
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::new_ceiling_bus;
+    use vl6180x_stm32f401_examples::sensor_array::{SensorArray, SensorSpec};
+
+    /// Highest priority of any task using the I2C bus.
+    const BUS_CEILING: u8 = 1;
+
+    type I2cProxy = bus::I2cProxy<board::I2c1, BUS_CEILING>;
+
+    type TofType = vl6180x::VL6180XwPins<
+        vl6180x::RangeContinuousMode,
+        I2cProxy,
+        hal::gpio::ErasedPin<hal::gpio::Output>,
+        hal::gpio::ErasedPin<hal::gpio::Input>,
+    >;
+
+    const SENSOR_COUNT: usize = 2;
+
+    #[shared]
+    struct Shared {
+        led: board::Led,
+        clock: board::Clock,
+        recogniser: GestureRecogniser,
+    }
+
+    #[local]
+    struct Local {
+        tof_left: TofType,
+        tof_right: TofType,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let Board {
+            mut delay,
+            clock,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            pins,
+            ..
+        } = Board::new(ctx.device, ctx.core);
+
+        let bus_manager = new_ceiling_bus!(board::I2c1, BUS_CEILING, i2c).unwrap();
+
+        let mut int_left = pins.pb1.into_pull_up_input();
+        int_left.make_interrupt_source(&mut syscfg);
+        int_left.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
+        int_left.enable_interrupt(&mut exti);
+
+        // PA2/PA3 are USART2, so this example can't be built with `log-uart`
+        let mut int_right = pins.pa2.into_pull_up_input();
+        int_right.make_interrupt_source(&mut syscfg);
+        int_right.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
+        int_right.enable_interrupt(&mut exti);
+
+        let specs = [
+            SensorSpec {
+                x_shutdown_pin: pins.pb2.into_push_pull_output().erase(),
+                interrupt_pin: int_left.erase(),
+                config: tof_config(),
+                address: 10,
+            },
+            SensorSpec {
+                x_shutdown_pin: pins.pa3.into_push_pull_output().erase(),
+                interrupt_pin: int_right.erase(),
+                config: tof_config(),
+                address: 11,
+            },
+        ];
+        let mut sensors: heapless::Vec<TofType, SENSOR_COUNT> =
+            SensorArray::new(specs, || bus_manager.acquire_i2c(), &mut delay)
+                .expect("array")
+                .start_range_continuous_mode()
+                .expect("ct");
+        let tof_right = sensors.pop().expect("right");
+        let tof_left = sensors.pop().expect("left");
+
+        (
+            Shared {
+                led,
+                clock,
+                recogniser: GestureRecogniser::new(GestureConfig::default()),
+            },
+            Local {
+                tof_left,
+                tof_right,
+            },
+            init::Monotonics(),
+        )
+    }
+
+    /// Which EXTI line each sensor's interrupt pin is on: PB1 and PA2.
+    const DISPATCHER: Dispatcher<SENSOR_COUNT> =
+        Dispatcher::new([Route::new(1, 0), Route::new(2, 1)]);
+
+    // Both at the same priority, so readings reach the recogniser in order
+    #[task(binds=EXTI1, shared = [led, clock, recogniser], local = [tof_left])]
+    fn exti1_event(ctx: exti1_event::Context) {
+        let led = ctx.shared.led;
+        let clock = ctx.shared.clock;
+        let recogniser = ctx.shared.recogniser;
+
+        (led, clock, recogniser).lock(|led, clock, recogniser| {
+            service(
+                ExtiVector::Exti1,
+                Side::Left,
+                ctx.local.tof_left,
+                led,
+                clock,
+                recogniser,
+            )
+        });
+    }
+
+    #[task(binds=EXTI2, shared = [led, clock, recogniser], local = [tof_right])]
+    fn exti2_event(ctx: exti2_event::Context) {
+        let led = ctx.shared.led;
+        let clock = ctx.shared.clock;
+        let recogniser = ctx.shared.recogniser;
+
+        (led, clock, recogniser).lock(|led, clock, recogniser| {
+            service(
+                ExtiVector::Exti2,
+                Side::Right,
+                ctx.local.tof_right,
+                led,
+                clock,
+                recogniser,
+            )
+        });
+    }
+
+    fn service(
+        vector: ExtiVector,
+        side: Side,
+        tof: &mut TofType,
+        led: &mut board::Led,
+        clock: &board::Clock,
+        recogniser: &mut GestureRecogniser,
+    ) {
+        DISPATCHER.dispatch_one(vector, side.index(), tof, led, |_, handled| {
+            // Out of range shows as an error, count any failed read as no target
+            let range = handled.reading.ok();
+            if let Some(gesture) = recogniser.update(side, range, board::now_ms(clock)) {
+                log!("{:?}", gesture);
+            }
+            handled.cleared.expect("clrall");
+        });
+    }
+
+    fn tof_config() -> vl6180x::Config {
+        let mut tof_config = vl6180x::Config::new();
+        tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+        tof_config
+    }
+
+    #[idle()]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {}
+    }
+}
@@ -0,0 +1,129 @@
+//! Presence detection: the LED is on while something is within 50 mm, and
+//! stays on until it has been further than 80 mm for half a second. Unlike
+//! the threshold interrupt examples, a target hovering around either
+//! distance doesn't make it flicker.
+
+#![no_main]
+#![no_std]
+
+use rtic::app;
+
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use hal::prelude::*;
+    use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::handlers::StatusLed;
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::presence::{PresenceConfig, PresenceDetector, PresenceEvent};
+
+    type I2cType = board::I2c1;
+
+    type Vl6180xType = vl6180x::VL6180X<vl6180x::RangeContinuousMode, I2cType>;
+
+    type Tof1Type = vl6180x::VL6180XwPins<
+        vl6180x::RangeContinuousMode,
+        I2cType,
+        board::SensorXshut,
+        board::SensorInterrupt,
+    >;
+
+    /// Time between continuous range measurements, the 100 ms the driver's
+    /// init sequence sets. Samples are counted to keep time.
+    const RANGE_PERIOD_MS: u32 = 100;
+
+    const PRESENCE: PresenceConfig = PresenceConfig {
+        enter_mm: 50,
+        exit_mm: 80,
+        enter_dwell_ms: 200,
+        exit_dwell_ms: 500,
+    };
+
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {
+        led: board::Led,
+        tof_1: Tof1Type,
+        detector: PresenceDetector,
+        now_ms: u32,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let Board {
+            mut delay,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            sensor,
+            ..
+        } = Board::new(ctx.device, ctx.core);
+
+        // Set up vl6180x
+        let mut x_shutdown_pin = sensor.xshut;
+        x_shutdown_pin.set_high();
+
+        // Ensure vl6180x is booted before trying to communicate with it
+        delay.delay_ms(2_u8);
+
+        let mut interrupt_pin = sensor.interrupt;
+        interrupt_pin.make_interrupt_source(&mut syscfg);
+        interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
+        interrupt_pin.enable_interrupt(&mut exti);
+
+        let mut tof_config = vl6180x::Config::new();
+        tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+        let vl6180x: Vl6180xType = vl6180x::VL6180X::with_config(i2c, &tof_config)
+            .expect("vl")
+            .start_range_continuous_mode()
+            .expect("ct");
+
+        let tof_1: Tof1Type = vl6180x::VL6180XwPins {
+            vl6180x,
+            x_shutdown_pin,
+            interrupt_pin,
+        };
+
+        (
+            Shared {},
+            Local {
+                led,
+                tof_1,
+                detector: PresenceDetector::new(PRESENCE),
+                now_ms: 0,
+            },
+            init::Monotonics(),
+        )
+    }
+
+    #[task(binds=EXTI9_5, local = [led, tof_1, detector, now_ms])]
+    fn exti95_event(ctx: exti95_event::Context) {
+        let led = ctx.local.led;
+        let tof_1 = ctx.local.tof_1;
+        let detector = ctx.local.detector;
+        let now_ms = ctx.local.now_ms;
+
+        *now_ms = now_ms.wrapping_add(RANGE_PERIOD_MS);
+        tof_1.interrupt_pin.clear_interrupt_pending_bit();
+        // Out of range shows as an error, count any failed read as no target
+        let range = tof_1.vl6180x.read_range_mm().ok();
+        tof_1.vl6180x.clear_all_interrupts().expect("clrall");
+
+        detector.update(range, *now_ms, |event, at_ms| {
+            log!("{:?} at {}ms", event, at_ms);
+            match event {
+                PresenceEvent::Arrived => led.on(),
+                PresenceEvent::Left => led.off(),
+                _ => (),
+            }
+        });
+    }
+
+    #[idle()]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {}
+    }
+}
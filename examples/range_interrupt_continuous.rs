@@ -1,90 +1,100 @@
 #![no_main]
 #![no_std]
 
-use panic_semihosting as _;
 use rtic::app;
 
 #[app(device = hal::pac, peripherals = true)]
 mod app {
-    use cortex_m_semihosting::hprintln;
     use hal::prelude::*;
     use stm32f4xx_hal as hal;
-
-    type I2cType = hal::i2c::I2c<
-        you_must_enable_the_rt_feature_for_the_pac_in_your_cargo_toml::I2C1,
-        (
-            hal::gpio::gpiob::PB8<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-            hal::gpio::gpiob::PB9<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-        ),
-    >;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::bus;
+    use vl6180x_stm32f401_examples::filter::{ChainConfig, FilterChain, KalmanConfig};
+    use vl6180x_stm32f401_examples::handlers;
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::new_ceiling_bus;
+    use vl6180x_stm32f401_examples::registers::DEFAULT_ADDRESS;
+    use vl6180x_stm32f401_examples::stats::{self, RangeReporter};
+
+    /// Only the EXTI task uses the bus.
+    const BUS_CEILING: u8 = 1;
+
+    type I2cType = bus::I2cProxy<board::I2c1, BUS_CEILING>;
+
+    /// Longest median window the filter has room for.
+    const MEDIAN_MAX: usize = 5;
+
+    /// Median of 3 against spikes, then a Kalman filter tuned for about
+    /// 3 mm of jitter on a target that moves slowly.
+    const FILTER: ChainConfig = ChainConfig {
+        median_len: 3,
+        ema_alpha: None,
+        kalman: Some(KalmanConfig {
+            process_noise: 1.0,
+            measurement_noise: 9.0,
+        }),
+    };
 
     type Vl6180xType = vl6180x::VL6180X<vl6180x::RangeContinuousMode, I2cType>;
 
     type Tof1Type = vl6180x::VL6180XwPins<
         vl6180x::RangeContinuousMode,
         I2cType,
-        hal::gpio::gpiob::PB7<hal::gpio::Output>,
-        hal::gpio::gpiob::PB6<hal::gpio::Input>,
+        board::SensorXshut,
+        board::SensorInterrupt,
     >;
 
     #[shared]
     struct Shared {
-        led: hal::gpio::gpioc::PC13<hal::gpio::Output<hal::gpio::PushPull>>,
+        led: board::Led,
         tof_1: Tof1Type,
     }
 
     #[local]
     struct Local {
         counter: u32,
+        filter: FilterChain<MEDIAN_MAX>,
+        clock: board::Clock,
+        /// Summarises the raw readings, before the filter.
+        reporter: RangeReporter,
+        /// Reads the error code of a failed reading, the driver keeps its
+        /// own proxy.
+        i2c: I2cType,
     }
 
     #[init]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
-        let dp = ctx.device;
-        let cp = ctx.core;
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
-        let mut delay = cp.SYST.delay(&clocks);
-        let mut exti = dp.EXTI;
-        let mut syscfg = dp.SYSCFG.constrain();
-
-        // Set up led
-        let gpioc = dp.GPIOC.split();
-        let mut led = gpioc.pc13.into_push_pull_output();
-        led.set_high();
-
-        // Set up I2C
-        let gpiob = dp.GPIOB.split();
-        let scl = gpiob
-            .pb8
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let sda = gpiob
-            .pb9
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let i2c: I2cType = dp.I2C1.i2c((scl, sda), 400.kHz(), &clocks);
+        let Board {
+            mut delay,
+            clock,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            sensor,
+            ..
+        } = Board::new(ctx.device, ctx.core);
 
         // Set up vl6180x
-        let mut x_shutdown_pin = gpiob.pb7.into_push_pull_output();
+        let mut x_shutdown_pin = sensor.xshut;
         x_shutdown_pin.set_high();
 
         // Ensure vl6180x is booted before trying to communicate with it
         delay.delay_ms(2_u8);
 
-        let mut interrupt_pin = gpiob.pb6.into_pull_up_input();
+        let mut interrupt_pin = sensor.interrupt;
         interrupt_pin.make_interrupt_source(&mut syscfg);
         interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
         interrupt_pin.enable_interrupt(&mut exti);
 
         let mut tof_config = vl6180x::Config::new();
         tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
-        let vl6180x: Vl6180xType = vl6180x::VL6180X::with_config(i2c, &tof_config)
-            .expect("vl")
-            .start_range_continuous_mode()
-            .expect("ct");
+        let bus_manager = new_ceiling_bus!(board::I2c1, BUS_CEILING, i2c).unwrap();
+        let vl6180x: Vl6180xType =
+            vl6180x::VL6180X::with_config(bus_manager.acquire_i2c(), &tof_config)
+                .expect("vl")
+                .start_range_continuous_mode()
+                .expect("ct");
 
         let tof_1: Tof1Type = vl6180x::VL6180XwPins {
             vl6180x,
@@ -93,29 +103,60 @@ mod app {
         };
 
         let counter: u32 = 0;
+        let filter = FilterChain::new(FILTER).expect("filter");
+        let reporter = stats::range_reporter(board::now_ms(&clock));
+        let i2c = bus_manager.acquire_i2c();
 
-        (Shared { led, tof_1 }, Local { counter }, init::Monotonics())
+        (
+            Shared { led, tof_1 },
+            Local {
+                counter,
+                filter,
+                clock,
+                reporter,
+                i2c,
+            },
+            init::Monotonics(),
+        )
     }
 
-    #[task(binds=EXTI9_5, shared = [led, tof_1], local = [counter])]
+    #[task(binds=EXTI9_5, shared = [led, tof_1], local = [counter, filter, clock, reporter, i2c])]
     fn exti95_event(ctx: exti95_event::Context) {
         let led = ctx.shared.led;
         let tof_1 = ctx.shared.tof_1;
         let counter = ctx.local.counter;
+        let filter = ctx.local.filter;
+        let reporter = ctx.local.reporter;
+        let i2c = ctx.local.i2c;
 
         *counter += 1;
 
-        hprintln!("-------- Interrupt! -------- ({})", *counter).unwrap();
+        log!("-------- Interrupt! -------- ({})", *counter);
         (led, tof_1).lock(|led, tof_1| {
-            led.set_low();
-            match tof_1.vl6180x.read_range_mm() {
-                Ok(range) => hprintln!("Range Read: {}mm", range).unwrap(),
-                Err(e) => hprintln!("Error {:?}", e).unwrap(),
+            let handled = handlers::range_interrupt(
+                &mut tof_1.vl6180x,
+                &mut tof_1.interrupt_pin,
+                led,
+                handlers::Clear::All,
+            );
+            match handled.reading {
+                Ok(range) => {
+                    reporter.stats_mut().record(range);
+                    log!(
+                        "Range Read: {}mm (filtered {}mm)",
+                        range,
+                        filter.update_mm(range)
+                    )
+                }
+                Err(e) => {
+                    stats::record_failed_read(reporter.stats_mut(), i2c, DEFAULT_ADDRESS);
+                    log!("Error {:?}", e)
+                }
             };
-            led.set_high();
-            tof_1.interrupt_pin.clear_interrupt_pending_bit();
-            tof_1.vl6180x.clear_all_interrupts().expect("clrall");
+            handled.cleared.expect("clrall");
         });
+
+        stats::log_report(reporter, board::now_ms(ctx.local.clock));
     }
 
     #[idle()]
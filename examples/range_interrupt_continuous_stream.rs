@@ -0,0 +1,198 @@
+//! Continuous ranging like `range_interrupt_continuous`, with every interrupt
+//! and reading also sent as binary frames on USART1 TX (PA9) for the host
+//! decoder. A failed read is sent with its range error code, and every
+//! second a status with the errors so far, after a timeout error if the
+//! sensor didn't interrupt in the meantime.
+
+#![no_main]
+#![no_std]
+
+use rtic::app;
+
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use hal::prelude::*;
+    use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::bus;
+    use vl6180x_stm32f401_examples::handlers;
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::new_ceiling_bus;
+    use vl6180x_stm32f401_examples::protocol::{Message, Reading};
+    use vl6180x_stm32f401_examples::registers::DEFAULT_ADDRESS;
+    use vl6180x_stm32f401_examples::stream::{self, Stream, STREAM_BAUD};
+    use vl6180x_stm32f401_examples::timeout::{self, ReadError};
+
+    /// Only the EXTI task uses the bus.
+    const BUS_CEILING: u8 = 1;
+
+    type I2cType = bus::I2cProxy<board::I2c1, BUS_CEILING>;
+
+    /// How often the status is sent. Far longer than a range measurement,
+    /// so a period without a reading means the sensor has stopped.
+    const STATUS_PERIOD_MS: u32 = 1000;
+
+    type Vl6180xType = vl6180x::VL6180X<vl6180x::RangeContinuousMode, I2cType>;
+
+    type Tof1Type = vl6180x::VL6180XwPins<
+        vl6180x::RangeContinuousMode,
+        I2cType,
+        board::SensorXshut,
+        board::SensorInterrupt,
+    >;
+
+    #[shared]
+    struct Shared {
+        led: board::Led,
+        tof_1: Tof1Type,
+        /// The EXTI and status tasks run at the same priority.
+        #[lock_free]
+        stream: Stream<hal::serial::Tx<hal::pac::USART1>>,
+        /// Failed reads since boot.
+        #[lock_free]
+        errors: u16,
+        /// Whether the sensor interrupted since the last status.
+        #[lock_free]
+        fresh: bool,
+    }
+
+    #[local]
+    struct Local {
+        /// Reads the error code of a failed reading, the driver keeps its
+        /// own proxy.
+        i2c: I2cType,
+        status_timer: hal::timer::CounterMs<hal::pac::TIM2>,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let Board {
+            clocks,
+            mut delay,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            usart1,
+            tim2,
+            sensor,
+            pins,
+            ..
+        } = Board::new(ctx.device, ctx.core);
+
+        // Set up vl6180x
+        let mut x_shutdown_pin = sensor.xshut;
+        x_shutdown_pin.set_high();
+
+        // Ensure vl6180x is booted before trying to communicate with it
+        delay.delay_ms(2_u8);
+
+        let mut interrupt_pin = sensor.interrupt;
+        interrupt_pin.make_interrupt_source(&mut syscfg);
+        interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
+        interrupt_pin.enable_interrupt(&mut exti);
+
+        let mut tof_config = vl6180x::Config::new();
+        tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+        let bus_manager = new_ceiling_bus!(board::I2c1, BUS_CEILING, i2c).unwrap();
+        let vl6180x: Vl6180xType =
+            vl6180x::VL6180X::with_config(bus_manager.acquire_i2c(), &tof_config)
+                .expect("vl")
+                .start_range_continuous_mode()
+                .expect("ct");
+
+        let tof_1: Tof1Type = vl6180x::VL6180XwPins {
+            vl6180x,
+            x_shutdown_pin,
+            interrupt_pin,
+        };
+
+        let tx = usart1
+            .tx(pins.pa9.into_alternate(), STREAM_BAUD.bps(), &clocks)
+            .expect("usart1");
+        let stream = Stream::new(tx);
+
+        let mut status_timer = tim2.counter_ms(&clocks);
+        status_timer.start(STATUS_PERIOD_MS.millis()).unwrap();
+        status_timer.listen(hal::timer::Event::Update);
+
+        (
+            Shared {
+                led,
+                tof_1,
+                stream,
+                errors: 0,
+                fresh: false,
+            },
+            Local {
+                i2c: bus_manager.acquire_i2c(),
+                status_timer,
+            },
+            init::Monotonics(),
+        )
+    }
+
+    #[task(binds=EXTI9_5, shared = [led, tof_1, stream, errors, fresh], local = [i2c])]
+    fn exti95_event(ctx: exti95_event::Context) {
+        let led = ctx.shared.led;
+        let tof_1 = ctx.shared.tof_1;
+        let stream = ctx.shared.stream;
+        let errors = ctx.shared.errors;
+        let i2c = ctx.local.i2c;
+
+        *ctx.shared.fresh = true;
+
+        let _ = stream.send(Message::Interrupt {
+            sensor: 0,
+            line: board::PIN_MAP.sensor_interrupt.number,
+        });
+        (led, tof_1).lock(|led, tof_1| {
+            let handled = handlers::range_interrupt(
+                &mut tof_1.vl6180x,
+                &mut tof_1.interrupt_pin,
+                led,
+                handlers::Clear::All,
+            );
+            let message = match handled.reading {
+                Ok(range) => Message::Measurement {
+                    sensor: 0,
+                    reading: Reading::RangeMm(range),
+                },
+                Err(e) => {
+                    log!("Error {:?}", e);
+                    *errors = errors.saturating_add(1);
+                    let error = timeout::failed_range_read(i2c, DEFAULT_ADDRESS, ());
+                    stream::error_message(0, &error)
+                }
+            };
+            let _ = stream.send(message);
+            handled.cleared.expect("clrall");
+        });
+    }
+
+    /// Sends the status, and a timeout error first if the sensor has gone
+    /// quiet since the last one.
+    #[task(binds = TIM2, shared = [stream, errors, fresh], local = [status_timer])]
+    fn status(ctx: status::Context) {
+        ctx.local
+            .status_timer
+            .clear_interrupt(hal::timer::Event::Update);
+        let stream = ctx.shared.stream;
+        let errors = ctx.shared.errors;
+        let fresh = ctx.shared.fresh;
+
+        if !core::mem::replace(fresh, false) {
+            *errors = errors.saturating_add(1);
+            let _ = stream.send(stream::error_message::<()>(0, &ReadError::Timeout));
+        }
+        let _ = stream.send(Message::Status {
+            sensors: 1,
+            errors: *errors,
+        });
+    }
+
+    #[idle()]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {}
+    }
+}
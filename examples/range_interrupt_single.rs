@@ -1,35 +1,30 @@
 #![no_main]
 #![no_std]
 
-use panic_semihosting as _;
 use rtic::app;
 
 #[app(device = hal::pac, peripherals = true)]
 mod app {
-    use cortex_m_semihosting::hprintln;
     use hal::prelude::*;
     use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::handlers::{self, StatusLed};
+    use vl6180x_stm32f401_examples::log;
 
-    type I2cType = hal::i2c::I2c<
-        you_must_enable_the_rt_feature_for_the_pac_in_your_cargo_toml::I2C1,
-        (
-            hal::gpio::gpiob::PB8<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-            hal::gpio::gpiob::PB9<hal::gpio::Alternate<4, hal::gpio::OpenDrain>>,
-        ),
-    >;
+    type I2cType = board::I2c1;
 
     type Vl6180xType = vl6180x::VL6180X<vl6180x::ReadyMode, I2cType>;
 
     type Tof1Type = vl6180x::VL6180XwPins<
         vl6180x::ReadyMode,
         I2cType,
-        hal::gpio::gpiob::PB7<hal::gpio::Output>,
-        hal::gpio::gpiob::PB6<hal::gpio::Input>,
+        board::SensorXshut,
+        board::SensorInterrupt,
     >;
 
     #[shared]
     struct Shared {
-        led: hal::gpio::gpioc::PC13<hal::gpio::Output<hal::gpio::PushPull>>,
+        led: board::Led,
         delay: hal::timer::SysDelay,
         tof_1: Tof1Type,
     }
@@ -39,35 +34,18 @@ mod app {
 
     #[init]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
-        let dp = ctx.device;
-        let cp = ctx.core;
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
-        let mut delay = cp.SYST.delay(&clocks);
-        let mut exti = dp.EXTI;
-        let mut syscfg = dp.SYSCFG.constrain();
-
-        // Set up led
-        let gpioc = dp.GPIOC.split();
-        let mut led = gpioc.pc13.into_push_pull_output();
-        led.set_high();
-
-        // Set up I2C
-        let gpiob = dp.GPIOB.split();
-        let scl = gpiob
-            .pb8
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let sda = gpiob
-            .pb9
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let i2c: I2cType = dp.I2C1.i2c((scl, sda), 400.kHz(), &clocks);
+        let Board {
+            mut delay,
+            i2c,
+            led,
+            mut exti,
+            mut syscfg,
+            sensor,
+            ..
+        } = Board::new(ctx.device, ctx.core);
 
         // Set up vl6180x
-        let mut x_shutdown_pin = gpiob.pb7.into_push_pull_output();
+        let mut x_shutdown_pin = sensor.xshut;
         x_shutdown_pin.set_high();
 
         // Ensure vl6180x is booted before trying to communicate with it
@@ -77,7 +55,7 @@ mod app {
         tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
         let vl6180x: Vl6180xType = vl6180x::VL6180X::with_config(i2c, &tof_config).expect("vl");
 
-        let mut interrupt_pin = gpiob.pb6.into_pull_up_input();
+        let mut interrupt_pin = sensor.interrupt;
         interrupt_pin.make_interrupt_source(&mut syscfg);
         interrupt_pin.trigger_on_edge(&mut exti, hal::gpio::Edge::Rising);
         interrupt_pin.enable_interrupt(&mut exti);
@@ -95,15 +73,18 @@ mod app {
         let led = ctx.shared.led;
         let tof_1 = ctx.shared.tof_1;
 
-        hprintln!("-------- Interrupt! --------").unwrap();
+        log!("-------- Interrupt! --------");
         (led, tof_1).lock(|led, tof_1| {
-            led.set_low();
-            match tof_1.vl6180x.read_range_mm() {
-                Ok(range) => hprintln!("Range Read: {}mm", range).unwrap(),
-                Err(e) => hprintln!("Error {:?}", e).unwrap(),
+            let handled = handlers::range_interrupt(
+                &mut tof_1.vl6180x,
+                &mut tof_1.interrupt_pin,
+                led,
+                handlers::Clear::None,
+            );
+            match handled.reading {
+                Ok(range) => log!("Range Read: {}mm", range),
+                Err(e) => log!("Error {:?}", e),
             };
-            tof_1.interrupt_pin.clear_interrupt_pending_bit();
-            led.set_high()
         });
     }
 
@@ -115,16 +96,16 @@ mod app {
 
         let ms = 3000_u16;
         loop {
-            hprintln!("Start Reading!").unwrap();
+            log!("Start Reading!");
             tof_1.lock(|tof_1| {
                 tof_1.vl6180x.start_range_single().expect("srs");
             });
             led.lock(|led| {
-                led.set_low();
+                led.on();
             });
             delay.lock(|delay| delay.delay_ms(50_u8));
             led.lock(|led| {
-                led.set_high();
+                led.off();
             });
             delay.lock(|delay| delay.delay_ms(ms));
         }
@@ -3,44 +3,27 @@
 
 use cortex_m_rt::ExceptionFrame;
 use cortex_m_rt::{entry, exception};
-use cortex_m_semihosting::hprintln;
-use hal::{pac, prelude::*};
-use panic_semihosting as _;
-use stm32f4xx_hal as hal;
 use vl6180x;
+use vl6180x_stm32f401_examples::board::Board;
+use vl6180x_stm32f401_examples::log;
+use vl6180x_stm32f401_examples::registers::DEFAULT_ADDRESS;
+use vl6180x_stm32f401_examples::timeout::TimedReader;
 
 #[entry]
 fn main() -> ! {
-    if let (Some(dp), Some(_cp)) = (
-        pac::Peripherals::take(),
-        cortex_m::peripheral::Peripherals::take(),
-    ) {
-        // Set up the system clock. We want to run at 48MHz for this one.
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
+    if let Some(board) = Board::take() {
+        let bus = shared_bus::BusManagerSimple::new(board.i2c);
 
-        // Set up I2C - SCL is PB8 and SDA is PB9; they are set to Alternate Function 4
-        let gpiob = dp.GPIOB.split();
-        let scl = gpiob
-            .pb8
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let sda = gpiob
-            .pb9
-            .into_alternate()
-            .internal_pull_up(true)
-            .set_open_drain();
-        let i2c = dp.I2C1.i2c((scl, sda), 400.kHz(), &clocks);
-
-        // To create sensor with default configuration:
-        let mut tof = vl6180x::VL6180X::new(i2c).expect("vl");
+        // The driver sets the sensor up with its default configuration, the
+        // reads go through a reader that gives up on a dead sensor
+        vl6180x::VL6180X::new(bus.acquire_i2c()).expect("vl");
+        let mut reader = TimedReader::new(bus.acquire_i2c(), DEFAULT_ADDRESS, board.cycles);
 
         // This runs continuously, as fast as possible
         loop {
-            match tof.poll_range_mm_single_blocking() {
-                Ok(range) => hprintln!("Range Single Poll: {}mm", range).unwrap(),
-                Err(e) => hprintln!("Error reading TOF sensor Single Poll! {:?}", e).unwrap(),
+            match reader.poll_range_mm_single() {
+                Ok(range) => log!("Range Single Poll: {}mm", range),
+                Err(e) => log!("Error reading TOF sensor Single Poll! {:?}", e),
             }
         }
     }
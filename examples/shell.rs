@@ -0,0 +1,252 @@
+//! Command shell on the log UART: type `help` into a terminal on USART2
+//! (PA2/PA3, 115200 baud) to change thresholds, interrupt modes, gain and
+//! scalers, start and stop continuous modes, or read once, all without
+//! reflashing. `save` keeps the settings in flash for the next boot.
+//!
+//! `calibrate offset <mm>` works out the sensor's range offset against a
+//! white target that far away. Holding the user button for two
+//! seconds does the same with the target at 50 mm and saves the result.
+//! `calibrate crosstalk <mm>` measures how much a cover window shortens
+//! readings of a dark target and compensates for it; `save` keeps both.
+//! Needs the `log-uart` feature, e.g.
+//! `cargo run --example shell --no-default-features --features blackpill-f401ce,log-uart`.
+
+#![no_main]
+#![no_std]
+
+use rtic::app;
+
+#[app(device = hal::pac, peripherals = true)]
+mod app {
+    use hal::prelude::*;
+    use stm32f4xx_hal as hal;
+    use vl6180x_stm32f401_examples::board::{self, Board};
+    use vl6180x_stm32f401_examples::bus::I2cProxy;
+    use vl6180x_stm32f401_examples::button::{ButtonConfig, ButtonEvent, DebouncedButton};
+    use vl6180x_stm32f401_examples::log;
+    use vl6180x_stm32f401_examples::new_ceiling_bus;
+    use vl6180x_stm32f401_examples::registers::DEFAULT_ADDRESS;
+    use vl6180x_stm32f401_examples::shell::{self, Command, LineBuffer, Shell};
+    use vl6180x_stm32f401_examples::storage::{InternalFlash, Store, StoredConfig};
+
+    /// The console and button tasks all run at priority 1.
+    const BUS_CEILING: u8 = 1;
+
+    /// Longest command line, longer ones are dropped.
+    const LINE_LEN: usize = 64;
+
+    /// How long the button has to be held to start a calibration.
+    const HOLD_MS: u32 = 2000;
+    /// How often the button is looked at while a press is in progress.
+    const BUTTON_POLL_MS: u32 = 5;
+
+    /// Distance of the target for a calibration started with the button.
+    const CALIBRATION_TARGET_MM: u16 = 50;
+
+    type ShellType = Shell<I2cProxy<board::I2c1, BUS_CEILING>>;
+
+    #[shared]
+    struct Shared {
+        shell: ShellType,
+        delay: hal::timer::SysDelay,
+        store: Store<InternalFlash>,
+        stored: StoredConfig,
+        /// Only the two button tasks use these, at the same priority.
+        #[lock_free]
+        button: board::Button,
+        #[lock_free]
+        button_timer: hal::timer::CounterMs<hal::pac::TIM2>,
+    }
+
+    #[local]
+    struct Local {
+        rx: hal::serial::Rx<hal::pac::USART2>,
+        line: LineBuffer<LINE_LEN>,
+        clock: board::Clock,
+        debounced: DebouncedButton,
+    }
+
+    #[init]
+    fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        let Board {
+            clocks,
+            mut delay,
+            clock,
+            i2c,
+            console_rx: mut rx,
+            mut button,
+            mut exti,
+            mut syscfg,
+            flash,
+            tim2,
+            sensor: sensor_pins,
+            ..
+        } = Board::new(ctx.device, ctx.core);
+
+        // Set up vl6180x
+        let mut x_shutdown_pin = sensor_pins.xshut;
+        x_shutdown_pin.set_high();
+
+        // Ensure vl6180x is booted before trying to communicate with it
+        delay.delay_ms(2_u8);
+
+        let bus_manager = new_ceiling_bus!(board::I2c1, BUS_CEILING, i2c).unwrap();
+
+        let mut store = Store::new(InternalFlash::new(flash));
+        let stored = store.load_or_default().expect("load");
+        let sensor = stored.sensor(DEFAULT_ADDRESS);
+        let settings = sensor.settings;
+
+        // The driver runs the init sequence, the shell takes over from there
+        vl6180x::VL6180X::with_config(bus_manager.acquire_i2c(), &shell::config(&settings))
+            .expect("vl");
+        let mut shell = Shell::new(bus_manager.acquire_i2c(), DEFAULT_ADDRESS, settings);
+        // The offset register resets with the sensor, so write it again
+        shell
+            .apply_calibration(sensor.calibration)
+            .expect("calibration");
+
+        button.make_interrupt_source(&mut syscfg);
+        button.trigger_on_edge(&mut exti, hal::gpio::Edge::RisingFalling);
+        button.enable_interrupt(&mut exti);
+        // Started by the button's edges, stopped once the press is over
+        let mut button_timer = tim2.counter_ms(&clocks);
+        button_timer.listen(hal::timer::Event::Update);
+        let debounced = DebouncedButton::new(ButtonConfig {
+            long_press_ms: HOLD_MS,
+            ..ButtonConfig::default()
+        });
+
+        rx.listen();
+        log!("{}", shell::Response::Help);
+
+        (
+            Shared {
+                shell,
+                delay,
+                store,
+                stored,
+                button,
+                button_timer,
+            },
+            Local {
+                rx,
+                line: LineBuffer::new(),
+                clock,
+                debounced,
+            },
+            init::Monotonics(),
+        )
+    }
+
+    #[task(binds = USART2, shared = [shell, delay, store, stored], local = [rx, line])]
+    fn console(ctx: console::Context) {
+        let line = ctx.local.line;
+        let rx = ctx.local.rx;
+
+        let shell = ctx.shared.shell;
+        let delay = ctx.shared.delay;
+        let store = ctx.shared.store;
+        let stored = ctx.shared.stored;
+
+        (shell, delay, store, stored).lock(|shell, delay, store, stored| {
+            while let Ok(byte) = rx.read() {
+                match line.push(byte) {
+                    Some(Ok(line)) => match shell::parse(line) {
+                        Ok(Command::Save) => save(shell, store, stored),
+                        Ok(command) => match shell.execute(command, delay) {
+                            Ok(response) => log!("{}", response),
+                            Err(e) => log!("error: {:?}", e),
+                        },
+                        Err(e) => log!("error: {}", e),
+                    },
+                    Some(Err(_)) => log!("error: line longer than {} characters", LINE_LEN),
+                    None => (),
+                }
+            }
+        });
+    }
+
+    /// Wakes the button polling on every edge, bounces included.
+    #[cfg(not(feature = "nucleo-f401re"))]
+    #[task(binds = EXTI0, shared = [button, button_timer])]
+    fn button_edge(ctx: button_edge::Context) {
+        wake_button_poll(ctx.shared.button, ctx.shared.button_timer);
+    }
+
+    /// The Nucleo's button is on PC13, in EXTI15_10.
+    #[cfg(feature = "nucleo-f401re")]
+    #[task(binds = EXTI15_10, shared = [button, button_timer])]
+    fn nucleo_button_edge(ctx: nucleo_button_edge::Context) {
+        wake_button_poll(ctx.shared.button, ctx.shared.button_timer);
+    }
+
+    fn wake_button_poll(
+        button: &mut board::Button,
+        timer: &mut hal::timer::CounterMs<hal::pac::TIM2>,
+    ) {
+        button.clear_interrupt_pending_bit();
+        timer.start(BUTTON_POLL_MS.millis()).unwrap();
+    }
+
+    /// Debounces the button and calibrates the offset against a target at
+    /// [`CALIBRATION_TARGET_MM`] once it has been held for [`HOLD_MS`], and
+    /// saves it.
+    #[task(
+        binds = TIM2,
+        shared = [button, button_timer, shell, delay, store, stored],
+        local = [clock, debounced]
+    )]
+    fn button_poll(ctx: button_poll::Context) {
+        let timer = ctx.shared.button_timer;
+        timer.clear_interrupt(hal::timer::Event::Update);
+
+        let debounced = ctx.local.debounced;
+        let mut long_press = false;
+        debounced.update(
+            ctx.shared.button.is_low(),
+            board::now_ms(ctx.local.clock),
+            |event| long_press |= event == ButtonEvent::LongPress,
+        );
+        if debounced.is_settled() {
+            let _ = timer.cancel();
+        }
+        if !long_press {
+            return;
+        }
+
+        let shell = ctx.shared.shell;
+        let delay = ctx.shared.delay;
+        let store = ctx.shared.store;
+        let stored = ctx.shared.stored;
+
+        (shell, delay, store, stored).lock(|shell, delay, store, stored| {
+            log!("calibrating with the target at {}mm", CALIBRATION_TARGET_MM);
+            match shell.execute(Command::CalibrateOffset(CALIBRATION_TARGET_MM), delay) {
+                Ok(response) => {
+                    log!("{}", response);
+                    save(shell, store, stored);
+                }
+                Err(e) => log!("error: {:?}", e),
+            }
+        });
+    }
+
+    /// Stores the shell's current settings and calibration.
+    fn save(shell: &ShellType, store: &mut Store<InternalFlash>, stored: &mut StoredConfig) {
+        let mut sensor = stored.sensor(DEFAULT_ADDRESS);
+        sensor.settings = *shell.settings();
+        sensor.calibration = *shell.calibration();
+        // Only this one address is ever stored, so it always fits
+        let _ = stored.set_sensor(sensor);
+        match store.save(stored) {
+            Ok(()) => log!("{}", shell::Response::Ok),
+            Err(e) => log!("error: {:?}", e),
+        }
+    }
+
+    #[idle()]
+    fn idle(_ctx: idle::Context) -> ! {
+        loop {}
+    }
+}
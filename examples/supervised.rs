@@ -0,0 +1,103 @@
+//! Continuous ranging that survives a wedged sensor: a health monitor watches
+//! the readings and power-cycles the sensor through XSHUT when it stops
+//! sampling, stops answering or reports system errors, backing off while it
+//! keeps failing. Every recovery is logged with the running counters. A
+//! sensor reset mid-transfer can also leave SDA held low, so the bus recovers
+//! itself after a few bus errors in a row. The readings and error codes are
+//! summarised every ten seconds.
+
+#![allow(clippy::empty_loop)]
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::ExceptionFrame;
+use cortex_m_rt::{entry, exception};
+use vl6180x_stm32f401_examples::board::{self, Board, I2c1Bus};
+use vl6180x_stm32f401_examples::bus_recovery::RecoveringBus;
+use vl6180x_stm32f401_examples::health::{
+    Continuous, DynamicSensor, HealthConfig, HealthMonitor, PowerCycle,
+};
+use vl6180x_stm32f401_examples::log;
+use vl6180x_stm32f401_examples::stats;
+use vl6180x_stm32f401_examples::timeout::{ReadError, TimedReader};
+
+const ADDRESS: u8 = 20;
+
+/// Bus errors in a row that trigger a bus recovery.
+const BUS_ERROR_LIMIT: u8 = 3;
+
+#[entry]
+fn main() -> ! {
+    if let Some(board) = Board::take() {
+        let Board {
+            clocks,
+            clock,
+            cycles,
+            i2c,
+            sensor: sensor_pins,
+            ..
+        } = board;
+
+        let i2c = RecoveringBus::new(I2c1Bus::new(i2c, clocks), BUS_ERROR_LIMIT);
+        let bus = shared_bus::BusManagerSimple::new(i2c);
+        let mut tof_config = vl6180x::Config::new();
+        tof_config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+        let mut sensor = DynamicSensor {
+            vl6180x: vl6180x::VL6180X::with_config(bus.acquire_i2c(), &tof_config)
+                .expect("vl")
+                .into_dynamic_mode(),
+            x_shutdown_pin: sensor_pins.xshut,
+            address: ADDRESS,
+            continuous: Continuous::Range,
+        };
+        // The same steps as a recovery bring it up the first time
+        sensor.power_cycle().expect("power cycle");
+        let mut reader = TimedReader::new(bus.acquire_i2c(), ADDRESS, cycles);
+        let mut monitor = HealthMonitor::new(HealthConfig::default(), board::now_ms(&clock));
+        let mut reporter = stats::range_reporter(board::now_ms(&clock));
+
+        loop {
+            let now_ms = board::now_ms(&clock);
+            match reader.read_range_mm() {
+                Ok(range) => {
+                    monitor.record_sample(now_ms);
+                    reporter.stats_mut().record(range);
+                    log!("Range Read: {}mm", range);
+                }
+                Err(ReadError::Range(code)) => {
+                    monitor.record_range_error(code, now_ms);
+                    reporter.stats_mut().record_error(code);
+                }
+                Err(ReadError::I2c(_)) => {
+                    monitor.record_i2c_error();
+                    reporter.stats_mut().record_failure();
+                }
+                // Covered by the monitor's stale time
+                Err(ReadError::Timeout) => reporter.stats_mut().record_failure(),
+            }
+            stats::log_report(&mut reporter, now_ms);
+
+            if let Some(recovery) = monitor.supervise(&mut sensor, board::now_ms(&clock)) {
+                log!(
+                    "{:?}: power cycle {} {}, next after {} ms",
+                    recovery.fault,
+                    recovery.attempt,
+                    if recovery.result.is_ok() {
+                        "ok"
+                    } else {
+                        "failed"
+                    },
+                    recovery.backoff_ms
+                );
+                log!("{}", monitor.counters());
+            }
+        }
+    }
+
+    loop {}
+}
+
+#[exception]
+unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
+    panic!("{:#?}", ef);
+}
@@ -0,0 +1,203 @@
+//! Median, EMA and Kalman stages run one after the other.
+
+use core::fmt;
+
+use crate::{Ema, Filter, Kalman, KalmanConfig, Median};
+
+/// Which stages a [`FilterChain`] runs and how. The default runs none and
+/// passes samples straight through.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChainConfig {
+    /// Median window, 0 or 1 leaves the median out.
+    pub median_len: usize,
+    /// See [`Ema`], `None` leaves the average out.
+    pub ema_alpha: Option<f32>,
+    /// See [`Kalman`], `None` leaves the Kalman filter out.
+    pub kalman: Option<KalmanConfig>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The median window is longer than the chain has room for.
+    MedianTooLong { max: usize },
+    /// The EMA factor isn't in `(0, 1]`.
+    EmaAlpha,
+    /// A Kalman noise variance isn't positive.
+    KalmanNoise,
+}
+
+/// Median, then EMA, then Kalman, each only if configured, with room for a
+/// median window of up to `N` samples.
+#[derive(Clone, Debug)]
+pub struct FilterChain<const N: usize> {
+    config: ChainConfig,
+    median: Option<Median<N>>,
+    ema: Option<Ema>,
+    kalman: Option<Kalman>,
+}
+
+impl<const N: usize> Default for FilterChain<N> {
+    fn default() -> Self {
+        Self {
+            config: ChainConfig::default(),
+            median: None,
+            ema: None,
+            kalman: None,
+        }
+    }
+}
+
+impl<const N: usize> FilterChain<N> {
+    pub fn new(config: ChainConfig) -> Result<Self, ConfigError> {
+        let mut chain = Self::default();
+        chain.configure(config)?;
+        Ok(chain)
+    }
+
+    pub fn config(&self) -> &ChainConfig {
+        &self.config
+    }
+
+    /// Switches to `config`. Stages whose settings didn't change keep their
+    /// history, the others start afresh. On an error nothing changes.
+    pub fn configure(&mut self, config: ChainConfig) -> Result<(), ConfigError> {
+        if config.median_len > N {
+            return Err(ConfigError::MedianTooLong { max: N });
+        }
+        if config
+            .ema_alpha
+            .is_some_and(|alpha| !(alpha > 0.0 && alpha <= 1.0))
+        {
+            return Err(ConfigError::EmaAlpha);
+        }
+        if config.kalman.is_some_and(|kalman| !kalman.is_valid()) {
+            return Err(ConfigError::KalmanNoise);
+        }
+
+        if config.median_len != self.config.median_len {
+            self.median = (config.median_len > 1).then(|| Median::new(config.median_len));
+        }
+        if config.ema_alpha != self.config.ema_alpha {
+            self.ema = config.ema_alpha.map(Ema::new);
+        }
+        if config.kalman != self.config.kalman {
+            self.kalman = config.kalman.map(Kalman::new);
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Filters a reading in whole millimetres, rounded to the nearest one.
+    pub fn update_mm(&mut self, mm: u16) -> u16 {
+        // `as` saturates, so anything below 0 comes out as 0
+        (self.update(mm as f32) + 0.5) as u16
+    }
+}
+
+impl<const N: usize> Filter for FilterChain<N> {
+    fn update(&mut self, sample: f32) -> f32 {
+        let mut value = sample;
+        if let Some(median) = &mut self.median {
+            value = median.update(value);
+        }
+        if let Some(ema) = &mut self.ema {
+            value = ema.update(value);
+        }
+        if let Some(kalman) = &mut self.kalman {
+            value = kalman.update(value);
+        }
+        value
+    }
+
+    fn reset(&mut self) {
+        if let Some(median) = &mut self.median {
+            median.reset();
+        }
+        if let Some(ema) = &mut self.ema {
+            ema.reset();
+        }
+        if let Some(kalman) = &mut self.kalman {
+            kalman.reset();
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MedianTooLong { max } => write!(f, "median window over {}", max),
+            ConfigError::EmaAlpha => f.write_str("ema alpha outside (0, 1]"),
+            ConfigError::KalmanNoise => f.write_str("kalman noise not positive"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KALMAN: KalmanConfig = KalmanConfig {
+        process_noise: 1.0,
+        measurement_noise: 9.0,
+    };
+
+    #[test]
+    fn default_passes_through() {
+        let mut chain = FilterChain::<5>::default();
+        assert_eq!(chain.update_mm(123), 123);
+        assert_eq!(chain.update_mm(7), 7);
+    }
+
+    #[test]
+    fn rejects_bad_configs_without_changing() {
+        let mut chain = FilterChain::<5>::default();
+        let too_long = ChainConfig {
+            median_len: 6,
+            ..ChainConfig::default()
+        };
+        assert_eq!(
+            chain.configure(too_long),
+            Err(ConfigError::MedianTooLong { max: 5 })
+        );
+        let alpha = ChainConfig {
+            ema_alpha: Some(1.5),
+            ..ChainConfig::default()
+        };
+        assert_eq!(chain.configure(alpha), Err(ConfigError::EmaAlpha));
+        let nan = ChainConfig {
+            ema_alpha: Some(f32::NAN),
+            ..ChainConfig::default()
+        };
+        assert_eq!(chain.configure(nan), Err(ConfigError::EmaAlpha));
+        let noise = ChainConfig {
+            kalman: Some(KalmanConfig {
+                process_noise: -1.0,
+                ..KALMAN
+            }),
+            ..ChainConfig::default()
+        };
+        assert_eq!(chain.configure(noise), Err(ConfigError::KalmanNoise));
+        assert_eq!(chain.config(), &ChainConfig::default());
+    }
+
+    #[test]
+    fn reconfiguring_keeps_unchanged_stages() {
+        let mut chain = FilterChain::<5>::new(ChainConfig {
+            median_len: 3,
+            ema_alpha: Some(0.5),
+            kalman: None,
+        })
+        .unwrap();
+        chain.update_mm(100);
+        chain.update_mm(100);
+
+        // Only the EMA starts over, the median still holds two 100s
+        chain
+            .configure(ChainConfig {
+                ema_alpha: Some(1.0),
+                ..*chain.config()
+            })
+            .unwrap();
+        assert_eq!(chain.update_mm(40), 100);
+    }
+}
@@ -0,0 +1,69 @@
+//! Exponential moving average.
+
+use crate::Filter;
+
+/// `out += alpha * (sample - out)`, starting from the first sample. An
+/// `alpha` of 1 passes samples through, smaller ones smooth harder and lag
+/// more: a step takes about `1 / alpha` samples to settle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ema {
+    alpha: f32,
+    value: Option<f32>,
+}
+
+impl Ema {
+    /// # Panics
+    ///
+    /// If `alpha` isn't in `(0, 1]`.
+    pub fn new(alpha: f32) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0, "ema alpha");
+        Self { alpha, value: None }
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl Filter for Ema {
+    fn update(&mut self, sample: f32) -> f32 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    fn reset(&mut self) {
+        self.value = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_from_the_first_sample() {
+        let mut ema = Ema::new(0.5);
+        assert_eq!(ema.update(80.0), 80.0);
+        assert_eq!(ema.update(100.0), 90.0);
+        assert_eq!(ema.update(100.0), 95.0);
+        ema.reset();
+        assert_eq!(ema.update(20.0), 20.0);
+    }
+
+    #[test]
+    fn alpha_one_passes_through() {
+        let mut ema = Ema::new(1.0);
+        assert_eq!(ema.update(3.0), 3.0);
+        assert_eq!(ema.update(7.0), 7.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ema alpha")]
+    fn rejects_zero_alpha() {
+        Ema::new(0.0);
+    }
+}
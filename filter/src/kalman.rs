@@ -0,0 +1,120 @@
+//! Scalar Kalman filter.
+
+use crate::Filter;
+
+/// Noise figures of a [`Kalman`] filter, both as variances in mm².
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KalmanConfig {
+    /// How much the target may move between samples. Larger follows
+    /// movement faster, smaller smooths harder.
+    pub process_noise: f32,
+    /// How much a single reading jitters, about 9 for a sigma of 3 mm.
+    pub measurement_noise: f32,
+}
+
+impl KalmanConfig {
+    /// True if both variances are positive.
+    pub fn is_valid(&self) -> bool {
+        self.process_noise > 0.0 && self.measurement_noise > 0.0
+    }
+}
+
+/// Kalman filter for a target that stays put apart from
+/// [`process_noise`](KalmanConfig::process_noise). The first sample is taken
+/// as is, with the measurement noise as its uncertainty; the gain then
+/// settles at a steady value set by the ratio of the two noise figures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Kalman {
+    config: KalmanConfig,
+    /// Estimate and its variance.
+    state: Option<(f32, f32)>,
+}
+
+impl Kalman {
+    /// # Panics
+    ///
+    /// If either variance in `config` isn't positive.
+    pub fn new(config: KalmanConfig) -> Self {
+        assert!(config.is_valid(), "kalman noise");
+        Self {
+            config,
+            state: None,
+        }
+    }
+
+    pub fn config(&self) -> KalmanConfig {
+        self.config
+    }
+
+    /// Variance of the current estimate, `None` before the first sample.
+    pub fn variance(&self) -> Option<f32> {
+        self.state.map(|(_, variance)| variance)
+    }
+}
+
+impl Filter for Kalman {
+    fn update(&mut self, sample: f32) -> f32 {
+        let (estimate, variance) = match self.state {
+            Some((estimate, variance)) => {
+                let predicted = variance + self.config.process_noise;
+                let gain = predicted / (predicted + self.config.measurement_noise);
+                (
+                    estimate + gain * (sample - estimate),
+                    (1.0 - gain) * predicted,
+                )
+            }
+            None => (sample, self.config.measurement_noise),
+        };
+        self.state = Some((estimate, variance));
+        estimate
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: KalmanConfig = KalmanConfig {
+        process_noise: 1.0,
+        measurement_noise: 9.0,
+    };
+
+    #[test]
+    fn variance_settles() {
+        let mut kalman = Kalman::new(CONFIG);
+        assert_eq!(kalman.variance(), None);
+        let mut previous = f32::MAX;
+        for _ in 0..50 {
+            kalman.update(100.0);
+            let variance = kalman.variance().unwrap();
+            assert!(variance <= previous);
+            previous = variance;
+        }
+        // Steady state of p = (p + q) r / (p + q + r) for q = 1, r = 9
+        let steady = (-1.0 + (1.0f32 + 36.0).sqrt()) / 2.0;
+        assert!((previous - steady).abs() < 1e-3, "{previous}");
+    }
+
+    #[test]
+    fn first_sample_is_taken_as_is() {
+        let mut kalman = Kalman::new(CONFIG);
+        assert_eq!(kalman.update(42.0), 42.0);
+        let second = kalman.update(52.0);
+        assert!(second > 42.0 && second < 52.0);
+        kalman.reset();
+        assert_eq!(kalman.update(7.0), 7.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "kalman noise")]
+    fn rejects_zero_noise() {
+        Kalman::new(KalmanConfig {
+            process_noise: 0.0,
+            measurement_noise: 9.0,
+        });
+    }
+}
@@ -0,0 +1,35 @@
+//! Streaming filters that smooth the jitter out of range readings.
+//!
+//! Each filter takes one sample at a time and implements [`Filter`]:
+//!
+//! - [`Median`] over the last N samples, which drops single spikes outright;
+//! - [`Ema`], an exponential moving average, cheap and tunable with one factor;
+//! - [`Kalman`], a scalar Kalman filter for a target that holds still apart
+//!   from some process noise, which settles on a steady gain.
+//!
+//! A [`FilterChain`] runs them in that order, each stage optional, from a
+//! [`ChainConfig`] that can be changed at runtime. Keep one chain per sensor.
+//!
+//! The crate is `no_std` and allocation free, and works in `f32` since the
+//! STM32F401 has an FPU.
+
+#![cfg_attr(not(test), no_std)]
+
+mod chain;
+mod ema;
+mod kalman;
+mod median;
+
+pub use chain::{ChainConfig, ConfigError, FilterChain};
+pub use ema::Ema;
+pub use kalman::{Kalman, KalmanConfig};
+pub use median::Median;
+
+/// A filter over a stream of samples.
+pub trait Filter {
+    /// Takes the next sample and returns the filtered value.
+    fn update(&mut self, sample: f32) -> f32;
+
+    /// Forgets every sample so far, the next one starts afresh.
+    fn reset(&mut self);
+}
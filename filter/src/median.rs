@@ -0,0 +1,88 @@
+//! Median of the last few samples.
+
+use heapless::Deque;
+
+use crate::Filter;
+
+/// Median of the last `len` samples, `len` being at most `N` and settable at
+/// runtime. Until `len` samples have come in it is the median of those
+/// there are. An even window gives the mean of the middle two.
+#[derive(Clone, Debug)]
+pub struct Median<const N: usize> {
+    window: Deque<f32, N>,
+    len: usize,
+}
+
+impl<const N: usize> Median<N> {
+    /// # Panics
+    ///
+    /// If `len` is 0 or more than `N`.
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0 && len <= N, "median length");
+        Self {
+            window: Deque::new(),
+            len,
+        }
+    }
+
+    pub fn window_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<const N: usize> Filter for Median<N> {
+    fn update(&mut self, sample: f32) -> f32 {
+        if self.window.len() == self.len {
+            self.window.pop_front();
+        }
+        // Can't fail, there is room after the pop above
+        let _ = self.window.push_back(sample);
+
+        let mut sorted = [0.0; N];
+        let sorted = &mut sorted[..self.window.len()];
+        for (slot, sample) in sorted.iter_mut().zip(self.window.iter()) {
+            *slot = *sample;
+        }
+        sorted.sort_unstable_by(f32::total_cmp);
+        let middle = sorted.len() / 2;
+        if sorted.len() % 2 == 1 {
+            sorted[middle]
+        } else {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_a_single_spike() {
+        let mut median = Median::<5>::new(3);
+        let out: Vec<_> = [100.0, 101.0, 180.0, 99.0, 100.0]
+            .iter()
+            .map(|&s| median.update(s))
+            .collect();
+        assert_eq!(out, [100.0, 100.5, 101.0, 101.0, 100.0]);
+    }
+
+    #[test]
+    fn reset_forgets_the_window() {
+        let mut median = Median::<3>::new(3);
+        median.update(10.0);
+        median.update(10.0);
+        median.reset();
+        assert_eq!(median.update(50.0), 50.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "median length")]
+    fn rejects_windows_longer_than_capacity() {
+        Median::<3>::new(4);
+    }
+}
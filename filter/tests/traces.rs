@@ -0,0 +1,138 @@
+//! Each filter against noisy range traces in `traces/`: how much jitter is
+//! left on a still target and how fast a step comes through.
+
+use vl6180x_filter::{ChainConfig, Ema, Filter, FilterChain, Kalman, KalmanConfig, Median};
+
+const STATIC: &str = include_str!("traces/static_100mm.txt");
+const STEP: &str = include_str!("traces/step_60_to_140mm.txt");
+
+/// Index of the first sample after the step in [`STEP`].
+const STEP_AT: usize = 100;
+
+/// A step has come through once the output is this close to the target.
+const SETTLED_MM: f32 = 4.0;
+
+const KALMAN: KalmanConfig = KalmanConfig {
+    process_noise: 0.5,
+    measurement_noise: 9.0,
+};
+
+fn trace(text: &str) -> Vec<f32> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(|word| word.parse().unwrap())
+        .collect()
+}
+
+fn run(filter: &mut (impl Filter + ?Sized), samples: &[f32]) -> Vec<f32> {
+    samples.iter().map(|&s| filter.update(s)).collect()
+}
+
+/// Root mean square distance from `target`.
+fn rms_error(samples: &[f32], target: f32) -> f32 {
+    let sum: f32 = samples.iter().map(|s| (s - target) * (s - target)).sum();
+    (sum / samples.len() as f32).sqrt()
+}
+
+fn max_error(samples: &[f32], target: f32) -> f32 {
+    samples
+        .iter()
+        .map(|s| (s - target).abs())
+        .fold(0.0, f32::max)
+}
+
+/// Samples after the step until the output first comes within
+/// [`SETTLED_MM`] of 140 mm.
+fn rise_time(output: &[f32]) -> usize {
+    output[STEP_AT..]
+        .iter()
+        .position(|s| (s - 140.0).abs() <= SETTLED_MM)
+        .unwrap_or(output.len() - STEP_AT)
+}
+
+fn filters() -> Vec<(&'static str, Box<dyn Filter>)> {
+    vec![
+        ("median", Box::new(Median::<5>::new(5))),
+        ("ema", Box::new(Ema::new(0.2))),
+        ("kalman", Box::new(Kalman::new(KALMAN))),
+        (
+            "chain",
+            Box::new(
+                FilterChain::<5>::new(ChainConfig {
+                    median_len: 5,
+                    ema_alpha: None,
+                    kalman: Some(KALMAN),
+                })
+                .unwrap(),
+            ),
+        ),
+    ]
+}
+
+#[test]
+fn traces_are_complete() {
+    assert_eq!(trace(STATIC).len(), 200);
+    assert_eq!(trace(STEP).len(), 200);
+}
+
+#[test]
+fn every_filter_cuts_the_jitter() {
+    let samples = trace(STATIC);
+    // Skip the first samples, while the filters warm up
+    let raw = rms_error(&samples[10..], 100.0);
+    for (name, mut filter) in filters() {
+        let output = run(&mut *filter, &samples);
+        let filtered = rms_error(&output[10..], 100.0);
+        assert!(filtered < raw / 2.0, "{name}: {filtered} vs {raw}");
+    }
+}
+
+#[test]
+fn median_drops_spikes() {
+    let samples = trace(STATIC);
+    let output = run(&mut Median::<5>::new(5), &samples);
+    assert!(max_error(&samples, 100.0) > 15.0);
+    assert!(max_error(&output[4..], 100.0) < 8.0);
+}
+
+#[test]
+fn every_filter_follows_a_step() {
+    let samples = trace(STEP);
+    for (name, mut filter) in filters() {
+        let output = run(&mut *filter, &samples);
+        let before = &output[20..STEP_AT];
+        assert!(max_error(before, 60.0) < 8.0, "{name} before the step");
+        let rise = rise_time(&output);
+        assert!(rise <= 20, "{name} took {rise} samples");
+        let after = &output[STEP_AT + rise..];
+        assert!(rms_error(after, 140.0) < 3.0, "{name} after the step");
+        // No overshoot past the new distance beyond the noise
+        let peak = output[STEP_AT..].iter().copied().fold(0.0, f32::max);
+        assert!(peak < 140.0 + 2.0 * SETTLED_MM, "{name} peaked at {peak}");
+    }
+}
+
+#[test]
+fn median_settles_in_half_a_window() {
+    let samples = trace(STEP);
+    let output = run(&mut Median::<5>::new(5), &samples);
+    assert!(output[STEP_AT + 1] < 100.0);
+    assert!(output[STEP_AT + 2] > 100.0);
+}
+
+#[test]
+fn smoother_settings_trade_jitter_for_lag() {
+    let still = trace(STATIC);
+    let step = trace(STEP);
+    let mut previous: Option<(f32, usize)> = None;
+    for alpha in [0.6, 0.3, 0.1] {
+        let jitter = rms_error(&run(&mut Ema::new(alpha), &still)[20..], 100.0);
+        let lag = rise_time(&run(&mut Ema::new(alpha), &step));
+        if let Some((previous_jitter, previous_lag)) = previous {
+            assert!(jitter < previous_jitter, "alpha {alpha}");
+            assert!(lag > previous_lag, "alpha {alpha}");
+        }
+        previous = Some((jitter, lag));
+    }
+}
@@ -0,0 +1,457 @@
+//! Named sensor set-ups, read from a small TOML file so one firmware can be
+//! built as any of them.
+//!
+//! ```toml
+//! default = "range-window"
+//!
+//! [range-window]
+//! start = "range"
+//! range-mode = "out-of-window"
+//! range-low = 20
+//! range-high = 50
+//! ```
+//!
+//! Each table is a profile. `start` names the measurement to run
+//! continuously and `clear` the sensor interrupts to clear after each reading
+//! (`range`, `ambient` or `all`, the default). Every other key is a shell
+//! setting taking what `set` takes, anything left out keeps its
+//! [`Settings::default`]. Only that much TOML is understood: tables,
+//! `key = value` pairs with string or integer values, and comments.
+//!
+//! The crate is `no_std`, so the build script can reject a bad profile and
+//! the firmware can load the same one at run time.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::fmt;
+
+pub use vl6180x_shell::{InterruptMode, Measurement, ParseError, Setting, Settings};
+
+use vl6180x_shell::Assignment;
+
+/// Which sensor interrupts to clear once a reading has been taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clear {
+    Range,
+    Ambient,
+    All,
+}
+
+impl Clear {
+    pub const ALL: [Clear; 3] = [Clear::Range, Clear::Ambient, Clear::All];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Clear::Range => "range",
+            Clear::Ambient => "ambient",
+            Clear::All => "all",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profile<'a> {
+    pub name: &'a str,
+    pub settings: Settings,
+    /// The measurement to run continuously, with its interrupt enabled.
+    pub start: Measurement,
+    pub clear: Clear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    /// No table with that name.
+    UnknownProfile,
+    /// Not a table header, `key = value` pair or comment.
+    Syntax {
+        line: usize,
+    },
+    UnknownKey {
+        line: usize,
+    },
+    DuplicateKey {
+        line: usize,
+    },
+    InvalidValue {
+        line: usize,
+        error: ParseError,
+    },
+    /// `clear` isn't `range`, `ambient` or `all`.
+    InvalidClear {
+        line: usize,
+    },
+    MissingStart,
+    /// The started measurement's interrupt is disabled, so it would never
+    /// report anything.
+    NoInterrupt,
+    /// The interrupt mode compares against a threshold the profile leaves at
+    /// its default.
+    MissingThreshold(Setting),
+    /// An out of window interrupt with the low threshold above the high one.
+    EmptyWindow,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::UnknownProfile => f.write_str("no such profile"),
+            ProfileError::Syntax { line } => {
+                write!(f, "line {}: expected [name] or key = value", line)
+            }
+            ProfileError::UnknownKey { line } => write!(f, "line {}: unknown key", line),
+            ProfileError::DuplicateKey { line } => write!(f, "line {}: key given twice", line),
+            ProfileError::InvalidValue { line, error } => write!(f, "line {}: {}", line, error),
+            ProfileError::InvalidClear { line } => {
+                write!(f, "line {}: expected range, ambient or all", line)
+            }
+            ProfileError::MissingStart => f.write_str("missing start = \"range\" or \"ambient\""),
+            ProfileError::NoInterrupt => {
+                f.write_str("the started measurement's interrupt is disabled")
+            }
+            ProfileError::MissingThreshold(setting) => {
+                write!(f, "the interrupt mode needs {}", setting.name())
+            }
+            ProfileError::EmptyWindow => f.write_str("the low threshold is above the high one"),
+        }
+    }
+}
+
+impl<'a> Profile<'a> {
+    /// Reads the profile `name` out of `source`, checking it makes sense.
+    /// A syntax error anywhere in `source` fails every profile.
+    pub fn load(source: &'a str, name: &str) -> Result<Self, ProfileError> {
+        let mut found = None;
+        let mut settings = Settings::default();
+        let mut start = None;
+        let mut clear = None;
+        let mut given = [false; Setting::ALL.len()];
+
+        let mut in_profile = false;
+        for (line, text) in lines(source) {
+            match parse_line(text).ok_or(ProfileError::Syntax { line })? {
+                Line::Table(table) => {
+                    in_profile = table == name;
+                    if in_profile {
+                        found = Some(table);
+                    }
+                }
+                Line::Pair(key, value) if in_profile => {
+                    if key == "start" {
+                        let measurement = find(&Measurement::ALL, value, Measurement::name).ok_or(
+                            ProfileError::InvalidValue {
+                                line,
+                                error: ParseError::UnknownMeasurement,
+                            },
+                        )?;
+                        set_once(&mut start, measurement, line)?;
+                    } else if key == "clear" {
+                        let mode = find(&Clear::ALL, value, Clear::name)
+                            .ok_or(ProfileError::InvalidClear { line })?;
+                        set_once(&mut clear, mode, line)?;
+                    } else {
+                        let index = Setting::ALL
+                            .iter()
+                            .position(|setting| setting.name() == key)
+                            .ok_or(ProfileError::UnknownKey { line })?;
+                        if given[index] {
+                            return Err(ProfileError::DuplicateKey { line });
+                        }
+                        given[index] = true;
+                        let assignment = Assignment::parse(Setting::ALL[index], value)
+                            .map_err(|error| ProfileError::InvalidValue { line, error })?;
+                        settings.apply(assignment);
+                    }
+                }
+                // Top level keys and other profiles
+                Line::Pair(..) => (),
+            }
+        }
+
+        let profile = Profile {
+            name: found.ok_or(ProfileError::UnknownProfile)?,
+            settings,
+            start: start.ok_or(ProfileError::MissingStart)?,
+            clear: clear.unwrap_or(Clear::All),
+        };
+        profile.check(|setting| {
+            Setting::ALL
+                .iter()
+                .zip(given)
+                .any(|(&s, given)| s == setting && given)
+        })?;
+        Ok(profile)
+    }
+
+    fn check(&self, is_given: impl Fn(Setting) -> bool) -> Result<(), ProfileError> {
+        let s = &self.settings;
+        let (mode, low, high, low_setting, high_setting) = match self.start {
+            Measurement::Range => (
+                s.range_mode,
+                s.range_low as u16,
+                s.range_high as u16,
+                Setting::RangeLow,
+                Setting::RangeHigh,
+            ),
+            Measurement::Ambient => (
+                s.ambient_mode,
+                s.ambient_low,
+                s.ambient_high,
+                Setting::AmbientLow,
+                Setting::AmbientHigh,
+            ),
+        };
+        let (uses_low, uses_high) = match mode {
+            InterruptMode::Disabled => return Err(ProfileError::NoInterrupt),
+            InterruptMode::LevelLow => (true, false),
+            InterruptMode::LevelHigh => (false, true),
+            InterruptMode::OutOfWindow => (true, true),
+            InterruptMode::NewSampleReady => (false, false),
+        };
+        if uses_low && !is_given(low_setting) {
+            return Err(ProfileError::MissingThreshold(low_setting));
+        }
+        if uses_high && !is_given(high_setting) {
+            return Err(ProfileError::MissingThreshold(high_setting));
+        }
+        if uses_low && uses_high && low > high {
+            return Err(ProfileError::EmptyWindow);
+        }
+        Ok(())
+    }
+}
+
+/// Names of the profiles in `source`, in file order.
+pub fn names(source: &str) -> impl Iterator<Item = &str> {
+    lines(source).filter_map(|(_, text)| match parse_line(text) {
+        Some(Line::Table(name)) => Some(name),
+        _ => None,
+    })
+}
+
+/// The profile the top level `default` key names, if there is one.
+pub fn default_name(source: &str) -> Option<&str> {
+    for (_, text) in lines(source) {
+        match parse_line(text) {
+            Some(Line::Table(_)) => return None,
+            Some(Line::Pair("default", name)) => return Some(name),
+            _ => (),
+        }
+    }
+    None
+}
+
+enum Line<'a> {
+    Table(&'a str),
+    Pair(&'a str, &'a str),
+}
+
+/// Non-empty lines with their numbers, counting from 1, comments removed.
+fn lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(n, text)| (n + 1, text.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, text)| !text.is_empty())
+}
+
+fn parse_line(text: &str) -> Option<Line<'_>> {
+    if let Some(table) = text.strip_prefix('[') {
+        let name = table.strip_suffix(']')?.trim();
+        return is_key(name).then_some(Line::Table(name));
+    }
+    let (key, value) = text.split_once('=')?;
+    let (key, value) = (key.trim(), value.trim());
+    let value = match value.strip_prefix('"') {
+        Some(quoted) => quoted.strip_suffix('"').filter(|v| !v.contains('"'))?,
+        None if !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphanumeric()) => value,
+        None => return None,
+    };
+    is_key(key).then_some(Line::Pair(key, value))
+}
+
+/// A bare TOML key.
+fn is_key(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn find<T: Copy>(all: &[T], word: &str, name: impl Fn(T) -> &'static str) -> Option<T> {
+    all.iter().copied().find(|&item| name(item) == word)
+}
+
+fn set_once<T>(slot: &mut Option<T>, value: T, line: usize) -> Result<(), ProfileError> {
+    if slot.is_some() {
+        return Err(ProfileError::DuplicateKey { line });
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+# Thresholds in mm, times the range scaler
+default = "window"
+
+[window]
+start = "range"
+range-mode = "out-of-window"
+range-low = 20   # near edge
+range-high = 50
+
+[ambient-low]
+start = "ambient"
+clear = "ambient"
+ambient-mode = "level-low"
+ambient-low = 40
+ambient-gain = 7
+ambient-scaler = 15
+"#;
+
+    #[test]
+    fn loads_profiles_by_name() {
+        let window = Profile::load(SOURCE, "window").unwrap();
+        assert_eq!(window.name, "window");
+        assert_eq!(window.start, Measurement::Range);
+        assert_eq!(window.clear, Clear::All);
+        assert_eq!(window.settings.range_mode, InterruptMode::OutOfWindow);
+        assert_eq!(
+            (window.settings.range_low, window.settings.range_high),
+            (20, 50)
+        );
+        // Everything else keeps its default
+        assert_eq!(window.settings.ambient_mode, InterruptMode::Disabled);
+        assert_eq!(window.settings.range_scaler, 1);
+
+        let ambient = Profile::load(SOURCE, "ambient-low").unwrap();
+        assert_eq!(ambient.start, Measurement::Ambient);
+        assert_eq!(ambient.clear, Clear::Ambient);
+        assert_eq!(
+            (
+                ambient.settings.ambient_gain,
+                ambient.settings.ambient_scaler
+            ),
+            (7, 15)
+        );
+
+        assert_eq!(
+            Profile::load(SOURCE, "nope"),
+            Err(ProfileError::UnknownProfile)
+        );
+    }
+
+    #[test]
+    fn lists_names_and_the_default() {
+        assert_eq!(names(SOURCE).collect::<Vec<_>>(), ["window", "ambient-low"]);
+        assert_eq!(default_name(SOURCE), Some("window"));
+        assert_eq!(default_name("[a]\ndefault = \"a\""), None);
+    }
+
+    #[test]
+    fn the_repository_profiles_all_load() {
+        let source = include_str!("../../profiles.toml");
+        let default = default_name(source).unwrap();
+        assert!(names(source).any(|name| name == default));
+        for name in names(source) {
+            if let Err(e) = Profile::load(source, name) {
+                panic!("{}: {}", name, e);
+            }
+        }
+    }
+
+    fn load(body: &str) -> Result<Profile<'_>, ProfileError> {
+        Profile::load(body, "p")
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let cases = [
+            "[p]\nstart range",
+            "[p\nstart = \"range\"",
+            "[p]\nstart = \"range",
+            "[p]\nstart = range mode",
+            "[p]\n = 5",
+            "[p q]",
+        ];
+        for source in cases {
+            assert!(
+                matches!(load(source), Err(ProfileError::Syntax { .. })),
+                "{:?}",
+                source
+            );
+        }
+        // Wherever in the file
+        assert_eq!(
+            load("[p]\nstart = \"range\"\nrange-mode = \"new-sample\"\n[q]\noops"),
+            Err(ProfileError::Syntax { line: 5 })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_keys_and_values() {
+        let profile = |lines: &str| {
+            let source = format!(
+                "[p]\nstart = \"range\"\nrange-mode = \"new-sample\"\n{}",
+                lines
+            );
+            load(&source).map(|_| ()).map_err(|e| e.to_string())
+        };
+        assert_eq!(profile(""), Ok(()));
+        assert_eq!(profile("range-hihg = 5"), Err("line 4: unknown key".into()));
+        assert_eq!(
+            profile("start = \"range\""),
+            Err("line 4: key given twice".into())
+        );
+        assert_eq!(
+            profile("range-mode = \"level-low\""),
+            Err("line 4: key given twice".into())
+        );
+        assert_eq!(
+            profile("range-scaler = 4"),
+            Err("line 4: must be 1 to 3".into())
+        );
+        assert_eq!(profile("range-high = 0x20"), Ok(()));
+        assert_eq!(
+            profile("clear = \"both\""),
+            Err("line 4: expected range, ambient or all".into())
+        );
+        assert_eq!(
+            load("[p]\nstart = \"light\""),
+            Err(ProfileError::InvalidValue {
+                line: 2,
+                error: ParseError::UnknownMeasurement
+            })
+        );
+    }
+
+    #[test]
+    fn checks_the_interrupt_set_up() {
+        assert_eq!(
+            load("[p]\nrange-mode = \"new-sample\""),
+            Err(ProfileError::MissingStart)
+        );
+        // Ambient is started but only range interrupts are on
+        assert_eq!(
+            load("[p]\nstart = \"ambient\"\nrange-mode = \"new-sample\""),
+            Err(ProfileError::NoInterrupt)
+        );
+        assert_eq!(
+            load("[p]\nstart = \"range\"\nrange-mode = \"level-high\"\nrange-low = 10"),
+            Err(ProfileError::MissingThreshold(Setting::RangeHigh))
+        );
+        assert_eq!(
+            load("[p]\nstart = \"ambient\"\nambient-mode = \"out-of-window\"\nambient-high = 10"),
+            Err(ProfileError::MissingThreshold(Setting::AmbientLow))
+        );
+        assert_eq!(
+            load("[p]\nstart = \"range\"\nrange-mode = \"out-of-window\"\nrange-low = 50\nrange-high = 20"),
+            Err(ProfileError::EmptyWindow)
+        );
+        // A threshold explicitly at its default still counts
+        assert!(load("[p]\nstart = \"range\"\nrange-mode = \"level-low\"\nrange-low = 0").is_ok());
+    }
+}
@@ -0,0 +1,118 @@
+//! Consistent Overhead Byte Stuffing.
+//!
+//! Rewrites a buffer so it contains no `0x00`, at a cost of one byte per 254,
+//! which leaves `0x00` free to mark the end of a frame.
+
+/// Worst case length of `len` bytes once encoded.
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// Encodes `input` into `output`, returning the encoded length, or `None` if
+/// `output` is shorter than [`max_encoded_len`].
+pub fn encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if output.len() < max_encoded_len(input.len()) {
+        return None;
+    }
+    let mut code_index = 0;
+    let mut code = 1u8;
+    let mut out = 1;
+    for &byte in input {
+        if byte == 0 {
+            output[code_index] = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+        } else {
+            output[out] = byte;
+            out += 1;
+            code += 1;
+            if code == 0xFF {
+                output[code_index] = code;
+                code_index = out;
+                out += 1;
+                code = 1;
+            }
+        }
+    }
+    output[code_index] = code;
+    Some(out)
+}
+
+/// Decodes `buf` in place, returning the decoded length, or `None` if it is
+/// not valid COBS (contains a zero or a block runs past the end).
+pub fn decode_in_place(buf: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+    while read < buf.len() {
+        let code = buf[read];
+        if code == 0 {
+            return None;
+        }
+        read += 1;
+        for _ in 1..code {
+            let byte = *buf.get(read)?;
+            if byte == 0 {
+                return None;
+            }
+            // `write` never overtakes `read`, so this only moves data back.
+            buf[write] = byte;
+            write += 1;
+            read += 1;
+        }
+        if code != 0xFF && read < buf.len() {
+            buf[write] = 0;
+            write += 1;
+        }
+    }
+    Some(write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(input: &[u8]) {
+        let mut encoded = [0; 600];
+        let len = encode(input, &mut encoded).unwrap();
+        assert!(len <= max_encoded_len(input.len()));
+        assert!(!encoded[..len].contains(&0));
+        let decoded = decode_in_place(&mut encoded[..len]).unwrap();
+        assert_eq!(&encoded[..decoded], input);
+    }
+
+    #[test]
+    fn known_vectors() {
+        let mut out = [0; 8];
+        let len = encode(&[0x11, 0x22, 0x00, 0x33], &mut out).unwrap();
+        assert_eq!(&out[..len], &[0x03, 0x11, 0x22, 0x02, 0x33]);
+        let len = encode(&[0x00], &mut out).unwrap();
+        assert_eq!(&out[..len], &[0x01, 0x01]);
+        let len = encode(&[], &mut out).unwrap();
+        assert_eq!(&out[..len], &[0x01]);
+    }
+
+    #[test]
+    fn roundtrips_across_block_boundaries() {
+        roundtrip(&[]);
+        roundtrip(&[0, 0, 0]);
+        let mut long = [0xA5; 520];
+        roundtrip(&long[..253]);
+        roundtrip(&long[..254]);
+        roundtrip(&long[..255]);
+        long[254] = 0;
+        roundtrip(&long);
+    }
+
+    #[test]
+    fn rejects_short_output() {
+        let mut out = [0; 3];
+        assert_eq!(encode(&[1, 2, 3], &mut out), None);
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(decode_in_place(&mut [0x03, 0x11, 0x00]), None);
+        assert_eq!(decode_in_place(&mut [0x05, 0x11]), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF), appended to
+//! every payload before framing.
+
+/// Bytes the CRC adds to a payload.
+pub const CRC_LEN: usize = 2;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn empty_input_is_initial_value() {
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+}
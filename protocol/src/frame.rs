@@ -0,0 +1,265 @@
+//! Framing of payloads: CRC, COBS and the `0x00` delimiter.
+
+use crate::cobs;
+use crate::crc::{crc16, CRC_LEN};
+use crate::{DecodeError, EncodeError, Envelope, MAX_PAYLOAD_LEN};
+
+/// Marks the end of every frame, and never appears inside one.
+pub const DELIMITER: u8 = 0x00;
+
+/// Bytes a payload of `payload_len` bytes takes on the wire, delimiter included.
+pub const fn frame_len(payload_len: usize) -> usize {
+    cobs::max_encoded_len(payload_len + CRC_LEN) + 1
+}
+
+/// Longest frame any message is sent as.
+pub const MAX_FRAME_LEN: usize = frame_len(MAX_PAYLOAD_LEN);
+
+/// Encodes `envelope` as a complete frame into `out`, returning its length.
+pub fn encode(envelope: &Envelope, out: &mut [u8]) -> Result<usize, EncodeError> {
+    let mut payload = [0; MAX_PAYLOAD_LEN + CRC_LEN];
+    let len = envelope.encode(&mut payload[..MAX_PAYLOAD_LEN])?;
+    let crc = crc16(&payload[..len]);
+    payload[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+    let encoded =
+        cobs::encode(&payload[..len + CRC_LEN], out).ok_or(EncodeError::BufferTooSmall)?;
+    *out.get_mut(encoded).ok_or(EncodeError::BufferTooSmall)? = DELIMITER;
+    Ok(encoded + 1)
+}
+
+/// Decodes one frame, without its delimiter, in place.
+pub fn decode(frame: &mut [u8]) -> Result<Envelope, DecodeError> {
+    let len = cobs::decode_in_place(frame).ok_or(DecodeError::Cobs)?;
+    if len < CRC_LEN {
+        return Err(DecodeError::Truncated);
+    }
+    let (payload, crc) = frame[..len].split_at(len - CRC_LEN);
+    let expected = u16::from_le_bytes([crc[0], crc[1]]);
+    let actual = crc16(payload);
+    if expected != actual {
+        return Err(DecodeError::Crc { expected, actual });
+    }
+    Envelope::decode(payload)
+}
+
+/// Reassembles frames from a byte stream.
+///
+/// Bytes are buffered until a delimiter, at which point the frame is decoded
+/// and the buffer starts over, so whatever a corrupted or partial frame did
+/// the decoder is back in sync at the next delimiter.
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Feeds one received byte. Returns the decoded frame, or why it was
+    /// dropped, once a delimiter ends it. Empty frames are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Envelope, DecodeError>> {
+        if byte != DELIMITER {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflowed = true,
+            }
+            return None;
+        }
+
+        let result = if self.overflowed {
+            Some(Err(DecodeError::Overflow))
+        } else if self.len == 0 {
+            None
+        } else {
+            Some(decode(&mut self.buf[..self.len]))
+        };
+        self.len = 0;
+        self.overflowed = false;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorKind, Message, Reading};
+
+    fn envelope(sequence: u16) -> Envelope {
+        Envelope {
+            sequence,
+            message: Message::Measurement {
+                sensor: 0,
+                reading: Reading::RangeMm(sequence),
+            },
+        }
+    }
+
+    fn frame(envelope: &Envelope) -> ([u8; MAX_FRAME_LEN], usize) {
+        let mut out = [0; MAX_FRAME_LEN];
+        let len = encode(envelope, &mut out).unwrap();
+        (out, len)
+    }
+
+    fn feed(
+        decoder: &mut Decoder,
+        bytes: &[u8],
+        results: &mut [Option<Result<Envelope, DecodeError>>; 8],
+    ) -> usize {
+        let mut count = 0;
+        for &byte in bytes {
+            if let Some(result) = decoder.push(byte) {
+                results[count] = Some(result);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn roundtrip_through_decoder() {
+        let sent = Envelope {
+            sequence: 7,
+            message: Message::Error {
+                sensor: 1,
+                kind: ErrorKind::Timeout,
+                detail: 0,
+            },
+        };
+        let (bytes, len) = frame(&sent);
+        assert!(!bytes[..len - 1].contains(&DELIMITER));
+        assert_eq!(bytes[len - 1], DELIMITER);
+
+        let mut decoder = Decoder::new();
+        let mut results = Default::default();
+        assert_eq!(feed(&mut decoder, &bytes[..len], &mut results), 1);
+        assert_eq!(results[0], Some(Ok(sent)));
+    }
+
+    #[test]
+    fn every_message_fits_max_frame_len() {
+        let envelope = Envelope {
+            sequence: 0,
+            message: Message::Measurement {
+                sensor: 0,
+                reading: Reading::AmbientLux(1.0),
+            },
+        };
+        let mut out = [0; MAX_FRAME_LEN];
+        assert!(encode(&envelope, &mut out).is_ok());
+        let mut short = [0; MAX_FRAME_LEN - 1];
+        assert_eq!(
+            encode(&envelope, &mut short),
+            Err(EncodeError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn corrupted_frame_fails_crc() {
+        // Frame by hand so the bit flip lands in the payload rather than in
+        // a COBS code byte.
+        let mut payload = [0; MAX_PAYLOAD_LEN + CRC_LEN];
+        let len = envelope(3).encode(&mut payload).unwrap();
+        let crc = crc16(&payload[..len]);
+        payload[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        payload[len - 1] ^= 0x10;
+
+        let mut bytes = [0; MAX_FRAME_LEN];
+        let encoded = cobs::encode(&payload[..len + CRC_LEN], &mut bytes).unwrap();
+        assert_eq!(
+            decode(&mut bytes[..encoded]),
+            Err(DecodeError::Crc {
+                expected: crc,
+                actual: crc16(&payload[..len]),
+            })
+        );
+    }
+
+    #[test]
+    fn resyncs_after_joining_mid_frame() {
+        let (first, first_len) = frame(&envelope(1));
+        let (second, second_len) = frame(&envelope(2));
+        let mut stream = [0; 2 * MAX_FRAME_LEN];
+        // Start listening halfway through the first frame.
+        let tail = &first[first_len / 2..first_len];
+        stream[..tail.len()].copy_from_slice(tail);
+        stream[tail.len()..tail.len() + second_len].copy_from_slice(&second[..second_len]);
+
+        let mut decoder = Decoder::new();
+        let mut results = Default::default();
+        let count = feed(
+            &mut decoder,
+            &stream[..tail.len() + second_len],
+            &mut results,
+        );
+        assert_eq!(count, 2);
+        assert!(matches!(results[0], Some(Err(_))));
+        assert_eq!(results[1], Some(Ok(envelope(2))));
+    }
+
+    #[test]
+    fn resyncs_after_dropped_bytes() {
+        let (first, first_len) = frame(&envelope(1));
+        let (second, second_len) = frame(&envelope(2));
+        let mut stream = [0; 2 * MAX_FRAME_LEN];
+        // Lose two bytes from the middle of the first frame.
+        let mut len = 0;
+        for (i, &byte) in first[..first_len].iter().enumerate() {
+            if i != 2 && i != 3 {
+                stream[len] = byte;
+                len += 1;
+            }
+        }
+        stream[len..len + second_len].copy_from_slice(&second[..second_len]);
+        len += second_len;
+
+        let mut decoder = Decoder::new();
+        let mut results = Default::default();
+        assert_eq!(feed(&mut decoder, &stream[..len], &mut results), 2);
+        assert!(matches!(results[0], Some(Err(_))));
+        assert_eq!(results[1], Some(Ok(envelope(2))));
+    }
+
+    #[test]
+    fn overlong_garbage_reports_overflow_then_resyncs() {
+        let mut decoder = Decoder::new();
+        for _ in 0..3 * MAX_FRAME_LEN {
+            assert_eq!(decoder.push(0x55), None);
+        }
+        assert_eq!(decoder.push(DELIMITER), Some(Err(DecodeError::Overflow)));
+
+        let (bytes, len) = frame(&envelope(9));
+        let mut results = Default::default();
+        assert_eq!(feed(&mut decoder, &bytes[..len], &mut results), 1);
+        assert_eq!(results[0], Some(Ok(envelope(9))));
+    }
+
+    #[test]
+    fn repeated_delimiters_are_ignored() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.push(DELIMITER), None);
+        assert_eq!(decoder.push(DELIMITER), None);
+    }
+
+    #[test]
+    fn short_frames_are_rejected() {
+        assert_eq!(decode(&mut [0x02, 0x01]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&mut [0x03, 0x01, 0x00]), Err(DecodeError::Cobs));
+    }
+}
@@ -0,0 +1,22 @@
+//! Binary measurement stream sent by the firmware to host tooling.
+//!
+//! A message ([`Envelope`]) is encoded into a compact little endian payload,
+//! followed by a CRC-16 of that payload, COBS encoded so it contains no zero
+//! bytes, and terminated by a single `0x00`. A receiver that starts listening
+//! mid-stream or loses bytes only has to wait for the next `0x00` to be back
+//! in sync, see [`frame::Decoder`].
+//!
+//! The crate is `no_std` and allocation free so the firmware and the host
+//! decoder share the exact same code.
+
+#![no_std]
+
+pub mod cobs;
+pub mod crc;
+pub mod frame;
+mod message;
+
+pub use message::{
+    DecodeError, EncodeError, Envelope, ErrorKind, Message, Reading, HEADER_LEN, MAX_PAYLOAD_LEN,
+    VERSION,
+};
@@ -0,0 +1,347 @@
+//! Messages carried by the stream and their payload encoding.
+//!
+//! Every payload starts with a [`HEADER_LEN`] byte header: the format
+//! [`VERSION`], the message kind and a sequence number the sender increments
+//! per message, so a receiver can count what it missed. Multi-byte fields are
+//! little endian.
+
+/// Payload format version. Bump on any incompatible change to the encoding.
+pub const VERSION: u8 = 1;
+
+pub const HEADER_LEN: usize = 4;
+
+/// Longest payload any message encodes to, without CRC or framing.
+pub const MAX_PAYLOAD_LEN: usize = HEADER_LEN + 6;
+
+const KIND_MEASUREMENT: u8 = 1;
+const KIND_INTERRUPT: u8 = 2;
+const KIND_ERROR: u8 = 3;
+const KIND_STATUS: u8 = 4;
+
+const READING_RANGE_MM: u8 = 0;
+const READING_AMBIENT_RAW: u8 = 1;
+const READING_AMBIENT_LUX: u8 = 2;
+
+/// A message with its sequence number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub sequence: u16,
+    pub message: Message,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    /// A reading from sensor `sensor`.
+    Measurement { sensor: u8, reading: Reading },
+    /// Sensor `sensor` raised its interrupt on EXTI `line`.
+    Interrupt { sensor: u8, line: u8 },
+    /// Sensor `sensor` failed, `detail` depends on `kind`.
+    Error {
+        sensor: u8,
+        kind: ErrorKind,
+        detail: u8,
+    },
+    /// Periodic summary: how many sensors are running and errors seen since boot.
+    Status { sensors: u8, errors: u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reading {
+    RangeMm(u16),
+    AmbientRaw(u16),
+    AmbientLux(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The I2C transfer failed.
+    Bus = 0,
+    /// The sensor reported a range error, `detail` is the error code.
+    Range = 1,
+    /// The sensor reported an ambient error, `detail` is the error code.
+    Ambient = 2,
+    /// No reading arrived in time.
+    Timeout = 3,
+}
+
+impl ErrorKind {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Bus),
+            1 => Some(Self::Range),
+            2 => Some(Self::Ambient),
+            3 => Some(Self::Timeout),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The output buffer cannot hold the encoded message.
+    BufferTooSmall,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload ended before the message did.
+    Truncated,
+    /// The payload continues after the message.
+    TrailingBytes,
+    /// Sent by a firmware using a different format version.
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    /// A field holds a value the format doesn't define.
+    InvalidValue,
+    /// The frame isn't valid COBS.
+    Cobs,
+    /// The frame's CRC doesn't match its payload.
+    Crc {
+        expected: u16,
+        actual: u16,
+    },
+    /// The frame was longer than any valid frame and was dropped.
+    Overflow,
+}
+
+impl Envelope {
+    /// Writes the payload into `buf`, returning its length.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let mut w = Writer { buf, len: 0 };
+        w.u8(VERSION)?;
+        w.u8(self.message.kind())?;
+        w.u16(self.sequence)?;
+        match self.message {
+            Message::Measurement { sensor, reading } => {
+                w.u8(sensor)?;
+                match reading {
+                    Reading::RangeMm(mm) => {
+                        w.u8(READING_RANGE_MM)?;
+                        w.u16(mm)?;
+                    }
+                    Reading::AmbientRaw(raw) => {
+                        w.u8(READING_AMBIENT_RAW)?;
+                        w.u16(raw)?;
+                    }
+                    Reading::AmbientLux(lux) => {
+                        w.u8(READING_AMBIENT_LUX)?;
+                        w.bytes(&lux.to_le_bytes())?;
+                    }
+                }
+            }
+            Message::Interrupt { sensor, line } => {
+                w.u8(sensor)?;
+                w.u8(line)?;
+            }
+            Message::Error {
+                sensor,
+                kind,
+                detail,
+            } => {
+                w.u8(sensor)?;
+                w.u8(kind as u8)?;
+                w.u8(detail)?;
+            }
+            Message::Status { sensors, errors } => {
+                w.u8(sensors)?;
+                w.u16(errors)?;
+            }
+        }
+        Ok(w.len)
+    }
+
+    /// Parses a whole payload.
+    pub fn decode(payload: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader { buf: payload };
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let kind = r.u8()?;
+        let sequence = r.u16()?;
+        let message = match kind {
+            KIND_MEASUREMENT => {
+                let sensor = r.u8()?;
+                let reading = match r.u8()? {
+                    READING_RANGE_MM => Reading::RangeMm(r.u16()?),
+                    READING_AMBIENT_RAW => Reading::AmbientRaw(r.u16()?),
+                    READING_AMBIENT_LUX => Reading::AmbientLux(f32::from_le_bytes(r.array()?)),
+                    _ => return Err(DecodeError::InvalidValue),
+                };
+                Message::Measurement { sensor, reading }
+            }
+            KIND_INTERRUPT => Message::Interrupt {
+                sensor: r.u8()?,
+                line: r.u8()?,
+            },
+            KIND_ERROR => Message::Error {
+                sensor: r.u8()?,
+                kind: ErrorKind::from_u8(r.u8()?).ok_or(DecodeError::InvalidValue)?,
+                detail: r.u8()?,
+            },
+            KIND_STATUS => Message::Status {
+                sensors: r.u8()?,
+                errors: r.u16()?,
+            },
+            other => return Err(DecodeError::UnknownKind(other)),
+        };
+        if !r.buf.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(Self { sequence, message })
+    }
+}
+
+impl Message {
+    fn kind(&self) -> u8 {
+        match self {
+            Message::Measurement { .. } => KIND_MEASUREMENT,
+            Message::Interrupt { .. } => KIND_INTERRUPT,
+            Message::Error { .. } => KIND_ERROR,
+            Message::Status { .. } => KIND_STATUS,
+        }
+    }
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        let end = self.len + bytes.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(EncodeError::BufferTooSmall)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<(), EncodeError> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), EncodeError> {
+        self.bytes(&value.to_le_bytes())
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        if self.buf.len() < N {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(N);
+        self.buf = tail;
+        let mut array = [0; N];
+        array.copy_from_slice(head);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        self.array::<1>().map(|[b]| b)
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        self.array().map(u16::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: [Message; 6] = [
+        Message::Measurement {
+            sensor: 0,
+            reading: Reading::RangeMm(42),
+        },
+        Message::Measurement {
+            sensor: 1,
+            reading: Reading::AmbientRaw(0xBEEF),
+        },
+        Message::Measurement {
+            sensor: 2,
+            reading: Reading::AmbientLux(123.25),
+        },
+        Message::Interrupt { sensor: 0, line: 6 },
+        Message::Error {
+            sensor: 1,
+            kind: ErrorKind::Range,
+            detail: 15,
+        },
+        Message::Status {
+            sensors: 2,
+            errors: 513,
+        },
+    ];
+
+    #[test]
+    fn roundtrips_every_message() {
+        for (sequence, message) in MESSAGES.into_iter().enumerate() {
+            let envelope = Envelope {
+                sequence: sequence as u16 + 0xFF00,
+                message,
+            };
+            let mut buf = [0; MAX_PAYLOAD_LEN];
+            let len = envelope.encode(&mut buf).unwrap();
+            assert_eq!(Envelope::decode(&buf[..len]), Ok(envelope));
+        }
+    }
+
+    #[test]
+    fn range_measurement_layout() {
+        let envelope = Envelope {
+            sequence: 0x0102,
+            message: MESSAGES[0],
+        };
+        let mut buf = [0; MAX_PAYLOAD_LEN];
+        let len = envelope.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[VERSION, 1, 0x02, 0x01, 0, 0, 42, 0]);
+    }
+
+    #[test]
+    fn encode_rejects_short_buffer() {
+        let envelope = Envelope {
+            sequence: 0,
+            message: MESSAGES[2],
+        };
+        let mut buf = [0; MAX_PAYLOAD_LEN - 1];
+        assert_eq!(envelope.encode(&mut buf), Err(EncodeError::BufferTooSmall));
+    }
+
+    #[test]
+    fn decode_rejects_malformed_payloads() {
+        assert_eq!(Envelope::decode(&[]), Err(DecodeError::Truncated));
+        assert_eq!(
+            Envelope::decode(&[VERSION + 1, 1, 0, 0]),
+            Err(DecodeError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(
+            Envelope::decode(&[VERSION, 9, 0, 0]),
+            Err(DecodeError::UnknownKind(9))
+        );
+        assert_eq!(
+            Envelope::decode(&[VERSION, 1, 0, 0, 0, 7, 0, 0]),
+            Err(DecodeError::InvalidValue)
+        );
+        assert_eq!(
+            Envelope::decode(&[VERSION, 3, 0, 0, 0, 9, 0]),
+            Err(DecodeError::InvalidValue)
+        );
+        assert_eq!(
+            Envelope::decode(&[VERSION, 2, 0, 0, 0]),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Envelope::decode(&[VERSION, 2, 0, 0, 0, 6, 0]),
+            Err(DecodeError::TrailingBytes)
+        );
+    }
+}
@@ -0,0 +1,638 @@
+//! Command grammar, parsing and the responses the shell prints.
+//!
+//! Words are separated by any run of spaces or tabs and matched without
+//! regard to case. Numbers are decimal or `0x` prefixed hexadecimal.
+
+use core::fmt;
+
+use crate::Settings;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Show,
+    Get(Setting),
+    Set(Assignment),
+    Start(Measurement),
+    Stop(Measurement),
+    Read(Measurement),
+    /// Keep the current settings over a reset, if the firmware has storage.
+    Save,
+    /// Work out the part-to-part range offset with a target this many
+    /// millimetres away, see [`OFFSET_TARGET_MM`].
+    CalibrateOffset(u16),
+    /// Work out the cover glass crosstalk with a dark target this many
+    /// millimetres away, see [`CROSSTALK_TARGET_MM`].
+    CalibrateCrosstalk(u16),
+}
+
+/// Inclusive bounds of the `calibrate offset` target distance, in millimetres.
+pub const OFFSET_TARGET_MM: (u16, u16) = (10, 200);
+
+/// Inclusive bounds of the `calibrate crosstalk` target distance, in
+/// millimetres. Crosstalk matters most close up, but the target has to be
+/// far enough for the glass to make a measurable difference.
+pub const CROSSTALK_TARGET_MM: (u16, u16) = (20, 200);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measurement {
+    Range,
+    Ambient,
+}
+
+/// Interrupt condition for range or ambient measurements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    Disabled,
+    LevelLow,
+    LevelHigh,
+    OutOfWindow,
+    NewSampleReady,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Setting {
+    RangeMode,
+    RangeHigh,
+    RangeLow,
+    AmbientMode,
+    AmbientHigh,
+    AmbientLow,
+    AmbientGain,
+    RangeScaler,
+    AmbientScaler,
+}
+
+/// A setting together with a value that is valid for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assignment {
+    RangeMode(InterruptMode),
+    /// Range high threshold, in range scaler units.
+    RangeHigh(u8),
+    /// Range low threshold, in range scaler units.
+    RangeLow(u8),
+    AmbientMode(InterruptMode),
+    AmbientHigh(u16),
+    AmbientLow(u16),
+    /// Analogue gain level, 0 (20x) to 7 (40x), see the datasheet's gain table.
+    AmbientGain(u8),
+    /// Range result scaling factor, 1 to 3.
+    RangeScaler(u8),
+    /// Ambient result scaler, 1 to 15.
+    AmbientScaler(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The line held nothing but whitespace.
+    Empty,
+    UnknownCommand,
+    UnknownSetting,
+    UnknownMeasurement,
+    UnknownMode,
+    UnknownCalibration,
+    MissingArgument,
+    UnexpectedArgument,
+    InvalidNumber,
+    OutOfRange {
+        min: u16,
+        max: u16,
+    },
+}
+
+/// Spread of a run of range readings and their return signal rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RangeStats {
+    pub mean_mm: u16,
+    pub min_mm: u16,
+    pub max_mm: u16,
+    /// Mean return signal rate, in Mcps as 9.7 fixed point.
+    pub mean_rate: u16,
+}
+
+/// What the firmware prints after running a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response {
+    Ok,
+    Help,
+    Value(Assignment),
+    Settings(Settings),
+    /// Result of `calibrate offset`: the mean reading before calibrating and
+    /// the offset now applied.
+    Offset {
+        offset_mm: i8,
+        measured_mm: u16,
+        target_mm: u16,
+    },
+    /// Result of `calibrate crosstalk`: the compensation rate now applied,
+    /// in Mcps as 9.7 fixed point, and the readings without and with it.
+    Crosstalk {
+        rate: u16,
+        target_mm: u16,
+        before: RangeStats,
+        after: RangeStats,
+    },
+    /// A range reading, already multiplied by the range scaler.
+    RangeMm(u16),
+    AmbientRaw(u16),
+}
+
+impl Measurement {
+    pub const ALL: [Measurement; 2] = [Measurement::Range, Measurement::Ambient];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Measurement::Range => "range",
+            Measurement::Ambient => "ambient",
+        }
+    }
+}
+
+impl InterruptMode {
+    pub const ALL: [InterruptMode; 5] = [
+        InterruptMode::Disabled,
+        InterruptMode::LevelLow,
+        InterruptMode::LevelHigh,
+        InterruptMode::OutOfWindow,
+        InterruptMode::NewSampleReady,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            InterruptMode::Disabled => "disabled",
+            InterruptMode::LevelLow => "level-low",
+            InterruptMode::LevelHigh => "level-high",
+            InterruptMode::OutOfWindow => "out-of-window",
+            InterruptMode::NewSampleReady => "new-sample",
+        }
+    }
+}
+
+impl Setting {
+    pub const ALL: [Setting; 9] = [
+        Setting::RangeMode,
+        Setting::RangeHigh,
+        Setting::RangeLow,
+        Setting::AmbientMode,
+        Setting::AmbientHigh,
+        Setting::AmbientLow,
+        Setting::AmbientGain,
+        Setting::RangeScaler,
+        Setting::AmbientScaler,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Setting::RangeMode => "range-mode",
+            Setting::RangeHigh => "range-high",
+            Setting::RangeLow => "range-low",
+            Setting::AmbientMode => "ambient-mode",
+            Setting::AmbientHigh => "ambient-high",
+            Setting::AmbientLow => "ambient-low",
+            Setting::AmbientGain => "ambient-gain",
+            Setting::RangeScaler => "range-scaler",
+            Setting::AmbientScaler => "ambient-scaler",
+        }
+    }
+
+    /// Inclusive bounds of a numeric setting, `None` for interrupt modes.
+    pub const fn limits(self) -> Option<(u16, u16)> {
+        match self {
+            Setting::RangeMode | Setting::AmbientMode => None,
+            Setting::RangeHigh | Setting::RangeLow => Some((0, u8::MAX as u16)),
+            Setting::AmbientHigh | Setting::AmbientLow => Some((0, u16::MAX)),
+            Setting::AmbientGain => Some((0, 7)),
+            Setting::RangeScaler => Some((1, 3)),
+            Setting::AmbientScaler => Some((1, 15)),
+        }
+    }
+}
+
+impl Assignment {
+    pub const fn setting(self) -> Setting {
+        match self {
+            Assignment::RangeMode(_) => Setting::RangeMode,
+            Assignment::RangeHigh(_) => Setting::RangeHigh,
+            Assignment::RangeLow(_) => Setting::RangeLow,
+            Assignment::AmbientMode(_) => Setting::AmbientMode,
+            Assignment::AmbientHigh(_) => Setting::AmbientHigh,
+            Assignment::AmbientLow(_) => Setting::AmbientLow,
+            Assignment::AmbientGain(_) => Setting::AmbientGain,
+            Assignment::RangeScaler(_) => Setting::RangeScaler,
+            Assignment::AmbientScaler(_) => Setting::AmbientScaler,
+        }
+    }
+
+    /// Parses the value half of `set <setting> <value>`.
+    pub fn parse(setting: Setting, value: &str) -> Result<Self, ParseError> {
+        let mode =
+            || find(&InterruptMode::ALL, value, InterruptMode::name).ok_or(ParseError::UnknownMode);
+        // Only called for numeric settings, which all have limits.
+        let number = || number_within(value, setting.limits().unwrap_or((0, u16::MAX)));
+        Ok(match setting {
+            Setting::RangeMode => Assignment::RangeMode(mode()?),
+            Setting::AmbientMode => Assignment::AmbientMode(mode()?),
+            Setting::RangeHigh => Assignment::RangeHigh(number()? as u8),
+            Setting::RangeLow => Assignment::RangeLow(number()? as u8),
+            Setting::AmbientHigh => Assignment::AmbientHigh(number()?),
+            Setting::AmbientLow => Assignment::AmbientLow(number()?),
+            Setting::AmbientGain => Assignment::AmbientGain(number()? as u8),
+            Setting::RangeScaler => Assignment::RangeScaler(number()? as u8),
+            Setting::AmbientScaler => Assignment::AmbientScaler(number()? as u8),
+        })
+    }
+}
+
+/// Parses one line, without its line ending.
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_ascii_whitespace();
+    let keyword = words.next().ok_or(ParseError::Empty)?;
+    let mut argument = || words.next().ok_or(ParseError::MissingArgument);
+
+    let command = if is(keyword, "help") || keyword == "?" {
+        Command::Help
+    } else if is(keyword, "show") {
+        Command::Show
+    } else if is(keyword, "save") {
+        Command::Save
+    } else if is(keyword, "get") {
+        Command::Get(setting(argument()?)?)
+    } else if is(keyword, "set") {
+        let setting = setting(argument()?)?;
+        Command::Set(Assignment::parse(setting, argument()?)?)
+    } else if is(keyword, "start") {
+        Command::Start(measurement(argument()?)?)
+    } else if is(keyword, "stop") {
+        Command::Stop(measurement(argument()?)?)
+    } else if is(keyword, "read") {
+        Command::Read(measurement(argument()?)?)
+    } else if is(keyword, "calibrate") {
+        let kind = argument()?;
+        if is(kind, "offset") {
+            Command::CalibrateOffset(number_within(argument()?, OFFSET_TARGET_MM)?)
+        } else if is(kind, "crosstalk") {
+            Command::CalibrateCrosstalk(number_within(argument()?, CROSSTALK_TARGET_MM)?)
+        } else {
+            return Err(ParseError::UnknownCalibration);
+        }
+    } else {
+        return Err(ParseError::UnknownCommand);
+    };
+
+    match words.next() {
+        Some(_) => Err(ParseError::UnexpectedArgument),
+        None => Ok(command),
+    }
+}
+
+fn is(word: &str, keyword: &str) -> bool {
+    word.eq_ignore_ascii_case(keyword)
+}
+
+fn setting(word: &str) -> Result<Setting, ParseError> {
+    find(&Setting::ALL, word, Setting::name).ok_or(ParseError::UnknownSetting)
+}
+
+fn measurement(word: &str) -> Result<Measurement, ParseError> {
+    find(&Measurement::ALL, word, Measurement::name).ok_or(ParseError::UnknownMeasurement)
+}
+
+fn find<T: Copy>(all: &[T], word: &str, name: impl Fn(T) -> &'static str) -> Option<T> {
+    all.iter().copied().find(|&item| is(word, name(item)))
+}
+
+fn number_within(word: &str, (min, max): (u16, u16)) -> Result<u16, ParseError> {
+    let n = parse_number(word)?;
+    if n < min as u32 || n > max as u32 {
+        return Err(ParseError::OutOfRange { min, max });
+    }
+    Ok(n as u16)
+}
+
+fn parse_number(word: &str) -> Result<u32, ParseError> {
+    let (digits, radix) = match word.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("0x") => (&word[2..], 16),
+        _ => (word, 10),
+    };
+    // `from_str_radix` accepts a leading sign, the shell doesn't.
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidNumber);
+    }
+    u32::from_str_radix(digits, radix).map_err(|_| ParseError::InvalidNumber)
+}
+
+impl fmt::Display for Command {
+    /// The canonical spelling, which [`parse`] reads back to the same command.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Help => f.write_str("help"),
+            Command::Show => f.write_str("show"),
+            Command::Get(setting) => write!(f, "get {}", setting.name()),
+            Command::Set(assignment) => {
+                write!(f, "set {} ", assignment.setting().name())?;
+                assignment.fmt_value(f)
+            }
+            Command::Start(m) => write!(f, "start {}", m.name()),
+            Command::Stop(m) => write!(f, "stop {}", m.name()),
+            Command::Read(m) => write!(f, "read {}", m.name()),
+            Command::Save => f.write_str("save"),
+            Command::CalibrateOffset(mm) => write!(f, "calibrate offset {}", mm),
+            Command::CalibrateCrosstalk(mm) => write!(f, "calibrate crosstalk {}", mm),
+        }
+    }
+}
+
+impl Assignment {
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Assignment::RangeMode(mode) | Assignment::AmbientMode(mode) => f.write_str(mode.name()),
+            Assignment::RangeHigh(v)
+            | Assignment::RangeLow(v)
+            | Assignment::AmbientGain(v)
+            | Assignment::RangeScaler(v)
+            | Assignment::AmbientScaler(v) => write!(f, "{}", v),
+            Assignment::AmbientHigh(v) | Assignment::AmbientLow(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.setting().name())?;
+        self.fmt_value(f)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => f.write_str("empty line"),
+            ParseError::UnknownCommand => f.write_str("unknown command, try `help`"),
+            ParseError::UnknownSetting => f.write_str("unknown setting, try `help`"),
+            ParseError::UnknownMeasurement => f.write_str("expected `range` or `ambient`"),
+            ParseError::UnknownMode => {
+                f.write_str("expected disabled, level-low, level-high, out-of-window or new-sample")
+            }
+            ParseError::UnknownCalibration => f.write_str("expected offset or crosstalk"),
+            ParseError::MissingArgument => f.write_str("missing argument"),
+            ParseError::UnexpectedArgument => f.write_str("too many arguments"),
+            ParseError::InvalidNumber => f.write_str("not a number"),
+            ParseError::OutOfRange { min, max } => write!(f, "must be {} to {}", min, max),
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Response::Ok => f.write_str("ok"),
+            Response::Help => {
+                f.write_str(
+                    "commands: help, show, get <setting>, set <setting> <value>, \
+                     start|stop|read range|ambient, calibrate offset|crosstalk <mm>, save; settings:",
+                )?;
+                for setting in Setting::ALL {
+                    write!(f, " {}", setting.name())?;
+                }
+                Ok(())
+            }
+            Response::Value(assignment) => write!(f, "{}", assignment),
+            Response::Settings(settings) => {
+                for (i, assignment) in settings.assignments().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "{}", assignment)?;
+                }
+                Ok(())
+            }
+            Response::Offset {
+                offset_mm,
+                measured_mm,
+                target_mm,
+            } => write!(
+                f,
+                "offset={}mm (read {}mm with the target at {}mm)",
+                offset_mm, measured_mm, target_mm
+            ),
+            Response::Crosstalk {
+                rate,
+                target_mm,
+                before,
+                after,
+            } => write!(
+                f,
+                "crosstalk={} with the target at {}mm; before: {}; after: {}",
+                Mcps(*rate),
+                target_mm,
+                before,
+                after
+            ),
+            Response::RangeMm(mm) => write!(f, "range={}mm", mm),
+            Response::AmbientRaw(raw) => write!(f, "ambient={}", raw),
+        }
+    }
+}
+
+impl fmt::Display for RangeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean={}mm min={}mm max={}mm rate={}",
+            self.mean_mm,
+            self.min_mm,
+            self.max_mm,
+            Mcps(self.mean_rate)
+        )
+    }
+}
+
+/// Prints a 9.7 fixed point rate in Mcps with two decimals.
+struct Mcps(u16);
+
+impl fmt::Display for Mcps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hundredths = (self.0 as u32 * 100 + 64) >> 7;
+        write!(f, "{}.{:02}Mcps", hundredths / 100, hundredths % 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn assignment() -> impl Strategy<Value = Assignment> {
+        let mode = proptest::sample::select(InterruptMode::ALL.to_vec());
+        prop_oneof![
+            mode.clone().prop_map(Assignment::RangeMode),
+            mode.prop_map(Assignment::AmbientMode),
+            any::<u8>().prop_map(Assignment::RangeHigh),
+            any::<u8>().prop_map(Assignment::RangeLow),
+            any::<u16>().prop_map(Assignment::AmbientHigh),
+            any::<u16>().prop_map(Assignment::AmbientLow),
+            (0..=7u8).prop_map(Assignment::AmbientGain),
+            (1..=3u8).prop_map(Assignment::RangeScaler),
+            (1..=15u8).prop_map(Assignment::AmbientScaler),
+        ]
+    }
+
+    fn command() -> impl Strategy<Value = Command> {
+        let measurement = proptest::sample::select(Measurement::ALL.to_vec());
+        prop_oneof![
+            Just(Command::Help),
+            Just(Command::Show),
+            Just(Command::Save),
+            (OFFSET_TARGET_MM.0..=OFFSET_TARGET_MM.1).prop_map(Command::CalibrateOffset),
+            (CROSSTALK_TARGET_MM.0..=CROSSTALK_TARGET_MM.1).prop_map(Command::CalibrateCrosstalk),
+            proptest::sample::select(Setting::ALL.to_vec()).prop_map(Command::Get),
+            assignment().prop_map(Command::Set),
+            measurement.clone().prop_map(Command::Start),
+            measurement.clone().prop_map(Command::Stop),
+            measurement.prop_map(Command::Read),
+        ]
+    }
+
+    /// Rewrites a canonical command line with random case and whitespace.
+    fn respell(line: &str, upper: &[bool], gaps: &[String]) -> String {
+        let mut out = gaps[0].clone();
+        for (i, word) in line.split(' ').enumerate() {
+            if i > 0 {
+                out.push_str(&gaps[i]);
+            }
+            if upper[i] {
+                out.push_str(&word.to_ascii_uppercase());
+            } else {
+                out.push_str(word);
+            }
+        }
+        out.push_str(&gaps[gaps.len() - 1]);
+        out
+    }
+
+    proptest! {
+        #[test]
+        fn parse_never_panics(line in "\\PC*") {
+            let _ = parse(&line);
+        }
+
+        #[test]
+        fn parse_never_panics_on_keywords(words in proptest::collection::vec(
+            prop_oneof![
+                Just("set"), Just("get"), Just("start"), Just("range-high"),
+                Just("ambient-mode"), Just("range"), Just("0x"), Just("0x1F"),
+                Just("-1"), Just("99999999999"), Just("level-low"), Just("\u{e9}"),
+            ],
+            0..6,
+        )) {
+            let _ = parse(&words.join(" "));
+        }
+
+        #[test]
+        fn display_roundtrips(command in command()) {
+            prop_assert_eq!(parse(&command.to_string()), Ok(command));
+        }
+
+        #[test]
+        fn case_and_whitespace_are_ignored(
+            command in command(),
+            upper in proptest::collection::vec(any::<bool>(), 3),
+            gaps in proptest::collection::vec("[ \t]{1,3}", 4),
+        ) {
+            let line = respell(&command.to_string(), &upper, &gaps);
+            prop_assert_eq!(parse(&line), Ok(command));
+        }
+
+        #[test]
+        fn numbers_out_of_range_are_rejected(setting in proptest::sample::select(Setting::ALL.to_vec()), n in any::<u32>()) {
+            if let Some((min, max)) = setting.limits() {
+                let line = format!("set {} {}", setting.name(), n);
+                let parsed = parse(&line);
+                if n < min as u32 || n > max as u32 {
+                    prop_assert_eq!(parsed, Err(ParseError::OutOfRange { min, max }));
+                } else {
+                    prop_assert!(parsed.is_ok());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(
+            parse("set range-high 0x32"),
+            Ok(Command::Set(Assignment::RangeHigh(50)))
+        );
+        assert_eq!(
+            parse("SET Range-Mode Level-High"),
+            Ok(Command::Set(Assignment::RangeMode(
+                InterruptMode::LevelHigh
+            )))
+        );
+        assert_eq!(
+            parse("  read   ambient "),
+            Ok(Command::Read(Measurement::Ambient))
+        );
+        assert_eq!(parse("?"), Ok(Command::Help));
+        assert_eq!(parse("SAVE"), Ok(Command::Save));
+        assert_eq!(
+            parse("calibrate Offset 0x32"),
+            Ok(Command::CalibrateOffset(50))
+        );
+        assert_eq!(
+            parse("calibrate crosstalk 100"),
+            Ok(Command::CalibrateCrosstalk(100))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse(" \t "), Err(ParseError::Empty));
+        assert_eq!(parse("reboot"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("get"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("get colour"), Err(ParseError::UnknownSetting));
+        assert_eq!(parse("set range-high"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("set range-high +5"), Err(ParseError::InvalidNumber));
+        assert_eq!(parse("set range-high 0x"), Err(ParseError::InvalidNumber));
+        assert_eq!(
+            parse("set range-high 256"),
+            Err(ParseError::OutOfRange { min: 0, max: 255 })
+        );
+        assert_eq!(
+            parse("set range-scaler 0"),
+            Err(ParseError::OutOfRange { min: 1, max: 3 })
+        );
+        assert_eq!(
+            parse("set range-mode sometimes"),
+            Err(ParseError::UnknownMode)
+        );
+        assert_eq!(parse("start light"), Err(ParseError::UnknownMeasurement));
+        assert_eq!(parse("show all"), Err(ParseError::UnexpectedArgument));
+        assert_eq!(
+            parse("calibrate gain 50"),
+            Err(ParseError::UnknownCalibration)
+        );
+        assert_eq!(
+            parse("calibrate offset 5"),
+            Err(ParseError::OutOfRange { min: 10, max: 200 })
+        );
+    }
+
+    #[test]
+    fn rates_print_in_mcps() {
+        assert_eq!(Mcps(0).to_string(), "0.00Mcps");
+        assert_eq!(Mcps(0x0080).to_string(), "1.00Mcps");
+        assert_eq!(Mcps(0x00A0).to_string(), "1.25Mcps");
+        assert_eq!(Mcps(0x0001).to_string(), "0.01Mcps");
+        assert_eq!(Mcps(u16::MAX).to_string(), "511.99Mcps");
+    }
+
+    #[test]
+    fn help_lists_every_setting() {
+        let help = Response::Help.to_string();
+        let names: Vec<_> = Setting::ALL.iter().map(|s| s.name()).collect();
+        assert!(names.iter().all(|name| help.contains(name)));
+    }
+}
@@ -0,0 +1,32 @@
+//! Line based command shell for reconfiguring a VL6180X at runtime.
+//!
+//! This crate only turns bytes into [`Command`]s and keeps the [`Settings`]
+//! mirror; applying commands to a sensor is up to the firmware. It is `no_std`
+//! and allocation free so the parser can be property tested on the host.
+//!
+//! ```text
+//! help                   list commands and settings
+//! show                   print every setting
+//! get <setting>          print one setting
+//! set <setting> <value>  change one setting
+//! start range|ambient    start continuous measurements
+//! stop range|ambient     stop continuous measurements
+//! read range|ambient     take one measurement
+//! calibrate offset <mm>  measure the range offset against a target <mm> away
+//! calibrate crosstalk <mm>
+//!                        measure cover glass crosstalk against a dark target
+//! save                   keep the settings over a reset
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod command;
+mod line;
+mod settings;
+
+pub use command::{
+    parse, Assignment, Command, InterruptMode, Measurement, ParseError, RangeStats, Response,
+    Setting, CROSSTALK_TARGET_MM, OFFSET_TARGET_MM,
+};
+pub use line::{LineBuffer, LineTooLong};
+pub use settings::Settings;
@@ -0,0 +1,127 @@
+//! Assembles command lines from received bytes.
+
+use heapless::String;
+
+/// The line was longer than the buffer and has been dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineTooLong;
+
+/// Collects printable ASCII until CR or LF.
+///
+/// Backspace and DEL remove the last character, other control and non-ASCII
+/// bytes are ignored, and empty lines (including the LF of a CRLF) are
+/// skipped. A line that outgrows the buffer is discarded as a whole.
+#[derive(Default)]
+pub struct LineBuffer<const N: usize> {
+    line: String<N>,
+    overflowed: bool,
+    complete: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub fn new() -> Self {
+        Self {
+            line: String::new(),
+            overflowed: false,
+            complete: false,
+        }
+    }
+
+    /// Feeds one received byte, returning the line once it ends.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, LineTooLong>> {
+        if self.complete {
+            self.line.clear();
+            self.overflowed = false;
+            self.complete = false;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                if self.line.is_empty() && !self.overflowed {
+                    return None;
+                }
+                self.complete = true;
+                if self.overflowed {
+                    Some(Err(LineTooLong))
+                } else {
+                    Some(Ok(self.line.as_str()))
+                }
+            }
+            0x08 | 0x7F => {
+                self.line.pop();
+                None
+            }
+            b' ' | b'\t' | 0x21..=0x7E => {
+                if self.line.push(byte as char).is_err() {
+                    self.overflowed = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String as StdString;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn lines<const N: usize>(
+        buffer: &mut LineBuffer<N>,
+        bytes: &[u8],
+    ) -> Vec<Result<StdString, LineTooLong>> {
+        bytes
+            .iter()
+            .filter_map(|&b| buffer.push(b).map(|line| line.map(StdString::from)))
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_any_line_ending() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(
+            lines(&mut buffer, b"show\r\nhelp\nget x\r\r\n"),
+            [Ok("show".into()), Ok("help".into()), Ok("get x".into())]
+        );
+    }
+
+    #[test]
+    fn backspace_edits_the_line() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(
+            lines(&mut buffer, b"shoq\x08w\x7f\x7fow\r"),
+            [Ok("show".into())]
+        );
+    }
+
+    #[test]
+    fn overlong_line_is_dropped_and_next_line_is_kept() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(
+            lines(&mut buffer, b"toolong\rok\r"),
+            [Err(LineTooLong), Ok("ok".into())]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_never_panic_and_fit(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let mut buffer = LineBuffer::<32>::new();
+            for line in lines(&mut buffer, &bytes).into_iter().flatten() {
+                prop_assert!(!line.is_empty() && line.len() <= 32);
+                prop_assert!(line.bytes().all(|b| b == b' ' || b == b'\t' || b.is_ascii_graphic()));
+            }
+        }
+
+        #[test]
+        fn printable_lines_pass_through(line in "[ -~]{1,32}") {
+            let mut buffer = LineBuffer::<32>::new();
+            let mut bytes = line.clone().into_bytes();
+            bytes.push(b'\n');
+            prop_assert_eq!(lines(&mut buffer, &bytes), [Ok(line)]);
+        }
+    }
+}
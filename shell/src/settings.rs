@@ -0,0 +1,75 @@
+//! The shell's copy of the sensor configuration.
+
+use crate::{Assignment, InterruptMode, Setting};
+
+/// Every setting the shell can change, as last written to the sensor.
+///
+/// The sensor's registers can't all be read back meaningfully (thresholds are
+/// in scaled units, gain codes carry extra bits), so the shell keeps this
+/// mirror instead and the firmware builds the initial `vl6180x::Config` from
+/// it, which keeps the two in step from power-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub range_mode: InterruptMode,
+    pub range_high: u8,
+    pub range_low: u8,
+    pub ambient_mode: InterruptMode,
+    pub ambient_high: u16,
+    pub ambient_low: u16,
+    pub ambient_gain: u8,
+    pub range_scaler: u8,
+    pub ambient_scaler: u8,
+}
+
+impl Default for Settings {
+    /// Interrupts off and unity scaling, with the ambient gain at 1.0x.
+    fn default() -> Self {
+        Self {
+            range_mode: InterruptMode::Disabled,
+            range_high: u8::MAX,
+            range_low: 0,
+            ambient_mode: InterruptMode::Disabled,
+            ambient_high: u16::MAX,
+            ambient_low: 0,
+            ambient_gain: 6,
+            range_scaler: 1,
+            ambient_scaler: 1,
+        }
+    }
+}
+
+impl Settings {
+    pub fn apply(&mut self, assignment: Assignment) {
+        match assignment {
+            Assignment::RangeMode(mode) => self.range_mode = mode,
+            Assignment::RangeHigh(v) => self.range_high = v,
+            Assignment::RangeLow(v) => self.range_low = v,
+            Assignment::AmbientMode(mode) => self.ambient_mode = mode,
+            Assignment::AmbientHigh(v) => self.ambient_high = v,
+            Assignment::AmbientLow(v) => self.ambient_low = v,
+            Assignment::AmbientGain(v) => self.ambient_gain = v,
+            Assignment::RangeScaler(v) => self.range_scaler = v,
+            Assignment::AmbientScaler(v) => self.ambient_scaler = v,
+        }
+    }
+
+    /// The current value of `setting`.
+    pub fn get(&self, setting: Setting) -> Assignment {
+        match setting {
+            Setting::RangeMode => Assignment::RangeMode(self.range_mode),
+            Setting::RangeHigh => Assignment::RangeHigh(self.range_high),
+            Setting::RangeLow => Assignment::RangeLow(self.range_low),
+            Setting::AmbientMode => Assignment::AmbientMode(self.ambient_mode),
+            Setting::AmbientHigh => Assignment::AmbientHigh(self.ambient_high),
+            Setting::AmbientLow => Assignment::AmbientLow(self.ambient_low),
+            Setting::AmbientGain => Assignment::AmbientGain(self.ambient_gain),
+            Setting::RangeScaler => Assignment::RangeScaler(self.range_scaler),
+            Setting::AmbientScaler => Assignment::AmbientScaler(self.ambient_scaler),
+        }
+    }
+
+    /// Every setting with its current value, in [`Setting::ALL`] order.
+    pub fn assignments(&self) -> impl Iterator<Item = Assignment> + '_ {
+        Setting::ALL.into_iter().map(|setting| self.get(setting))
+    }
+}
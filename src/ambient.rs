@@ -0,0 +1,185 @@
+//! Auto-ranging for the ambient light sensor.
+//!
+//! A fixed analogue gain and integration time only suit one light level: high
+//! gain saturates the 16 bit count outdoors, low gain leaves a handful of
+//! counts in the dark. [`AutoGain`] watches the raw counts and moves along
+//! [`ALS_STEPS`], from the most sensitive setting to the least, to keep them
+//! between [`AutoGainConfig::low_raw`] and [`AutoGainConfig::high_raw`]. The
+//! gap between the two is wider than any one step, so a reading that makes
+//! it step can't make it step straight back.
+//!
+//! Lux is worked out from the setting each reading was taken with, so it
+//! stays right across changes. That assumes the ALS result scaler
+//! (`FIRMWARE__RESULT_SCALER`) is left at 1.
+
+use embedded_hal::blocking::i2c::Write;
+
+use crate::registers::*;
+
+/// Analogue gain of each `SYSALS__ANALOGUE_GAIN` level, from the datasheet's
+/// calibrated values.
+pub const ALS_GAINS: [f32; 8] = [20.0, 10.32, 5.21, 2.6, 1.72, 1.28, 1.01, 40.0];
+
+/// Lux per count at a gain of 1 and 100 ms integration.
+pub const ALS_LUX_RESOLUTION: f32 = 0.32;
+
+/// One ALS sensitivity setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlsStep {
+    /// `SYSALS__ANALOGUE_GAIN` level, 0 to 7.
+    pub gain_level: u8,
+    /// 1 to 512 ms.
+    pub integration_ms: u16,
+}
+
+impl AlsStep {
+    pub const fn new(gain_level: u8, integration_ms: u16) -> Self {
+        Self {
+            gain_level,
+            integration_ms,
+        }
+    }
+
+    pub fn gain(self) -> f32 {
+        ALS_GAINS[self.gain_level as usize]
+    }
+
+    /// Lux a `raw` count taken with this setting stands for.
+    pub fn lux(self, raw: u16) -> f32 {
+        ALS_LUX_RESOLUTION * raw as f32 * 100.0 / (self.gain() * self.integration_ms as f32)
+    }
+
+    /// Counts per lux, relative between steps.
+    fn sensitivity(self) -> f32 {
+        self.gain() * self.integration_ms as f32
+    }
+}
+
+/// The settings [`AutoGain`] moves through, most sensitive first. Gain goes
+/// down first, then integration time gets shorter, so the dark end keeps the
+/// noise averaging of 100 ms. The last step still resolves over 300 klux.
+pub const ALS_STEPS: [AlsStep; 9] = [
+    AlsStep::new(7, 100),
+    AlsStep::new(0, 100),
+    AlsStep::new(1, 100),
+    AlsStep::new(2, 100),
+    AlsStep::new(3, 100),
+    AlsStep::new(6, 100),
+    AlsStep::new(6, 40),
+    AlsStep::new(6, 16),
+    AlsStep::new(6, 6),
+];
+
+/// Where [`AutoGain::new`] starts: 2.6x gain, up to about 8 klux.
+pub const ALS_START_STEP: usize = 4;
+
+/// Raw count window of an [`AutoGain`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoGainConfig {
+    /// Below this the next more sensitive step is taken.
+    pub low_raw: u16,
+    /// Above this the next less sensitive step is taken.
+    pub high_raw: u16,
+}
+
+impl Default for AutoGainConfig {
+    /// Leaves headroom for the light to double between two readings before
+    /// the count saturates.
+    fn default() -> Self {
+        Self {
+            low_raw: 8_000,
+            high_raw: 30_000,
+        }
+    }
+}
+
+/// An ambient reading as [`AutoGain::update`] reports it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbientReading {
+    pub raw: u16,
+    pub lux: f32,
+    /// The setting `raw` was taken with.
+    pub step: AlsStep,
+    /// The setting to write for the next reading, if it changes.
+    pub next: Option<AlsStep>,
+}
+
+/// Picks the ALS setting from the readings it gives, see the module docs.
+#[derive(Clone, Debug)]
+pub struct AutoGain {
+    config: AutoGainConfig,
+    step: usize,
+}
+
+impl AutoGain {
+    /// Starts at [`ALS_START_STEP`].
+    ///
+    /// # Panics
+    ///
+    /// If `low_raw` is so close to `high_raw` that a step up from just
+    /// below it could land above `high_raw`.
+    pub fn new(config: AutoGainConfig) -> Self {
+        let fits = ALS_STEPS.windows(2).all(|pair| {
+            let ratio = pair[0].sensitivity() / pair[1].sensitivity();
+            (config.low_raw as f32) * ratio < config.high_raw as f32
+        });
+        assert!(fits, "auto gain thresholds");
+        Self {
+            config,
+            step: ALS_START_STEP,
+        }
+    }
+
+    pub fn config(&self) -> &AutoGainConfig {
+        &self.config
+    }
+
+    /// The setting the sensor should be measuring with.
+    pub fn step(&self) -> AlsStep {
+        ALS_STEPS[self.step]
+    }
+
+    /// Takes a `raw` count measured with [`AutoGain::step`] and moves to the
+    /// next step if it is outside the window. The new one has to be written,
+    /// e.g. with [`write_step`], before the sensor starts its next
+    /// measurement.
+    pub fn update(&mut self, raw: u16) -> AmbientReading {
+        let step = self.step();
+        let previous = self.step;
+        if raw > self.config.high_raw && self.step + 1 < ALS_STEPS.len() {
+            self.step += 1;
+        } else if raw < self.config.low_raw && self.step > 0 {
+            self.step -= 1;
+        }
+        AmbientReading {
+            raw,
+            lux: step.lux(raw),
+            step,
+            next: (self.step != previous).then(|| self.step()),
+        }
+    }
+
+    /// Back to [`ALS_START_STEP`], e.g. after the sensor was reset.
+    pub fn reset(&mut self) {
+        self.step = ALS_START_STEP;
+    }
+}
+
+/// Writes `step` to the sensor at `address`. In continuous mode this is safe
+/// from the new sample interrupt, as the next measurement only starts after
+/// the inter-measurement period, which has to be longer than the integration
+/// time anyway.
+pub fn write_step<I2C: Write>(i2c: &mut I2C, address: u8, step: AlsStep) -> Result<(), I2C::Error> {
+    write_u8(
+        i2c,
+        address,
+        SYSALS__ANALOGUE_GAIN,
+        ALS_GAIN_BASE | step.gain_level,
+    )?;
+    write_u16(
+        i2c,
+        address,
+        SYSALS__INTEGRATION_PERIOD,
+        step.integration_ms - 1,
+    )
+}
@@ -0,0 +1,499 @@
+//! Board bring-up shared by every example.
+//!
+//! The board is picked by a cargo feature: a Black Pill with an STM32F401CC,
+//! STM32F401CE or STM32F411CE, which all share one pinout, or a
+//! Nucleo-F401RE. The pin map and clock settings live in plain constant
+//! tables so they can be checked on the host; [`Board::new`] applies them to
+//! the real peripherals.
+
+use hal::{gpio, pac, prelude::*};
+use stm32f4xx_hal as hal;
+
+use crate::bus_recovery::{self, BusRecovery, BusStuck};
+use crate::handlers::StatusLed;
+use crate::timeout::TimeSource;
+
+/// System clock the examples run at.
+pub const SYSCLK_MHZ: u32 = 48;
+
+/// I2C1 bus frequency used to talk to the VL6180X.
+pub const I2C_FREQ_KHZ: u32 = 400;
+
+/// A GPIO pin identified by its port letter and number, e.g. `PinId::new('B', 8)` for PB8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinId {
+    pub port: char,
+    pub number: u8,
+}
+
+impl PinId {
+    pub const fn new(port: char, number: u8) -> Self {
+        Self { port, number }
+    }
+
+    /// Const-friendly equality, `PartialEq` cannot be used in a `const fn`.
+    pub const fn same_as(&self, other: &PinId) -> bool {
+        self.port == other.port && self.number == other.number
+    }
+}
+
+/// Pins claimed by [`Board`], including the first sensor's, before the rest
+/// are handed out in [`Pins`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinMap {
+    pub i2c_scl: PinId,
+    pub i2c_sda: PinId,
+    pub led: PinId,
+    pub button: PinId,
+    pub sensor_xshut: PinId,
+    /// The sensor's GPIO1.
+    pub sensor_interrupt: PinId,
+}
+
+impl PinMap {
+    /// All claimed pins, in declaration order.
+    pub const fn pins(&self) -> [PinId; 6] {
+        [
+            self.i2c_scl,
+            self.i2c_sda,
+            self.led,
+            self.button,
+            self.sensor_xshut,
+            self.sensor_interrupt,
+        ]
+    }
+
+    /// Returns true if `pin` is already claimed by the board.
+    pub const fn claims(&self, pin: PinId) -> bool {
+        let pins = self.pins();
+        let mut i = 0;
+        while i < pins.len() {
+            if pins[i].same_as(&pin) {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// Returns true if no pin is claimed twice.
+    pub const fn is_valid(&self) -> bool {
+        let pins = self.pins();
+        let mut i = 0;
+        while i < pins.len() {
+            let mut j = i + 1;
+            while j < pins.len() {
+                if pins[i].same_as(&pins[j]) {
+                    return false;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        true
+    }
+}
+
+/// The Black Pill wiring used by every example.
+/// SCL is PB8 and SDA is PB9 (Alternate Function 4), the LED is on PC13 and
+/// the user button on PA0. The sensor's XSHUT goes to PB7 and GPIO1 to PB6.
+#[cfg(not(feature = "nucleo-f401re"))]
+pub const PIN_MAP: PinMap = PinMap {
+    i2c_scl: PinId::new('B', 8),
+    i2c_sda: PinId::new('B', 9),
+    led: PinId::new('C', 13),
+    button: PinId::new('A', 0),
+    sensor_xshut: PinId::new('B', 7),
+    sensor_interrupt: PinId::new('B', 6),
+};
+
+/// The Nucleo-F401RE wiring, the sensor on the Arduino header.
+/// SCL is D15 (PB8) and SDA is D14 (PB9), XSHUT goes to D7 (PA8) and GPIO1 to
+/// D10 (PB6). The LED LD2 is on PA5 and the user button B1 on PC13.
+#[cfg(feature = "nucleo-f401re")]
+pub const PIN_MAP: PinMap = PinMap {
+    i2c_scl: PinId::new('B', 8),
+    i2c_sda: PinId::new('B', 9),
+    led: PinId::new('A', 5),
+    button: PinId::new('C', 13),
+    sensor_xshut: PinId::new('A', 8),
+    sensor_interrupt: PinId::new('B', 6),
+};
+
+const _: () = assert!(PIN_MAP.is_valid());
+
+// The examples service the sensor's interrupt in EXTI9_5
+const _: () = assert!(matches!(PIN_MAP.sensor_interrupt.number, 5..=9));
+
+/// The LED lights with its pin low, see [`crate::handlers::StatusLed`].
+#[cfg(not(feature = "nucleo-f401re"))]
+pub const LED_ACTIVE_LOW: bool = true;
+
+#[cfg(feature = "nucleo-f401re")]
+pub const LED_ACTIVE_LOW: bool = false;
+
+/// USART2 TX, claimed by the `log-uart` backend and then missing from [`Pins`].
+/// On the Nucleo, USART2 is the ST-LINK's virtual COM port.
+pub const LOG_UART_TX: PinId = PinId::new('A', 2);
+
+const _: () = assert!(!PIN_MAP.claims(LOG_UART_TX));
+
+/// USART2 RX, claimed along with [`LOG_UART_TX`] as the command shell input.
+pub const CONSOLE_RX: PinId = PinId::new('A', 3);
+
+const _: () = assert!(!PIN_MAP.claims(CONSOLE_RX));
+
+/// USART1 TX, where the examples send the binary measurement stream.
+pub const STREAM_UART_TX: PinId = PinId::new('A', 9);
+
+const _: () = assert!(!PIN_MAP.claims(STREAM_UART_TX));
+
+pub type I2c1 = hal::i2c::I2c<
+    pac::I2C1,
+    (
+        gpio::Pin<
+            { PIN_MAP.i2c_scl.port },
+            { PIN_MAP.i2c_scl.number },
+            gpio::Alternate<4, gpio::OpenDrain>,
+        >,
+        gpio::Pin<
+            { PIN_MAP.i2c_sda.port },
+            { PIN_MAP.i2c_sda.number },
+            gpio::Alternate<4, gpio::OpenDrain>,
+        >,
+    ),
+>;
+
+/// Half an SCL period at the 100 kHz bus recovery clocks SDA free with.
+const RECOVERY_HALF_PERIOD_US: u32 = 5;
+
+/// I2C1 with the clocks it needs to be rebuilt after a bus recovery, see
+/// [`crate::bus_recovery`].
+pub struct I2c1Bus {
+    i2c: I2c1,
+    clocks: hal::rcc::Clocks,
+}
+
+impl I2c1Bus {
+    pub fn new(i2c: I2c1, clocks: hal::rcc::Clocks) -> Self {
+        Self { i2c, clocks }
+    }
+}
+
+impl embedded_hal::blocking::i2c::Write for I2c1Bus {
+    type Error = hal::i2c::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.write(address, bytes)
+    }
+}
+
+impl embedded_hal::blocking::i2c::WriteRead for I2c1Bus {
+    type Error = hal::i2c::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c.write_read(address, bytes, buffer)
+    }
+}
+
+impl BusRecovery for I2c1Bus {
+    type Error = hal::i2c::Error;
+
+    /// A NACK only says one device didn't answer.
+    fn is_bus_error(error: &hal::i2c::Error) -> bool {
+        matches!(
+            error,
+            hal::i2c::Error::Bus | hal::i2c::Error::ArbitrationLoss | hal::i2c::Error::Timeout
+        )
+    }
+
+    /// Hands PB8/PB9 to GPIO for the recovery, then sets I2C1 up again the
+    /// way [`Board::new`] does, which also resets the peripheral.
+    fn recover(self) -> (Self, Result<u8, BusStuck>) {
+        let (i2c1, (scl, sda)) = self.i2c.release();
+        let mut scl = scl.into_open_drain_output();
+        let mut sda = sda.into_open_drain_output();
+        let cycles = self.clocks.sysclk().raw() / 1_000_000 * RECOVERY_HALF_PERIOD_US;
+        let result =
+            bus_recovery::recover_lines(&mut scl, &mut sda, || cortex_m::asm::delay(cycles));
+
+        let scl = scl.into_alternate().internal_pull_up(true).set_open_drain();
+        let sda = sda.into_alternate().internal_pull_up(true).set_open_drain();
+        let i2c = i2c1.i2c((scl, sda), I2C_FREQ_KHZ.kHz(), &self.clocks);
+        (
+            Self {
+                i2c,
+                clocks: self.clocks,
+            },
+            result,
+        )
+    }
+}
+
+// The pin types follow PIN_MAP, so a change there that Board::new doesn't
+// follow fails to build instead of driving the wrong pins
+
+/// On-board LED, see [`LED_ACTIVE_LOW`].
+pub type Led =
+    gpio::Pin<{ PIN_MAP.led.port }, { PIN_MAP.led.number }, gpio::Output<gpio::PushPull>>;
+
+/// On-board user button, pulled up so it reads low while pressed. On the
+/// Nucleo its interrupt is EXTI15_10.
+pub type Button = gpio::Pin<{ PIN_MAP.button.port }, { PIN_MAP.button.number }, gpio::Input>;
+
+/// XSHUT of the sensor, low keeps it powered off.
+pub type SensorXshut =
+    gpio::Pin<{ PIN_MAP.sensor_xshut.port }, { PIN_MAP.sensor_xshut.number }, gpio::Output>;
+
+/// The sensor's GPIO1, pulled up as it is open drain.
+pub type SensorInterrupt =
+    gpio::Pin<{ PIN_MAP.sensor_interrupt.port }, { PIN_MAP.sensor_interrupt.number }, gpio::Input>;
+
+/// The lines of the sensor the single sensor examples drive, see
+/// [`PIN_MAP`].
+pub struct SensorPins {
+    /// Starts high, the sensor on as with the pin left floating.
+    pub xshut: SensorXshut,
+    /// Not yet an interrupt source.
+    pub interrupt: SensorInterrupt,
+}
+
+/// Pins not claimed by the board, free for more sensors' XSHUT and interrupt
+/// lines.
+pub struct Pins {
+    #[cfg(feature = "nucleo-f401re")]
+    pub pa0: gpio::gpioa::PA0,
+    pub pa1: gpio::gpioa::PA1,
+    #[cfg(not(feature = "log-uart"))]
+    pub pa2: gpio::gpioa::PA2,
+    #[cfg(not(feature = "log-uart"))]
+    pub pa3: gpio::gpioa::PA3,
+    pub pa4: gpio::gpioa::PA4,
+    #[cfg(not(feature = "nucleo-f401re"))]
+    pub pa5: gpio::gpioa::PA5,
+    pub pa6: gpio::gpioa::PA6,
+    pub pa7: gpio::gpioa::PA7,
+    #[cfg(not(feature = "nucleo-f401re"))]
+    pub pa8: gpio::gpioa::PA8,
+    pub pa9: gpio::gpioa::PA9,
+    pub pa10: gpio::gpioa::PA10,
+    pub pb0: gpio::gpiob::PB0,
+    pub pb1: gpio::gpiob::PB1,
+    pub pb2: gpio::gpiob::PB2,
+    pub pb5: gpio::gpiob::PB5,
+    #[cfg(feature = "nucleo-f401re")]
+    pub pb7: gpio::gpiob::PB7,
+    pub pb10: gpio::gpiob::PB10,
+    pub pb12: gpio::gpiob::PB12,
+    pub pb13: gpio::gpiob::PB13,
+    pub pb14: gpio::gpiob::PB14,
+    pub pb15: gpio::gpiob::PB15,
+}
+
+/// Free-running millisecond counter on TIM5, see [`now_ms`].
+pub type Clock = hal::timer::CounterMs<pac::TIM5>;
+
+/// Milliseconds since [`Board::new`], wrapping after about 49 days.
+pub fn now_ms(clock: &Clock) -> u32 {
+    clock.now().ticks()
+}
+
+/// The DWT cycle counter, counting at the system clock. It wraps every
+/// 89 s at 48 MHz, plenty for timeouts.
+#[derive(Clone, Copy, Debug)]
+pub struct CycleClock {
+    ticks_per_ms: u32,
+}
+
+impl TimeSource for CycleClock {
+    fn ticks(&self) -> u32 {
+        cortex_m::peripheral::DWT::cycle_count()
+    }
+
+    fn ticks_per_ms(&self) -> u32 {
+        self.ticks_per_ms
+    }
+}
+
+impl TimeSource for Clock {
+    fn ticks(&self) -> u32 {
+        now_ms(self)
+    }
+
+    fn ticks_per_ms(&self) -> u32 {
+        1
+    }
+}
+
+pub struct Board {
+    pub clocks: hal::rcc::Clocks,
+    pub delay: hal::timer::SysDelay,
+    /// Timestamps for examples that need them, TIM5 being 32 bits wide.
+    pub clock: Clock,
+    /// For [`crate::timeout`], leaves every timer free.
+    pub cycles: CycleClock,
+    pub i2c: I2c1,
+    pub led: Led,
+    pub button: Button,
+    pub sensor: SensorPins,
+    pub exti: pac::EXTI,
+    pub syscfg: hal::syscfg::SysCfg,
+    /// Left free for periodic tasks, e.g. polling the button while a press
+    /// is in progress.
+    pub tim2: pac::TIM2,
+    /// Left unconfigured for the binary stream, see [`STREAM_UART_TX`].
+    pub usart1: pac::USART1,
+    /// For [`crate::storage::InternalFlash`].
+    pub flash: pac::FLASH,
+    /// Input for the command shell, see [`CONSOLE_RX`].
+    #[cfg(feature = "log-uart")]
+    pub console_rx: hal::serial::Rx<pac::USART2>,
+    pub pins: Pins,
+}
+
+impl Board {
+    /// Takes the device and core peripherals and sets up the board.
+    /// Returns `None` if either has already been taken.
+    pub fn take() -> Option<Self> {
+        match (
+            pac::Peripherals::take(),
+            cortex_m::peripheral::Peripherals::take(),
+        ) {
+            (Some(dp), Some(cp)) => Some(Self::new(dp, cp)),
+            _ => None,
+        }
+    }
+
+    /// Sets up the clocks, I2C1, LED (off), button, sensor pins (sensor on),
+    /// delay, millisecond clock, cycle counter and EXTI/SYSCFG, and starts the
+    /// log backend.
+    pub fn new(dp: pac::Peripherals, cp: cortex_m::peripheral::Peripherals) -> Self {
+        let rcc = dp.RCC.constrain();
+        let clocks = rcc.cfgr.sysclk(SYSCLK_MHZ.MHz()).freeze();
+
+        // Create a delay abstraction based on SysTick
+        let delay = cp.SYST.delay(&clocks);
+
+        let mut clock = dp.TIM5.counter_ms(&clocks);
+        // Count up to the top of the 32 bit range before wrapping
+        clock.start(u32::MAX.millis()).unwrap();
+
+        let (mut dcb, mut dwt) = (cp.DCB, cp.DWT);
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+        let cycles = CycleClock {
+            ticks_per_ms: clocks.sysclk().raw() / 1000,
+        };
+
+        let gpioa = dp.GPIOA.split();
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+
+        #[cfg(not(feature = "nucleo-f401re"))]
+        let (mut led, button, xshut) = (
+            gpioc.pc13.into_push_pull_output(),
+            gpioa.pa0.into_pull_up_input(),
+            gpiob
+                .pb7
+                .into_push_pull_output_in_state(gpio::PinState::High),
+        );
+        #[cfg(feature = "nucleo-f401re")]
+        let (mut led, button, xshut) = (
+            gpioa.pa5.into_push_pull_output(),
+            gpioc.pc13.into_pull_up_input(),
+            gpioa
+                .pa8
+                .into_push_pull_output_in_state(gpio::PinState::High),
+        );
+        led.off();
+
+        let sensor = SensorPins {
+            xshut,
+            interrupt: gpiob.pb6.into_pull_up_input(),
+        };
+
+        let scl = gpiob
+            .pb8
+            .into_alternate()
+            .internal_pull_up(true)
+            .set_open_drain();
+        let sda = gpiob
+            .pb9
+            .into_alternate()
+            .internal_pull_up(true)
+            .set_open_drain();
+        let i2c = dp.I2C1.i2c((scl, sda), I2C_FREQ_KHZ.kHz(), &clocks);
+
+        #[cfg(feature = "log-rtt")]
+        crate::log::init();
+        // The log takes TX, RX is left for the command shell
+        #[cfg(feature = "log-uart")]
+        let console_rx = {
+            let (tx, rx) = dp
+                .USART2
+                .serial(
+                    (gpioa.pa2.into_alternate(), gpioa.pa3.into_alternate()),
+                    crate::log::UART_BAUD.bps(),
+                    &clocks,
+                )
+                .unwrap()
+                .split();
+            crate::log::init(tx);
+            rx
+        };
+
+        let pins = Pins {
+            #[cfg(feature = "nucleo-f401re")]
+            pa0: gpioa.pa0,
+            pa1: gpioa.pa1,
+            #[cfg(not(feature = "log-uart"))]
+            pa2: gpioa.pa2,
+            #[cfg(not(feature = "log-uart"))]
+            pa3: gpioa.pa3,
+            pa4: gpioa.pa4,
+            #[cfg(not(feature = "nucleo-f401re"))]
+            pa5: gpioa.pa5,
+            pa6: gpioa.pa6,
+            pa7: gpioa.pa7,
+            #[cfg(not(feature = "nucleo-f401re"))]
+            pa8: gpioa.pa8,
+            pa9: gpioa.pa9,
+            pa10: gpioa.pa10,
+            pb0: gpiob.pb0,
+            pb1: gpiob.pb1,
+            pb2: gpiob.pb2,
+            pb5: gpiob.pb5,
+            #[cfg(feature = "nucleo-f401re")]
+            pb7: gpiob.pb7,
+            pb10: gpiob.pb10,
+            pb12: gpiob.pb12,
+            pb13: gpiob.pb13,
+            pb14: gpiob.pb14,
+            pb15: gpiob.pb15,
+        };
+
+        Self {
+            clocks,
+            delay,
+            clock,
+            cycles,
+            i2c,
+            led,
+            button,
+            sensor,
+            exti: dp.EXTI,
+            syscfg: dp.SYSCFG.constrain(),
+            tim2: dp.TIM2,
+            usart1: dp.USART1,
+            flash: dp.FLASH,
+            #[cfg(feature = "log-uart")]
+            console_rx,
+            pins,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! I2C bus sharing that cooperates with RTIC's priority ceiling locking.
+//!
+//! `shared_bus::AtomicCheckMutex` panics as soon as a higher priority task
+//! touches the bus while a lower priority one is using it. [`CeilingMutex`]
+//! instead does what an RTIC resource lock does: for the duration of each bus
+//! transaction it raises BASEPRI to the bus ceiling, so every task at or below
+//! the ceiling waits its turn. Sensors on the bus can then be separate RTIC
+//! resources used from tasks at different priorities.
+//!
+//! The ceiling must be at least the highest priority of any task that uses a
+//! proxy, just like an RTIC resource ceiling. Tasks above the ceiling are not
+//! held off and must not touch the bus.
+
+use core::cell::RefCell;
+
+use stm32f4xx_hal::pac::NVIC_PRIO_BITS;
+
+/// Bus mutex locking at RTIC logical priority `CEILING`, which like any RTIC
+/// priority is between 1 and `2^NVIC_PRIO_BITS`.
+pub struct CeilingMutex<T, const CEILING: u8> {
+    bus: RefCell<T>,
+}
+
+impl<T, const CEILING: u8> CeilingMutex<T, CEILING> {
+    /// Evaluated by [`create`](shared_bus::BusMutex::create), failing the
+    /// build for a ceiling no task can have instead of masking the wrong
+    /// priorities.
+    const VALID_CEILING: () = assert!(
+        CEILING >= 1 && CEILING <= 1 << NVIC_PRIO_BITS,
+        "bus ceiling must be between 1 and 2^NVIC_PRIO_BITS"
+    );
+}
+
+/// Proxy handed to each driver sharing the bus.
+pub type I2cProxy<I2C, const CEILING: u8> =
+    shared_bus::I2cProxy<'static, CeilingMutex<I2C, CEILING>>;
+
+pub type BusManager<I2C, const CEILING: u8> = shared_bus::BusManager<CeilingMutex<I2C, CEILING>>;
+
+// Sound on a single core: every borrow happens with BASEPRI at the ceiling,
+// so no task that may access the bus can preempt it. The host build masks
+// nothing, so there the mutex stays a plain `RefCell`, not shared between
+// threads.
+#[cfg(target_arch = "arm")]
+unsafe impl<T: Send, const CEILING: u8> Sync for CeilingMutex<T, CEILING> {}
+
+impl<T, const CEILING: u8> shared_bus::BusMutex for CeilingMutex<T, CEILING> {
+    type Bus = T;
+
+    fn create(v: T) -> Self {
+        let () = Self::VALID_CEILING;
+        Self {
+            bus: RefCell::new(v),
+        }
+    }
+
+    fn lock<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        with_ceiling(CEILING, || f(&mut self.bus.borrow_mut()))
+    }
+}
+
+#[cfg(target_arch = "arm")]
+fn with_ceiling<R>(ceiling: u8, f: impl FnOnce() -> R) -> R {
+    use cortex_m::register::{basepri, basepri_max};
+
+    let hw = ((1 << NVIC_PRIO_BITS) - ceiling) << (8 - NVIC_PRIO_BITS);
+    let previous = basepri::read();
+    // `basepri_max` only ever raises the masking level, so taking the lock
+    // from inside a higher ceiling keeps the higher one.
+    basepri_max::write(hw);
+    let r = f();
+    unsafe { basepri::write(previous) };
+    r
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn with_ceiling<R>(_ceiling: u8, f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Creates a `'static` bus manager with a [`CeilingMutex`] at `CEILING`.
+///
+/// ```ignore
+/// let bus_manager = new_ceiling_bus!(board::I2c1, 2, i2c).unwrap();
+/// ```
+///
+/// Returns `None` if called more than once from the same call site, without
+/// evaluating the bus expression.
+#[macro_export]
+macro_rules! new_ceiling_bus {
+    ($bus_type:ty, $ceiling:expr, $bus:expr) => {{
+        type Manager = $crate::bus::BusManager<$bus_type, { $ceiling }>;
+        static TAKEN: ::core::sync::atomic::AtomicBool =
+            ::core::sync::atomic::AtomicBool::new(false);
+        static mut MANAGER: ::core::mem::MaybeUninit<Manager> = ::core::mem::MaybeUninit::uninit();
+        if TAKEN.swap(true, ::core::sync::atomic::Ordering::AcqRel) {
+            None
+        } else {
+            let manager = Manager::new($bus);
+            // Only the first call gets here, so nothing else refers to `MANAGER`
+            let manager: &'static Manager =
+                unsafe { (*::core::ptr::addr_of_mut!(MANAGER)).write(manager) };
+            Some(manager)
+        }
+    }};
+}
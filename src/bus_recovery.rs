@@ -0,0 +1,170 @@
+//! Recovery of an I2C bus a device holds SDA low on.
+//!
+//! A VL6180X reset in the middle of a read can be left waiting for the clocks
+//! to finish the byte it was sending, holding SDA low. The MCU's I2C
+//! peripheral then sees a busy bus and every transfer fails, whatever it is
+//! addressed to. Clocking SCL by hand until the device lets go of SDA, at
+//! most [`MAX_PULSES`] times (8 data bits and an ACK), then sending a STOP
+//! gets the bus back to idle.
+//!
+//! [`recover_lines`] does that on two GPIOs. [`RecoveringBus`] wraps a bus
+//! and runs a recovery through [`BusRecovery`] by itself once it sees
+//! [`RecoveringBus::new`]'s number of bus errors in a row.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+/// Clocks it takes a device to finish any byte it is sending.
+pub const MAX_PULSES: u8 = 9;
+
+/// SDA was still low after [`MAX_PULSES`] clocks and a STOP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusStuck;
+
+/// Frees a bus through its two lines, both open drain outputs that can be
+/// read back. `half_period` waits half an SCL period, e.g. 5 µs for 100 kHz.
+///
+/// Returns how many clock pulses it took for SDA to be released.
+pub fn recover_lines<SCL, SDA>(
+    scl: &mut SCL,
+    sda: &mut SDA,
+    mut half_period: impl FnMut(),
+) -> Result<u8, BusStuck>
+where
+    SCL: OutputPin + InputPin,
+    SDA: OutputPin + InputPin,
+{
+    let _ = sda.set_high();
+    let _ = scl.set_high();
+    half_period();
+
+    let mut pulses = 0;
+    while sda.is_low().unwrap_or(true) && pulses < MAX_PULSES {
+        let _ = scl.set_low();
+        half_period();
+        let _ = scl.set_high();
+        half_period();
+        pulses += 1;
+    }
+
+    // STOP: SDA goes high while SCL is high
+    let _ = scl.set_low();
+    half_period();
+    let _ = sda.set_low();
+    half_period();
+    let _ = scl.set_high();
+    half_period();
+    let _ = sda.set_high();
+    half_period();
+
+    if sda.is_high().unwrap_or(false) {
+        Ok(pulses)
+    } else {
+        Err(BusStuck)
+    }
+}
+
+/// A bus that can be taken apart to run [`recover_lines`] on its pins and
+/// put back together.
+pub trait BusRecovery: Sized {
+    type Error;
+
+    /// True for errors that say the bus itself is in trouble, rather than
+    /// one device not answering.
+    fn is_bus_error(error: &Self::Error) -> bool;
+
+    /// Frees the bus and sets the peripheral up again.
+    fn recover(self) -> (Self, Result<u8, BusStuck>);
+}
+
+/// A bus that recovers itself after repeated bus errors. The transfer that
+/// failed still returns its error; the next one gets the recovered bus.
+pub struct RecoveringBus<I2C> {
+    /// Only `None` while a recovery is running.
+    i2c: Option<I2C>,
+    error_limit: u8,
+    errors: u8,
+    recoveries: u32,
+    last_recovery: Option<Result<u8, BusStuck>>,
+}
+
+impl<I2C: BusRecovery> RecoveringBus<I2C> {
+    /// Recovers `i2c` after `error_limit` bus errors in a row, at least 1.
+    pub fn new(i2c: I2C, error_limit: u8) -> Self {
+        Self {
+            i2c: Some(i2c),
+            error_limit: error_limit.max(1),
+            errors: 0,
+            recoveries: 0,
+            last_recovery: None,
+        }
+    }
+
+    /// Recoveries run so far.
+    pub fn recoveries(&self) -> u32 {
+        self.recoveries
+    }
+
+    /// How the latest recovery went, if there was one.
+    pub fn last_recovery(&self) -> Option<Result<u8, BusStuck>> {
+        self.last_recovery
+    }
+
+    /// Recovers right away, e.g. at boot in case a device was reset mid-transfer.
+    pub fn recover(&mut self) -> Result<u8, BusStuck> {
+        let i2c = self.i2c.take().expect("bus");
+        let (i2c, result) = i2c.recover();
+        self.i2c = Some(i2c);
+        self.errors = 0;
+        self.recoveries += 1;
+        self.last_recovery = Some(result);
+        result
+    }
+
+    pub fn free(self) -> I2C {
+        self.i2c.expect("bus")
+    }
+
+    fn bus(&mut self) -> &mut I2C {
+        self.i2c.as_mut().expect("bus")
+    }
+
+    fn track<T>(&mut self, result: Result<T, I2C::Error>) -> Result<T, I2C::Error> {
+        match &result {
+            Ok(_) => self.errors = 0,
+            Err(e) if I2C::is_bus_error(e) => {
+                self.errors += 1;
+                if self.errors >= self.error_limit {
+                    let _ = self.recover();
+                }
+            }
+            // A device not answering says nothing about the bus
+            Err(_) => (),
+        }
+        result
+    }
+}
+
+impl<I2C, E> Write for RecoveringBus<I2C>
+where
+    I2C: Write<Error = E> + BusRecovery<Error = E>,
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        let result = self.bus().write(address, bytes);
+        self.track(result)
+    }
+}
+
+impl<I2C, E> WriteRead for RecoveringBus<I2C>
+where
+    I2C: WriteRead<Error = E> + BusRecovery<Error = E>,
+{
+    type Error = E;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), E> {
+        let result = self.bus().write_read(address, bytes, buffer);
+        self.track(result)
+    }
+}
@@ -0,0 +1,177 @@
+//! Debounced push button with short, long and double press events.
+//!
+//! A mechanical button bounces for a few milliseconds on every press and
+//! release, so a bare edge check on the pin sees several presses for one. The
+//! [`DebouncedButton`] only takes a new level once the pin has read the same
+//! for [`ButtonConfig::debounce_ms`], and turns the debounced presses into
+//! [`ButtonEvent`]s:
+//!
+//! ```text
+//! Idle --press--> Down --held long_press_ms--> Held (LongPress)
+//!  ^               |                            |
+//!  |            release                      release
+//!  |               v                            |
+//!  +--double_press_ms over-- Up <---------------+--> Idle
+//!       (ShortPress)         |
+//!                          press --> Held (DoublePress)
+//! ```
+//!
+//! A short press is only reported once the double press window is over, so
+//! the first half of a double press never shows up as one.
+//!
+//! Feed it the pin level either from a periodic tick, every few milliseconds,
+//! or from an EXTI on both edges plus a tick while it isn't
+//! [settled](DebouncedButton::is_settled).
+
+/// Timings of a [`DebouncedButton`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonConfig {
+    /// How long the pin has to read the same before the level is taken.
+    pub debounce_ms: u32,
+    /// How long a press has to be held to be a long one.
+    pub long_press_ms: u32,
+    /// How soon after a release a second press makes a double press.
+    pub double_press_ms: u32,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce_ms: 20,
+            long_press_ms: 1000,
+            double_press_ms: 300,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    /// Pressed and released, with no second press following.
+    ShortPress,
+    /// Held for [`ButtonConfig::long_press_ms`], reported while still held.
+    LongPress,
+    /// Pressed again within [`ButtonConfig::double_press_ms`] of a short
+    /// press, reported on the second press.
+    DoublePress,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    /// Pressed, not for long enough to be a long press yet.
+    Down {
+        since_ms: u32,
+    },
+    /// Released after a short press, waiting to see if a second one follows.
+    Up {
+        since_ms: u32,
+    },
+    /// Already reported, waiting for the release.
+    Held,
+}
+
+/// Turns raw pin levels into [`ButtonEvent`]s, see the module docs.
+#[derive(Clone, Debug)]
+pub struct DebouncedButton {
+    config: ButtonConfig,
+    /// The level at the last update, and since when.
+    raw: bool,
+    raw_since_ms: u32,
+    pressed: bool,
+    phase: Phase,
+}
+
+impl DebouncedButton {
+    /// Starts out released.
+    pub fn new(config: ButtonConfig) -> Self {
+        Self {
+            config,
+            raw: false,
+            raw_since_ms: 0,
+            pressed: false,
+            phase: Phase::Idle,
+        }
+    }
+
+    pub fn config(&self) -> &ButtonConfig {
+        &self.config
+    }
+
+    /// The debounced level.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// True if nothing is waiting on time passing: until the pin changes,
+    /// updates have nothing to do.
+    pub fn is_settled(&self) -> bool {
+        self.raw == self.pressed && matches!(self.phase, Phase::Idle | Phase::Held)
+    }
+
+    /// Takes the pin level, true while pressed, at `now_ms` on any
+    /// millisecond clock (it may wrap). `on_event` gets every event in order;
+    /// updates far enough apart can give two in one.
+    ///
+    /// Edges are timed from the update that first saw the new level, so
+    /// press lengths are only as precise as the updates are frequent.
+    pub fn update(&mut self, pressed: bool, now_ms: u32, mut on_event: impl FnMut(ButtonEvent)) {
+        if pressed != self.raw {
+            // The level it leaves may have been stable for long enough, if
+            // nothing looked in between
+            self.settle(now_ms, &mut on_event);
+            self.raw = pressed;
+            self.raw_since_ms = now_ms;
+        }
+        self.settle(now_ms, &mut on_event);
+        self.expire(now_ms, &mut on_event);
+    }
+
+    /// Back to released and idle without any events.
+    pub fn reset(&mut self) {
+        self.raw = false;
+        self.pressed = false;
+        self.phase = Phase::Idle;
+    }
+
+    /// Takes the raw level if it has been stable for the debounce time.
+    fn settle(&mut self, now_ms: u32, on_event: &mut impl FnMut(ButtonEvent)) {
+        if self.raw == self.pressed
+            || now_ms.wrapping_sub(self.raw_since_ms) < self.config.debounce_ms
+        {
+            return;
+        }
+        let at_ms = self.raw_since_ms;
+        // Anything that ran out before the edge goes first
+        self.expire(at_ms, on_event);
+        self.pressed = self.raw;
+
+        self.phase = match (self.phase, self.pressed) {
+            (Phase::Idle, true) => Phase::Down { since_ms: at_ms },
+            (Phase::Up { .. }, true) => {
+                on_event(ButtonEvent::DoublePress);
+                Phase::Held
+            }
+            (Phase::Down { .. }, false) => Phase::Up { since_ms: at_ms },
+            (Phase::Held, false) => Phase::Idle,
+            (phase, _) => phase,
+        };
+    }
+
+    fn expire(&mut self, now_ms: u32, on_event: &mut impl FnMut(ButtonEvent)) {
+        match self.phase {
+            Phase::Down { since_ms }
+                if now_ms.wrapping_sub(since_ms) >= self.config.long_press_ms =>
+            {
+                on_event(ButtonEvent::LongPress);
+                self.phase = Phase::Held;
+            }
+            Phase::Up { since_ms }
+                if now_ms.wrapping_sub(since_ms) >= self.config.double_press_ms =>
+            {
+                on_event(ButtonEvent::ShortPress);
+                self.phase = Phase::Idle;
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Part-to-part range offset and cover glass crosstalk calibration.
+//!
+//! Every VL6180X reads a few millimetres off. With the offset register at 0
+//! and a target at a known distance (ST recommends a white target at 50 mm),
+//! the mean of many single shot readings gives the offset that brings the
+//! part back in line: `offset = target - mean`.
+//!
+//! A cover window reflects some of the emitted light straight back, which
+//! pulls readings short. With compensation off and a dark target at a known
+//! distance (ST suggests 100 mm), the share of the return signal that came
+//! from the glass is `rate * (1 - mean / target)`; writing that rate to the
+//! sensor makes it subtract the glass's contribution.
+//!
+//! The sensor keeps both values until it is reset, so they have to be
+//! written again after each power-up, e.g. from the stored
+//! [`Calibration`](crate::storage::Calibration).
+
+use vl6180x_shell::RangeStats;
+
+/// Readings averaged for one offset calibration.
+pub const OFFSET_SAMPLES: u16 = 32;
+
+/// Readings averaged for one crosstalk calibration, and for the statistics
+/// before and after it.
+pub const CROSSTALK_SAMPLES: u16 = 32;
+
+/// What a calibration measured and the offset it came up with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetCalibration {
+    pub target_mm: u16,
+    /// Mean of the readings, rounded to the nearest millimetre.
+    pub measured_mm: u16,
+    /// Value for `SYSRANGE__PART_TO_PART_RANGE_OFFSET`.
+    pub offset_mm: i8,
+}
+
+/// A range reading and the return signal rate it came with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeSample {
+    pub range_mm: u16,
+    /// In Mcps as 9.7 fixed point, as in `RESULT__RANGE_RETURN_RATE`.
+    pub return_rate: u16,
+}
+
+/// What a crosstalk calibration measured and the rate it came up with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrosstalkCalibration {
+    pub target_mm: u16,
+    /// The readings with compensation off.
+    pub before: RangeStats,
+    /// Value for `SYSRANGE__CROSSTALK_COMPENSATION_RATE`, in Mcps as 9.7
+    /// fixed point.
+    pub rate: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CalibrationError<E> {
+    /// A reading failed.
+    Sensor(E),
+    /// No readings were asked for.
+    NoSamples,
+    /// The mean reading is too far from the target for the offset register,
+    /// most likely the target isn't where it should be.
+    OffsetOutOfRange { measured_mm: u16 },
+}
+
+/// Offset that turns a reading of `measured_mm` into `target_mm`, `None` if
+/// it doesn't fit the signed 8 bit register.
+pub fn offset_for(target_mm: u16, measured_mm: u16) -> Option<i8> {
+    i8::try_from(target_mm as i32 - measured_mm as i32).ok()
+}
+
+/// Mean of `samples` readings from `measure`, rounded to the nearest value.
+pub fn mean<E>(
+    samples: u16,
+    mut measure: impl FnMut() -> Result<u16, E>,
+) -> Result<u16, CalibrationError<E>> {
+    if samples == 0 {
+        return Err(CalibrationError::NoSamples);
+    }
+    let mut sum = 0u32;
+    for _ in 0..samples {
+        sum += measure().map_err(CalibrationError::Sensor)? as u32;
+    }
+    Ok(((sum + samples as u32 / 2) / samples as u32) as u16)
+}
+
+/// Averages `samples` readings from `measure` against a target `target_mm`
+/// away and works out the offset.
+///
+/// `measure` is typically `|| sensor.poll_range_mm_single_blocking()` and
+/// must read with the offset register at 0. Writing the result to the
+/// sensor is left to the caller.
+pub fn calibrate_offset<E>(
+    target_mm: u16,
+    samples: u16,
+    measure: impl FnMut() -> Result<u16, E>,
+) -> Result<OffsetCalibration, CalibrationError<E>> {
+    let measured_mm = mean(samples, measure)?;
+    let offset_mm = offset_for(target_mm, measured_mm)
+        .ok_or(CalibrationError::OffsetOutOfRange { measured_mm })?;
+    Ok(OffsetCalibration {
+        target_mm,
+        measured_mm,
+        offset_mm,
+    })
+}
+
+/// Mean, minimum and maximum of `samples` readings from `measure`, means
+/// rounded to the nearest value.
+pub fn range_stats<E>(
+    samples: u16,
+    mut measure: impl FnMut() -> Result<RangeSample, E>,
+) -> Result<RangeStats, CalibrationError<E>> {
+    if samples == 0 {
+        return Err(CalibrationError::NoSamples);
+    }
+    let mut stats = RangeStats {
+        min_mm: u16::MAX,
+        ..RangeStats::default()
+    };
+    let (mut range_sum, mut rate_sum) = (0u32, 0u32);
+    for _ in 0..samples {
+        let sample = measure().map_err(CalibrationError::Sensor)?;
+        stats.min_mm = stats.min_mm.min(sample.range_mm);
+        stats.max_mm = stats.max_mm.max(sample.range_mm);
+        range_sum += sample.range_mm as u32;
+        rate_sum += sample.return_rate as u32;
+    }
+    let half = samples as u32 / 2;
+    stats.mean_mm = ((range_sum + half) / samples as u32) as u16;
+    stats.mean_rate = ((rate_sum + half) / samples as u32) as u16;
+    Ok(stats)
+}
+
+/// Crosstalk rate that explains reading `range_mm` with a return rate of
+/// `return_rate` against a target `target_mm` away. 0 if the reading isn't
+/// short, i.e. there is nothing to compensate.
+pub fn crosstalk_for(target_mm: u16, range_mm: u16, return_rate: u16) -> u16 {
+    if target_mm == 0 || range_mm >= target_mm {
+        return 0;
+    }
+    let short_mm = (target_mm - range_mm) as u32;
+    ((return_rate as u32 * short_mm + target_mm as u32 / 2) / target_mm as u32) as u16
+}
+
+/// Measures `samples` readings from `measure` against a dark target
+/// `target_mm` away and works out the crosstalk compensation rate.
+///
+/// `measure` must read with the compensation rate at 0. Writing the result
+/// to the sensor, and measuring again to see the effect, is left to the
+/// caller.
+pub fn calibrate_crosstalk<E>(
+    target_mm: u16,
+    samples: u16,
+    measure: impl FnMut() -> Result<RangeSample, E>,
+) -> Result<CrosstalkCalibration, CalibrationError<E>> {
+    let before = range_stats(samples, measure)?;
+    Ok(CrosstalkCalibration {
+        target_mm,
+        before,
+        rate: crosstalk_for(target_mm, before.mean_mm, before.mean_rate),
+    })
+}
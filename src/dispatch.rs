@@ -0,0 +1,137 @@
+//! Routes EXTI interrupts to the sensor that raised them.
+//!
+//! On the STM32F4 EXTI lines 0 to 4 each have their own vector, while lines
+//! 5-9 and 10-15 share `EXTI9_5` and `EXTI15_10`. A [`Dispatcher`] holds a
+//! table of which sensor drives which line, so one task per vector can service
+//! every sensor wired to it and adding a sensor is a new table entry.
+
+use crate::handlers::{self, Clear, Handled, InterruptPin, InterruptSensor, StatusLed};
+
+/// NVIC vectors the EXTI lines are grouped into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtiVector {
+    Exti0,
+    Exti1,
+    Exti2,
+    Exti3,
+    Exti4,
+    Exti9_5,
+    Exti15_10,
+}
+
+impl ExtiVector {
+    /// The vector EXTI `line` is delivered on, `None` for lines above 15.
+    pub const fn for_line(line: u8) -> Option<Self> {
+        match line {
+            0 => Some(Self::Exti0),
+            1 => Some(Self::Exti1),
+            2 => Some(Self::Exti2),
+            3 => Some(Self::Exti3),
+            4 => Some(Self::Exti4),
+            5..=9 => Some(Self::Exti9_5),
+            10..=15 => Some(Self::Exti15_10),
+            _ => None,
+        }
+    }
+}
+
+/// Sensor `sensor` signals its interrupt on EXTI `line`, which is the pin
+/// number of its interrupt pin (e.g. 6 for PB6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub line: u8,
+    pub sensor: usize,
+}
+
+impl Route {
+    pub const fn new(line: u8, sensor: usize) -> Self {
+        Self { line, sensor }
+    }
+}
+
+pub struct Dispatcher<const N: usize> {
+    routes: [Route; N],
+}
+
+impl<const N: usize> Dispatcher<N> {
+    /// Builds the table. Panics (at compile time when used in a `const`) if a
+    /// line is above 15 or routed twice, since only one pin can drive a line.
+    pub const fn new(routes: [Route; N]) -> Self {
+        let mut i = 0;
+        while i < N {
+            assert!(routes[i].line <= 15, "EXTI line out of range");
+            let mut j = i + 1;
+            while j < N {
+                assert!(routes[i].line != routes[j].line, "EXTI line routed twice");
+                j += 1;
+            }
+            i += 1;
+        }
+        Self { routes }
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Services every sensor routed to `vector` whose interrupt pin is pending:
+    /// reads its range, clears the MCU pending bit and all of the sensor's
+    /// interrupts, then reports the result to `on_reading` with the sensor index.
+    ///
+    /// Returns how many sensors were serviced.
+    pub fn dispatch<MODE, I2C, X, P, L>(
+        &self,
+        vector: ExtiVector,
+        sensors: &mut [vl6180x::VL6180XwPins<MODE, I2C, X, P>],
+        led: &mut L,
+        mut on_reading: impl FnMut(usize, Handled<u16, SensorError<MODE, I2C>>),
+    ) -> usize
+    where
+        vl6180x::VL6180X<MODE, I2C>: InterruptSensor,
+        P: InterruptPin,
+        L: StatusLed,
+    {
+        let mut serviced = 0;
+        for (index, sensor) in sensors.iter_mut().enumerate() {
+            if self.dispatch_one(vector, index, sensor, led, &mut on_reading) {
+                serviced += 1;
+            }
+        }
+        serviced
+    }
+
+    /// Like [`dispatch`](Dispatcher::dispatch) for a single sensor, for when
+    /// each sensor is its own resource rather than an element of one slice.
+    ///
+    /// Returns true if the sensor was routed to `vector` and pending.
+    pub fn dispatch_one<MODE, I2C, X, P, L>(
+        &self,
+        vector: ExtiVector,
+        index: usize,
+        sensor: &mut vl6180x::VL6180XwPins<MODE, I2C, X, P>,
+        led: &mut L,
+        mut on_reading: impl FnMut(usize, Handled<u16, SensorError<MODE, I2C>>),
+    ) -> bool
+    where
+        vl6180x::VL6180X<MODE, I2C>: InterruptSensor,
+        P: InterruptPin,
+        L: StatusLed,
+    {
+        let routed = self.routes.iter().any(|route| {
+            route.sensor == index && ExtiVector::for_line(route.line) == Some(vector)
+        });
+        if !routed || !sensor.interrupt_pin.check_interrupt() {
+            return false;
+        }
+        let handled = handlers::range_interrupt(
+            &mut sensor.vl6180x,
+            &mut sensor.interrupt_pin,
+            led,
+            Clear::All,
+        );
+        on_reading(index, handled);
+        true
+    }
+}
+
+type SensorError<MODE, I2C> = <vl6180x::VL6180X<MODE, I2C> as InterruptSensor>::Error;
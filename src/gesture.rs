@@ -0,0 +1,179 @@
+//! Swipe, tap and hover gestures from two range sensors side by side.
+//!
+//! Each sensor's readings only say whether it is covered, i.e. something is
+//! closer than [`GestureConfig::near_mm`]. A stroke starts when either
+//! sensor is covered and ends when both are clear again; what it was comes
+//! from when each sensor was first covered:
+//!
+//! - one sensor well before the other, all within
+//!   [`swipe_max_ms`](GestureConfig::swipe_max_ms): a swipe from that side;
+//! - both at about the same time, or just one, and clear again within
+//!   [`tap_max_ms`](GestureConfig::tap_max_ms): a tap;
+//! - still covered after [`hover_ms`](GestureConfig::hover_ms): a hover,
+//!   reported once while the stroke goes on. Nothing else is reported for it.
+//!
+//! Strokes shorter than [`min_ms`](GestureConfig::min_ms), e.g. one noisy
+//! reading, and slow ones that fit none of the above are dropped.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub const fn index(self) -> usize {
+        match self {
+            Side::Left => 0,
+            Side::Right => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    SwipeLeftToRight,
+    SwipeRightToLeft,
+    Tap,
+    /// Held over the sensors, `range_mm` away from the closer one.
+    Hover {
+        range_mm: u16,
+    },
+}
+
+/// Thresholds of a [`GestureRecogniser`], times in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// A reading closer than this covers its sensor.
+    pub near_mm: u16,
+    /// Shortest stroke that counts at all.
+    pub min_ms: u32,
+    /// Sensors first covered at most this far apart were covered together;
+    /// further apart it is a swipe.
+    pub sync_ms: u32,
+    /// Longest a tap may take from the first cover to both sensors clear.
+    pub tap_max_ms: u32,
+    /// Longest a swipe may take from the first cover to both sensors clear.
+    pub swipe_max_ms: u32,
+    /// Covered this long is a hover.
+    pub hover_ms: u32,
+}
+
+impl Default for GestureConfig {
+    /// Tuned for a hand 2-15 cm over sensors about 3 cm apart, each ranging
+    /// every 30 ms or faster.
+    fn default() -> Self {
+        Self {
+            near_mm: 150,
+            min_ms: 40,
+            sync_ms: 40,
+            tap_max_ms: 400,
+            swipe_max_ms: 700,
+            hover_ms: 800,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Stroke {
+    start_ms: u32,
+    /// When each side was first covered.
+    covered_at: [Option<u32>; 2],
+    hovered: bool,
+}
+
+/// Turns the readings of a left and a right sensor into [`Gesture`]s, see
+/// the module docs.
+#[derive(Clone, Debug)]
+pub struct GestureRecogniser {
+    config: GestureConfig,
+    /// Latest reading of each side, if it was near.
+    near: [Option<u16>; 2],
+    stroke: Option<Stroke>,
+}
+
+impl GestureRecogniser {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            near: [None; 2],
+            stroke: None,
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Takes a reading from `side`, `None` if nothing is in range, at
+    /// `now_ms` on a millisecond clock shared by both sides (it may wrap).
+    /// Returns the gesture it completes, if any.
+    pub fn update(&mut self, side: Side, range_mm: Option<u16>, now_ms: u32) -> Option<Gesture> {
+        let near = range_mm.filter(|&mm| mm < self.config.near_mm);
+        self.near[side.index()] = near;
+
+        let stroke = match (&mut self.stroke, near) {
+            (Some(stroke), _) => stroke,
+            (None, Some(_)) => self.stroke.insert(Stroke {
+                start_ms: now_ms,
+                covered_at: [None; 2],
+                hovered: false,
+            }),
+            (None, None) => return None,
+        };
+        let covered_at = &mut stroke.covered_at[side.index()];
+        if near.is_some() && covered_at.is_none() {
+            *covered_at = Some(now_ms);
+        }
+
+        let closest = self.near.iter().flatten().min().copied();
+        match closest {
+            None => {
+                let stroke = *stroke;
+                self.stroke = None;
+                self.classify(&stroke, now_ms)
+            }
+            Some(range_mm) => {
+                let held_ms = now_ms.wrapping_sub(stroke.start_ms);
+                if !stroke.hovered && held_ms >= self.config.hover_ms {
+                    stroke.hovered = true;
+                    Some(Gesture::Hover { range_mm })
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Drops any stroke in progress.
+    pub fn reset(&mut self) {
+        self.near = [None; 2];
+        self.stroke = None;
+    }
+
+    /// What a stroke that ended at `end_ms` was.
+    fn classify(&self, stroke: &Stroke, end_ms: u32) -> Option<Gesture> {
+        let config = &self.config;
+        let duration_ms = end_ms.wrapping_sub(stroke.start_ms);
+        if stroke.hovered || duration_ms < config.min_ms {
+            return None;
+        }
+        let tap = (duration_ms <= config.tap_max_ms).then_some(Gesture::Tap);
+        match stroke.covered_at {
+            [Some(left), Some(right)] => {
+                // Signed, so that wrapping between the two still compares right
+                let gap_ms = right.wrapping_sub(left) as i32;
+                if gap_ms.unsigned_abs() <= config.sync_ms {
+                    tap
+                } else if duration_ms > config.swipe_max_ms {
+                    None
+                } else if gap_ms > 0 {
+                    Some(Gesture::SwipeLeftToRight)
+                } else {
+                    Some(Gesture::SwipeRightToLeft)
+                }
+            }
+            _ => tap,
+        }
+    }
+}
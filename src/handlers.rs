@@ -0,0 +1,242 @@
+//! Bodies of the EXTI interrupt tasks used by the interrupt examples.
+//!
+//! The RTIC tasks only lock their resources and call one of these functions,
+//! which keeps the sequencing (LED, read, clear the MCU pending bit, clear the
+//! sensor interrupt) in plain code that also runs against the simulator.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use stm32f4xx_hal::gpio::ExtiPin;
+
+use crate::board;
+use crate::registers::{als_int_status, range_int_status};
+
+/// The MCU side of the sensor's GPIO1 interrupt line.
+pub trait InterruptPin {
+    /// True if the EXTI line has a pending interrupt.
+    fn check_interrupt(&self) -> bool;
+    fn clear_interrupt_pending_bit(&mut self);
+}
+
+impl<P: ExtiPin> InterruptPin for P {
+    fn check_interrupt(&self) -> bool {
+        ExtiPin::check_interrupt(self)
+    }
+
+    fn clear_interrupt_pending_bit(&mut self) {
+        ExtiPin::clear_interrupt_pending_bit(self)
+    }
+}
+
+/// An indicator LED, independent of its polarity.
+pub trait StatusLed {
+    fn on(&mut self);
+    fn off(&mut self);
+}
+
+impl StatusLed for board::Led {
+    fn on(&mut self) {
+        if board::LED_ACTIVE_LOW {
+            self.set_low();
+        } else {
+            self.set_high();
+        }
+    }
+
+    fn off(&mut self) {
+        if board::LED_ACTIVE_LOW {
+            self.set_high();
+        } else {
+            self.set_low();
+        }
+    }
+}
+
+/// A [`StatusLed`] that is an RTIC resource shared between tasks, locked
+/// only while it is switched. Locking it around a whole handler instead
+/// would raise that handler to the LED's ceiling and hold off the other
+/// tasks sharing it for as long as the I2C transfers take.
+pub struct SharedLed<M>(pub M);
+
+impl<M> StatusLed for SharedLed<M>
+where
+    M: rtic::Mutex,
+    M::T: StatusLed,
+{
+    fn on(&mut self) {
+        self.0.lock(|led| led.on());
+    }
+
+    fn off(&mut self) {
+        self.0.lock(|led| led.off());
+    }
+}
+
+/// The sensor operations the interrupt handlers need.
+pub trait InterruptSensor {
+    type Error;
+
+    fn read_interrupt_status(&mut self) -> Result<u8, Self::Error>;
+    fn read_range_mm(&mut self) -> Result<u16, Self::Error>;
+    fn read_ambient(&mut self) -> Result<u16, Self::Error>;
+    fn read_ambient_lux(&mut self) -> Result<f32, Self::Error>;
+    fn clear_range_interrupt(&mut self) -> Result<(), Self::Error>;
+    fn clear_ambient_interrupt(&mut self) -> Result<(), Self::Error>;
+    fn clear_all_interrupts(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<MODE, I2C, E> InterruptSensor for vl6180x::VL6180X<MODE, I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = vl6180x::Error<E>;
+
+    fn read_interrupt_status(&mut self) -> Result<u8, Self::Error> {
+        vl6180x::VL6180X::read_interrupt_status(self)
+    }
+
+    fn read_range_mm(&mut self) -> Result<u16, Self::Error> {
+        vl6180x::VL6180X::read_range_mm(self)
+    }
+
+    fn read_ambient(&mut self) -> Result<u16, Self::Error> {
+        vl6180x::VL6180X::read_ambient(self)
+    }
+
+    fn read_ambient_lux(&mut self) -> Result<f32, Self::Error> {
+        vl6180x::VL6180X::read_ambient_lux(self)
+    }
+
+    fn clear_range_interrupt(&mut self) -> Result<(), Self::Error> {
+        vl6180x::VL6180X::clear_range_interrupt(self)
+    }
+
+    fn clear_ambient_interrupt(&mut self) -> Result<(), Self::Error> {
+        vl6180x::VL6180X::clear_ambient_interrupt(self)
+    }
+
+    fn clear_all_interrupts(&mut self) -> Result<(), Self::Error> {
+        vl6180x::VL6180X::clear_all_interrupts(self)
+    }
+}
+
+/// Which sensor interrupt a handler clears once it has read the result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clear {
+    /// Leave the sensor interrupt set, e.g. for single shot measurements.
+    None,
+    Range,
+    Ambient,
+    All,
+}
+
+/// What a handler read, and whether clearing the sensor interrupt worked.
+#[derive(Debug)]
+pub struct Handled<T, E> {
+    pub reading: Result<T, E>,
+    pub cleared: Result<(), E>,
+}
+
+/// Range and/or ambient results of an interleaved mode interrupt.
+/// A field is `None` when the interrupt status reported no event for it.
+#[derive(Debug)]
+pub struct InterleavedReading<E> {
+    pub range_mm: Option<Result<u16, E>>,
+    pub ambient_lux: Option<Result<f32, E>>,
+}
+
+fn clear<S: InterruptSensor>(sensor: &mut S, mode: Clear) -> Result<(), S::Error> {
+    match mode {
+        Clear::None => Ok(()),
+        Clear::Range => sensor.clear_range_interrupt(),
+        Clear::Ambient => sensor.clear_ambient_interrupt(),
+        Clear::All => sensor.clear_all_interrupts(),
+    }
+}
+
+fn handle<S, P, L, T>(
+    sensor: &mut S,
+    pin: &mut P,
+    led: &mut L,
+    mode: Clear,
+    read: impl FnOnce(&mut S) -> Result<T, S::Error>,
+) -> Handled<T, S::Error>
+where
+    S: InterruptSensor,
+    P: InterruptPin,
+    L: StatusLed,
+{
+    led.on();
+    let reading = read(sensor);
+    led.off();
+    pin.clear_interrupt_pending_bit();
+    let cleared = clear(sensor, mode);
+    Handled { reading, cleared }
+}
+
+/// Reads the range in millimetres.
+pub fn range_interrupt<S, P, L>(
+    sensor: &mut S,
+    pin: &mut P,
+    led: &mut L,
+    mode: Clear,
+) -> Handled<u16, S::Error>
+where
+    S: InterruptSensor,
+    P: InterruptPin,
+    L: StatusLed,
+{
+    handle(sensor, pin, led, mode, S::read_range_mm)
+}
+
+/// Reads the ambient light level in lux.
+pub fn ambient_lux_interrupt<S, P, L>(
+    sensor: &mut S,
+    pin: &mut P,
+    led: &mut L,
+    mode: Clear,
+) -> Handled<f32, S::Error>
+where
+    S: InterruptSensor,
+    P: InterruptPin,
+    L: StatusLed,
+{
+    handle(sensor, pin, led, mode, S::read_ambient_lux)
+}
+
+/// Reads the raw ambient count, which is what the ALS thresholds compare against.
+pub fn ambient_raw_interrupt<S, P, L>(
+    sensor: &mut S,
+    pin: &mut P,
+    led: &mut L,
+    mode: Clear,
+) -> Handled<u16, S::Error>
+where
+    S: InterruptSensor,
+    P: InterruptPin,
+    L: StatusLed,
+{
+    handle(sensor, pin, led, mode, S::read_ambient)
+}
+
+/// Reads whichever of range and ambient the interrupt status reports an event for.
+pub fn interleaved_interrupt<S, P, L>(
+    sensor: &mut S,
+    pin: &mut P,
+    led: &mut L,
+    mode: Clear,
+) -> Handled<InterleavedReading<S::Error>, S::Error>
+where
+    S: InterruptSensor,
+    P: InterruptPin,
+    L: StatusLed,
+{
+    handle(sensor, pin, led, mode, |sensor| {
+        let status = sensor.read_interrupt_status()?;
+        let range_mm = (range_int_status(status) != 0).then(|| sensor.read_range_mm());
+        let ambient_lux = (als_int_status(status) != 0).then(|| sensor.read_ambient_lux());
+        Ok(InterleavedReading {
+            range_mm,
+            ambient_lux,
+        })
+    })
+}
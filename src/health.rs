@@ -0,0 +1,280 @@
+//! Sensor health supervision with XSHUT power-cycle recovery.
+//!
+//! A VL6180X that wedges stops sampling or stops answering on the bus, and
+//! only a reset brings it back. A [`HealthMonitor`] per sensor keeps track of
+//! when it last delivered a sample and how many I2C errors and system range
+//! errors it has had in a row. Once that adds up to a [`Fault`],
+//! [`HealthMonitor::supervise`] power-cycles the sensor through
+//! [`PowerCycle`], which brings it back on its address, with its `Config`
+//! and continuous mode.
+//!
+//! A sensor that keeps failing is retried with exponential backoff, from
+//! [`HealthConfig::backoff_initial_ms`] up to
+//! [`HealthConfig::backoff_max_ms`], until it delivers a sample again.
+
+use core::fmt;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use embedded_hal::digital::v2::OutputPin;
+
+/// Thresholds and backoff of a [`HealthMonitor`], times in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// No sample for this long is a fault.
+    pub stale_ms: u32,
+    /// This many I2C errors in a row is a fault.
+    pub max_i2c_errors: u8,
+    /// This many system range errors in a row is a fault, see
+    /// [`is_system_error`].
+    pub max_range_errors: u8,
+    /// Wait after the first recovery before trying again.
+    pub backoff_initial_ms: u32,
+    /// Longest wait between recoveries.
+    pub backoff_max_ms: u32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            stale_ms: 1_000,
+            max_i2c_errors: 3,
+            max_range_errors: 5,
+            backoff_initial_ms: 500,
+            backoff_max_ms: 60_000,
+        }
+    }
+}
+
+/// Why a sensor was found unhealthy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// No sample for this long.
+    Stale { silent_ms: u32 },
+    /// This many I2C errors in a row.
+    I2cErrors(u8),
+    /// System range errors in a row, the last one with `code`.
+    RangeErrors { code: u8, count: u8 },
+}
+
+/// True for the range error codes (1 to 5: VCSEL and PLL failures) that
+/// mean the sensor itself is in trouble. The others, such as no target or
+/// overflow, are what a working sensor reports with nothing in range.
+pub const fn is_system_error(code: u8) -> bool {
+    matches!(code, 1..=5)
+}
+
+/// Running totals since the monitor was made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HealthCounters {
+    pub samples: u32,
+    pub i2c_errors: u32,
+    pub range_errors: u32,
+    pub faults: u32,
+    pub power_cycles: u32,
+    /// Power cycles that themselves failed.
+    pub failed_power_cycles: u32,
+}
+
+impl fmt::Display for HealthCounters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "samples {}, i2c errors {}, range errors {}, faults {}, power cycles {} ({} failed)",
+            self.samples,
+            self.i2c_errors,
+            self.range_errors,
+            self.faults,
+            self.power_cycles,
+            self.failed_power_cycles
+        )
+    }
+}
+
+/// Brings a sensor back from whatever state it is in.
+pub trait PowerCycle {
+    type Error;
+
+    /// Switches the sensor off and on with its XSHUT line, then restores its
+    /// I2C address and `Config` and restarts the continuous mode it ran.
+    fn power_cycle(&mut self) -> Result<(), Self::Error>;
+}
+
+/// What [`HealthMonitor::supervise`] did about a fault.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Recovery<E> {
+    pub fault: Fault,
+    /// 1 for the first power cycle since the sensor last delivered a sample.
+    pub attempt: u32,
+    pub result: Result<(), E>,
+    /// No further attempt before this much time has passed.
+    pub backoff_ms: u32,
+}
+
+/// Health of one sensor, see the module docs.
+#[derive(Clone, Debug)]
+pub struct HealthMonitor {
+    config: HealthConfig,
+    last_sample_ms: u32,
+    i2c_errors: u8,
+    range_errors: u8,
+    last_range_error: u8,
+    /// Power cycles since the last sample.
+    attempts: u32,
+    /// When the last one was, and how long to wait after it.
+    backoff: Option<(u32, u32)>,
+    counters: HealthCounters,
+}
+
+impl HealthMonitor {
+    /// A healthy sensor whose staleness counts from `now_ms`, on any
+    /// millisecond clock (it may wrap).
+    pub fn new(config: HealthConfig, now_ms: u32) -> Self {
+        Self {
+            config,
+            last_sample_ms: now_ms,
+            i2c_errors: 0,
+            range_errors: 0,
+            last_range_error: 0,
+            attempts: 0,
+            backoff: None,
+            counters: HealthCounters::default(),
+        }
+    }
+
+    pub fn config(&self) -> &HealthConfig {
+        &self.config
+    }
+
+    pub fn counters(&self) -> &HealthCounters {
+        &self.counters
+    }
+
+    /// A sample came in, so the sensor works: clears the error runs and the
+    /// backoff.
+    pub fn record_sample(&mut self, now_ms: u32) {
+        self.last_sample_ms = now_ms;
+        self.i2c_errors = 0;
+        self.range_errors = 0;
+        self.attempts = 0;
+        self.backoff = None;
+        self.counters.samples += 1;
+    }
+
+    pub fn record_i2c_error(&mut self) {
+        self.i2c_errors = self.i2c_errors.saturating_add(1);
+        self.counters.i2c_errors += 1;
+    }
+
+    /// A range measurement reported error `code`. Only system errors count
+    /// towards a fault, any other code is a sample like a distance is.
+    pub fn record_range_error(&mut self, code: u8, now_ms: u32) {
+        self.counters.range_errors += 1;
+        if is_system_error(code) {
+            self.range_errors = self.range_errors.saturating_add(1);
+            self.last_range_error = code;
+        } else {
+            self.record_sample(now_ms);
+        }
+    }
+
+    /// The fault the sensor has at `now_ms`, if any.
+    pub fn fault(&self, now_ms: u32) -> Option<Fault> {
+        let silent_ms = now_ms.wrapping_sub(self.last_sample_ms);
+        if self.i2c_errors >= self.config.max_i2c_errors {
+            Some(Fault::I2cErrors(self.i2c_errors))
+        } else if self.range_errors >= self.config.max_range_errors {
+            Some(Fault::RangeErrors {
+                code: self.last_range_error,
+                count: self.range_errors,
+            })
+        } else if silent_ms >= self.config.stale_ms {
+            Some(Fault::Stale { silent_ms })
+        } else {
+            None
+        }
+    }
+
+    /// Power-cycles `sensor` if it has a fault and isn't waiting out a
+    /// backoff. Returns what was done, for the caller to log.
+    pub fn supervise<S: PowerCycle>(
+        &mut self,
+        sensor: &mut S,
+        now_ms: u32,
+    ) -> Option<Recovery<S::Error>> {
+        if let Some((since_ms, backoff_ms)) = self.backoff {
+            if now_ms.wrapping_sub(since_ms) < backoff_ms {
+                return None;
+            }
+        }
+        let fault = self.fault(now_ms)?;
+        self.counters.faults += 1;
+
+        let result = sensor.power_cycle();
+        self.counters.power_cycles += 1;
+        if result.is_err() {
+            self.counters.failed_power_cycles += 1;
+        }
+        let backoff_ms = self.backoff_ms();
+        self.attempts += 1;
+        self.backoff = Some((now_ms, backoff_ms));
+        // A fresh start: it has until `stale_ms` from now to deliver
+        self.last_sample_ms = now_ms;
+        self.i2c_errors = 0;
+        self.range_errors = 0;
+
+        Some(Recovery {
+            fault,
+            attempt: self.attempts,
+            result,
+            backoff_ms,
+        })
+    }
+
+    /// Doubles with every attempt since the last sample.
+    fn backoff_ms(&self) -> u32 {
+        let factor = 1u32.checked_shl(self.attempts).unwrap_or(u32::MAX);
+        self.config
+            .backoff_initial_ms
+            .saturating_mul(factor)
+            .min(self.config.backoff_max_ms)
+    }
+}
+
+/// Which continuous mode a [`DynamicSensor`] is restarted in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Continuous {
+    None,
+    Range,
+    Ambient,
+}
+
+/// A sensor in the driver's dynamic mode with its XSHUT pin, and what it
+/// needs to be brought back after a power cycle.
+pub struct DynamicSensor<I2C, X> {
+    pub vl6180x: vl6180x::VL6180X<vl6180x::DynamicMode, I2C>,
+    pub x_shutdown_pin: X,
+    pub address: u8,
+    pub continuous: Continuous,
+}
+
+impl<I2C, X, E> PowerCycle for DynamicSensor<I2C, X>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    X: OutputPin,
+{
+    type Error = vl6180x::Error<E>;
+
+    /// The driver applies the `Config` it was made with as part of
+    /// `power_on_and_init`.
+    fn power_cycle(&mut self) -> Result<(), Self::Error> {
+        self.vl6180x.try_power_off(&mut self.x_shutdown_pin)?;
+        self.vl6180x
+            .try_power_on_and_init(&mut self.x_shutdown_pin)?;
+        self.vl6180x.try_change_i2c_address(self.address)?;
+        match self.continuous {
+            Continuous::None => Ok(()),
+            Continuous::Range => self.vl6180x.try_start_range_continuous_mode(),
+            Continuous::Ambient => self.vl6180x.try_start_ambient_continuous_mode(),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Board support and helpers shared by the VL6180X examples.
+
+#![no_std]
+
+pub mod ambient;
+pub mod board;
+pub mod bus;
+pub mod bus_recovery;
+pub mod button;
+pub mod calibration;
+pub mod dispatch;
+pub mod gesture;
+pub mod handlers;
+pub mod health;
+pub mod log;
+pub mod presence;
+pub mod profile;
+pub mod registers;
+pub mod sensor_array;
+pub mod shell;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod state_machine;
+pub mod stats;
+pub mod storage;
+pub mod stream;
+pub mod suite;
+pub mod timeout;
+
+pub use vl6180x_filter as filter;
+pub use vl6180x_protocol as protocol;
+
+#[cfg(all(target_os = "none", feature = "log-rtt"))]
+use panic_rtt_target as _;
+#[cfg(all(target_os = "none", feature = "log-semihosting"))]
+use panic_semihosting as _;
@@ -0,0 +1,421 @@
+//! Logging facade used by the examples in place of `hprintln!`.
+//!
+//! Exactly one backend is chosen with a cargo feature:
+//!
+//! - `log-semihosting` (default): the debugger's console. Slow, and halts the
+//!   board when no debugger is attached.
+//! - `log-rtt`: RTT, with [`Channel::Log`] on up channel 0 and
+//!   [`Channel::Data`] on up channel 1.
+//! - `log-uart`: USART2 TX on PA2 at [`UART_BAUD`], set up by `Board::new`,
+//!   which leaves RX on PA3 for the command shell. Lines are queued and
+//!   sent with interrupts enabled.
+//! - `log-capture`: an in-memory buffer per channel, for checking output on
+//!   the host.
+//!
+//! Each call formats one line into a fixed buffer of [`LINE_CAPACITY`] bytes
+//! (longer lines are truncated) before handing it to the backend in one go.
+
+use core::fmt::{self, Write};
+
+use heapless::String;
+
+/// Longest line a backend is handed, including the trailing newline.
+pub const LINE_CAPACITY: usize = 256;
+
+/// Baud rate of the `log-uart` backend.
+pub const UART_BAUD: u32 = 115_200;
+
+pub type Line = String<LINE_CAPACITY>;
+
+const ENABLED_BACKENDS: usize = cfg!(feature = "log-semihosting") as usize
+    + cfg!(feature = "log-rtt") as usize
+    + cfg!(feature = "log-uart") as usize
+    + cfg!(feature = "log-capture") as usize;
+
+const _: () = assert!(
+    ENABLED_BACKENDS == 1,
+    "enable exactly one of the log-semihosting, log-rtt, log-uart and log-capture features"
+);
+
+/// Output stream a line is written to. Backends with a single stream write
+/// both channels to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Human readable messages.
+    Log = 0,
+    /// Measurement output meant for tooling.
+    Data = 1,
+}
+
+/// Somewhere formatted lines can go.
+pub trait Backend {
+    fn write_line(&mut self, channel: Channel, line: &str);
+}
+
+/// Formats `args` followed by a newline, truncating to [`LINE_CAPACITY`].
+pub fn format_line(args: fmt::Arguments) -> Line {
+    let mut line = Line::new();
+    let mut truncating = Truncating(&mut line);
+    let _ = truncating.write_fmt(args);
+    if line.push('\n').is_err() {
+        line.pop();
+        let _ = line.push('\n');
+    }
+    line
+}
+
+/// Writes into a fixed buffer, silently dropping whatever doesn't fit.
+struct Truncating<'a>(&'a mut Line);
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of the backend selected at build time.
+pub const fn backend_name() -> &'static str {
+    backend::NAME
+}
+
+/// Writes one line to `channel` of the selected backend.
+pub fn log(channel: Channel, args: fmt::Arguments) {
+    let line = format_line(args);
+    backend::write_line(channel, &line);
+}
+
+/// Logs a line on [`Channel::Log`], formatted like `println!`.
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::log($crate::log::Channel::Log, format_args!($($arg)*))
+    };
+}
+
+/// Logs a line on the given [`Channel`], formatted like `println!`.
+#[macro_export]
+macro_rules! log_to {
+    ($channel:expr, $($arg:tt)*) => {
+        $crate::log::log($channel, format_args!($($arg)*))
+    };
+}
+
+#[cfg(feature = "log-semihosting")]
+mod backend {
+    use core::cell::RefCell;
+
+    use cortex_m::interrupt::{self, Mutex};
+    use cortex_m_semihosting::hio::{self, HStdout};
+
+    use super::{Backend, Channel};
+
+    pub const NAME: &str = "semihosting";
+
+    /// Opening the debugger's stdout is a round trip to the host that takes
+    /// a file handle there, so it is opened on the first line and kept.
+    static STDOUT: Mutex<RefCell<Semihosting>> =
+        Mutex::new(RefCell::new(Semihosting { stdout: None }));
+
+    pub struct Semihosting {
+        stdout: Option<HStdout>,
+    }
+
+    impl Backend for Semihosting {
+        fn write_line(&mut self, _channel: Channel, line: &str) {
+            if self.stdout.is_none() {
+                self.stdout = hio::hstdout().ok();
+            }
+            if let Some(stdout) = self.stdout.as_mut() {
+                let _ = stdout.write_all(line.as_bytes());
+            }
+        }
+    }
+
+    /// The core is halted while the debugger services the write, so the
+    /// critical section holds nothing off that wasn't already.
+    pub fn write_line(channel: Channel, line: &str) {
+        interrupt::free(|cs| {
+            if let Ok(mut semihosting) = STDOUT.borrow(cs).try_borrow_mut() {
+                semihosting.write_line(channel, line);
+            }
+        });
+    }
+}
+
+#[cfg(feature = "log-rtt")]
+mod backend {
+    use core::cell::RefCell;
+
+    use cortex_m::interrupt::{self, Mutex};
+    use rtt_target::{rtt_init, UpChannel};
+
+    use super::{Backend, Channel};
+
+    pub const NAME: &str = "rtt";
+
+    static CHANNELS: Mutex<RefCell<Option<Rtt>>> = Mutex::new(RefCell::new(None));
+
+    pub struct Rtt {
+        log: UpChannel,
+        data: UpChannel,
+    }
+
+    impl Backend for Rtt {
+        fn write_line(&mut self, channel: Channel, line: &str) {
+            match channel {
+                Channel::Log => self.log.write_str(line),
+                Channel::Data => self.data.write_str(line),
+            }
+        }
+    }
+
+    /// Sets up the RTT control block. Lines logged before this are dropped.
+    pub fn init() {
+        let channels = rtt_init! {
+            up: {
+                0: {
+                    size: 1024
+                    mode: NoBlockSkip
+                    name: "Log"
+                }
+                1: {
+                    size: 1024
+                    mode: NoBlockSkip
+                    name: "Data"
+                }
+            }
+        };
+        let rtt = Rtt {
+            log: channels.up.0,
+            data: channels.up.1,
+        };
+        interrupt::free(|cs| CHANNELS.borrow(cs).replace(Some(rtt)));
+    }
+
+    pub fn write_line(channel: Channel, line: &str) {
+        interrupt::free(|cs| {
+            if let Ok(mut rtt) = CHANNELS.borrow(cs).try_borrow_mut() {
+                if let Some(rtt) = rtt.as_mut() {
+                    rtt.write_line(channel, line);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(feature = "log-uart")]
+mod backend {
+    use core::cell::{RefCell, UnsafeCell};
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use cortex_m::interrupt::{self, Mutex};
+    use embedded_hal::blocking::serial::Write;
+    use heapless::{Deque, Vec};
+    use stm32f4xx_hal::{pac, serial::Tx};
+
+    use super::{Backend, Channel};
+
+    pub const NAME: &str = "uart";
+
+    /// Bytes waiting for the UART, a few lines' worth. A line that doesn't
+    /// fit any more is dropped whole.
+    pub const QUEUE_CAPACITY: usize = 1024;
+
+    /// Bytes taken off the queue per critical section.
+    const CHUNK_LEN: usize = 16;
+
+    type Queue = Deque<u8, QUEUE_CAPACITY>;
+
+    static QUEUE: Mutex<RefCell<Queue>> = Mutex::new(RefCell::new(Deque::new()));
+
+    impl Backend for Queue {
+        fn write_line(&mut self, _channel: Channel, line: &str) {
+            if self.capacity() - self.len() < line.len() {
+                return;
+            }
+            for &byte in line.as_bytes() {
+                let _ = self.push_back(byte);
+            }
+        }
+    }
+
+    /// The transmitter, used by whoever holds `sending`.
+    struct Sender {
+        sending: AtomicBool,
+        tx: UnsafeCell<Option<Tx<pac::USART2>>>,
+    }
+
+    // Access to `tx` is serialised by `sending`.
+    unsafe impl Sync for Sender {}
+
+    static SENDER: Sender = Sender {
+        sending: AtomicBool::new(false),
+        tx: UnsafeCell::new(None),
+    };
+
+    /// Hands the USART2 transmitter to the logger. Lines logged before this are dropped.
+    pub fn init(tx: Tx<pac::USART2>) {
+        while SENDER.sending.swap(true, Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        unsafe { *SENDER.tx.get() = Some(tx) };
+        SENDER.sending.store(false, Ordering::Release);
+    }
+
+    /// Queues the line in a critical section and sends it outside of one,
+    /// so interrupts only wait for the copy, not for the UART. A line logged
+    /// from an interrupt while another is being sent is left to the
+    /// interrupted sender, behind the lines before it.
+    pub fn write_line(channel: Channel, line: &str) {
+        interrupt::free(|cs| QUEUE.borrow(cs).borrow_mut().write_line(channel, line));
+        loop {
+            if SENDER.sending.swap(true, Ordering::Acquire) {
+                return;
+            }
+            unsafe { send_queued() };
+            SENDER.sending.store(false, Ordering::Release);
+            // A line queued by an interrupt after the last chunk found the
+            // sender still busy
+            if interrupt::free(|cs| QUEUE.borrow(cs).borrow().is_empty()) {
+                return;
+            }
+        }
+    }
+
+    /// Sends what is left in the queue even if a sender was interrupted,
+    /// for the panic handler, which never returns to it.
+    pub fn flush() {
+        unsafe { send_queued() };
+    }
+
+    /// Empties the queue into the UART, or drops it before [`init`].
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the transmitter, see [`Sender`].
+    unsafe fn send_queued() {
+        let tx = &mut *SENDER.tx.get();
+        loop {
+            let chunk: Vec<u8, CHUNK_LEN> = interrupt::free(|cs| {
+                let mut queue = QUEUE.borrow(cs).borrow_mut();
+                (0..CHUNK_LEN).map_while(|_| queue.pop_front()).collect()
+            });
+            if chunk.is_empty() {
+                return;
+            }
+            if let Some(tx) = tx.as_mut() {
+                let _ = tx.bwrite_all(&chunk);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "log-capture")]
+mod backend {
+    use core::cell::UnsafeCell;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use heapless::String;
+
+    use super::{Backend, Channel};
+
+    pub const NAME: &str = "capture";
+
+    /// Bytes kept per channel; further lines are dropped until [`take`] is called.
+    pub const CAPTURE_CAPACITY: usize = 1024;
+
+    pub type Captured = String<CAPTURE_CAPACITY>;
+
+    /// Per channel buffers.
+    #[derive(Default)]
+    pub struct Capture {
+        log: Captured,
+        data: Captured,
+    }
+
+    impl Capture {
+        pub const fn new() -> Self {
+            Self {
+                log: String::new(),
+                data: String::new(),
+            }
+        }
+
+        /// Returns everything captured on `channel` and clears it.
+        pub fn take(&mut self, channel: Channel) -> Captured {
+            match channel {
+                Channel::Log => core::mem::take(&mut self.log),
+                Channel::Data => core::mem::take(&mut self.data),
+            }
+        }
+    }
+
+    impl Backend for Capture {
+        fn write_line(&mut self, channel: Channel, line: &str) {
+            let buffer = match channel {
+                Channel::Log => &mut self.log,
+                Channel::Data => &mut self.data,
+            };
+            let _ = buffer.push_str(line);
+        }
+    }
+
+    struct Global {
+        locked: AtomicBool,
+        capture: UnsafeCell<Capture>,
+    }
+
+    // Access to `capture` is serialised by `locked`.
+    unsafe impl Sync for Global {}
+
+    static GLOBAL: Global = Global {
+        locked: AtomicBool::new(false),
+        capture: UnsafeCell::new(Capture::new()),
+    };
+
+    fn with<R>(f: impl FnOnce(&mut Capture) -> R) -> R {
+        while GLOBAL
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let r = f(unsafe { &mut *GLOBAL.capture.get() });
+        GLOBAL.locked.store(false, Ordering::Release);
+        r
+    }
+
+    pub fn write_line(channel: Channel, line: &str) {
+        with(|capture| capture.write_line(channel, line));
+    }
+
+    /// Returns everything logged on `channel` since the last call and clears it.
+    pub fn take(channel: Channel) -> Captured {
+        with(|capture| capture.take(channel))
+    }
+}
+
+#[cfg(feature = "log-rtt")]
+pub use self::backend::init;
+#[cfg(feature = "log-uart")]
+pub use self::backend::{init, QUEUE_CAPACITY};
+#[cfg(feature = "log-capture")]
+pub use self::backend::{take, Capture, Captured, CAPTURE_CAPACITY};
+
+#[cfg(all(target_os = "none", any(feature = "log-uart", feature = "log-capture")))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    log(Channel::Log, format_args!("{}", info));
+    #[cfg(feature = "log-uart")]
+    backend::flush();
+    loop {
+        cortex_m::asm::bkpt();
+    }
+}
@@ -1,67 +1,68 @@
-//! Prints "Hello, world!" on the host console using semihosting
+//! Prints "Hello, world!" through the selected log backend, then toggles the
+//! LED with the user button
 
 #![no_main]
 #![no_std]
 
-use panic_semihosting as _;
+use core::convert::Infallible;
 
-use crate::hal::{pac, prelude::*};
 use cortex_m_rt::entry;
-use cortex_m_semihosting::hprintln;
-use stm32f4xx_hal as hal;
+use stm32f4xx_hal::prelude::*;
+use vl6180x_stm32f401_examples::board::{self, Board};
+use vl6180x_stm32f401_examples::button::{ButtonConfig, DebouncedButton};
+use vl6180x_stm32f401_examples::handlers::StatusLed;
+use vl6180x_stm32f401_examples::log;
+use vl6180x_stm32f401_examples::state_machine::{Actions, State, StateMachine, Transition};
 
 #[entry]
 fn main() -> ! {
     let _ = 5;
-    hprintln!("Hello, world!").unwrap();
+    if let Some(board) = Board::take() {
+        log!("Hello, world!");
 
-    if let (Some(dp), Some(cp)) = (
-        pac::Peripherals::take(),
-        cortex_m::peripheral::Peripherals::take(),
-    ) {
-        // Set up the LED. On the Black Pill it's connected to pin PC13.
-        let gpioc = dp.GPIOC.split();
-        let mut led = gpioc.pc13.into_push_pull_output();
+        let Board {
+            mut delay,
+            clock,
+            mut led,
+            button: user_button,
+            ..
+        } = board;
 
-        // Set up User button. On the Black Pill it's connected to pin PA0
-        let gpioa = dp.GPIOA.split();
-        let user_button = gpioa.pa0.into_pull_up_input();
-
-        // Set up the system clock. We want to run at 48MHz for this one.
-        let rcc = dp.RCC.constrain();
-        let clocks = rcc.cfgr.sysclk(48.MHz()).freeze();
-
-        // Create a delay abstraction based on SysTick
-        let mut delay = cp.SYST.delay(&clocks);
-
-        let mut state = State::LedOff;
+        let mut machine = StateMachine::new(&STATES);
+        let Ok(()) = machine.start(&mut led);
+        let mut button = DebouncedButton::new(ButtonConfig::default());
 
         loop {
-            match state {
-                State::LedOn => led.set_low(),
-                State::LedOff => led.set_high(),
-            }
-
-            if user_button.is_low() {
-                state.cycle();
-            }
+            let now_ms = board::now_ms(&clock);
+            button.update(user_button.is_low(), now_ms, |event| {
+                let Ok(_) = machine.handle(Transition::from_button(event), &mut led);
+            });
             delay.delay_ms(1_u32);
         }
     }
 
     loop {}
 }
-enum State {
-    LedOn,
-    LedOff,
+
+#[derive(Clone, Copy)]
+enum LedAction {
+    On,
+    Off,
 }
 
-impl State {
-    fn cycle(&mut self) {
-        use State::*;
-        *self = match *self {
-            LedOn => LedOff,
-            LedOff => LedOn,
+const STATES: [State<LedAction>; 2] = [
+    State::new("LED off").on_enter(&[LedAction::Off]),
+    State::new("LED on").on_enter(&[LedAction::On]),
+];
+
+impl Actions<LedAction> for board::Led {
+    type Error = Infallible;
+
+    fn run(&mut self, action: &LedAction) -> Result<(), Infallible> {
+        match action {
+            LedAction::On => self.on(),
+            LedAction::Off => self.off(),
         }
+        Ok(())
     }
 }
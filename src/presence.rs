@@ -0,0 +1,159 @@
+//! Presence detection on top of the range stream.
+//!
+//! The sensor's own threshold interrupts fire on every sample past the
+//! threshold, so anything hovering near it flickers in and out. The
+//! [`PresenceDetector`] adds what they lack: separate enter and exit
+//! thresholds, so a target between the two keeps whatever state it has, and
+//! dwell times a target has to stay in before the change is confirmed.
+//!
+//! ```text
+//! Absent --near--> Entering --dwell over--> Present
+//!   ^                 |                        |
+//!   +-------far-------+                       far
+//!   |                                          v
+//!   +--------------dwell over--------------- Leaving
+//!                                  (near: back to Present)
+//! ```
+//!
+//! A reading is near below [`PresenceConfig::enter_mm`] and far above
+//! [`PresenceConfig::exit_mm`]; no reading at all (nothing in range) is far.
+
+/// Thresholds and dwell times of a [`PresenceDetector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PresenceConfig {
+    /// A target closer than this starts entering.
+    pub enter_mm: u16,
+    /// A target further than this starts leaving, at least `enter_mm`.
+    pub exit_mm: u16,
+    /// How long a target has to stay before it is present.
+    pub enter_dwell_ms: u32,
+    /// How long a target has to stay away before it is gone.
+    pub exit_dwell_ms: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresenceState {
+    Absent,
+    /// Something came near, not for long enough yet.
+    Entering,
+    Present,
+    /// The target went away, not for long enough yet.
+    Leaving,
+}
+
+/// A change of [`PresenceState`], handed to the callback of
+/// [`PresenceDetector::update`] along with the time it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresenceEvent {
+    /// Absent to entering.
+    Approached,
+    /// Entering to present.
+    Arrived,
+    /// Entering back to absent before the dwell time was over.
+    Passed,
+    /// Present to leaving.
+    Withdrew,
+    /// Leaving to absent.
+    Left,
+    /// Leaving back to present before the dwell time was over.
+    Returned,
+}
+
+impl PresenceEvent {
+    /// The state the event leaves the detector in.
+    pub fn state(self) -> PresenceState {
+        match self {
+            PresenceEvent::Approached => PresenceState::Entering,
+            PresenceEvent::Arrived | PresenceEvent::Returned => PresenceState::Present,
+            PresenceEvent::Withdrew => PresenceState::Leaving,
+            PresenceEvent::Passed | PresenceEvent::Left => PresenceState::Absent,
+        }
+    }
+}
+
+/// Tracks whether something is in front of the sensor, see the module docs.
+#[derive(Clone, Debug)]
+pub struct PresenceDetector {
+    config: PresenceConfig,
+    state: PresenceState,
+    /// When the current entering or leaving started.
+    since_ms: u32,
+}
+
+impl PresenceDetector {
+    /// Starts out absent.
+    ///
+    /// # Panics
+    ///
+    /// If `exit_mm` is below `enter_mm`.
+    pub fn new(config: PresenceConfig) -> Self {
+        assert!(config.exit_mm >= config.enter_mm, "presence thresholds");
+        Self {
+            config,
+            state: PresenceState::Absent,
+            since_ms: 0,
+        }
+    }
+
+    pub fn config(&self) -> &PresenceConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> PresenceState {
+        self.state
+    }
+
+    /// True while present or leaving, i.e. since the last arrival was
+    /// confirmed and until the departure is.
+    pub fn is_present(&self) -> bool {
+        matches!(self.state, PresenceState::Present | PresenceState::Leaving)
+    }
+
+    /// Takes the next reading, `None` if nothing is in range, at `now_ms` on
+    /// any millisecond clock (it may wrap). `on_event` gets every state change
+    /// in order; a dwell time of 0 can give two in one update.
+    pub fn update(
+        &mut self,
+        range_mm: Option<u16>,
+        now_ms: u32,
+        mut on_event: impl FnMut(PresenceEvent, u32),
+    ) -> PresenceState {
+        let near = range_mm.is_some_and(|mm| mm < self.config.enter_mm);
+        let far = range_mm.map_or(true, |mm| mm > self.config.exit_mm);
+        let mut emit = |state: &mut PresenceState, event: PresenceEvent| {
+            *state = event.state();
+            on_event(event, now_ms);
+        };
+
+        match self.state {
+            PresenceState::Absent if near => {
+                self.since_ms = now_ms;
+                emit(&mut self.state, PresenceEvent::Approached);
+            }
+            PresenceState::Entering if far => emit(&mut self.state, PresenceEvent::Passed),
+            PresenceState::Present if far => {
+                self.since_ms = now_ms;
+                emit(&mut self.state, PresenceEvent::Withdrew);
+            }
+            PresenceState::Leaving if near => emit(&mut self.state, PresenceEvent::Returned),
+            _ => (),
+        }
+
+        let dwelt_ms = now_ms.wrapping_sub(self.since_ms);
+        match self.state {
+            PresenceState::Entering if dwelt_ms >= self.config.enter_dwell_ms => {
+                emit(&mut self.state, PresenceEvent::Arrived)
+            }
+            PresenceState::Leaving if dwelt_ms >= self.config.exit_dwell_ms => {
+                emit(&mut self.state, PresenceEvent::Left)
+            }
+            _ => (),
+        }
+        self.state
+    }
+
+    /// Back to absent without any events, e.g. after the sensor was reset.
+    pub fn reset(&mut self) {
+        self.state = PresenceState::Absent;
+    }
+}
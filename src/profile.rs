@@ -0,0 +1,27 @@
+//! The profile the configurable example was built with, picked out of
+//! `profiles.toml` by the build script.
+
+pub use vl6180x_profile::*;
+
+use crate::handlers;
+
+/// Every profile, as built in.
+pub const PROFILES: &str = include_str!("../profiles.toml");
+
+/// Name of the profile picked when building.
+pub const SELECTED: &str = env!("VL6180X_PROFILE_NAME");
+
+/// Loads the profile picked when building.
+pub fn selected() -> Profile<'static> {
+    // The build script has loaded this one already, so it can't fail here
+    Profile::load(PROFILES, SELECTED).expect("profile")
+}
+
+/// What the interrupt handlers clear for a profile's `clear`.
+pub fn clear_mode(clear: Clear) -> handlers::Clear {
+    match clear {
+        Clear::Range => handlers::Clear::Range,
+        Clear::Ambient => handlers::Clear::Ambient,
+        Clear::All => handlers::Clear::All,
+    }
+}
@@ -0,0 +1,128 @@
+//! VL6180X register map (datasheet DocID026171), limited to the registers the
+//! examples and helpers in this crate touch directly.
+//!
+//! All registers are addressed with a 16 bit index, sent MSB first.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// Factory default 7 bit I2C address.
+pub const DEFAULT_ADDRESS: u8 = 0x29;
+
+/// Value of [`IDENTIFICATION__MODEL_ID`] on every VL6180X.
+pub const MODEL_ID: u8 = 0xB4;
+
+pub const IDENTIFICATION__MODEL_ID: u16 = 0x000;
+pub const SYSTEM__MODE_GPIO1: u16 = 0x011;
+pub const SYSTEM__INTERRUPT_CONFIG_GPIO: u16 = 0x014;
+pub const SYSTEM__INTERRUPT_CLEAR: u16 = 0x015;
+pub const SYSTEM__FRESH_OUT_OF_RESET: u16 = 0x016;
+pub const SYSRANGE__START: u16 = 0x018;
+pub const SYSRANGE__THRESH_HIGH: u16 = 0x019;
+pub const SYSRANGE__THRESH_LOW: u16 = 0x01A;
+pub const SYSRANGE__INTERMEASUREMENT_PERIOD: u16 = 0x01B;
+pub const SYSRANGE__MAX_CONVERGENCE_TIME: u16 = 0x01C;
+pub const SYSRANGE__CROSSTALK_COMPENSATION_RATE: u16 = 0x01E;
+pub const SYSRANGE__PART_TO_PART_RANGE_OFFSET: u16 = 0x024;
+pub const SYSALS__START: u16 = 0x038;
+pub const SYSALS__THRESH_HIGH: u16 = 0x03A;
+pub const SYSALS__THRESH_LOW: u16 = 0x03C;
+pub const SYSALS__INTERMEASUREMENT_PERIOD: u16 = 0x03E;
+pub const SYSALS__ANALOGUE_GAIN: u16 = 0x03F;
+pub const SYSALS__INTEGRATION_PERIOD: u16 = 0x040;
+pub const RESULT__RANGE_STATUS: u16 = 0x04D;
+pub const RESULT__ALS_STATUS: u16 = 0x04E;
+pub const RESULT__INTERRUPT_STATUS_GPIO: u16 = 0x04F;
+pub const RESULT__ALS_VAL: u16 = 0x050;
+pub const RESULT__RANGE_VAL: u16 = 0x062;
+pub const RESULT__RANGE_RETURN_RATE: u16 = 0x066;
+pub const RANGE_SCALER: u16 = 0x096;
+pub const FIRMWARE__RESULT_SCALER: u16 = 0x120;
+pub const I2C_SLAVE__DEVICE_ADDRESS: u16 = 0x212;
+pub const INTERLEAVED_MODE__ENABLE: u16 = 0x2A3;
+
+/// `RANGE_SCALER` values for range result scaling factors 1, 2 and 3.
+pub const RANGE_SCALER_VALUES: [u16; 3] = [253, 127, 84];
+
+/// Bit 6 of `SYSALS__ANALOGUE_GAIN` must stay set, the gain level is in bits 2:0.
+pub const ALS_GAIN_BASE: u8 = 0x40;
+
+/// Bit 0 of `SYSRANGE__START`/`SYSALS__START`: start (or stop, in continuous mode).
+pub const START_STOP: u8 = 0x01;
+/// Bit 1 of `SYSRANGE__START`/`SYSALS__START`: continuous rather than single shot.
+pub const MODE_CONTINUOUS: u8 = 0x02;
+
+/// Bits of `SYSTEM__INTERRUPT_CLEAR`.
+pub const CLEAR_RANGE_INT: u8 = 0x01;
+pub const CLEAR_ALS_INT: u8 = 0x02;
+pub const CLEAR_ERROR_INT: u8 = 0x04;
+
+/// Interrupt source codes, used both in `SYSTEM__INTERRUPT_CONFIG_GPIO` and
+/// `RESULT__INTERRUPT_STATUS_GPIO` (range in bits 2:0, ALS in bits 5:3).
+pub const INT_DISABLED: u8 = 0;
+pub const INT_LEVEL_LOW: u8 = 1;
+pub const INT_LEVEL_HIGH: u8 = 2;
+pub const INT_OUT_OF_WINDOW: u8 = 3;
+pub const INT_NEW_SAMPLE_READY: u8 = 4;
+
+/// Range interrupt code from a `RESULT__INTERRUPT_STATUS_GPIO` value.
+pub const fn range_int_status(status: u8) -> u8 {
+    status & 0x07
+}
+
+/// Ambient interrupt code from a `RESULT__INTERRUPT_STATUS_GPIO` value.
+pub const fn als_int_status(status: u8) -> u8 {
+    (status >> 3) & 0x07
+}
+
+/// Error interrupt bits from a `RESULT__INTERRUPT_STATUS_GPIO` value.
+pub const fn error_int_status(status: u8) -> u8 {
+    (status >> 6) & 0x03
+}
+
+/// Range error code from a `RESULT__RANGE_STATUS` value, 0 means no error.
+pub const fn range_error_code(status: u8) -> u8 {
+    status >> 4
+}
+
+/// Range scaling factor (1 to 3) a `RANGE_SCALER` value selects.
+pub fn range_scaling_factor(scaler: u16) -> Option<u8> {
+    RANGE_SCALER_VALUES
+        .iter()
+        .position(|&value| value == scaler)
+        .map(|index| index as u8 + 1)
+}
+
+pub fn read_u8<I2C: WriteRead>(i2c: &mut I2C, address: u8, index: u16) -> Result<u8, I2C::Error> {
+    let mut value = [0];
+    i2c.write_read(address, &index.to_be_bytes(), &mut value)?;
+    Ok(value[0])
+}
+
+/// Reads a 16 bit register, stored MSB first.
+pub fn read_u16<I2C: WriteRead>(i2c: &mut I2C, address: u8, index: u16) -> Result<u16, I2C::Error> {
+    let mut value = [0; 2];
+    i2c.write_read(address, &index.to_be_bytes(), &mut value)?;
+    Ok(u16::from_be_bytes(value))
+}
+
+pub fn write_u8<I2C: Write>(
+    i2c: &mut I2C,
+    address: u8,
+    index: u16,
+    value: u8,
+) -> Result<(), I2C::Error> {
+    let [msb, lsb] = index.to_be_bytes();
+    i2c.write(address, &[msb, lsb, value])
+}
+
+/// Writes a 16 bit register, MSB first.
+pub fn write_u16<I2C: Write>(
+    i2c: &mut I2C,
+    address: u8,
+    index: u16,
+    value: u16,
+) -> Result<(), I2C::Error> {
+    let [msb, lsb] = index.to_be_bytes();
+    let [high, low] = value.to_be_bytes();
+    i2c.write(address, &[msb, lsb, high, low])
+}
@@ -0,0 +1,165 @@
+//! Bring-up of several VL6180X on one I2C bus.
+//!
+//! Every VL6180X boots at the default address 0x29, so sensors sharing a bus
+//! have to be woken one at a time with their XSHUT line and moved to their own
+//! address before the next one is powered.
+
+use core::convert::Infallible;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::Vec;
+
+use crate::registers::{DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID, MODEL_ID};
+
+/// Time the sensor needs after XSHUT goes high before it answers on the bus.
+const BOOT_DELAY_MS: u8 = 2;
+
+/// One sensor to bring up: its pins, configuration and the address it should end up on.
+pub struct SensorSpec<X, P> {
+    pub x_shutdown_pin: X,
+    pub interrupt_pin: P,
+    pub config: vl6180x::Config,
+    pub address: u8,
+}
+
+#[derive(Debug)]
+pub enum SensorArrayError<E> {
+    /// More sensors were given than the array can hold.
+    TooManySensors,
+    /// The address is reserved, outside the 7 bit range, or requested twice.
+    InvalidAddress(u8),
+    /// Configuring sensor `index` failed.
+    Sensor {
+        index: usize,
+        error: vl6180x::Error<E>,
+    },
+    /// Sensor `index` did not answer on its new address.
+    NoResponse { index: usize, address: u8 },
+    /// A device still answers on the default address once all sensors are assigned.
+    DefaultAddressInUse,
+}
+
+pub type Sensor<MODE, I2C, X, P> = vl6180x::VL6180XwPins<MODE, I2C, X, P>;
+
+/// Up to `N` sensors, each on its own I2C address.
+pub struct SensorArray<I2C, X, P, const N: usize> {
+    sensors: Vec<Sensor<vl6180x::ReadyMode, I2C, X, P>, N>,
+}
+
+impl<I2C, X, P, E, const N: usize> SensorArray<I2C, X, P, N>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    X: OutputPin<Error = Infallible>,
+{
+    /// Powers every sensor down, then wakes them one by one in the order
+    /// given, applying each `Config` and moving each sensor to its address.
+    ///
+    /// `acquire` hands out a bus handle for every sensor plus one used to
+    /// probe addresses, e.g. `|| bus_manager.acquire_i2c()` with shared-bus.
+    pub fn new<S, F, D>(
+        specs: S,
+        mut acquire: F,
+        delay: &mut D,
+    ) -> Result<Self, SensorArrayError<E>>
+    where
+        S: IntoIterator<Item = SensorSpec<X, P>>,
+        F: FnMut() -> I2C,
+        D: DelayMs<u8>,
+    {
+        let mut specs: Vec<SensorSpec<X, P>, N> = specs
+            .into_iter()
+            .try_fold(Vec::new(), |mut specs, spec| {
+                specs.push(spec).map(|_| specs).ok()
+            })
+            .ok_or(SensorArrayError::TooManySensors)?;
+
+        for (i, spec) in specs.iter().enumerate() {
+            let address = spec.address;
+            if !(0x08..=0x77).contains(&address)
+                || address == DEFAULT_ADDRESS
+                || specs[..i].iter().any(|other| other.address == address)
+            {
+                return Err(SensorArrayError::InvalidAddress(address));
+            }
+        }
+
+        for spec in specs.iter_mut() {
+            let _ = spec.x_shutdown_pin.set_low();
+        }
+
+        let mut probe = acquire();
+        let mut sensors = Vec::new();
+        for (index, mut spec) in specs.into_iter().enumerate() {
+            let _ = spec.x_shutdown_pin.set_high();
+            delay.delay_ms(BOOT_DELAY_MS);
+
+            let mut vl6180x = vl6180x::VL6180X::with_config(acquire(), &spec.config)
+                .map_err(|error| SensorArrayError::Sensor { index, error })?;
+            vl6180x
+                .change_i2c_address(spec.address)
+                .map_err(|error| SensorArrayError::Sensor { index, error })?;
+
+            if !responds(&mut probe, spec.address) {
+                return Err(SensorArrayError::NoResponse {
+                    index,
+                    address: spec.address,
+                });
+            }
+
+            // Cannot overflow, `specs` has the same capacity.
+            let _ = sensors.push(vl6180x::VL6180XwPins {
+                vl6180x,
+                x_shutdown_pin: spec.x_shutdown_pin,
+                interrupt_pin: spec.interrupt_pin,
+            });
+        }
+
+        if responds(&mut probe, DEFAULT_ADDRESS) {
+            return Err(SensorArrayError::DefaultAddressInUse);
+        }
+
+        Ok(Self { sensors })
+    }
+
+    /// Starts continuous ranging on every sensor.
+    #[allow(clippy::type_complexity)]
+    pub fn start_range_continuous_mode(
+        self,
+    ) -> Result<Vec<Sensor<vl6180x::RangeContinuousMode, I2C, X, P>, N>, SensorArrayError<E>> {
+        let mut sensors = Vec::new();
+        for (index, sensor) in self.sensors.into_iter().enumerate() {
+            let vl6180x = sensor
+                .vl6180x
+                .start_range_continuous_mode()
+                .map_err(|error| SensorArrayError::Sensor { index, error })?;
+            let _ = sensors.push(vl6180x::VL6180XwPins {
+                vl6180x,
+                x_shutdown_pin: sensor.x_shutdown_pin,
+                interrupt_pin: sensor.interrupt_pin,
+            });
+        }
+        Ok(sensors)
+    }
+
+    pub fn sensors(&self) -> &[Sensor<vl6180x::ReadyMode, I2C, X, P>] {
+        &self.sensors
+    }
+
+    pub fn sensors_mut(&mut self) -> &mut [Sensor<vl6180x::ReadyMode, I2C, X, P>] {
+        &mut self.sensors
+    }
+
+    pub fn into_sensors(self) -> Vec<Sensor<vl6180x::ReadyMode, I2C, X, P>, N> {
+        self.sensors
+    }
+}
+
+/// True if a VL6180X answers on `address` with the expected model ID.
+pub fn responds<I2C: WriteRead>(i2c: &mut I2C, address: u8) -> bool {
+    let mut model_id = [0];
+    i2c.write_read(address, &IDENTIFICATION__MODEL_ID.to_be_bytes(), &mut model_id)
+        .is_ok()
+        && model_id[0] == MODEL_ID
+}
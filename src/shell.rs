@@ -0,0 +1,426 @@
+//! Serial command shell that reconfigures a running sensor, so thresholds,
+//! modes and scalers can be tried without reflashing. The command grammar
+//! lives in the host-tested `vl6180x-shell` crate, re-exported here.
+//!
+//! [`Shell`] writes changes straight to the sensor registers over its own bus
+//! proxy, so they apply to a sensor the driver already set up. [`config`]
+//! turns the same [`Settings`] into the `vl6180x::Config` to start from.
+//! `calibrate offset` and `calibrate crosstalk` run [`crate::calibration`]
+//! and keep the result in the shell's [`Calibration`] for the caller to store.
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+pub use vl6180x_shell::*;
+
+use crate::calibration::{
+    calibrate_crosstalk, calibrate_offset, range_stats, CalibrationError, RangeSample,
+    CROSSTALK_SAMPLES, OFFSET_SAMPLES,
+};
+use crate::registers::*;
+use crate::storage::Calibration;
+use crate::timeout::{self, range_mm, ReadError};
+
+/// Polls a single shot `read` makes for its result, 1 ms apart.
+pub const READ_ATTEMPTS: u8 = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShellError<E> {
+    I2c(E),
+    /// The command needs something the shell doesn't own: `save` is up to
+    /// whoever holds the [`crate::storage::Store`].
+    Unsupported,
+    /// A single shot measurement didn't complete within [`READ_ATTEMPTS`] ms.
+    Timeout,
+    /// A range measurement reported this error code instead of a distance.
+    Range(u8),
+    /// Calibrating needs single shot readings, stop continuous range first.
+    Busy,
+    /// The readings were too far from the calibration target for the offset
+    /// register, the previous offset was kept.
+    OffsetOutOfRange {
+        measured_mm: u16,
+    },
+    /// A calibration was asked to average no readings.
+    NoSamples,
+}
+
+/// Executes shell commands against the sensor at `address`.
+pub struct Shell<I2C> {
+    i2c: I2C,
+    address: u8,
+    settings: Settings,
+    calibration: Calibration,
+    range_running: bool,
+    ambient_running: bool,
+}
+
+impl<I2C, E> Shell<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// `settings` must be what the sensor was configured with, e.g. through
+    /// [`config`], and neither continuous mode may be running.
+    pub fn new(i2c: I2C, address: u8, settings: Settings) -> Self {
+        Self {
+            i2c,
+            address,
+            settings,
+            calibration: Calibration::default(),
+            range_running: false,
+            ambient_running: false,
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// What has been calibrated or applied since [`Shell::new`]. Values left
+    /// at `None` are whatever the sensor came up with.
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    /// Writes the values `calibration` has to the sensor, e.g. the stored
+    /// ones after a reset. The others keep their current value.
+    pub fn apply_calibration(&mut self, calibration: Calibration) -> Result<(), ShellError<E>> {
+        if let Some(offset_mm) = calibration.offset_mm {
+            self.write_offset(offset_mm, self.settings.range_scaler)?;
+            self.calibration.offset_mm = Some(offset_mm);
+        }
+        if let Some(rate) = calibration.crosstalk_rate {
+            self.write_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE, rate)?;
+            self.calibration.crosstalk_rate = Some(rate);
+        }
+        Ok(())
+    }
+
+    pub fn execute(
+        &mut self,
+        command: Command,
+        delay: &mut impl DelayMs<u8>,
+    ) -> Result<Response, ShellError<E>> {
+        match command {
+            Command::Help => Ok(Response::Help),
+            Command::Show => Ok(Response::Settings(self.settings)),
+            Command::Get(setting) => Ok(Response::Value(self.settings.get(setting))),
+            Command::Set(assignment) => {
+                self.apply(assignment)?;
+                self.settings.apply(assignment);
+                Ok(Response::Ok)
+            }
+            Command::Start(measurement) => self.start_stop(measurement, true),
+            Command::Stop(measurement) => self.start_stop(measurement, false),
+            Command::Read(measurement) => self.read(measurement, delay),
+            Command::Save => Err(ShellError::Unsupported),
+            Command::CalibrateOffset(target_mm) => self.calibrate_offset(target_mm, delay),
+            Command::CalibrateCrosstalk(target_mm) => self.calibrate_crosstalk(target_mm, delay),
+        }
+    }
+
+    pub fn free(self) -> I2C {
+        self.i2c
+    }
+
+    fn apply(&mut self, assignment: Assignment) -> Result<(), ShellError<E>> {
+        match assignment {
+            Assignment::RangeMode(mode) => {
+                let config = self.read_u8(SYSTEM__INTERRUPT_CONFIG_GPIO)?;
+                let config = (config & !0x07) | interrupt_code(mode);
+                self.write_u8(SYSTEM__INTERRUPT_CONFIG_GPIO, config)
+            }
+            Assignment::AmbientMode(mode) => {
+                let config = self.read_u8(SYSTEM__INTERRUPT_CONFIG_GPIO)?;
+                let config = (config & !0x38) | (interrupt_code(mode) << 3);
+                self.write_u8(SYSTEM__INTERRUPT_CONFIG_GPIO, config)
+            }
+            Assignment::RangeHigh(mm) => self.write_u8(SYSRANGE__THRESH_HIGH, mm),
+            Assignment::RangeLow(mm) => self.write_u8(SYSRANGE__THRESH_LOW, mm),
+            Assignment::AmbientHigh(raw) => self.write_u16(SYSALS__THRESH_HIGH, raw),
+            Assignment::AmbientLow(raw) => self.write_u16(SYSALS__THRESH_LOW, raw),
+            Assignment::AmbientGain(level) => {
+                self.write_u8(SYSALS__ANALOGUE_GAIN, ALS_GAIN_BASE | level)
+            }
+            Assignment::RangeScaler(factor) => {
+                let scaler = RANGE_SCALER_VALUES[factor as usize - 1];
+                self.write_u16(RANGE_SCALER, scaler)?;
+                // The offset register counts in scaled units too
+                match self.calibration.offset_mm {
+                    Some(offset_mm) => self.write_offset(offset_mm, factor),
+                    None => Ok(()),
+                }
+            }
+            Assignment::AmbientScaler(factor) => self.write_u8(FIRMWARE__RESULT_SCALER, factor),
+        }
+    }
+
+    fn start_stop(
+        &mut self,
+        measurement: Measurement,
+        start: bool,
+    ) -> Result<Response, ShellError<E>> {
+        let (register, running) = match measurement {
+            Measurement::Range => (SYSRANGE__START, &mut self.range_running),
+            Measurement::Ambient => (SYSALS__START, &mut self.ambient_running),
+        };
+        // The same bit starts and stops, so only toggle it on a change
+        if *running != start {
+            *running = start;
+            let value = if start {
+                START_STOP | MODE_CONTINUOUS
+            } else {
+                START_STOP
+            };
+            self.write_u8(register, value)?;
+        }
+        Ok(Response::Ok)
+    }
+
+    /// Reads the latest continuous result, or takes a single shot while the
+    /// mode isn't running. A single shot briefly switches the interrupt to
+    /// new sample ready so completion shows in the interrupt status.
+    fn read(
+        &mut self,
+        measurement: Measurement,
+        delay: &mut impl DelayMs<u8>,
+    ) -> Result<Response, ShellError<E>> {
+        let running = match measurement {
+            Measurement::Range => self.range_running,
+            Measurement::Ambient => self.ambient_running,
+        };
+        let scaler = self.settings.range_scaler as u16;
+        let read = move |i2c: &mut I2C, address| {
+            match measurement {
+                Measurement::Range => read_u8(i2c, address, RESULT__RANGE_VAL)
+                    .map(|raw| Response::RangeMm(raw as u16 * scaler)),
+                Measurement::Ambient => {
+                    read_u16(i2c, address, RESULT__ALS_VAL).map(Response::AmbientRaw)
+                }
+            }
+            .map_err(ReadError::I2c)
+        };
+        if running {
+            read(&mut self.i2c, self.address).map_err(read_error)
+        } else {
+            self.single_shot(measurement, delay, read)
+        }
+    }
+
+    /// Averages [`OFFSET_SAMPLES`] single shot readings with the offset
+    /// register cleared and writes the offset that brings them to
+    /// `target_mm`. The previous offset is put back if that fails.
+    fn calibrate_offset(
+        &mut self,
+        target_mm: u16,
+        delay: &mut impl DelayMs<u8>,
+    ) -> Result<Response, ShellError<E>> {
+        if self.range_running {
+            return Err(ShellError::Busy);
+        }
+        let previous = self.read_u8(SYSRANGE__PART_TO_PART_RANGE_OFFSET)?;
+        self.write_u8(SYSRANGE__PART_TO_PART_RANGE_OFFSET, 0)?;
+
+        let measure = || self.range_sample(delay).map(|sample| sample.range_mm);
+        match calibrate_offset(target_mm, OFFSET_SAMPLES, measure) {
+            Ok(calibration) => {
+                self.write_offset(calibration.offset_mm, self.settings.range_scaler)?;
+                self.calibration.offset_mm = Some(calibration.offset_mm);
+                Ok(Response::Offset {
+                    offset_mm: calibration.offset_mm,
+                    measured_mm: calibration.measured_mm,
+                    target_mm,
+                })
+            }
+            Err(e) => {
+                self.write_u8(SYSRANGE__PART_TO_PART_RANGE_OFFSET, previous)?;
+                Err(calibration_error(e))
+            }
+        }
+    }
+
+    /// Measures [`CROSSTALK_SAMPLES`] single shot readings with crosstalk
+    /// compensation off, writes the rate that explains how short they read
+    /// and measures again with it. The previous rate is put back if that
+    /// fails.
+    fn calibrate_crosstalk(
+        &mut self,
+        target_mm: u16,
+        delay: &mut impl DelayMs<u8>,
+    ) -> Result<Response, ShellError<E>> {
+        if self.range_running {
+            return Err(ShellError::Busy);
+        }
+        let previous = self.read_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE)?;
+        self.write_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE, 0)?;
+
+        let calibration =
+            match calibrate_crosstalk(target_mm, CROSSTALK_SAMPLES, || self.range_sample(delay)) {
+                Ok(calibration) => calibration,
+                Err(e) => {
+                    self.write_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE, previous)?;
+                    return Err(calibration_error(e));
+                }
+            };
+        self.write_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE, calibration.rate)?;
+        self.calibration.crosstalk_rate = Some(calibration.rate);
+
+        let after = range_stats(CROSSTALK_SAMPLES, || self.range_sample(delay))
+            .map_err(calibration_error)?;
+        Ok(Response::Crosstalk {
+            rate: calibration.rate,
+            target_mm,
+            before: calibration.before,
+            after,
+        })
+    }
+
+    /// One single shot range reading and its return rate, failing on a
+    /// range error.
+    fn range_sample(&mut self, delay: &mut impl DelayMs<u8>) -> Result<RangeSample, ShellError<E>> {
+        self.single_shot(Measurement::Range, delay, |i2c, address| {
+            Ok(RangeSample {
+                range_mm: range_mm(i2c, address)?,
+                return_rate: read_u16(i2c, address, RESULT__RANGE_RETURN_RATE)
+                    .map_err(ReadError::I2c)?,
+            })
+        })
+    }
+
+    /// Writes `offset_mm` to the offset register, which the sensor adds to
+    /// the scaled result, so it is divided by the range `scaler` first.
+    fn write_offset(&mut self, offset_mm: i8, scaler: u8) -> Result<(), ShellError<E>> {
+        let (offset, scaler) = (offset_mm as i16, scaler as i16);
+        let half = if offset < 0 { -scaler / 2 } else { scaler / 2 };
+        let scaled = ((offset + half) / scaler) as i8;
+        self.write_u8(SYSRANGE__PART_TO_PART_RANGE_OFFSET, scaled as u8)
+    }
+
+    /// Takes a single shot of `measurement` and reads it with `read`,
+    /// giving up after [`READ_ATTEMPTS`] polls.
+    fn single_shot<R>(
+        &mut self,
+        measurement: Measurement,
+        delay: &mut impl DelayMs<u8>,
+        read: impl FnOnce(&mut I2C, u8) -> Result<R, ReadError<E>>,
+    ) -> Result<R, ShellError<E>> {
+        let mut attempts = 1;
+        let expired = || {
+            if attempts == READ_ATTEMPTS {
+                return true;
+            }
+            attempts += 1;
+            delay.delay_ms(1);
+            false
+        };
+        timeout::single_shot(
+            &mut self.i2c,
+            self.address,
+            measurement.into(),
+            expired,
+            read,
+        )
+        .map_err(read_error)
+    }
+
+    fn read_u8(&mut self, index: u16) -> Result<u8, ShellError<E>> {
+        read_u8(&mut self.i2c, self.address, index).map_err(ShellError::I2c)
+    }
+
+    fn read_u16(&mut self, index: u16) -> Result<u16, ShellError<E>> {
+        read_u16(&mut self.i2c, self.address, index).map_err(ShellError::I2c)
+    }
+
+    fn write_u8(&mut self, index: u16, value: u8) -> Result<(), ShellError<E>> {
+        write_u8(&mut self.i2c, self.address, index, value).map_err(ShellError::I2c)
+    }
+
+    fn write_u16(&mut self, index: u16, value: u16) -> Result<(), ShellError<E>> {
+        write_u16(&mut self.i2c, self.address, index, value).map_err(ShellError::I2c)
+    }
+}
+
+fn calibration_error<E>(e: CalibrationError<ShellError<E>>) -> ShellError<E> {
+    match e {
+        CalibrationError::Sensor(e) => e,
+        CalibrationError::NoSamples => ShellError::NoSamples,
+        CalibrationError::OffsetOutOfRange { measured_mm } => {
+            ShellError::OffsetOutOfRange { measured_mm }
+        }
+    }
+}
+
+fn read_error<E>(e: ReadError<E>) -> ShellError<E> {
+    match e {
+        ReadError::I2c(e) => ShellError::I2c(e),
+        ReadError::Timeout => ShellError::Timeout,
+        ReadError::Range(code) => ShellError::Range(code),
+    }
+}
+
+impl From<Measurement> for timeout::Measurement {
+    fn from(measurement: Measurement) -> Self {
+        match measurement {
+            Measurement::Range => timeout::Measurement::Range,
+            Measurement::Ambient => timeout::Measurement::Ambient,
+        }
+    }
+}
+
+fn interrupt_code(mode: InterruptMode) -> u8 {
+    match mode {
+        InterruptMode::Disabled => INT_DISABLED,
+        InterruptMode::LevelLow => INT_LEVEL_LOW,
+        InterruptMode::LevelHigh => INT_LEVEL_HIGH,
+        InterruptMode::OutOfWindow => INT_OUT_OF_WINDOW,
+        InterruptMode::NewSampleReady => INT_NEW_SAMPLE_READY,
+    }
+}
+
+fn range_interrupt_mode(mode: InterruptMode) -> Option<vl6180x::RangeInterruptMode> {
+    match mode {
+        InterruptMode::Disabled => None,
+        InterruptMode::LevelLow => Some(vl6180x::RangeInterruptMode::LevelLow),
+        InterruptMode::LevelHigh => Some(vl6180x::RangeInterruptMode::LevelHigh),
+        InterruptMode::OutOfWindow => Some(vl6180x::RangeInterruptMode::OutOfWindow),
+        InterruptMode::NewSampleReady => Some(vl6180x::RangeInterruptMode::NewSampleReady),
+    }
+}
+
+fn ambient_interrupt_mode(mode: InterruptMode) -> Option<vl6180x::AmbientInterruptMode> {
+    match mode {
+        InterruptMode::Disabled => None,
+        InterruptMode::LevelLow => Some(vl6180x::AmbientInterruptMode::LevelLow),
+        InterruptMode::LevelHigh => Some(vl6180x::AmbientInterruptMode::LevelHigh),
+        InterruptMode::OutOfWindow => Some(vl6180x::AmbientInterruptMode::OutOfWindow),
+        InterruptMode::NewSampleReady => Some(vl6180x::AmbientInterruptMode::NewSampleReady),
+    }
+}
+
+/// The driver configuration matching `settings`. A disabled interrupt mode
+/// leaves the driver default, which has interrupts off.
+///
+/// Panics if the gain or a scaler is outside what the shell accepts.
+pub fn config(settings: &Settings) -> vl6180x::Config {
+    let mut config = vl6180x::Config::new();
+    if let Some(mode) = range_interrupt_mode(settings.range_mode) {
+        config.set_range_interrupt_mode(mode);
+    }
+    config.set_range_high_interrupt_threshold(settings.range_high);
+    config.set_range_low_interrupt_threshold(settings.range_low);
+    config
+        .set_range_result_scaler(settings.range_scaler)
+        .expect("range scaler");
+    if let Some(mode) = ambient_interrupt_mode(settings.ambient_mode) {
+        config.set_ambient_interrupt_mode(mode);
+    }
+    config.set_ambient_high_interrupt_threshold(settings.ambient_high);
+    config.set_ambient_low_interrupt_threshold(settings.ambient_low);
+    config
+        .set_ambient_analogue_gain_level(settings.ambient_gain)
+        .expect("ambient gain");
+    config
+        .set_ambient_result_scaler(settings.ambient_scaler)
+        .expect("ambient scaler");
+    config
+}
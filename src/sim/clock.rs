@@ -0,0 +1,38 @@
+//! Simulated time source for the timeout-bounded reads.
+
+use core::cell::Cell;
+
+use crate::timeout::TimeSource;
+
+/// A millisecond clock that moves on by a fixed step every time it is read,
+/// so a loop waiting on it always gets to its deadline.
+#[derive(Debug)]
+pub struct SimClock {
+    now_ms: Cell<u32>,
+    step_ms: u32,
+}
+
+impl SimClock {
+    pub fn new(start_ms: u32, step_ms: u32) -> Self {
+        Self {
+            now_ms: Cell::new(start_ms),
+            step_ms,
+        }
+    }
+
+    pub fn now_ms(&self) -> u32 {
+        self.now_ms.get()
+    }
+}
+
+impl TimeSource for SimClock {
+    fn ticks(&self) -> u32 {
+        let now_ms = self.now_ms.get();
+        self.now_ms.set(now_ms.wrapping_add(self.step_ms));
+        now_ms
+    }
+
+    fn ticks_per_ms(&self) -> u32 {
+        1
+    }
+}
@@ -0,0 +1,116 @@
+//! Simulated EXTI line and LED for driving the interrupt handlers on the host.
+
+use core::cell::RefCell;
+
+use super::SimVl6180x;
+use crate::handlers::{InterruptPin, StatusLed};
+
+/// Which edge of GPIO1 sets the pending bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    RisingFalling,
+}
+
+/// An MCU input with EXTI enabled, wired to a simulated sensor's GPIO1.
+///
+/// Call [`sample`](SimInterruptPin::sample) after anything that may change the
+/// line, typically [`SimVl6180x::tick`]; an edge matching the trigger latches
+/// the pending bit until the handler clears it, as the real EXTI does.
+pub struct SimInterruptPin<'a> {
+    device: &'a RefCell<SimVl6180x>,
+    edge: Edge,
+    level: bool,
+    pending: bool,
+    interrupts: u32,
+}
+
+impl<'a> SimInterruptPin<'a> {
+    pub fn new(device: &'a RefCell<SimVl6180x>, edge: Edge) -> Self {
+        let level = device.borrow().gpio1_is_high();
+        Self {
+            device,
+            edge,
+            level,
+            pending: false,
+            interrupts: 0,
+        }
+    }
+
+    /// Samples GPIO1 and returns true if the pending bit is set.
+    pub fn sample(&mut self) -> bool {
+        let level = self.device.borrow().gpio1_is_high();
+        let fired = match self.edge {
+            Edge::Rising => !self.level && level,
+            Edge::Falling => self.level && !level,
+            Edge::RisingFalling => self.level != level,
+        };
+        if fired {
+            self.pending = true;
+            self.interrupts += 1;
+        }
+        self.level = level;
+        self.pending
+    }
+
+    /// Advances the sensor by one measurement period and samples the line.
+    pub fn tick(&mut self) -> bool {
+        self.device.borrow_mut().tick();
+        self.sample()
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.level
+    }
+
+    /// Number of edges that have set the pending bit so far.
+    pub fn interrupts(&self) -> u32 {
+        self.interrupts
+    }
+}
+
+impl InterruptPin for SimInterruptPin<'_> {
+    fn check_interrupt(&self) -> bool {
+        self.pending
+    }
+
+    fn clear_interrupt_pending_bit(&mut self) {
+        self.pending = false;
+    }
+}
+
+/// Records what the handlers do with the LED.
+#[derive(Debug, Default)]
+pub struct SimLed {
+    on: bool,
+    flashes: u32,
+}
+
+impl SimLed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Number of times the LED has been switched on.
+    pub fn flashes(&self) -> u32 {
+        self.flashes
+    }
+}
+
+impl StatusLed for SimLed {
+    fn on(&mut self) {
+        if !self.on {
+            self.flashes += 1;
+        }
+        self.on = true;
+    }
+
+    fn off(&mut self) {
+        self.on = false;
+    }
+}
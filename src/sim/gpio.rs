@@ -0,0 +1,62 @@
+//! Simulated XSHUT line and delay provider.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::OutputPin;
+
+use super::SimVl6180x;
+
+/// An MCU output wired to a simulated sensor's XSHUT input.
+pub struct SimXshutPin<'a> {
+    device: &'a RefCell<SimVl6180x>,
+}
+
+impl<'a> SimXshutPin<'a> {
+    pub fn new(device: &'a RefCell<SimVl6180x>) -> Self {
+        Self { device }
+    }
+}
+
+impl OutputPin for SimXshutPin<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.device.borrow_mut().set_powered(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.device.borrow_mut().set_powered(true);
+        Ok(())
+    }
+}
+
+/// A delay that returns immediately, only adding up how long it was asked to wait.
+#[derive(Debug, Default)]
+pub struct SimDelay {
+    elapsed_ms: u32,
+}
+
+impl SimDelay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn elapsed_ms(&self) -> u32 {
+        self.elapsed_ms
+    }
+}
+
+impl DelayMs<u8> for SimDelay {
+    fn delay_ms(&mut self, ms: u8) {
+        self.elapsed_ms += ms as u32;
+    }
+}
+
+impl DelayMs<u32> for SimDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        self.elapsed_ms += ms;
+    }
+}
@@ -0,0 +1,131 @@
+//! Simulated SCL and SDA lines, for driving bus recovery on the host.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+#[derive(Debug, Default)]
+struct Lines {
+    scl_low: bool,
+    sda_low: bool,
+    /// Clock pulses the device keeps holding SDA low for.
+    held_for: u8,
+    pulses: u32,
+    stops: u32,
+}
+
+impl Lines {
+    fn sda_is_high(&self) -> bool {
+        !self.sda_low && self.held_for == 0
+    }
+}
+
+/// An open drain I2C bus with one device that may be stuck holding SDA low,
+/// e.g. after it was reset in the middle of sending a byte. Every rising
+/// edge on SCL clocks one bit out of it.
+#[derive(Debug, Default)]
+pub struct SimI2cLines {
+    lines: RefCell<Lines>,
+}
+
+impl SimI2cLines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the device hold SDA low for `pulses` more clock pulses,
+    /// `u8::MAX` for good.
+    pub fn hold_sda(&self, pulses: u8) {
+        self.lines.borrow_mut().held_for = pulses;
+    }
+
+    /// Both lines high, nothing driving them.
+    pub fn is_idle(&self) -> bool {
+        let lines = self.lines.borrow();
+        !lines.scl_low && lines.sda_is_high()
+    }
+
+    /// Rising edges seen on SCL.
+    pub fn pulses(&self) -> u32 {
+        self.lines.borrow().pulses
+    }
+
+    /// STOP conditions seen: SDA rising while SCL is high.
+    pub fn stops(&self) -> u32 {
+        self.lines.borrow().stops
+    }
+
+    pub fn scl(&self) -> SimLine<'_> {
+        SimLine {
+            lines: &self.lines,
+            scl: true,
+        }
+    }
+
+    pub fn sda(&self) -> SimLine<'_> {
+        SimLine {
+            lines: &self.lines,
+            scl: false,
+        }
+    }
+}
+
+/// One line of a [`SimI2cLines`], as an open drain MCU pin.
+pub struct SimLine<'a> {
+    lines: &'a RefCell<Lines>,
+    scl: bool,
+}
+
+impl SimLine<'_> {
+    fn drive(&mut self, low: bool) {
+        let mut lines = self.lines.borrow_mut();
+        if self.scl {
+            let rising = lines.scl_low && !low;
+            lines.scl_low = low;
+            if rising {
+                lines.pulses += 1;
+                if lines.held_for != u8::MAX {
+                    lines.held_for = lines.held_for.saturating_sub(1);
+                }
+            }
+        } else {
+            let was_high = lines.sda_is_high();
+            lines.sda_low = low;
+            if !was_high && lines.sda_is_high() && !lines.scl_low {
+                lines.stops += 1;
+            }
+        }
+    }
+}
+
+impl OutputPin for SimLine<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.drive(true);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.drive(false);
+        Ok(())
+    }
+}
+
+impl InputPin for SimLine<'_> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        let lines = self.lines.borrow();
+        Ok(if self.scl {
+            !lines.scl_low
+        } else {
+            lines.sda_is_high()
+        })
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
@@ -0,0 +1,16 @@
+//! Host-side simulation of the hardware the examples talk to, so sensor logic
+//! can be exercised without a Black Pill on the bench.
+//!
+//! Enabled with the `sim` feature.
+
+mod clock;
+mod exti;
+mod gpio;
+mod lines;
+mod sensor;
+
+pub use self::clock::SimClock;
+pub use self::exti::{Edge, SimInterruptPin, SimLed};
+pub use self::gpio::{SimDelay, SimXshutPin};
+pub use self::lines::{SimI2cLines, SimLine};
+pub use self::sensor::{SimBus, SimError, SimVl6180x};
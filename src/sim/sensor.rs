@@ -0,0 +1,454 @@
+//! Register-level model of a VL6180X and the I2C bus it sits on.
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use heapless::Deque;
+
+use crate::ambient::AlsStep;
+use crate::registers::*;
+
+const REGISTER_SPACE: usize = 0x300;
+const SCRIPT_DEPTH: usize = 32;
+
+/// Range error code reported when the target is further away than the scaled
+/// range register can hold.
+const RANGE_OVERFLOW: u8 = 15;
+
+/// Return signal rate of the target until set otherwise, 4 Mcps in 9.7 fixed point.
+const DEFAULT_RETURN_RATE: u16 = 4 << 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// No powered device acknowledged the address.
+    Nack(u8),
+    /// More than one powered device answered on the same address.
+    AddressCollision(u8),
+    /// A transfer was too short to carry a 16 bit register index.
+    ShortTransfer,
+    /// A transfer ran past the end of the modelled register map.
+    InvalidRegister(u16),
+}
+
+/// One simulated VL6180X.
+///
+/// Measurements complete as soon as they are started; continuous modes take a
+/// new sample on every [`tick`](SimVl6180x::tick). The target distance comes
+/// from [`set_range_mm`](SimVl6180x::set_range_mm) or, when queued, from a
+/// script of distances consumed one per measurement.
+pub struct SimVl6180x {
+    regs: [u8; REGISTER_SPACE],
+    powered: bool,
+    range_continuous: bool,
+    ambient_continuous: bool,
+    range_mm: u16,
+    range_script: Deque<u16, SCRIPT_DEPTH>,
+    range_error: u8,
+    part_offset_mm: i16,
+    return_rate: u16,
+    crosstalk_rate: u16,
+    ambient_raw: u16,
+    ambient_lux: Option<f32>,
+    stalled: bool,
+}
+
+impl Default for SimVl6180x {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimVl6180x {
+    /// A powered sensor fresh out of reset at the default address.
+    pub fn new() -> Self {
+        let mut sim = Self {
+            regs: [0; REGISTER_SPACE],
+            powered: true,
+            range_continuous: false,
+            ambient_continuous: false,
+            range_mm: 0,
+            range_script: Deque::new(),
+            range_error: 0,
+            part_offset_mm: 0,
+            return_rate: DEFAULT_RETURN_RATE,
+            crosstalk_rate: 0,
+            ambient_raw: 0,
+            ambient_lux: None,
+            stalled: false,
+        };
+        sim.reset();
+        sim
+    }
+
+    fn reset(&mut self) {
+        self.regs = [0; REGISTER_SPACE];
+        self.regs[IDENTIFICATION__MODEL_ID as usize] = MODEL_ID;
+        self.regs[SYSTEM__FRESH_OUT_OF_RESET as usize] = 1;
+        self.regs[I2C_SLAVE__DEVICE_ADDRESS as usize] = DEFAULT_ADDRESS;
+        self.regs[RESULT__RANGE_STATUS as usize] = 0x01;
+        self.regs[RESULT__ALS_STATUS as usize] = 0x01;
+        self.regs[SYSRANGE__THRESH_HIGH as usize] = 0xFF;
+        self.write_u16(RANGE_SCALER, RANGE_SCALER_VALUES[0]);
+        self.range_continuous = false;
+        self.ambient_continuous = false;
+    }
+
+    /// Current 7 bit I2C address.
+    pub fn address(&self) -> u8 {
+        self.regs[I2C_SLAVE__DEVICE_ADDRESS as usize]
+    }
+
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// Drives the XSHUT line. Powering back up resets every register,
+    /// including the I2C address.
+    pub fn set_powered(&mut self, powered: bool) {
+        if powered && !self.powered {
+            self.reset();
+        }
+        self.powered = powered;
+    }
+
+    /// Distance to the target used when no scripted distance is queued.
+    pub fn set_range_mm(&mut self, mm: u16) {
+        self.range_mm = mm;
+    }
+
+    /// Queues distances for the next measurements, one per measurement.
+    /// Returns how many fitted in the script.
+    pub fn queue_range_mm(&mut self, distances: &[u16]) -> usize {
+        distances
+            .iter()
+            .take_while(|mm| self.range_script.push_back(**mm).is_ok())
+            .count()
+    }
+
+    /// Forces the range error code reported by the following measurements, 0 clears it.
+    pub fn set_range_error(&mut self, code: u8) {
+        self.range_error = code & 0x0F;
+    }
+
+    /// How far this part reads off before its offset register is applied,
+    /// the error [`crate::calibration`] corrects.
+    pub fn set_part_offset_mm(&mut self, mm: i16) {
+        self.part_offset_mm = mm;
+    }
+
+    /// Return signal rate from the target, in Mcps as 9.7 fixed point.
+    pub fn set_return_rate(&mut self, rate: u16) {
+        self.return_rate = rate;
+    }
+
+    /// Signal a cover window in front of the sensor reflects straight back,
+    /// in Mcps as 9.7 fixed point. It comes from about 0 mm, so it pulls
+    /// readings short by its share of the total return signal, less what
+    /// `SYSRANGE__CROSSTALK_COMPENSATION_RATE` takes back out.
+    pub fn set_crosstalk_rate(&mut self, rate: u16) {
+        self.crosstalk_rate = rate;
+    }
+
+    /// Raw ALS count reported by the following measurements.
+    pub fn set_ambient_raw(&mut self, raw: u16) {
+        self.ambient_raw = raw;
+        self.ambient_lux = None;
+    }
+
+    /// Light level for the following measurements, which count it with the
+    /// gain and integration time the registers hold, saturating at 0xFFFF.
+    pub fn set_ambient_lux(&mut self, lux: f32) {
+        self.ambient_lux = Some(lux);
+    }
+
+    /// Makes measurements start but never finish, like a sensor that locked
+    /// up: no results and no interrupts until it is cleared again.
+    pub fn set_stalled(&mut self, stalled: bool) {
+        self.stalled = stalled;
+    }
+
+    pub fn is_range_continuous(&self) -> bool {
+        self.range_continuous
+    }
+
+    pub fn is_ambient_continuous(&self) -> bool {
+        self.ambient_continuous
+    }
+
+    /// Reads a register without any side effects.
+    pub fn register(&self, index: u16) -> u8 {
+        self.regs[index as usize]
+    }
+
+    /// Reads a big endian 16 bit register without any side effects.
+    pub fn register_u16(&self, index: u16) -> u16 {
+        u16::from_be_bytes([self.regs[index as usize], self.regs[index as usize + 1]])
+    }
+
+    /// Writes a register without any side effects.
+    pub fn set_register(&mut self, index: u16, value: u8) {
+        self.regs[index as usize] = value;
+    }
+
+    /// Current `RESULT__INTERRUPT_STATUS_GPIO` value.
+    pub fn interrupt_status(&self) -> u8 {
+        self.regs[RESULT__INTERRUPT_STATUS_GPIO as usize]
+    }
+
+    /// True while an interrupt is pending on GPIO1, regardless of polarity.
+    pub fn gpio1_asserted(&self) -> bool {
+        self.powered && self.interrupt_status() != 0
+    }
+
+    /// Electrical level of GPIO1, taking the polarity bit of
+    /// `SYSTEM__MODE_GPIO1` into account.
+    pub fn gpio1_is_high(&self) -> bool {
+        let active_high = self.regs[SYSTEM__MODE_GPIO1 as usize] & 0x20 != 0;
+        self.gpio1_asserted() == active_high
+    }
+
+    /// Advances one inter-measurement period, sampling whatever continuous
+    /// modes are running.
+    pub fn tick(&mut self) {
+        if !self.powered {
+            return;
+        }
+        let interleaved = self.regs[INTERLEAVED_MODE__ENABLE as usize] & 0x01 != 0;
+        if self.range_continuous || (self.ambient_continuous && interleaved) {
+            self.measure_range();
+        }
+        if self.ambient_continuous {
+            self.measure_ambient();
+        }
+    }
+
+    fn write_u16(&mut self, index: u16, value: u16) {
+        let [msb, lsb] = value.to_be_bytes();
+        self.regs[index as usize] = msb;
+        self.regs[index as usize + 1] = lsb;
+    }
+
+    fn write_register(&mut self, index: u16, value: u8) {
+        match index {
+            SYSRANGE__START => {
+                self.regs[index as usize] = value & MODE_CONTINUOUS;
+                if value & START_STOP == 0 {
+                    return;
+                }
+                if self.range_continuous {
+                    self.range_continuous = false;
+                } else {
+                    self.range_continuous = value & MODE_CONTINUOUS != 0;
+                    self.measure_range();
+                }
+            }
+            SYSALS__START => {
+                self.regs[index as usize] = value & MODE_CONTINUOUS;
+                if value & START_STOP == 0 {
+                    return;
+                }
+                if self.ambient_continuous {
+                    self.ambient_continuous = false;
+                } else {
+                    self.ambient_continuous = value & MODE_CONTINUOUS != 0;
+                    self.measure_ambient();
+                }
+            }
+            SYSTEM__INTERRUPT_CLEAR => {
+                let mut mask = 0;
+                if value & CLEAR_RANGE_INT != 0 {
+                    mask |= 0x07;
+                }
+                if value & CLEAR_ALS_INT != 0 {
+                    mask |= 0x38;
+                }
+                if value & CLEAR_ERROR_INT != 0 {
+                    mask |= 0xC0;
+                }
+                self.regs[RESULT__INTERRUPT_STATUS_GPIO as usize] &= !mask;
+            }
+            I2C_SLAVE__DEVICE_ADDRESS => self.regs[index as usize] = value & 0x7F,
+            _ => self.regs[index as usize] = value,
+        }
+    }
+
+    fn measure_range(&mut self) {
+        if self.stalled {
+            return;
+        }
+        if let Some(mm) = self.range_script.pop_front() {
+            self.range_mm = mm;
+        }
+        let scaler = range_scaling_factor(self.register_u16(RANGE_SCALER)).unwrap_or(1) as i32;
+        // The offset register is added to the scaled result
+        let offset = self.regs[SYSRANGE__PART_TO_PART_RANGE_OFFSET as usize] as i8 as i32;
+        let mm = self.apparent_mm() as i32 + self.part_offset_mm as i32;
+        let scaled = (mm / scaler + offset).clamp(0, u16::MAX as i32) as u16;
+        let (raw, error) = match (self.range_error, scaled) {
+            (0, raw) if raw < 255 => (raw as u8, 0),
+            (0, _) => (255, RANGE_OVERFLOW),
+            (code, _) => (255, code),
+        };
+        self.regs[RESULT__RANGE_VAL as usize] = raw;
+        let total_rate = self.return_rate.saturating_add(self.crosstalk_rate);
+        self.write_u16(RESULT__RANGE_RETURN_RATE, total_rate);
+        self.regs[RESULT__RANGE_STATUS as usize] = (error << 4) | 0x01;
+
+        let mode = self.regs[SYSTEM__INTERRUPT_CONFIG_GPIO as usize] & 0x07;
+        let low = self.regs[SYSRANGE__THRESH_LOW as usize] as u16;
+        let high = self.regs[SYSRANGE__THRESH_HIGH as usize] as u16;
+        if triggered(mode, raw as u16, low, high) {
+            let status = &mut self.regs[RESULT__INTERRUPT_STATUS_GPIO as usize];
+            *status = (*status & !0x07) | mode;
+        }
+    }
+
+    /// Target distance as seen through the cover window: the signal weighted
+    /// mean of the target and the glass, the compensated rate left out.
+    fn apparent_mm(&self) -> u16 {
+        let compensation = self.register_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE) as u32;
+        let signal = self.return_rate as u32;
+        let total = signal + self.crosstalk_rate as u32;
+        let effective = total.saturating_sub(compensation).max(1);
+        let mm = (self.range_mm as u32 * signal + effective / 2) / effective;
+        mm.min(u16::MAX as u32) as u16
+    }
+
+    fn measure_ambient(&mut self) {
+        if self.stalled {
+            return;
+        }
+        if let Some(lux) = self.ambient_lux {
+            let step = AlsStep::new(
+                self.regs[SYSALS__ANALOGUE_GAIN as usize] & 0x07,
+                (self.register_u16(SYSALS__INTEGRATION_PERIOD) & 0x1FF) + 1,
+            );
+            let counts = lux / step.lux(1) + 0.5;
+            self.ambient_raw = counts.min(u16::MAX as f32) as u16;
+        }
+        self.write_u16(RESULT__ALS_VAL, self.ambient_raw);
+
+        let mode = (self.regs[SYSTEM__INTERRUPT_CONFIG_GPIO as usize] >> 3) & 0x07;
+        let low = self.register_u16(SYSALS__THRESH_LOW);
+        let high = self.register_u16(SYSALS__THRESH_HIGH);
+        if triggered(mode, self.ambient_raw, low, high) {
+            let status = &mut self.regs[RESULT__INTERRUPT_STATUS_GPIO as usize];
+            *status = (*status & !0x38) | (mode << 3);
+        }
+    }
+
+    fn handle_write(&mut self, bytes: &[u8]) -> Result<(), SimError> {
+        let (index, data) = split_index(bytes)?;
+        check_span(index, data.len())?;
+        for (offset, value) in data.iter().enumerate() {
+            self.write_register(index + offset as u16, *value);
+        }
+        Ok(())
+    }
+
+    fn handle_write_read(&mut self, bytes: &[u8], buffer: &mut [u8]) -> Result<(), SimError> {
+        let (index, data) = split_index(bytes)?;
+        // Nothing is written unless the whole transfer is valid
+        check_span(index, data.len())?;
+        check_span(index, buffer.len())?;
+        for (offset, value) in data.iter().enumerate() {
+            self.write_register(index + offset as u16, *value);
+        }
+        let start = index as usize;
+        buffer.copy_from_slice(&self.regs[start..start + buffer.len()]);
+        Ok(())
+    }
+}
+
+fn triggered(mode: u8, value: u16, low: u16, high: u16) -> bool {
+    match mode {
+        INT_LEVEL_LOW => value < low,
+        INT_LEVEL_HIGH => value > high,
+        INT_OUT_OF_WINDOW => value < low || value > high,
+        INT_NEW_SAMPLE_READY => true,
+        _ => false,
+    }
+}
+
+fn split_index(bytes: &[u8]) -> Result<(u16, &[u8]), SimError> {
+    match bytes {
+        [msb, lsb, data @ ..] => Ok((u16::from_be_bytes([*msb, *lsb]), data)),
+        _ => Err(SimError::ShortTransfer),
+    }
+}
+
+fn check_span(index: u16, len: usize) -> Result<(), SimError> {
+    match (index as usize).checked_add(len) {
+        Some(end) if end <= REGISTER_SPACE => Ok(()),
+        _ => Err(SimError::InvalidRegister(index)),
+    }
+}
+
+/// An I2C bus with simulated sensors on it.
+///
+/// The handle is `Copy`, so one can be given to the driver while the test
+/// keeps another to script and inspect the sensors.
+#[derive(Clone, Copy)]
+pub struct SimBus<'a> {
+    devices: &'a [RefCell<SimVl6180x>],
+}
+
+impl<'a> SimBus<'a> {
+    pub fn new(devices: &'a [RefCell<SimVl6180x>]) -> Self {
+        Self { devices }
+    }
+
+    /// Every sensor on the bus, powered or not.
+    pub fn devices(&self) -> &'a [RefCell<SimVl6180x>] {
+        self.devices
+    }
+
+    /// Number of powered sensors answering on `address`.
+    pub fn responders(&self, address: u8) -> usize {
+        self.devices
+            .iter()
+            .filter(|device| {
+                let device = device.borrow();
+                device.is_powered() && device.address() == address
+            })
+            .count()
+    }
+
+    fn with_device<R>(
+        &self,
+        address: u8,
+        f: impl FnOnce(&mut SimVl6180x) -> Result<R, SimError>,
+    ) -> Result<R, SimError> {
+        match self.responders(address) {
+            0 => Err(SimError::Nack(address)),
+            1 => {
+                let device = self
+                    .devices
+                    .iter()
+                    .find(|device| {
+                        let device = device.borrow();
+                        device.is_powered() && device.address() == address
+                    })
+                    .ok_or(SimError::Nack(address))?;
+                f(&mut device.borrow_mut())
+            }
+            _ => Err(SimError::AddressCollision(address)),
+        }
+    }
+}
+
+impl Write for SimBus<'_> {
+    type Error = SimError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        self.with_device(address, |device| device.handle_write(bytes))
+    }
+}
+
+impl WriteRead for SimBus<'_> {
+    type Error = SimError;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), SimError> {
+        self.with_device(address, |device| device.handle_write_read(bytes, buffer))
+    }
+}
@@ -0,0 +1,178 @@
+//! Table driven state machine for menus and test sequences.
+//!
+//! Each [`State`] lists the actions to run on entering it, on every tick
+//! while in it and on leaving it; a [`Transition`] from a button or the
+//! console moves between them. The actions are plain values, `A`, run by an
+//! [`Actions`] implementation, so a table can be a `const` and its ordering
+//! checked on the host against one that only records them.
+//!
+//! A transition runs the exit actions of the state it leaves, then the enter
+//! actions of the one it arrives at, each in table order. Moving to the
+//! state it is already in leaves and enters it again.
+
+use crate::button::ButtonEvent;
+
+/// One row of the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct State<A: 'static> {
+    pub name: &'static str,
+    pub enter: &'static [A],
+    /// Run by every [`StateMachine::tick`].
+    pub tick: &'static [A],
+    pub exit: &'static [A],
+}
+
+impl<A> State<A> {
+    /// A state with no actions, to add them to.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            enter: &[],
+            tick: &[],
+            exit: &[],
+        }
+    }
+
+    pub const fn on_enter(mut self, actions: &'static [A]) -> Self {
+        self.enter = actions;
+        self
+    }
+
+    pub const fn on_tick(mut self, actions: &'static [A]) -> Self {
+        self.tick = actions;
+        self
+    }
+
+    pub const fn on_exit(mut self, actions: &'static [A]) -> Self {
+        self.exit = actions;
+        self
+    }
+}
+
+/// Runs the actions of a table.
+pub trait Actions<A> {
+    type Error;
+
+    fn run(&mut self, action: &A) -> Result<(), Self::Error>;
+}
+
+/// Where to go from the current state. Next and previous wrap around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transition {
+    Next,
+    Previous,
+    First,
+    /// To the state at this index, ignored if there is none.
+    To(usize),
+}
+
+impl Transition {
+    /// A short press moves on, a double press goes back and a long press
+    /// starts over.
+    pub fn from_button(event: ButtonEvent) -> Self {
+        match event {
+            ButtonEvent::ShortPress => Transition::Next,
+            ButtonEvent::DoublePress => Transition::Previous,
+            ButtonEvent::LongPress => Transition::First,
+        }
+    }
+
+    /// `n`/space for next, `p` for previous, `r` to restart, `0`-`9` to go
+    /// straight to a state; `None` for any other key.
+    pub fn from_key(key: u8) -> Option<Self> {
+        match key {
+            b'n' | b' ' => Some(Transition::Next),
+            b'p' => Some(Transition::Previous),
+            b'r' => Some(Transition::First),
+            b'0'..=b'9' => Some(Transition::To((key - b'0') as usize)),
+            _ => None,
+        }
+    }
+}
+
+/// Steps through a table of [`State`]s, see the module docs.
+#[derive(Clone, Debug)]
+pub struct StateMachine<A: 'static> {
+    states: &'static [State<A>],
+    current: usize,
+}
+
+impl<A> StateMachine<A> {
+    /// Starts in the first state, without entering it, see
+    /// [`start`](Self::start).
+    ///
+    /// # Panics
+    ///
+    /// If `states` is empty.
+    pub fn new(states: &'static [State<A>]) -> Self {
+        assert!(!states.is_empty(), "no states");
+        Self { states, current: 0 }
+    }
+
+    pub fn states(&self) -> &'static [State<A>] {
+        self.states
+    }
+
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> &'static State<A> {
+        &self.states[self.current]
+    }
+
+    /// Runs the enter actions of the first state.
+    pub fn start<R: Actions<A>>(&mut self, actions: &mut R) -> Result<(), R::Error> {
+        self.start_at(0, actions)
+    }
+
+    /// Runs the enter actions of the state at `index`, for a table that
+    /// doesn't start at its first state.
+    ///
+    /// # Panics
+    ///
+    /// If there is no state at `index`.
+    pub fn start_at<R: Actions<A>>(
+        &mut self,
+        index: usize,
+        actions: &mut R,
+    ) -> Result<(), R::Error> {
+        assert!(index < self.states.len(), "no state {}", index);
+        self.current = index;
+        run_all(actions, self.current().enter)
+    }
+
+    /// Runs the tick actions of the current state.
+    pub fn tick<R: Actions<A>>(&mut self, actions: &mut R) -> Result<(), R::Error> {
+        run_all(actions, self.current().tick)
+    }
+
+    /// Leaves the current state and enters the one `transition` leads to.
+    /// Returns false for a transition to nowhere, which does nothing.
+    ///
+    /// If an exit action fails the machine stays where it was; if an enter
+    /// action fails it is in the new state with the rest of its enter
+    /// actions skipped.
+    pub fn handle<R: Actions<A>>(
+        &mut self,
+        transition: Transition,
+        actions: &mut R,
+    ) -> Result<bool, R::Error> {
+        let count = self.states.len();
+        let next = match transition {
+            Transition::Next => (self.current + 1) % count,
+            Transition::Previous => (self.current + count - 1) % count,
+            Transition::First => 0,
+            Transition::To(index) if index < count => index,
+            Transition::To(_) => return Ok(false),
+        };
+        run_all(actions, self.current().exit)?;
+        self.current = next;
+        run_all(actions, self.current().enter)?;
+        Ok(true)
+    }
+}
+
+fn run_all<A, R: Actions<A>>(actions: &mut R, list: &[A]) -> Result<(), R::Error> {
+    list.iter().try_for_each(|action| actions.run(action))
+}
@@ -0,0 +1,57 @@
+//! Rolling statistics of the range readings. The statistics themselves live
+//! in the host-tested `vl6180x-stats` crate, re-exported here.
+//!
+//! [`range_reporter`] is the report the examples share, [`record_failed_read`]
+//! files a failed read under the sensor's range error code and [`log_report`]
+//! logs a report once it is due.
+
+use embedded_hal::blocking::i2c::WriteRead;
+
+pub use vl6180x_stats::*;
+
+use crate::timeout::{failed_range_read, ReadError};
+
+/// How often [`range_reporter`] hands over a report.
+pub const REPORT_PERIOD_MS: u32 = 10_000;
+
+/// Histogram buckets of a [`RangeReporter`].
+pub const RANGE_BUCKETS: usize = 16;
+
+/// 16 mm buckets over the whole 0 to 255 mm; narrow them around the
+/// target's distance to see the noise of a mount.
+pub const RANGE_STATS: StatsConfig = StatsConfig {
+    histogram_start: 0,
+    bucket_width: 16,
+};
+
+pub type RangeReporter = Reporter<RANGE_BUCKETS>;
+
+/// Reports [`RANGE_STATS`] every [`REPORT_PERIOD_MS`], the first period
+/// starting at `now_ms`.
+pub fn range_reporter(now_ms: u32) -> RangeReporter {
+    Reporter::new(RANGE_STATS, REPORT_PERIOD_MS, now_ms)
+}
+
+/// Records a range read that failed on the sensor at `address`: under the
+/// error code of its last result, or as a failure if there is none or the
+/// status can't be read either.
+pub fn record_failed_read<const N: usize, I2C: WriteRead>(
+    stats: &mut SensorStats<N>,
+    i2c: &mut I2C,
+    address: u8,
+) {
+    match failed_range_read(i2c, address, ()) {
+        ReadError::Range(code) => stats.record_error(code),
+        _ => stats.record_failure(),
+    }
+}
+
+/// Logs the report `reporter` has due at `now_ms`, if any, in three lines:
+/// the summary, the histogram and the error counts.
+pub fn log_report<const N: usize>(reporter: &mut Reporter<N>, now_ms: u32) {
+    if let Some(report) = reporter.poll(now_ms) {
+        crate::log!("Range stats: {}", report);
+        crate::log!("Range histogram: {}", report.histogram());
+        crate::log!("Range errors: {}", report.errors());
+    }
+}
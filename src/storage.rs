@@ -0,0 +1,81 @@
+//! Settings kept in internal flash across resets, see `vl6180x-store`.
+//!
+//! The store takes two sectors of flash that the `memory.x` `build.rs`
+//! writes keeps the program out of: the last two, 6 and 7, on the 512K
+//! chips. On the 256K STM32F401CC those would take 192K of it, so the store
+//! takes the 16K sectors 1 and 2 instead, between the vector table and the
+//! rest of the program.
+
+use stm32f4xx_hal::flash::{self, FlashExt};
+use stm32f4xx_hal::pac;
+
+pub use vl6180x_store::*;
+
+/// Start of the store, the first byte of sector 6. Must match `build.rs`.
+#[cfg(not(feature = "blackpill-f401cc"))]
+pub const STORE_ADDRESS: usize = 0x0804_0000;
+
+/// Sectors 6 and 7 are 128K each.
+#[cfg(not(feature = "blackpill-f401cc"))]
+pub const BANK_SIZE: usize = 128 * 1024;
+
+#[cfg(not(feature = "blackpill-f401cc"))]
+const SECTORS: [u8; 2] = [6, 7];
+
+/// Start of the store, the first byte of sector 1. Must match `build.rs`.
+#[cfg(feature = "blackpill-f401cc")]
+pub const STORE_ADDRESS: usize = 0x0800_4000;
+
+/// Sectors 1 and 2 are 16K each.
+#[cfg(feature = "blackpill-f401cc")]
+pub const BANK_SIZE: usize = 16 * 1024;
+
+#[cfg(feature = "blackpill-f401cc")]
+const SECTORS: [u8; 2] = [1, 2];
+
+/// Start of flash, which offsets given to the HAL are relative to.
+const FLASH_BASE: usize = 0x0800_0000;
+
+/// The store's two sectors as the two banks of a [`Store`].
+pub struct InternalFlash {
+    flash: pac::FLASH,
+}
+
+impl InternalFlash {
+    pub fn new(flash: pac::FLASH) -> Self {
+        Self { flash }
+    }
+
+    pub fn free(self) -> pac::FLASH {
+        self.flash
+    }
+}
+
+/// Offset from the start of flash of `offset` in `bank`.
+fn flash_offset(bank: Bank, offset: usize) -> usize {
+    STORE_ADDRESS - FLASH_BASE + bank.index() * BANK_SIZE + offset
+}
+
+impl Flash for InternalFlash {
+    type Error = flash::Error;
+
+    fn bank_size(&self) -> usize {
+        BANK_SIZE
+    }
+
+    fn read(&mut self, bank: Bank, offset: usize, buf: &mut [u8]) -> Result<(), flash::Error> {
+        let start = flash_offset(bank, offset);
+        buf.copy_from_slice(&self.flash.read()[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn erase(&mut self, bank: Bank) -> Result<(), flash::Error> {
+        self.flash.unlocked().erase(SECTORS[bank.index()])
+    }
+
+    fn program(&mut self, bank: Bank, offset: usize, data: &[u8]) -> Result<(), flash::Error> {
+        self.flash
+            .unlocked()
+            .program(flash_offset(bank, offset), data.iter())
+    }
+}
@@ -0,0 +1,61 @@
+//! Sends [`protocol`](crate::protocol) frames over a serial port.
+//!
+//! The examples use USART1 TX on PA9, leaving USART2 to the `log-uart` backend.
+
+use embedded_hal::blocking::serial::Write;
+
+use crate::protocol::{frame, Envelope, ErrorKind, Message};
+use crate::timeout::ReadError;
+
+/// Baud rate the examples run the stream at.
+pub const STREAM_BAUD: u32 = 115_200;
+
+/// Frames messages, numbers them and writes them out.
+pub struct Stream<W> {
+    tx: W,
+    sequence: u16,
+}
+
+impl<W: Write<u8>> Stream<W> {
+    pub fn new(tx: W) -> Self {
+        Self { tx, sequence: 0 }
+    }
+
+    /// Sends `message` with the next sequence number, blocking until every
+    /// byte has been handed to the port.
+    pub fn send(&mut self, message: Message) -> Result<(), W::Error> {
+        let envelope = Envelope {
+            sequence: self.sequence,
+            message,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let mut buf = [0; frame::MAX_FRAME_LEN];
+        let len = frame::encode(&envelope, &mut buf).expect("every message fits MAX_FRAME_LEN");
+        self.tx.bwrite_all(&buf[..len])
+    }
+
+    /// Sequence number the next message will carry.
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    pub fn free(self) -> W {
+        self.tx
+    }
+}
+
+/// The [`Message::Error`] for a read of sensor `sensor` that failed with
+/// `error`, carrying the range error code if there is one.
+pub fn error_message<E>(sensor: u8, error: &ReadError<E>) -> Message {
+    let (kind, detail) = match error {
+        ReadError::I2c(_) => (ErrorKind::Bus, 0),
+        ReadError::Timeout => (ErrorKind::Timeout, 0),
+        ReadError::Range(code) => (ErrorKind::Range, *code),
+    };
+    Message::Error {
+        sensor,
+        kind,
+        detail,
+    }
+}
@@ -0,0 +1,46 @@
+//! The dynamic mode test suite as a [`crate::state_machine`] table: each
+//! state shows off one way of reading the sensor, starting and stopping
+//! continuous mode as it is entered and left. The suite starts in
+//! [`DYNAMIC_SUITE_START`] rather than at the welcome text.
+
+use crate::state_machine::State;
+
+use self::SuiteAction::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SuiteAction {
+    Print(&'static str),
+    StartRangeContinuous,
+    StopRangeContinuous,
+    StartAmbientContinuous,
+    StopAmbientContinuous,
+    /// Reads the next sample of continuous ranging.
+    ReadRange,
+    /// Takes a single range measurement.
+    PollRange,
+    ReadAmbient,
+    PollAmbient,
+    DelayMs(u32),
+}
+
+pub const WELCOME_TEXT: &str = "VL6180X\nDynamic Mode Single Sensor Test Suite";
+pub const END_TEXT: &str = "Goodbye\nSee you soon!";
+
+/// Index of "Range Single Poll", where the suite starts.
+pub const DYNAMIC_SUITE_START: usize = 2;
+
+pub const DYNAMIC_SUITE: [State<SuiteAction>; 6] = [
+    State::new("Welcome").on_enter(&[Print(WELCOME_TEXT)]),
+    State::new("Range Continuous Poll")
+        .on_enter(&[StartRangeContinuous])
+        .on_tick(&[ReadRange])
+        .on_exit(&[StopRangeContinuous]),
+    State::new("Range Single Poll").on_tick(&[PollRange]),
+    State::new("Ambient Continuous Poll")
+        .on_enter(&[StartAmbientContinuous])
+        // No need for a line every 100 ms
+        .on_tick(&[ReadAmbient, DelayMs(500)])
+        .on_exit(&[StopAmbientContinuous]),
+    State::new("Ambient Single Poll").on_tick(&[PollAmbient]),
+    State::new("End").on_enter(&[Print(END_TEXT)]),
+];
@@ -0,0 +1,260 @@
+//! Blocking sensor reads with a time limit.
+//!
+//! The driver's `*_blocking` reads poll the sensor until a result is ready,
+//! so a sensor that stops answering or never raises its interrupt hangs the
+//! caller for good. [`TimedReader`] does the same reads on its own bus proxy,
+//! watching a [`TimeSource`], and gives up with [`ReadError::Timeout`] once
+//! its timeout is over. [`crate::shell`] takes its single shots through the
+//! same polling, giving up after a number of delayed attempts instead.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+use crate::ambient::AlsStep;
+use crate::registers::*;
+
+/// Time limit of [`TimedReader::new`], far beyond the longest range
+/// convergence or ALS integration time.
+pub const DEFAULT_TIMEOUT_MS: u32 = 600;
+
+/// A free-running counter to measure timeouts with, e.g. the DWT cycle
+/// counter or a hardware timer. It may wrap at `u32::MAX`, as long as that
+/// takes longer than any timeout.
+pub trait TimeSource {
+    fn ticks(&self) -> u32;
+    fn ticks_per_ms(&self) -> u32;
+}
+
+impl<T: TimeSource> TimeSource for &T {
+    fn ticks(&self) -> u32 {
+        T::ticks(self)
+    }
+
+    fn ticks_per_ms(&self) -> u32 {
+        T::ticks_per_ms(self)
+    }
+}
+
+/// The end of a timeout on a [`TimeSource`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    start: u32,
+    ticks: u32,
+}
+
+impl Deadline {
+    pub fn after_ms<T: TimeSource>(time: &T, ms: u32) -> Self {
+        Self {
+            start: time.ticks(),
+            ticks: ms.saturating_mul(time.ticks_per_ms()),
+        }
+    }
+
+    pub fn has_passed<T: TimeSource>(&self, time: &T) -> bool {
+        time.ticks().wrapping_sub(self.start) >= self.ticks
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadError<E> {
+    I2c(E),
+    /// No result was ready within the timeout.
+    Timeout,
+    /// A range measurement reported this error code instead of a distance.
+    Range(u8),
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Measurement {
+    Range,
+    Ambient,
+}
+
+impl Measurement {
+    /// Start register, shift of its field in the interrupt registers and
+    /// its interrupt clear bit.
+    fn registers(self) -> (u16, u8, u8) {
+        match self {
+            Measurement::Range => (SYSRANGE__START, 0, CLEAR_RANGE_INT),
+            Measurement::Ambient => (SYSALS__START, 3, CLEAR_ALS_INT),
+        }
+    }
+}
+
+/// Timeout-bounded counterparts of the driver's blocking reads, for the
+/// sensor at `address`. Starting and stopping continuous modes is left to
+/// the driver.
+pub struct TimedReader<I2C, T> {
+    i2c: I2C,
+    address: u8,
+    time: T,
+    timeout_ms: u32,
+}
+
+impl<I2C, T, E> TimedReader<I2C, T>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    T: TimeSource,
+{
+    /// Gives up after [`DEFAULT_TIMEOUT_MS`].
+    pub fn new(i2c: I2C, address: u8, time: T) -> Self {
+        Self {
+            i2c,
+            address,
+            time,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+        }
+    }
+
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    pub fn timeout_ms(&self) -> u32 {
+        self.timeout_ms
+    }
+
+    /// Follows the sensor to a new address, e.g. after the driver changed it.
+    pub fn set_address(&mut self, address: u8) {
+        self.address = address;
+    }
+
+    /// Takes a single shot range measurement, like
+    /// `poll_range_mm_single_blocking`.
+    pub fn poll_range_mm_single(&mut self) -> Result<u16, ReadError<E>> {
+        self.read(Measurement::Range, true, range_mm)
+    }
+
+    /// Waits for the next range interrupt in continuous mode and reads it,
+    /// like `read_range_mm_blocking`.
+    pub fn read_range_mm(&mut self) -> Result<u16, ReadError<E>> {
+        self.read(Measurement::Range, false, range_mm)
+    }
+
+    /// Takes a single shot ambient measurement, like
+    /// `poll_ambient_lux_single_blocking`.
+    pub fn poll_ambient_lux_single(&mut self) -> Result<f32, ReadError<E>> {
+        self.read(Measurement::Ambient, true, ambient_lux)
+    }
+
+    /// Waits for the next ambient interrupt in continuous mode and reads it,
+    /// like `read_ambient_lux_blocking`.
+    pub fn read_ambient_lux(&mut self) -> Result<f32, ReadError<E>> {
+        self.read(Measurement::Ambient, false, ambient_lux)
+    }
+
+    pub fn free(self) -> (I2C, T) {
+        (self.i2c, self.time)
+    }
+
+    /// Waits for the next result of `measurement` until the timeout is
+    /// over, starting a single shot for it first if `single`.
+    fn read<R>(
+        &mut self,
+        measurement: Measurement,
+        single: bool,
+        read: impl FnOnce(&mut I2C, u8) -> Result<R, ReadError<E>>,
+    ) -> Result<R, ReadError<E>> {
+        let deadline = Deadline::after_ms(&self.time, self.timeout_ms);
+        let expired = || deadline.has_passed(&self.time);
+        if single {
+            single_shot(&mut self.i2c, self.address, measurement, expired, read)
+        } else {
+            next_result(&mut self.i2c, self.address, measurement, expired, read)
+        }
+    }
+}
+
+/// Starts a single shot of `measurement` on the sensor at `address` and
+/// waits for its result like [`next_result`], with the interrupt briefly
+/// switched to new sample ready so completion shows in the status.
+pub(crate) fn single_shot<I2C, E, R>(
+    i2c: &mut I2C,
+    address: u8,
+    measurement: Measurement,
+    expired: impl FnMut() -> bool,
+    read: impl FnOnce(&mut I2C, u8) -> Result<R, ReadError<E>>,
+) -> Result<R, ReadError<E>>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    let (start, shift, clear) = measurement.registers();
+    let config = read_u8(i2c, address, SYSTEM__INTERRUPT_CONFIG_GPIO).map_err(ReadError::I2c)?;
+    let single = (config & !(0x07 << shift)) | (INT_NEW_SAMPLE_READY << shift);
+    write_u8(i2c, address, SYSTEM__INTERRUPT_CONFIG_GPIO, single).map_err(ReadError::I2c)?;
+    write_u8(i2c, address, SYSTEM__INTERRUPT_CLEAR, clear).map_err(ReadError::I2c)?;
+    write_u8(i2c, address, start, START_STOP).map_err(ReadError::I2c)?;
+
+    let result = next_result(i2c, address, measurement, expired, read);
+    write_u8(i2c, address, SYSTEM__INTERRUPT_CONFIG_GPIO, config).map_err(ReadError::I2c)?;
+    result
+}
+
+/// Polls the interrupt status of the sensor at `address` until
+/// `measurement` reports an event, reads the result with `read` and clears
+/// the interrupt. Gives up with [`ReadError::Timeout`] once `expired`
+/// returns true, which is asked between polls.
+pub(crate) fn next_result<I2C, E, R>(
+    i2c: &mut I2C,
+    address: u8,
+    measurement: Measurement,
+    mut expired: impl FnMut() -> bool,
+    read: impl FnOnce(&mut I2C, u8) -> Result<R, ReadError<E>>,
+) -> Result<R, ReadError<E>>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    let (_, shift, clear) = measurement.registers();
+    loop {
+        let status =
+            read_u8(i2c, address, RESULT__INTERRUPT_STATUS_GPIO).map_err(ReadError::I2c)?;
+        if (status >> shift) & 0x07 != 0 {
+            break;
+        }
+        if expired() {
+            return Err(ReadError::Timeout);
+        }
+    }
+    let result = read(i2c, address);
+    write_u8(i2c, address, SYSTEM__INTERRUPT_CLEAR, clear).map_err(ReadError::I2c)?;
+    result
+}
+
+/// The range result in mm, with whatever scaling the sensor has, or its
+/// range error.
+pub(crate) fn range_mm<I2C, E>(i2c: &mut I2C, address: u8) -> Result<u16, ReadError<E>>
+where
+    I2C: WriteRead<Error = E>,
+{
+    let status = read_u8(i2c, address, RESULT__RANGE_STATUS).map_err(ReadError::I2c)?;
+    let error = range_error_code(status);
+    if error != 0 {
+        return Err(ReadError::Range(error));
+    }
+    let raw = read_u8(i2c, address, RESULT__RANGE_VAL).map_err(ReadError::I2c)?;
+    let scaler = read_u16(i2c, address, RANGE_SCALER).map_err(ReadError::I2c)?;
+    Ok(raw as u16 * range_scaling_factor(scaler).unwrap_or(1) as u16)
+}
+
+/// Makes sense of a range read that failed with `e` outside a
+/// [`TimedReader`], e.g. the driver's: [`ReadError::Range`] with the error
+/// code of the last result of the sensor at `address`, if it has one,
+/// otherwise [`ReadError::I2c`] with `e`.
+pub fn failed_range_read<I2C: WriteRead, E>(i2c: &mut I2C, address: u8, e: E) -> ReadError<E> {
+    match read_u8(i2c, address, RESULT__RANGE_STATUS).map(range_error_code) {
+        Ok(code) if code != 0 => ReadError::Range(code),
+        _ => ReadError::I2c(e),
+    }
+}
+
+/// Lux from the raw count and the gain and integration time the sensor
+/// has, whoever set them.
+fn ambient_lux<I2C, E>(i2c: &mut I2C, address: u8) -> Result<f32, ReadError<E>>
+where
+    I2C: WriteRead<Error = E>,
+{
+    let raw = read_u16(i2c, address, RESULT__ALS_VAL).map_err(ReadError::I2c)?;
+    let gain = read_u8(i2c, address, SYSALS__ANALOGUE_GAIN).map_err(ReadError::I2c)? & 0x07;
+    let period = read_u16(i2c, address, SYSALS__INTEGRATION_PERIOD).map_err(ReadError::I2c)?;
+    let step = AlsStep::new(gain, (period & 0x1FF) + 1);
+    Ok(step.lux(raw))
+}
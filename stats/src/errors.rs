@@ -0,0 +1,110 @@
+//! Counts of failed readings.
+
+use core::fmt;
+
+/// Range error codes are the top four bits of `RESULT__RANGE_STATUS`.
+pub const RANGE_ERROR_CODES: usize = 16;
+
+/// How often each range error code came back instead of a reading, and how
+/// many reads failed some other way, such as on the bus.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    codes: [u32; RANGE_ERROR_CODES],
+    other: u32,
+}
+
+impl ErrorCounts {
+    pub const fn new() -> Self {
+        Self {
+            codes: [0; RANGE_ERROR_CODES],
+            other: 0,
+        }
+    }
+
+    /// Counts a range error code. Codes that don't fit in four bits count
+    /// as [`other`](Self::other).
+    pub fn add_code(&mut self, code: u8) {
+        let count = match self.codes.get_mut(code as usize) {
+            Some(count) => count,
+            None => &mut self.other,
+        };
+        *count = count.saturating_add(1);
+    }
+
+    pub fn add_other(&mut self) {
+        self.other = self.other.saturating_add(1);
+    }
+
+    /// How often `code` came back, 0 for codes past [`RANGE_ERROR_CODES`].
+    pub fn code(&self, code: u8) -> u32 {
+        self.codes.get(code as usize).copied().unwrap_or(0)
+    }
+
+    pub fn other(&self) -> u32 {
+        self.other
+    }
+
+    pub fn total(&self) -> u32 {
+        self.codes
+            .iter()
+            .fold(self.other, |total, &count| total.saturating_add(count))
+    }
+
+    /// The codes that came back at least once, lowest first, with their
+    /// counts.
+    pub fn codes(&self) -> impl Iterator<Item = (u8, u32)> + '_ {
+        self.codes
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(code, &count)| (code as u8, count))
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// `none`, or `code:count` pairs for the codes seen and `other:count`.
+impl fmt::Display for ErrorCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total() == 0 {
+            return f.write_str("none");
+        }
+        let mut separator = "";
+        for (code, count) in self.codes() {
+            write!(f, "{}{}:{}", separator, code, count)?;
+            separator = " ";
+        }
+        if self.other > 0 {
+            write!(f, "{}other:{}", separator, self.other)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_codes_and_other_failures() {
+        let mut errors = ErrorCounts::new();
+        assert_eq!(errors.to_string(), "none");
+
+        for code in [11, 13, 11, 16] {
+            errors.add_code(code);
+        }
+        errors.add_other();
+        assert_eq!(errors.code(11), 2);
+        assert_eq!(errors.code(16), 0);
+        assert_eq!(errors.other(), 2);
+        assert_eq!(errors.total(), 5);
+        assert_eq!(errors.codes().collect::<Vec<_>>(), [(11, 2), (13, 1)]);
+        assert_eq!(errors.to_string(), "11:2 13:1 other:2");
+
+        errors.reset();
+        errors.add_other();
+        assert_eq!(errors.to_string(), "other:1");
+    }
+}
@@ -0,0 +1,123 @@
+//! Fixed bucket histogram.
+
+use core::fmt;
+
+/// Counts readings into `N` buckets of `width`, the first starting at
+/// `start`, with one more count each for readings below and beyond them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Histogram<const N: usize> {
+    start: u16,
+    width: u16,
+    counts: [u32; N],
+    below: u32,
+    above: u32,
+}
+
+impl<const N: usize> Histogram<N> {
+    /// # Panics
+    ///
+    /// If `width` is 0.
+    pub fn new(start: u16, width: u16) -> Self {
+        assert!(width > 0, "histogram width");
+        Self {
+            start,
+            width,
+            counts: [0; N],
+            below: 0,
+            above: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: u16) {
+        let count = match value.checked_sub(self.start) {
+            None => &mut self.below,
+            Some(offset) => match self.counts.get_mut((offset / self.width) as usize) {
+                Some(count) => count,
+                None => &mut self.above,
+            },
+        };
+        *count = count.saturating_add(1);
+    }
+
+    /// First value counted by bucket `index`. Can be past `u16::MAX` for the
+    /// last buckets of a wide histogram, which then never count anything.
+    pub fn bucket_start(&self, index: usize) -> u32 {
+        self.start as u32 + index as u32 * self.width as u32
+    }
+
+    pub fn counts(&self) -> &[u32; N] {
+        &self.counts
+    }
+
+    /// Readings below [`bucket_start(0)`](Self::bucket_start).
+    pub fn below(&self) -> u32 {
+        self.below
+    }
+
+    /// Readings at or beyond [`bucket_start(N)`](Self::bucket_start).
+    pub fn above(&self) -> u32 {
+        self.above
+    }
+
+    /// Every reading counted, in range or not.
+    pub fn total(&self) -> u32 {
+        self.counts
+            .iter()
+            .fold(self.below.saturating_add(self.above), |total, &count| {
+                total.saturating_add(count)
+            })
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.start, self.width);
+    }
+}
+
+/// One line of `start:count` pairs, from `<start:below` to `>=end:above`,
+/// every bucket included so that reports line up.
+impl<const N: usize> fmt::Display for Histogram<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}:{}", self.bucket_start(0), self.below)?;
+        for (index, count) in self.counts.iter().enumerate() {
+            write!(f, " {}:{}", self.bucket_start(index), count)?;
+        }
+        write!(f, " >={}:{}", self.bucket_start(N), self.above)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_closed_below_and_open_above() {
+        let mut histogram = Histogram::<3>::new(10, 5);
+        for value in [0, 9, 10, 14, 15, 24, 25, u16::MAX] {
+            histogram.add(value);
+        }
+        assert_eq!(histogram.below(), 2);
+        assert_eq!(histogram.counts(), &[2, 1, 1]);
+        assert_eq!(histogram.above(), 2);
+        assert_eq!(histogram.total(), 8);
+        assert_eq!(histogram.to_string(), "<10:2 10:2 15:1 20:1 >=25:2");
+
+        histogram.reset();
+        assert_eq!(histogram.total(), 0);
+        assert_eq!(histogram.bucket_start(1), 15);
+    }
+
+    #[test]
+    fn buckets_past_the_end_of_u16() {
+        let mut histogram = Histogram::<4>::new(60_000, 4_000);
+        histogram.add(u16::MAX);
+        assert_eq!(histogram.counts(), &[0, 1, 0, 0]);
+        assert_eq!(histogram.bucket_start(4), 76_000);
+        assert_eq!(histogram.above(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "histogram width")]
+    fn rejects_zero_width() {
+        Histogram::<4>::new(0, 0);
+    }
+}
@@ -0,0 +1,28 @@
+//! Running statistics of a sensor's readings, for characterising noise on a
+//! mount without copying the log into a spreadsheet.
+//!
+//! - [`Welford`] keeps the mean and variance with Welford's method, which
+//!   stays accurate over long runs where summing squares would not;
+//! - [`Histogram`] counts readings into fixed width buckets;
+//! - [`ErrorCounts`] counts range error codes and other failed reads.
+//!
+//! [`SensorStats`] keeps all three for one sensor, along with the minimum
+//! and maximum, and a [`Reporter`] hands one over every period for the log
+//! and starts afresh. Each prints as one line.
+//!
+//! The crate is `no_std` and allocation free. Keep one [`SensorStats`] or
+//! [`Reporter`] per sensor.
+
+#![cfg_attr(not(test), no_std)]
+
+mod errors;
+mod histogram;
+mod reporter;
+mod sensor;
+mod welford;
+
+pub use errors::{ErrorCounts, RANGE_ERROR_CODES};
+pub use histogram::Histogram;
+pub use reporter::Reporter;
+pub use sensor::{SensorStats, StatsConfig};
+pub use welford::Welford;
@@ -0,0 +1,84 @@
+//! Statistics handed over once a period.
+
+use crate::{SensorStats, StatsConfig};
+
+/// Gathers a [`SensorStats`] for `period_ms` at a time. Each report covers
+/// only its own period, so a mount that warms up or a target that moves
+/// shows up as a change from one report to the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reporter<const N: usize> {
+    stats: SensorStats<N>,
+    period_ms: u32,
+    since_ms: u32,
+}
+
+impl<const N: usize> Reporter<N> {
+    /// Starts the first period at `now_ms`.
+    ///
+    /// # Panics
+    ///
+    /// If the bucket width is 0.
+    pub fn new(config: StatsConfig, period_ms: u32, now_ms: u32) -> Self {
+        Self {
+            stats: SensorStats::new(config),
+            period_ms,
+            since_ms: now_ms,
+        }
+    }
+
+    pub fn period_ms(&self) -> u32 {
+        self.period_ms
+    }
+
+    /// The current period so far.
+    pub fn stats(&self) -> &SensorStats<N> {
+        &self.stats
+    }
+
+    /// For recording readings and errors.
+    pub fn stats_mut(&mut self) -> &mut SensorStats<N> {
+        &mut self.stats
+    }
+
+    /// Once `period_ms` has passed, returns the statistics of the period to
+    /// report them and starts the next one at `now_ms`. The millisecond
+    /// clock may wrap.
+    pub fn poll(&mut self, now_ms: u32) -> Option<SensorStats<N>> {
+        if now_ms.wrapping_sub(self.since_ms) < self.period_ms {
+            return None;
+        }
+        let report = self.stats;
+        self.stats.reset();
+        self.since_ms = now_ms;
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: StatsConfig = StatsConfig {
+        histogram_start: 0,
+        bucket_width: 16,
+    };
+
+    #[test]
+    fn reports_each_period_on_its_own() {
+        let mut reporter = Reporter::<16>::new(CONFIG, 1000, u32::MAX - 500);
+        reporter.stats_mut().record(100);
+        assert_eq!(reporter.poll(u32::MAX), None);
+        reporter.stats_mut().record(102);
+
+        // Across the wrap of the clock
+        let report = reporter.poll(499).unwrap();
+        assert_eq!(report.count(), 2);
+        assert_eq!(report.mean(), Some(101.0));
+        assert_eq!(reporter.stats().count(), 0);
+
+        reporter.stats_mut().record_failure();
+        assert_eq!(reporter.poll(1498), None);
+        let report = reporter.poll(1499).unwrap();
+        assert_eq!((report.count(), report.errors().total()), (0, 1));
+    }
+}
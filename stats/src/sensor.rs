@@ -0,0 +1,160 @@
+//! Everything kept about one sensor's readings.
+
+use core::fmt;
+
+use crate::{ErrorCounts, Histogram, Welford};
+
+/// Where the buckets of a [`SensorStats`] histogram lie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StatsConfig {
+    /// Start of the first bucket.
+    pub histogram_start: u16,
+    pub bucket_width: u16,
+}
+
+/// Minimum, maximum, mean and spread of a sensor's readings, a histogram of
+/// them in `N` buckets and counts of the reads that failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorStats<const N: usize> {
+    welford: Welford,
+    /// Both `None` before the first reading.
+    range: Option<(u16, u16)>,
+    histogram: Histogram<N>,
+    errors: ErrorCounts,
+}
+
+impl<const N: usize> SensorStats<N> {
+    /// # Panics
+    ///
+    /// If the bucket width is 0.
+    pub fn new(config: StatsConfig) -> Self {
+        Self {
+            welford: Welford::new(),
+            range: None,
+            histogram: Histogram::new(config.histogram_start, config.bucket_width),
+            errors: ErrorCounts::new(),
+        }
+    }
+
+    /// Adds a reading, in mm or ambient counts.
+    pub fn record(&mut self, value: u16) {
+        self.welford.add(value as f32);
+        self.range = Some(match self.range {
+            Some((min, max)) => (min.min(value), max.max(value)),
+            None => (value, value),
+        });
+        self.histogram.add(value);
+    }
+
+    /// Counts a range error code that came back instead of a reading.
+    pub fn record_error(&mut self, code: u8) {
+        self.errors.add_code(code);
+    }
+
+    /// Counts a read that failed without an error code, e.g. on the bus.
+    pub fn record_failure(&mut self) {
+        self.errors.add_other();
+    }
+
+    /// Readings recorded, errors not included.
+    pub fn count(&self) -> u32 {
+        self.welford.count()
+    }
+
+    pub fn min(&self) -> Option<u16> {
+        self.range.map(|(min, _)| min)
+    }
+
+    pub fn max(&self) -> Option<u16> {
+        self.range.map(|(_, max)| max)
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        self.welford.mean()
+    }
+
+    /// See [`Welford::variance`].
+    pub fn variance(&self) -> Option<f32> {
+        self.welford.variance()
+    }
+
+    pub fn std_dev(&self) -> Option<f32> {
+        self.welford.std_dev()
+    }
+
+    pub fn histogram(&self) -> &Histogram<N> {
+        &self.histogram
+    }
+
+    pub fn errors(&self) -> &ErrorCounts {
+        &self.errors
+    }
+
+    /// Forgets every reading and error, keeping the histogram's buckets.
+    pub fn reset(&mut self) {
+        self.welford.reset();
+        self.range = None;
+        self.histogram.reset();
+        self.errors.reset();
+    }
+}
+
+/// The summary on one line, e.g.
+/// `n 500, min 48, max 55, mean 51.23, std dev 1.07, errors 2`. Only what
+/// is known so far: the mean needs a reading and the spread two.
+impl<const N: usize> fmt::Display for SensorStats<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "n {}", self.count())?;
+        if let Some((min, max)) = self.range {
+            write!(f, ", min {}, max {}", min, max)?;
+        }
+        if let Some(mean) = self.mean() {
+            write!(f, ", mean {:.2}", mean)?;
+        }
+        if let Some(std_dev) = self.std_dev() {
+            write!(f, ", std dev {:.2}", std_dev)?;
+        }
+        write!(f, ", errors {}", self.errors.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: StatsConfig = StatsConfig {
+        histogram_start: 40,
+        bucket_width: 4,
+    };
+
+    #[test]
+    fn summarises_readings_and_errors() {
+        let mut stats = SensorStats::<4>::new(CONFIG);
+        assert_eq!(stats.to_string(), "n 0, errors 0");
+
+        stats.record(50);
+        assert_eq!(
+            stats.to_string(),
+            "n 1, min 50, max 50, mean 50.00, errors 0"
+        );
+
+        for value in [48, 52, 46, 54] {
+            stats.record(value);
+        }
+        stats.record_error(11);
+        stats.record_failure();
+        assert_eq!(stats.count(), 5);
+        assert_eq!((stats.min(), stats.max()), (Some(46), Some(54)));
+        assert_eq!(stats.mean(), Some(50.0));
+        assert_eq!(stats.variance(), Some(10.0));
+        assert_eq!(stats.histogram().counts(), &[0, 1, 2, 2]);
+        assert_eq!(stats.errors().to_string(), "11:1 other:1");
+        assert_eq!(
+            stats.to_string(),
+            "n 5, min 46, max 54, mean 50.00, std dev 3.16, errors 2"
+        );
+
+        stats.reset();
+        assert_eq!(stats, SensorStats::new(CONFIG));
+    }
+}
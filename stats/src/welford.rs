@@ -0,0 +1,170 @@
+//! Running mean and variance.
+
+/// Mean and variance of a stream of samples, updated one sample at a time
+/// with Welford's method: each sample moves the mean by its share of the
+/// difference, and the squared differences from the mean are summed as it
+/// goes. Unlike summing the samples and their squares, this doesn't lose the
+/// spread of a reading of 200 mm that jitters by 1 mm to rounding.
+///
+/// It works in `f64` all the same, so that a run of millions of samples
+/// keeps its last digits; the FPU is single precision, but a few microseconds
+/// per sample are nothing next to a measurement.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Welford {
+    count: u32,
+    mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+}
+
+impl Welford {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, sample: f32) {
+        let sample = sample as f64;
+        self.count = self.count.saturating_add(1);
+        let delta = sample - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (sample - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// `None` before the first sample.
+    pub fn mean(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.mean as f32)
+    }
+
+    /// Sample variance, dividing by `n - 1` like a spreadsheet's `VAR.S`.
+    /// `None` before the second sample.
+    pub fn variance(&self) -> Option<f32> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64) as f32)
+    }
+
+    /// Square root of the [`variance`](Self::variance).
+    pub fn std_dev(&self) -> Option<f32> {
+        self.variance().map(|variance| sqrt(variance as f64) as f32)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// `f64::sqrt` needs `std`. Newton's method from above, which converges
+/// on the root in a few dozen steps for any finite value. NaN for NaN or
+/// infinity, on which the steps would never stop going down.
+fn sqrt(value: f64) -> f64 {
+    if !value.is_finite() {
+        return f64::NAN;
+    }
+    if value <= 0.0 {
+        return 0.0;
+    }
+    let mut root = if value > 1.0 { value } else { 1.0 };
+    loop {
+        let next = 0.5 * (root + value / root);
+        if next >= root {
+            return root;
+        }
+        root = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_single_samples() {
+        let mut welford = Welford::new();
+        assert_eq!(welford.mean(), None);
+        assert_eq!(welford.variance(), None);
+        welford.add(42.0);
+        assert_eq!(welford.mean(), Some(42.0));
+        assert_eq!(welford.variance(), None);
+        welford.reset();
+        assert_eq!(welford.count(), 0);
+    }
+
+    #[test]
+    fn matches_the_textbook_example() {
+        let mut welford = Welford::new();
+        for sample in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            welford.add(sample);
+        }
+        assert_eq!(welford.mean(), Some(5.0));
+        // 32 / 7
+        assert!((welford.variance().unwrap() - 4.571_428_5).abs() < 1e-6);
+        assert!((welford.std_dev().unwrap() - 2.138_09).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sqrt_of_small_and_large_values() {
+        for value in [0.0, 1e-6, 0.25, 1.0, 2.0, 9.0, 1e6, 4.3e9] {
+            assert!((sqrt(value) - value.sqrt()).abs() <= 1e-12 * value.sqrt().max(1.0));
+        }
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, f64::MAX] {
+            assert_eq!(sqrt(value).is_nan(), !value.is_finite());
+        }
+    }
+
+    #[test]
+    fn std_dev_of_non_finite_samples() {
+        let mut welford = Welford::new();
+        welford.add(1.0);
+        welford.add(f32::INFINITY);
+        assert!(welford.std_dev().unwrap().is_nan());
+    }
+
+    /// A million readings of a target far away with a millimetre of jitter:
+    /// summing squares in `f32` loses the jitter to rounding entirely.
+    #[test]
+    fn keeps_small_spread_on_a_large_offset() {
+        let mut welford = Welford::new();
+        let (mut sum, mut sum_sq) = (0f32, 0f32);
+        let n = 1_000_000;
+        for i in 0..n {
+            // Alternating 59_999 and 60_001, variance just over 1
+            let sample = if i % 2 == 0 { 59_999.0 } else { 60_001.0 };
+            welford.add(sample);
+            sum += sample;
+            sum_sq += sample * sample;
+        }
+        let naive = (sum_sq - sum * sum / n as f32) / (n - 1) as f32;
+        assert!((naive - 1.0).abs() > 100.0, "naive variance {}", naive);
+
+        assert!((welford.mean().unwrap() - 60_000.0).abs() < 1e-3);
+        assert!((welford.variance().unwrap() - 1.000_001).abs() < 1e-5);
+        assert!((welford.std_dev().unwrap() - 1.0).abs() < 1e-5);
+    }
+
+    /// The order samples arrive in doesn't change the result beyond
+    /// rounding, a drifting target included.
+    #[test]
+    fn order_independent_on_a_ramp() {
+        let samples: Vec<f32> = (0..10_000).map(|i| 100.0 + (i % 50) as f32 * 0.1).collect();
+        let mut forwards = Welford::new();
+        let mut backwards = Welford::new();
+        for &sample in &samples {
+            forwards.add(sample);
+        }
+        for &sample in samples.iter().rev() {
+            backwards.add(sample);
+        }
+        // Exact: mean 102.45, variance of 0.0 to 4.9 in steps of 0.1
+        let variance = 2.0825 * 10_000.0 / 9_999.0;
+        for welford in [forwards, backwards] {
+            assert!((welford.mean().unwrap() - 102.45).abs() < 1e-4);
+            assert!((welford.variance().unwrap() - variance).abs() < 1e-4);
+        }
+    }
+}
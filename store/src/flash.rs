@@ -0,0 +1,242 @@
+//! The flash operations the store needs, and an in-memory model of them.
+
+/// Value of every byte of an erased bank.
+pub const ERASED: u8 = 0xFF;
+
+/// One of the two banks the store alternates between.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bank {
+    A,
+    B,
+}
+
+impl Bank {
+    pub const BOTH: [Bank; 2] = [Bank::A, Bank::B];
+
+    pub const fn other(self) -> Self {
+        match self {
+            Bank::A => Bank::B,
+            Bank::B => Bank::A,
+        }
+    }
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Two equally sized banks of NOR flash, each erased as a whole.
+///
+/// Offsets are relative to the start of the bank. As on the STM32, erasing
+/// sets every byte to [`ERASED`] and programming can only clear bits, so each
+/// byte is programmed at most once between erases.
+pub trait Flash {
+    type Error;
+
+    /// Size of each bank in bytes.
+    fn bank_size(&self) -> usize;
+    fn read(&mut self, bank: Bank, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+    fn erase(&mut self, bank: Bank) -> Result<(), Self::Error>;
+    fn program(&mut self, bank: Bank, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemFlashError {
+    /// The access runs past the end of the bank.
+    OutOfBounds,
+    /// Programming would have to set a cleared bit back to 1.
+    NotErased { bank: Bank, offset: usize },
+    /// Power was cut, see [`MemFlash::cut_power_after`].
+    PowerLoss,
+}
+
+/// RAM backed [`Flash`] with banks of `SIZE` bytes, for host tests.
+///
+/// Programming a byte that isn't erased fails like it would on the real part,
+/// and power cuts can be injected in the middle of a save.
+pub struct MemFlash<const SIZE: usize> {
+    banks: [[u8; SIZE]; 2],
+    erases: [u32; 2],
+    budget: Option<usize>,
+    powered: bool,
+}
+
+impl<const SIZE: usize> Default for MemFlash<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> MemFlash<SIZE> {
+    /// Both banks erased.
+    pub fn new() -> Self {
+        Self {
+            banks: [[ERASED; SIZE]; 2],
+            erases: [0; 2],
+            budget: None,
+            powered: true,
+        }
+    }
+
+    /// Cuts power once `bytes` more bytes have been programmed. An erase
+    /// counts as one byte and, when cut, leaves only the first half of the
+    /// bank erased. Every access fails from the cut until
+    /// [`restore_power`](Self::restore_power).
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.budget = Some(bytes);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.budget = None;
+        self.powered = true;
+    }
+
+    pub fn is_powered(&self) -> bool {
+        self.powered
+    }
+
+    /// How often `bank` has been erased, completely or not.
+    pub fn erase_count(&self, bank: Bank) -> u32 {
+        self.erases[bank.index()]
+    }
+
+    pub fn bank(&self, bank: Bank) -> &[u8; SIZE] {
+        &self.banks[bank.index()]
+    }
+
+    pub fn bank_mut(&mut self, bank: Bank) -> &mut [u8; SIZE] {
+        &mut self.banks[bank.index()]
+    }
+
+    /// Takes one byte from the power budget, false if power is or goes out.
+    fn spend(&mut self) -> bool {
+        match &mut self.budget {
+            _ if !self.powered => false,
+            Some(0) => {
+                self.powered = false;
+                false
+            }
+            Some(budget) => {
+                *budget -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn span(offset: usize, len: usize) -> Result<core::ops::Range<usize>, MemFlashError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= SIZE => Ok(offset..end),
+            _ => Err(MemFlashError::OutOfBounds),
+        }
+    }
+}
+
+impl<const SIZE: usize> Flash for MemFlash<SIZE> {
+    type Error = MemFlashError;
+
+    fn bank_size(&self) -> usize {
+        SIZE
+    }
+
+    fn read(&mut self, bank: Bank, offset: usize, buf: &mut [u8]) -> Result<(), MemFlashError> {
+        if !self.powered {
+            return Err(MemFlashError::PowerLoss);
+        }
+        let span = Self::span(offset, buf.len())?;
+        buf.copy_from_slice(&self.banks[bank.index()][span]);
+        Ok(())
+    }
+
+    fn erase(&mut self, bank: Bank) -> Result<(), MemFlashError> {
+        if !self.powered {
+            return Err(MemFlashError::PowerLoss);
+        }
+        self.erases[bank.index()] += 1;
+        if self.spend() {
+            self.banks[bank.index()] = [ERASED; SIZE];
+            Ok(())
+        } else {
+            self.banks[bank.index()][..SIZE / 2].fill(ERASED);
+            Err(MemFlashError::PowerLoss)
+        }
+    }
+
+    fn program(&mut self, bank: Bank, offset: usize, data: &[u8]) -> Result<(), MemFlashError> {
+        if !self.powered {
+            return Err(MemFlashError::PowerLoss);
+        }
+        let span = Self::span(offset, data.len())?;
+        for (offset, &byte) in span.zip(data) {
+            if byte & !self.banks[bank.index()][offset] != 0 {
+                return Err(MemFlashError::NotErased { bank, offset });
+            }
+            if !self.spend() {
+                return Err(MemFlashError::PowerLoss);
+            }
+            self.banks[bank.index()][offset] &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programming_only_clears_bits() {
+        let mut flash = MemFlash::<16>::new();
+        flash.program(Bank::A, 2, &[0x0F]).unwrap();
+        assert_eq!(
+            flash.program(Bank::A, 2, &[0xF0]),
+            Err(MemFlashError::NotErased {
+                bank: Bank::A,
+                offset: 2
+            })
+        );
+        flash.program(Bank::A, 2, &[0x05]).unwrap();
+        assert_eq!(flash.bank(Bank::A)[2], 0x05);
+        assert_eq!(flash.bank(Bank::B), &[ERASED; 16]);
+
+        flash.erase(Bank::A).unwrap();
+        assert_eq!(flash.bank(Bank::A), &[ERASED; 16]);
+        assert_eq!(flash.erase_count(Bank::A), 1);
+    }
+
+    #[test]
+    fn rejects_out_of_bounds() {
+        let mut flash = MemFlash::<16>::new();
+        let mut buf = [0; 4];
+        assert_eq!(
+            flash.read(Bank::B, 14, &mut buf),
+            Err(MemFlashError::OutOfBounds)
+        );
+        assert_eq!(
+            flash.program(Bank::B, usize::MAX, &[0]),
+            Err(MemFlashError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn power_cut_stops_part_way() {
+        let mut flash = MemFlash::<16>::new();
+        flash.cut_power_after(2);
+        assert_eq!(
+            flash.program(Bank::A, 10, &[1, 2, 3, 4]),
+            Err(MemFlashError::PowerLoss)
+        );
+        assert!(!flash.is_powered());
+        assert_eq!(&flash.bank(Bank::A)[10..14], &[1, 2, ERASED, ERASED]);
+        assert_eq!(
+            flash.read(Bank::A, 0, &mut [0; 1]),
+            Err(MemFlashError::PowerLoss)
+        );
+
+        flash.restore_power();
+        flash.cut_power_after(0);
+        assert_eq!(flash.erase(Bank::A), Err(MemFlashError::PowerLoss));
+        flash.restore_power();
+        assert_eq!(&flash.bank(Bank::A)[10..12], &[1, 2]);
+    }
+}
@@ -0,0 +1,21 @@
+//! Sensor settings that survive a reset, kept in two banks of internal flash.
+//!
+//! Each save appends one fixed size record (format [`VERSION`], a sequence
+//! number, every sensor's [`SensorConfig`] and a CRC-16) to the active bank,
+//! so a bank is only erased once it is full and the store then moves to the
+//! other one. Loading picks the valid record with the highest sequence
+//! number; a torn write or a record from another format version is skipped,
+//! falling back to the previous record or to the defaults.
+//!
+//! The crate is `no_std` and only talks to flash through the [`Flash`] trait,
+//! so the same code runs against [`MemFlash`] in host tests.
+
+#![cfg_attr(not(test), no_std)]
+
+mod flash;
+mod record;
+mod store;
+
+pub use flash::{Bank, Flash, MemFlash, MemFlashError, ERASED};
+pub use record::{Calibration, SensorConfig, StoredConfig, MAX_SENSORS, SLOT_LEN, VERSION};
+pub use store::{Store, StoreError};
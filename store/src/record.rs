@@ -0,0 +1,310 @@
+//! What gets stored and its layout in a flash slot.
+//!
+//! A record is a header (magic, [`VERSION`], sensor count, sequence number),
+//! one fixed size entry per sensor and a CRC-16 of all of that, multi-byte
+//! fields little endian. It is written at the start of a [`SLOT_LEN`] slot
+//! and the rest of the slot is left erased.
+
+use heapless::Vec;
+use vl6180x_protocol::crc::{crc16, CRC_LEN};
+use vl6180x_shell::{InterruptMode, Setting, Settings};
+
+/// Record layout version. Bump on any incompatible change to the layout;
+/// records with another version are ignored when loading.
+pub const VERSION: u8 = 1;
+
+/// Most sensors a record holds.
+pub const MAX_SENSORS: usize = 8;
+
+/// Flash each save takes, whatever the number of sensors.
+pub const SLOT_LEN: usize = 256;
+
+/// "VL", marks a slot that has been written to.
+const MAGIC: [u8; 2] = *b"VL";
+
+const HEADER_LEN: usize = 8;
+const SENSOR_LEN: usize = 16;
+
+/// Bits of a sensor entry's last byte saying which calibration values are set.
+/// Entries written before calibration was stored have 0 there.
+const HAS_OFFSET: u8 = 0x01;
+const HAS_CROSSTALK: u8 = 0x02;
+
+const _: () = assert!(HEADER_LEN + MAX_SENSORS * SENSOR_LEN + CRC_LEN <= SLOT_LEN);
+
+/// Per-part corrections written to the sensor after its `Config`, `None`
+/// until calibrated so the sensor's factory value is left alone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Calibration {
+    /// `SYSRANGE__PART_TO_PART_RANGE_OFFSET`, in millimetres.
+    pub offset_mm: Option<i8>,
+    /// `SYSRANGE__CROSSTALK_COMPENSATION_RATE`, in Mcps as 9.7 fixed point.
+    pub crosstalk_rate: Option<u16>,
+}
+
+/// Everything kept for the sensor at one I2C address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SensorConfig {
+    pub address: u8,
+    pub settings: Settings,
+    pub calibration: Calibration,
+}
+
+impl SensorConfig {
+    /// Default settings and no calibration.
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            settings: Settings::default(),
+            calibration: Calibration::default(),
+        }
+    }
+}
+
+/// The whole stored configuration, one [`SensorConfig`] per address.
+/// The default has no sensors, so every sensor gets its defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoredConfig {
+    sensors: Vec<SensorConfig, MAX_SENSORS>,
+}
+
+impl StoredConfig {
+    pub fn sensors(&self) -> &[SensorConfig] {
+        &self.sensors
+    }
+
+    /// What is stored for `address`, or its defaults.
+    pub fn sensor(&self, address: u8) -> SensorConfig {
+        self.sensors
+            .iter()
+            .find(|sensor| sensor.address == address)
+            .copied()
+            .unwrap_or_else(|| SensorConfig::new(address))
+    }
+
+    /// Replaces the entry for `sensor.address`, or adds one. Gives `sensor`
+    /// back if there is no room for another address.
+    pub fn set_sensor(&mut self, sensor: SensorConfig) -> Result<(), SensorConfig> {
+        let existing = self
+            .sensors
+            .iter_mut()
+            .find(|s| s.address == sensor.address);
+        match existing {
+            Some(entry) => {
+                *entry = sensor;
+                Ok(())
+            }
+            None => self.sensors.push(sensor),
+        }
+    }
+
+    pub fn remove_sensor(&mut self, address: u8) -> Option<SensorConfig> {
+        let index = self.sensors.iter().position(|s| s.address == address)?;
+        Some(self.sensors.swap_remove(index))
+    }
+
+    /// Writes the record into the start of `slot`, returning its length.
+    pub(crate) fn encode(&self, sequence: u32, slot: &mut [u8; SLOT_LEN]) -> usize {
+        slot[..2].copy_from_slice(&MAGIC);
+        slot[2] = VERSION;
+        slot[3] = self.sensors.len() as u8;
+        slot[4..HEADER_LEN].copy_from_slice(&sequence.to_le_bytes());
+        let mut len = HEADER_LEN;
+        for sensor in &self.sensors {
+            encode_sensor(sensor, &mut slot[len..len + SENSOR_LEN]);
+            len += SENSOR_LEN;
+        }
+        let crc = crc16(&slot[..len]);
+        slot[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        len + CRC_LEN
+    }
+
+    /// Parses the record at the start of `slot`, with its sequence number.
+    /// `None` for anything but a complete record of this [`VERSION`].
+    pub(crate) fn decode(slot: &[u8; SLOT_LEN]) -> Option<(u32, Self)> {
+        if slot[..2] != MAGIC || slot[2] != VERSION {
+            return None;
+        }
+        let count = slot[3] as usize;
+        if count > MAX_SENSORS {
+            return None;
+        }
+        let len = HEADER_LEN + count * SENSOR_LEN;
+        let crc = u16::from_le_bytes([slot[len], slot[len + 1]]);
+        if crc != crc16(&slot[..len]) {
+            return None;
+        }
+        let sequence = u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]);
+        let mut config = Self::default();
+        for entry in slot[HEADER_LEN..len].chunks_exact(SENSOR_LEN) {
+            config.sensors.push(decode_sensor(entry)?).ok()?;
+        }
+        Some((sequence, config))
+    }
+}
+
+fn encode_sensor(sensor: &SensorConfig, out: &mut [u8]) {
+    let settings = &sensor.settings;
+    let [high_lsb, high_msb] = settings.ambient_high.to_le_bytes();
+    let [low_lsb, low_msb] = settings.ambient_low.to_le_bytes();
+    let calibration = &sensor.calibration;
+    let [xtalk_lsb, xtalk_msb] = calibration.crosstalk_rate.unwrap_or(0).to_le_bytes();
+    let flags = calibration.offset_mm.map_or(0, |_| HAS_OFFSET)
+        | calibration.crosstalk_rate.map_or(0, |_| HAS_CROSSTALK);
+    out.copy_from_slice(&[
+        sensor.address,
+        mode_code(settings.range_mode),
+        settings.range_high,
+        settings.range_low,
+        mode_code(settings.ambient_mode),
+        high_lsb,
+        high_msb,
+        low_lsb,
+        low_msb,
+        settings.ambient_gain,
+        settings.range_scaler,
+        settings.ambient_scaler,
+        calibration.offset_mm.unwrap_or(0) as u8,
+        xtalk_lsb,
+        xtalk_msb,
+        flags,
+    ]);
+}
+
+fn decode_sensor(entry: &[u8]) -> Option<SensorConfig> {
+    let settings = Settings {
+        range_mode: mode(entry[1])?,
+        range_high: entry[2],
+        range_low: entry[3],
+        ambient_mode: mode(entry[4])?,
+        ambient_high: u16::from_le_bytes([entry[5], entry[6]]),
+        ambient_low: u16::from_le_bytes([entry[7], entry[8]]),
+        ambient_gain: within(Setting::AmbientGain, entry[9])?,
+        range_scaler: within(Setting::RangeScaler, entry[10])?,
+        ambient_scaler: within(Setting::AmbientScaler, entry[11])?,
+    };
+    let flags = entry[15];
+    if flags & !(HAS_OFFSET | HAS_CROSSTALK) != 0 {
+        return None;
+    }
+    Some(SensorConfig {
+        address: entry[0],
+        settings,
+        calibration: Calibration {
+            offset_mm: (flags & HAS_OFFSET != 0).then_some(entry[12] as i8),
+            crosstalk_rate: (flags & HAS_CROSSTALK != 0)
+                .then_some(u16::from_le_bytes([entry[13], entry[14]])),
+        },
+    })
+}
+
+fn mode_code(mode: InterruptMode) -> u8 {
+    InterruptMode::ALL
+        .iter()
+        .position(|&m| m == mode)
+        .unwrap_or(0) as u8
+}
+
+fn mode(code: u8) -> Option<InterruptMode> {
+    InterruptMode::ALL.get(code as usize).copied()
+}
+
+/// `value` if the shell would accept it for `setting`.
+fn within(setting: Setting, value: u8) -> Option<u8> {
+    let (min, max) = setting.limits()?;
+    (min..=max).contains(&(value as u16)).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ERASED;
+
+    fn two_sensors() -> StoredConfig {
+        let mut config = StoredConfig::default();
+        let mut first = SensorConfig::new(0x29);
+        first.settings.range_mode = InterruptMode::OutOfWindow;
+        first.settings.range_low = 20;
+        first.settings.ambient_high = 0x1234;
+        first.calibration.offset_mm = Some(-7);
+        let mut second = SensorConfig::new(0x30);
+        second.settings.ambient_gain = 3;
+        second.settings.range_scaler = 3;
+        second.calibration.crosstalk_rate = Some(0x0155);
+        config.set_sensor(first).unwrap();
+        config.set_sensor(second).unwrap();
+        config
+    }
+
+    #[test]
+    fn roundtrips() {
+        let config = two_sensors();
+        let mut slot = [ERASED; SLOT_LEN];
+        let len = config.encode(42, &mut slot);
+        assert_eq!(len, HEADER_LEN + 2 * SENSOR_LEN + CRC_LEN);
+        assert!(slot[len..].iter().all(|&b| b == ERASED));
+        assert_eq!(StoredConfig::decode(&slot), Some((42, config)));
+    }
+
+    #[test]
+    fn rejects_damaged_records() {
+        let mut slot = [ERASED; SLOT_LEN];
+        assert_eq!(StoredConfig::decode(&slot), None);
+
+        let len = two_sensors().encode(1, &mut slot);
+        for index in 0..len {
+            let mut damaged = slot;
+            damaged[index] ^= 0x10;
+            assert_eq!(StoredConfig::decode(&damaged), None, "byte {index}");
+        }
+    }
+
+    #[test]
+    fn uncalibrated_values_stay_unset() {
+        let config = two_sensors();
+        let mut slot = [ERASED; SLOT_LEN];
+        config.encode(1, &mut slot);
+        let (_, decoded) = StoredConfig::decode(&slot).unwrap();
+        assert_eq!(decoded.sensor(0x29).calibration.crosstalk_rate, None);
+        assert_eq!(decoded.sensor(0x30).calibration.offset_mm, None);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut slot = [ERASED; SLOT_LEN];
+        let len = two_sensors().encode(1, &mut slot);
+        slot[2] = VERSION + 1;
+        let crc = crc16(&slot[..len - CRC_LEN]);
+        slot[len - CRC_LEN..len].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(StoredConfig::decode(&slot), None);
+    }
+
+    #[test]
+    fn rejects_values_the_shell_would_not_accept() {
+        let mut config = StoredConfig::default();
+        let mut sensor = SensorConfig::new(0x29);
+        sensor.settings.range_scaler = 4;
+        config.set_sensor(sensor).unwrap();
+        let mut slot = [ERASED; SLOT_LEN];
+        config.encode(1, &mut slot);
+        assert_eq!(StoredConfig::decode(&slot), None);
+    }
+
+    #[test]
+    fn one_entry_per_address() {
+        let mut config = two_sensors();
+        let mut sensor = config.sensor(0x29);
+        sensor.calibration.offset_mm = Some(3);
+        config.set_sensor(sensor).unwrap();
+        assert_eq!(config.sensors().len(), 2);
+        assert_eq!(config.sensor(0x29).calibration.offset_mm, Some(3));
+        assert_eq!(config.sensor(0x31), SensorConfig::new(0x31));
+
+        for address in 0x31..0x31 + (MAX_SENSORS - 2) as u8 {
+            config.set_sensor(SensorConfig::new(address)).unwrap();
+        }
+        assert!(config.set_sensor(SensorConfig::new(0x50)).is_err());
+        assert_eq!(config.remove_sensor(0x30).map(|s| s.address), Some(0x30));
+        assert_eq!(config.sensors().len(), MAX_SENSORS - 1);
+    }
+}
@@ -0,0 +1,254 @@
+//! Loading and saving [`StoredConfig`] records across the two banks.
+
+use crate::flash::{Bank, Flash, ERASED};
+use crate::record::{StoredConfig, SLOT_LEN};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError<E> {
+    Flash(E),
+    /// The record read back differently than it was written.
+    Verify,
+}
+
+/// Where the next record goes, found by scanning both banks.
+#[derive(Clone, Copy, Debug)]
+struct Head {
+    /// Bank holding the newest valid record, or A if there is none.
+    bank: Bank,
+    /// First erased slot in `bank`, `None` once it is full.
+    free: Option<usize>,
+    /// Sequence number of the newest valid record, 0 if there is none.
+    sequence: u32,
+}
+
+/// Settings store on top of a [`Flash`] with two banks.
+///
+/// The sequence number grows by one per save. With 128K banks that is 512
+/// saves per erase, so it can't wrap within the flash's endurance.
+pub struct Store<F> {
+    flash: F,
+    head: Option<Head>,
+}
+
+impl<F: Flash> Store<F> {
+    /// # Panics
+    ///
+    /// If the bank size is not a non-zero multiple of [`SLOT_LEN`].
+    pub fn new(flash: F) -> Self {
+        let size = flash.bank_size();
+        assert!(size >= SLOT_LEN && size % SLOT_LEN == 0, "bank size");
+        Self { flash, head: None }
+    }
+
+    /// The newest valid record, `None` if there isn't one.
+    pub fn load(&mut self) -> Result<Option<StoredConfig>, F::Error> {
+        let (head, newest) = self.scan()?;
+        self.head = Some(head);
+        Ok(newest)
+    }
+
+    /// The newest valid record, or the defaults if there isn't one.
+    pub fn load_or_default(&mut self) -> Result<StoredConfig, F::Error> {
+        Ok(self.load()?.unwrap_or_default())
+    }
+
+    /// Appends `config` as the newest record. When the active bank is full
+    /// the other one is erased and used instead, so the previous record stays
+    /// valid until the new one is completely written.
+    pub fn save(&mut self, config: &StoredConfig) -> Result<(), StoreError<F::Error>> {
+        let head = match self.head.take() {
+            Some(head) => head,
+            None => self.scan().map_err(StoreError::Flash)?.0,
+        };
+        let (bank, slot) = match head.free {
+            Some(slot) => (head.bank, slot),
+            None => {
+                let bank = head.bank.other();
+                self.flash.erase(bank).map_err(StoreError::Flash)?;
+                (bank, 0)
+            }
+        };
+
+        let sequence = head.sequence + 1;
+        let mut record = [ERASED; SLOT_LEN];
+        let len = config.encode(sequence, &mut record);
+        self.flash
+            .program(bank, slot * SLOT_LEN, &record[..len])
+            .map_err(StoreError::Flash)?;
+
+        let mut written = [0; SLOT_LEN];
+        self.read_slot(bank, slot, &mut written)
+            .map_err(StoreError::Flash)?;
+        if StoredConfig::decode(&written) != Some((sequence, config.clone())) {
+            return Err(StoreError::Verify);
+        }
+
+        let next = slot + 1;
+        self.head = Some(Head {
+            bank,
+            free: (next < self.slots()).then_some(next),
+            sequence,
+        });
+        Ok(())
+    }
+
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    fn slots(&self) -> usize {
+        self.flash.bank_size() / SLOT_LEN
+    }
+
+    fn read_slot(
+        &mut self,
+        bank: Bank,
+        slot: usize,
+        buf: &mut [u8; SLOT_LEN],
+    ) -> Result<(), F::Error> {
+        self.flash.read(bank, slot * SLOT_LEN, buf)
+    }
+
+    /// Finds the newest valid record and the first erased slot after it.
+    /// Records are only ever appended, so a bank is scanned up to its first
+    /// erased slot; slots that don't decode (torn writes) are stepped over.
+    fn scan(&mut self) -> Result<(Head, Option<StoredConfig>), F::Error> {
+        let mut newest: Option<(u32, StoredConfig)> = None;
+        let mut free = [None; 2];
+        let mut newest_bank = Bank::A;
+        let mut slot_buf = [0; SLOT_LEN];
+
+        for bank in Bank::BOTH {
+            for slot in 0..self.slots() {
+                self.read_slot(bank, slot, &mut slot_buf)?;
+                if slot_buf.iter().all(|&b| b == ERASED) {
+                    free[bank.index()] = Some(slot);
+                    break;
+                }
+                let Some((sequence, config)) = StoredConfig::decode(&slot_buf) else {
+                    continue;
+                };
+                if newest
+                    .as_ref()
+                    .map_or(true, |(newest, _)| sequence > *newest)
+                {
+                    newest = Some((sequence, config));
+                    newest_bank = bank;
+                }
+            }
+        }
+
+        let head = Head {
+            bank: newest_bank,
+            free: free[newest_bank.index()],
+            sequence: newest.as_ref().map_or(0, |(sequence, _)| *sequence),
+        };
+        Ok((head, newest.map(|(_, config)| config)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{MemFlash, MemFlashError, SensorConfig};
+
+    /// Four slots per bank.
+    type Flash4 = MemFlash<{ 4 * SLOT_LEN }>;
+
+    fn config(offset_mm: i8) -> StoredConfig {
+        let mut config = StoredConfig::default();
+        let mut sensor = SensorConfig::new(0x29);
+        sensor.calibration.offset_mm = Some(offset_mm);
+        sensor.settings.range_high = offset_mm as u8;
+        config.set_sensor(sensor).unwrap();
+        config
+    }
+
+    #[test]
+    fn empty_flash_loads_defaults() {
+        let mut store = Store::new(Flash4::new());
+        assert_eq!(store.load(), Ok(None));
+        assert_eq!(store.load_or_default(), Ok(StoredConfig::default()));
+    }
+
+    #[test]
+    fn loads_latest_save() {
+        let mut store = Store::new(Flash4::new());
+        store.save(&config(1)).unwrap();
+        store.save(&config(2)).unwrap();
+
+        let mut store = Store::new(store.free());
+        assert_eq!(store.load(), Ok(Some(config(2))));
+    }
+
+    #[test]
+    fn alternates_banks_and_erases_once_per_bank_full() {
+        let mut store = Store::new(Flash4::new());
+        for n in 0..10 {
+            store.save(&config(n)).unwrap();
+            let mut reloaded = Store::new(store.free());
+            assert_eq!(reloaded.load(), Ok(Some(config(n))));
+            store = reloaded;
+        }
+        let flash = store.free();
+        // Saves 0-3 in A, 4-7 in B, 8-9 in A again.
+        assert_eq!(flash.erase_count(Bank::A), 1);
+        assert_eq!(flash.erase_count(Bank::B), 1);
+        assert!(flash.bank(Bank::B)[..SLOT_LEN].iter().any(|&b| b != ERASED));
+    }
+
+    #[test]
+    fn skips_garbage_and_records_of_other_versions() {
+        let mut flash = Flash4::new();
+        flash.bank_mut(Bank::A)[..4].copy_from_slice(b"VL\x02\x00");
+        flash.bank_mut(Bank::A)[SLOT_LEN..SLOT_LEN + 4].copy_from_slice(b"junk");
+        let mut store = Store::new(flash);
+        assert_eq!(store.load(), Ok(None));
+
+        store.save(&config(5)).unwrap();
+        let mut store = Store::new(store.free());
+        assert_eq!(store.load(), Ok(Some(config(5))));
+    }
+
+    #[test]
+    fn reports_flash_errors() {
+        let mut flash = Flash4::new();
+        flash.cut_power_after(3);
+        let mut store = Store::new(flash);
+        assert_eq!(
+            store.save(&config(1)),
+            Err(StoreError::Flash(MemFlashError::PowerLoss))
+        );
+        assert_eq!(store.load(), Err(MemFlashError::PowerLoss));
+    }
+
+    proptest! {
+        /// Whenever power is cut during a save, the store comes back with
+        /// either the interrupted save or the one before it.
+        #[test]
+        fn survives_power_cuts(cuts in proptest::collection::vec(0usize..40, 1..24)) {
+            let mut flash = Flash4::new();
+            let mut committed = None;
+            for (n, cut) in cuts.into_iter().enumerate() {
+                let next = config(n as i8);
+                flash.cut_power_after(cut);
+                let mut store = Store::new(flash);
+                let saved = store.save(&next).is_ok();
+                flash = store.free();
+                flash.restore_power();
+
+                let mut store = Store::new(flash);
+                let loaded = store.load().unwrap();
+                flash = store.free();
+                if saved {
+                    prop_assert_eq!(loaded.as_ref(), Some(&next));
+                } else {
+                    prop_assert!(loaded == committed || loaded.as_ref() == Some(&next));
+                }
+                committed = loaded;
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! The ambient auto-gain stepping against simulated light levels. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::ambient::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimVl6180x};
+
+/// Runs continuous ALS on a sim at each of `levels`, feeding every count to
+/// `auto_gain` and writing the settings it asks for, like the example does.
+fn measure(auto_gain: &mut AutoGain, levels: &[f32]) -> Vec<AmbientReading> {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut i2c = SimBus::new(&devices);
+    write_step(&mut i2c, DEFAULT_ADDRESS, auto_gain.step()).unwrap();
+    write_u8(
+        &mut i2c,
+        DEFAULT_ADDRESS,
+        SYSALS__START,
+        START_STOP | MODE_CONTINUOUS,
+    )
+    .unwrap();
+
+    let mut readings = Vec::new();
+    for &lux in levels {
+        devices[0].borrow_mut().set_ambient_lux(lux);
+        devices[0].borrow_mut().tick();
+        let raw = read_u16(&mut i2c, DEFAULT_ADDRESS, RESULT__ALS_VAL).unwrap();
+        let reading = auto_gain.update(raw);
+        if let Some(step) = reading.next {
+            write_step(&mut i2c, DEFAULT_ADDRESS, step).unwrap();
+        }
+        readings.push(reading);
+    }
+    readings
+}
+
+/// From 0.1 lux to 200 klux and back, a tenth of a decade per reading.
+fn ramp() -> Vec<f32> {
+    let up: Vec<f32> = (0..=64)
+        .map(|n| 0.1 * 10f32.powf(n as f32 / 10.0))
+        .collect();
+    up.iter().chain(up.iter().rev()).copied().collect()
+}
+
+fn relative_error(reading: &AmbientReading, lux: f32) -> f32 {
+    (reading.lux - lux).abs() / lux
+}
+
+/// Within what one count is worth at the step it was read with.
+fn within_a_count(reading: &AmbientReading, lux: f32) -> bool {
+    (reading.lux - lux).abs() <= reading.step.lux(1)
+}
+
+#[test]
+fn follows_a_light_ramp_both_ways() {
+    let mut auto_gain = AutoGain::new(AutoGainConfig::default());
+    let levels = ramp();
+    let readings = measure(&mut auto_gain, &levels);
+
+    // A few readings to step up from the start, then every one is good
+    for (reading, &lux) in readings.iter().zip(&levels).skip(4) {
+        assert!(within_a_count(reading, lux), "{:?} at {} lux", reading, lux);
+        assert!(reading.raw < u16::MAX, "saturated at {} lux", lux);
+        // Only too dark for the most sensitive step leaves few counts
+        assert!(
+            reading.raw >= 6_000 || reading.step == ALS_STEPS[0],
+            "{:?} at {} lux",
+            reading,
+            lux
+        );
+    }
+    let steps: Vec<AlsStep> = readings.iter().map(|reading| reading.step).collect();
+    assert_eq!(steps.first(), Some(&ALS_STEPS[ALS_START_STEP]));
+    assert!(steps.contains(&ALS_STEPS[0]));
+    assert!(steps.contains(&ALS_STEPS[ALS_STEPS.len() - 1]));
+    assert_eq!(auto_gain.step(), ALS_STEPS[0]);
+}
+
+#[test]
+fn lux_is_right_on_both_sides_of_a_change() {
+    let mut auto_gain = AutoGain::new(AutoGainConfig::default());
+    let readings = measure(&mut auto_gain, &[2_000.0, 4_500.0, 4_500.0]);
+
+    assert_eq!(readings[0].next, None);
+    let next = readings[1].next.expect("steps down");
+    assert_eq!(readings[2].step, next);
+    for reading in &readings[1..] {
+        assert!(relative_error(reading, 4_500.0) < 0.01, "{:?}", reading);
+    }
+    assert!(readings[2].raw < readings[1].raw);
+}
+
+#[test]
+fn does_not_hunt_between_two_steps() {
+    let mut auto_gain = AutoGain::new(AutoGainConfig::default());
+    // Flicker back and forth around where one step hands over to the next
+    let levels: Vec<f32> = (0..40)
+        .map(|n| if n % 2 == 0 { 3_000.0 } else { 4_000.0 })
+        .collect();
+    let readings = measure(&mut auto_gain, &levels);
+    let changes = readings
+        .iter()
+        .filter(|reading| reading.next.is_some())
+        .count();
+    assert_eq!(changes, 1);
+}
+
+#[test]
+fn stays_put_at_either_end() {
+    let mut auto_gain = AutoGain::new(AutoGainConfig::default());
+    let dark = measure(&mut auto_gain, &[0.0; 12]);
+    assert_eq!(dark.last().unwrap().step, ALS_STEPS[0]);
+    assert_eq!(dark.last().unwrap().next, None);
+
+    let bright = measure(&mut auto_gain, &[1_000_000.0; 12]);
+    let last = bright.last().unwrap();
+    assert_eq!(last.step, ALS_STEPS[ALS_STEPS.len() - 1]);
+    assert_eq!((last.raw, last.next), (u16::MAX, None));
+}
+
+#[test]
+fn reset_goes_back_to_the_start() {
+    let mut auto_gain = AutoGain::new(AutoGainConfig::default());
+    auto_gain.update(0);
+    assert_ne!(auto_gain.step(), ALS_STEPS[ALS_START_STEP]);
+    auto_gain.reset();
+    assert_eq!(auto_gain.step(), ALS_STEPS[ALS_START_STEP]);
+}
+
+#[test]
+#[should_panic(expected = "auto gain thresholds")]
+fn rejects_a_window_narrower_than_a_step() {
+    AutoGain::new(AutoGainConfig {
+        low_raw: 20_000,
+        high_raw: 30_000,
+    });
+}
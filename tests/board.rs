@@ -0,0 +1,61 @@
+//! The pin map checks the board module asserts at compile time. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use vl6180x_stm32f401_examples::board::*;
+
+const MAP: PinMap = PinMap {
+    i2c_scl: PinId::new('B', 8),
+    i2c_sda: PinId::new('B', 9),
+    led: PinId::new('C', 13),
+    button: PinId::new('A', 0),
+    sensor_xshut: PinId::new('B', 7),
+    sensor_interrupt: PinId::new('B', 6),
+};
+
+#[test]
+fn claims_only_its_own_pins() {
+    for pin in MAP.pins() {
+        assert!(MAP.claims(pin), "{:?}", pin);
+    }
+    // Same number on another port, same port with another number
+    assert!(!MAP.claims(PinId::new('A', 8)));
+    assert!(!MAP.claims(PinId::new('C', 0)));
+}
+
+#[test]
+fn a_pin_claimed_twice_is_invalid() {
+    assert!(MAP.is_valid());
+    let cases = [
+        PinMap {
+            sensor_interrupt: MAP.sensor_xshut,
+            ..MAP
+        },
+        PinMap {
+            i2c_sda: MAP.i2c_scl,
+            ..MAP
+        },
+        PinMap {
+            sensor_interrupt: MAP.i2c_scl,
+            ..MAP
+        },
+    ];
+    for map in cases {
+        assert!(!map.is_valid(), "{:?}", map);
+    }
+    // Same number on different ports is fine
+    let map = PinMap {
+        button: PinId::new('A', 13),
+        ..MAP
+    };
+    assert!(map.is_valid());
+}
+
+#[test]
+fn the_board_leaves_the_uart_pins_free() {
+    assert!(PIN_MAP.is_valid());
+    for pin in [LOG_UART_TX, CONSOLE_RX, STREAM_UART_TX] {
+        assert!(!PIN_MAP.claims(pin), "{:?}", pin);
+    }
+}
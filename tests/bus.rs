@@ -0,0 +1,65 @@
+//! The ceiling bus mutex and `new_ceiling_bus!` on the host, where taking
+//! the ceiling masks nothing. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use shared_bus::BusMutex;
+use vl6180x_stm32f401_examples::bus::{BusManager, CeilingMutex};
+use vl6180x_stm32f401_examples::new_ceiling_bus;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimVl6180x};
+
+type Manager = BusManager<SimBus<'static>, 2>;
+
+fn devices() -> &'static [RefCell<SimVl6180x>] {
+    Box::leak(Box::new([RefCell::new(SimVl6180x::new())]))
+}
+
+#[test]
+fn the_mutex_lends_the_bus_out() {
+    let mutex = CeilingMutex::<_, 2>::create(0u32);
+    mutex.lock(|bus| *bus += 1);
+    assert_eq!(mutex.lock(|bus| *bus), 1);
+    // The lowest and highest ceilings with four priority bits
+    CeilingMutex::<_, 1>::create(()).lock(|_| ());
+    CeilingMutex::<_, 16>::create(()).lock(|_| ());
+}
+
+/// A task locking the bus again from inside its own transaction is a bug,
+/// caught rather than handing out the bus twice.
+#[test]
+#[should_panic]
+fn the_mutex_is_not_reentrant() {
+    let mutex = CeilingMutex::<_, 2>::create(0u32);
+    mutex.lock(|_| mutex.lock(|_| ()));
+}
+
+#[test]
+fn proxies_share_one_bus() {
+    let devices = devices();
+    let manager: &'static Manager =
+        new_ceiling_bus!(SimBus<'static>, 2, SimBus::new(devices)).unwrap();
+    let mut writer = manager.acquire_i2c();
+    let mut reader = manager.acquire_i2c();
+
+    write_u8(&mut writer, DEFAULT_ADDRESS, SYSRANGE__THRESH_HIGH, 150).unwrap();
+    assert_eq!(
+        read_u8(&mut reader, DEFAULT_ADDRESS, SYSRANGE__THRESH_HIGH),
+        Ok(150)
+    );
+}
+
+#[test]
+fn one_manager_per_call_site() {
+    fn take(bus: SimBus<'static>) -> Option<&'static Manager> {
+        new_ceiling_bus!(SimBus<'static>, 2, bus)
+    }
+
+    let bus = SimBus::new(devices());
+    assert!(take(bus).is_some());
+    assert!(take(bus).is_none());
+    // A different call site has a manager of its own
+    assert!(new_ceiling_bus!(SimBus<'static>, 2, bus).is_some());
+}
@@ -0,0 +1,196 @@
+//! Bus recovery sequencing against simulated SCL/SDA lines. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use vl6180x_stm32f401_examples::bus_recovery::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimError, SimI2cLines, SimVl6180x};
+
+fn recover(lines: &SimI2cLines) -> (Result<u8, BusStuck>, u32) {
+    let mut half_periods = 0;
+    let result = recover_lines(&mut lines.scl(), &mut lines.sda(), || half_periods += 1);
+    (result, half_periods)
+}
+
+#[test]
+fn clocks_until_sda_is_released_then_stops() {
+    let lines = SimI2cLines::new();
+    lines.hold_sda(4);
+    let (result, half_periods) = recover(&lines);
+    assert_eq!(result, Ok(4));
+    // The STOP pulses SCL once more
+    assert_eq!((lines.pulses(), lines.stops()), (5, 1));
+    assert_eq!(half_periods, 1 + 4 * 2 + 4);
+    assert!(lines.is_idle());
+}
+
+#[test]
+fn an_idle_bus_only_gets_a_stop() {
+    let lines = SimI2cLines::new();
+    assert_eq!(recover(&lines).0, Ok(0));
+    assert_eq!((lines.pulses(), lines.stops()), (1, 1));
+    assert!(lines.is_idle());
+}
+
+#[test]
+fn gives_up_after_nine_pulses() {
+    let lines = SimI2cLines::new();
+    lines.hold_sda(u8::MAX);
+    assert_eq!(recover(&lines).0, Err(BusStuck));
+    assert_eq!(lines.pulses(), MAX_PULSES as u32 + 1);
+    assert_eq!(lines.stops(), 0);
+
+    // A device that needs all nine is still freed
+    let lines = SimI2cLines::new();
+    lines.hold_sda(MAX_PULSES);
+    assert_eq!(recover(&lines).0, Ok(MAX_PULSES));
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MockError {
+    /// What the peripheral reports while SDA is held low.
+    Bus,
+    Sim(SimError),
+}
+
+/// An I2C peripheral on the simulated lines: every transfer fails while a
+/// device holds SDA low.
+struct MockI2c<'a> {
+    lines: &'a SimI2cLines,
+    bus: SimBus<'a>,
+}
+
+impl MockI2c<'_> {
+    fn check(&self) -> Result<(), MockError> {
+        if self.lines.is_idle() {
+            Ok(())
+        } else {
+            Err(MockError::Bus)
+        }
+    }
+}
+
+impl Write for MockI2c<'_> {
+    type Error = MockError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), MockError> {
+        self.check()?;
+        self.bus.write(address, bytes).map_err(MockError::Sim)
+    }
+}
+
+impl WriteRead for MockI2c<'_> {
+    type Error = MockError;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), MockError> {
+        self.check()?;
+        self.bus
+            .write_read(address, bytes, buffer)
+            .map_err(MockError::Sim)
+    }
+}
+
+impl BusRecovery for MockI2c<'_> {
+    type Error = MockError;
+
+    fn is_bus_error(error: &MockError) -> bool {
+        *error == MockError::Bus
+    }
+
+    fn recover(self) -> (Self, Result<u8, BusStuck>) {
+        let result = recover_lines(&mut self.lines.scl(), &mut self.lines.sda(), || ());
+        (self, result)
+    }
+}
+
+#[test]
+fn repeated_bus_errors_recover_the_bus() {
+    let lines = SimI2cLines::new();
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut i2c = RecoveringBus::new(
+        MockI2c {
+            lines: &lines,
+            bus: SimBus::new(&devices),
+        },
+        3,
+    );
+    assert_eq!(
+        read_u8(&mut i2c, DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID),
+        Ok(MODEL_ID)
+    );
+
+    // Reset mid-read, the sensor holds SDA until it has clocked out its byte
+    lines.hold_sda(7);
+    for _ in 0..3 {
+        assert_eq!(
+            read_u8(&mut i2c, DEFAULT_ADDRESS, RESULT__RANGE_VAL),
+            Err(MockError::Bus)
+        );
+    }
+    assert_eq!((i2c.recoveries(), i2c.last_recovery()), (1, Some(Ok(7))));
+    assert_eq!(
+        read_u8(&mut i2c, DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID),
+        Ok(MODEL_ID)
+    );
+}
+
+#[test]
+fn nacks_and_interrupted_runs_do_not_recover() {
+    let lines = SimI2cLines::new();
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut i2c = RecoveringBus::new(
+        MockI2c {
+            lines: &lines,
+            bus: SimBus::new(&devices),
+        },
+        2,
+    );
+    // Nothing on the address is no reason to touch the bus
+    for _ in 0..5 {
+        assert_eq!(
+            read_u8(&mut i2c, 0x30, IDENTIFICATION__MODEL_ID),
+            Err(MockError::Sim(SimError::Nack(0x30)))
+        );
+    }
+    assert_eq!(i2c.recoveries(), 0);
+
+    // A recovery or a good transfer starts the count over
+    lines.hold_sda(1);
+    assert!(read_u8(&mut i2c, DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID).is_err());
+    assert_eq!(i2c.recover(), Ok(1));
+    assert!(read_u8(&mut i2c, DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID).is_ok());
+    lines.hold_sda(1);
+    assert!(read_u8(&mut i2c, DEFAULT_ADDRESS, IDENTIFICATION__MODEL_ID).is_err());
+    assert_eq!(i2c.recoveries(), 1);
+}
+
+#[test]
+fn a_bus_that_stays_stuck_is_reported() {
+    let lines = SimI2cLines::new();
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut i2c = RecoveringBus::new(
+        MockI2c {
+            lines: &lines,
+            bus: SimBus::new(&devices),
+        },
+        1,
+    );
+    lines.hold_sda(u8::MAX);
+    assert_eq!(
+        write_u8(&mut i2c, DEFAULT_ADDRESS, SYSRANGE__START, START_STOP),
+        Err(MockError::Bus)
+    );
+    assert_eq!(i2c.last_recovery(), Some(Err(BusStuck)));
+    // And keeps being tried on every further error
+    let _ = write_u8(&mut i2c, DEFAULT_ADDRESS, SYSRANGE__START, START_STOP);
+    assert_eq!(i2c.recoveries(), 2);
+}
@@ -0,0 +1,204 @@
+//! The button driver against simulated bouncing contacts. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use vl6180x_stm32f401_examples::button::*;
+
+const CONFIG: ButtonConfig = ButtonConfig {
+    debounce_ms: 20,
+    long_press_ms: 1_000,
+    double_press_ms: 300,
+};
+
+/// A pin level over time, built from segments.
+#[derive(Default)]
+struct Waveform {
+    /// The level of each millisecond, true while pressed.
+    levels: Vec<bool>,
+}
+
+impl Waveform {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn hold(mut self, pressed: bool, ms: u32) -> Self {
+        self.levels.extend((0..ms).map(|_| pressed));
+        self
+    }
+
+    /// Contacts closing: chatter for `bounce_ms`, then held for `ms`.
+    fn press(self, bounce_ms: u32, ms: u32) -> Self {
+        self.chatter(bounce_ms, true).hold(true, ms)
+    }
+
+    fn release(self, bounce_ms: u32, ms: u32) -> Self {
+        self.chatter(bounce_ms, false).hold(false, ms)
+    }
+
+    /// Flips every 1-3 ms, never staying put for the debounce time, and
+    /// ends just before the contacts settle on `pressed`.
+    fn chatter(mut self, ms: u32, pressed: bool) -> Self {
+        let mut level = !self.levels.last().copied().unwrap_or(false);
+        let mut left = ms;
+        let mut run = 1;
+        while left > 0 {
+            let len = run.min(left);
+            self.levels.extend((0..len).map(|_| level));
+            level = !level;
+            left -= len;
+            run = run % 3 + 1;
+        }
+        if let Some(last) = self.levels.last_mut() {
+            *last = !pressed;
+        }
+        self
+    }
+
+    /// Samples every `tick_ms` from `start_ms`, collecting each event with
+    /// the time it came out.
+    fn tick(
+        &self,
+        button: &mut DebouncedButton,
+        start_ms: u32,
+        tick_ms: usize,
+    ) -> Vec<(ButtonEvent, u32)> {
+        let mut events = Vec::new();
+        for (t, &pressed) in self.levels.iter().enumerate().step_by(tick_ms) {
+            let now_ms = start_ms.wrapping_add(t as u32);
+            button.update(pressed, now_ms, |event| events.push((event, t as u32)));
+        }
+        events
+    }
+
+    /// Updates on every edge, as an EXTI on both edges would, and every
+    /// 10 ms only while the button isn't settled.
+    fn edges(&self, button: &mut DebouncedButton) -> (Vec<(ButtonEvent, u32)>, u32) {
+        let mut events = Vec::new();
+        let mut updates = 0;
+        let mut last = false;
+        for (t, &pressed) in self.levels.iter().enumerate() {
+            let t = t as u32;
+            let edge = pressed != last;
+            let tick = t % 10 == 0 && !button.is_settled();
+            if edge || tick {
+                button.update(pressed, t, |event| events.push((event, t)));
+                updates += 1;
+            }
+            last = pressed;
+        }
+        (events, updates)
+    }
+}
+
+fn run(waveform: &Waveform) -> Vec<(ButtonEvent, u32)> {
+    waveform.tick(&mut DebouncedButton::new(CONFIG), 0, 1)
+}
+
+fn kinds(events: &[(ButtonEvent, u32)]) -> Vec<ButtonEvent> {
+    events.iter().map(|&(event, _)| event).collect()
+}
+
+#[test]
+fn a_bouncing_press_is_one_short_press() {
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(8, 150)
+        .release(12, 500);
+    let events = run(&waveform);
+    assert_eq!(kinds(&events), [ButtonEvent::ShortPress]);
+    // The double press window runs from the end of the release bounce
+    assert_eq!(events[0].1, 100 + 8 + 150 + 12 + 300);
+}
+
+#[test]
+fn glitches_shorter_than_the_debounce_time_are_ignored() {
+    let mut waveform = Waveform::new().hold(false, 50);
+    for _ in 0..10 {
+        waveform = waveform.hold(true, 5).hold(false, 100);
+    }
+    let waveform = waveform.chatter(200, false).hold(false, 500);
+    let mut button = DebouncedButton::new(CONFIG);
+    assert!(waveform.tick(&mut button, 0, 1).is_empty());
+    assert!(!button.is_pressed());
+    assert!(button.is_settled());
+}
+
+#[test]
+fn holding_is_a_long_press_while_still_held() {
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(10, 2_000)
+        .release(10, 500);
+    let events = run(&waveform);
+    assert_eq!(kinds(&events), [ButtonEvent::LongPress]);
+    // The press is timed from the end of its bounce
+    assert_eq!(events[0].1, 100 + 10 + 1_000);
+}
+
+#[test]
+fn two_quick_presses_are_a_double_press() {
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(5, 100)
+        .release(5, 150)
+        .press(5, 1_500)
+        .release(5, 500);
+    let events = run(&waveform);
+    // Reported on the second press once debounced, not followed by a long one
+    assert_eq!(kinds(&events), [ButtonEvent::DoublePress]);
+    assert_eq!(events[0].1, 100 + 5 + 100 + 5 + 150 + 5 + 20);
+
+    // Too far apart they are two short presses
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(5, 100)
+        .release(5, 400)
+        .press(5, 100)
+        .release(5, 400);
+    assert_eq!(
+        kinds(&run(&waveform)),
+        [ButtonEvent::ShortPress, ButtonEvent::ShortPress]
+    );
+}
+
+#[test]
+fn sparse_updates_still_see_every_press() {
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(10, 200)
+        .release(10, 600)
+        .press(10, 1_500)
+        .release(10, 600)
+        .press(10, 100)
+        .release(10, 100)
+        .press(10, 100)
+        .release(10, 600);
+    let expected = [
+        ButtonEvent::ShortPress,
+        ButtonEvent::LongPress,
+        ButtonEvent::DoublePress,
+    ];
+    // A busy main loop only looking every 50 ms
+    let mut button = DebouncedButton::new(CONFIG);
+    assert_eq!(kinds(&waveform.tick(&mut button, 0, 50)), expected);
+
+    // An EXTI only polling while something is pending
+    let mut button = DebouncedButton::new(CONFIG);
+    let (events, updates) = waveform.edges(&mut button);
+    assert_eq!(kinds(&events), expected);
+    assert!(updates < waveform.levels.len() as u32 / 5);
+    assert!(button.is_settled());
+}
+
+#[test]
+fn timing_survives_the_clock_wrapping() {
+    let waveform = Waveform::new()
+        .hold(false, 100)
+        .press(5, 1_200)
+        .release(5, 100);
+    let mut button = DebouncedButton::new(CONFIG);
+    let events = waveform.tick(&mut button, u32::MAX - 500, 1);
+    assert_eq!(kinds(&events), [ButtonEvent::LongPress]);
+}
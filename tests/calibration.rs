@@ -0,0 +1,258 @@
+//! Offset and crosstalk calibration math and the `calibrate` commands
+//! against the simulated sensor. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::calibration::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::shell::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimDelay, SimError, SimVl6180x};
+use vl6180x_stm32f401_examples::storage::Calibration;
+
+/// 1 Mcps in 9.7 fixed point.
+const MCPS: u16 = 1 << 7;
+
+fn readings(values: &[u16]) -> impl FnMut() -> Result<u16, ()> + '_ {
+    let mut values = values.iter();
+    move || values.next().copied().ok_or(())
+}
+
+#[test]
+fn mean_rounds_to_nearest() {
+    assert_eq!(mean(4, readings(&[50, 51, 51, 51])), Ok(51));
+    assert_eq!(mean(4, readings(&[50, 50, 51, 51])), Ok(51));
+    assert_eq!(mean(4, readings(&[50, 50, 50, 51])), Ok(50));
+    assert_eq!(mean(0, readings(&[])), Err(CalibrationError::NoSamples));
+}
+
+#[test]
+fn offset_corrects_the_mean() {
+    let calibration = calibrate_offset(50, 3, readings(&[57, 57, 58])).unwrap();
+    assert_eq!(
+        calibration,
+        OffsetCalibration {
+            target_mm: 50,
+            measured_mm: 57,
+            offset_mm: -7,
+        }
+    );
+    assert_eq!(offset_for(50, 50), Some(0));
+    assert_eq!(offset_for(200, 73), Some(127));
+    assert_eq!(offset_for(10, 138), Some(-128));
+    assert_eq!(offset_for(10, 139), None);
+}
+
+#[test]
+fn reports_out_of_range_and_sensor_errors() {
+    assert_eq!(
+        calibrate_offset(50, 2, readings(&[250, 250])),
+        Err(CalibrationError::OffsetOutOfRange { measured_mm: 250 })
+    );
+    assert_eq!(
+        calibrate_offset(50, 3, readings(&[50, 50])),
+        Err(CalibrationError::Sensor(()))
+    );
+}
+
+#[test]
+fn calibrate_command_writes_the_offset() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let bus = SimBus::new(&devices);
+    let mut shell = Shell::new(bus, DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+    devices[0].borrow_mut().set_part_offset_mm(7);
+    devices[0].borrow_mut().set_range_mm(50);
+
+    assert_eq!(
+        shell.execute(Command::CalibrateOffset(50), &mut delay),
+        Ok(Response::Offset {
+            offset_mm: -7,
+            measured_mm: 57,
+            target_mm: 50,
+        })
+    );
+    let offset = devices[0]
+        .borrow()
+        .register(SYSRANGE__PART_TO_PART_RANGE_OFFSET);
+    assert_eq!(offset, 0xF9);
+    assert_eq!(shell.calibration().offset_mm, Some(-7));
+    assert_eq!(
+        shell.execute(Command::Read(Measurement::Range), &mut delay),
+        Ok(Response::RangeMm(50))
+    );
+
+    // The register counts in scaled units
+    let scaler = Assignment::RangeScaler(2);
+    shell.execute(Command::Set(scaler), &mut delay).unwrap();
+    let offset = devices[0]
+        .borrow()
+        .register(SYSRANGE__PART_TO_PART_RANGE_OFFSET);
+    assert_eq!(offset as i8, -4);
+}
+
+#[test]
+fn failed_calibration_keeps_the_previous_offset() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let bus = SimBus::new(&devices);
+    let mut shell = Shell::new(bus, DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+    let previous = Calibration {
+        offset_mm: Some(3),
+        crosstalk_rate: None,
+    };
+    shell.apply_calibration(previous).unwrap();
+    devices[0].borrow_mut().set_range_mm(190);
+
+    assert_eq!(
+        shell.execute(Command::CalibrateOffset(20), &mut delay),
+        Err(ShellError::OffsetOutOfRange { measured_mm: 190 })
+    );
+    devices[0].borrow_mut().set_range_error(7);
+    assert_eq!(
+        shell.execute(Command::CalibrateOffset(190), &mut delay),
+        Err(ShellError::Range(7))
+    );
+    assert_eq!(
+        devices[0]
+            .borrow()
+            .register(SYSRANGE__PART_TO_PART_RANGE_OFFSET),
+        3
+    );
+    assert_eq!(shell.calibration(), &previous);
+}
+
+#[test]
+fn calibration_needs_range_stopped() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut shell = Shell::new(SimBus::new(&devices), DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+    shell
+        .execute(Command::Start(Measurement::Range), &mut delay)
+        .unwrap();
+    let busy: Result<Response, ShellError<SimError>> = Err(ShellError::Busy);
+    assert_eq!(
+        shell.execute(Command::CalibrateOffset(50), &mut delay),
+        busy
+    );
+}
+
+fn samples(values: &[(u16, u16)]) -> impl FnMut() -> Result<RangeSample, ()> + '_ {
+    let mut values = values.iter();
+    move || {
+        let &(range_mm, return_rate) = values.next().ok_or(())?;
+        Ok(RangeSample {
+            range_mm,
+            return_rate,
+        })
+    }
+}
+
+#[test]
+fn range_stats_track_spread_and_rate() {
+    let stats = range_stats(3, samples(&[(80, 600), (78, 640), (83, 641)])).unwrap();
+    assert_eq!(
+        stats,
+        RangeStats {
+            mean_mm: 80,
+            min_mm: 78,
+            max_mm: 83,
+            mean_rate: 627,
+        }
+    );
+    assert_eq!(
+        range_stats(0, samples(&[])),
+        Err(CalibrationError::NoSamples)
+    );
+    assert_eq!(
+        range_stats(2, samples(&[(80, 600)])),
+        Err(CalibrationError::Sensor(()))
+    );
+}
+
+#[test]
+fn crosstalk_is_the_share_of_signal_that_reads_short() {
+    // 4 Mcps from a target at 100 mm and 1 Mcps from the glass read 80 mm
+    assert_eq!(crosstalk_for(100, 80, 5 * MCPS), MCPS);
+    assert_eq!(crosstalk_for(100, 100, 5 * MCPS), 0);
+    assert_eq!(crosstalk_for(100, 120, 5 * MCPS), 0);
+    assert_eq!(crosstalk_for(100, 0, u16::MAX), u16::MAX);
+
+    let calibration = calibrate_crosstalk(100, 2, samples(&[(80, 640), (80, 640)])).unwrap();
+    assert_eq!(calibration.rate, MCPS);
+    assert_eq!(calibration.before.mean_mm, 80);
+}
+
+#[test]
+fn calibrate_crosstalk_command_compensates_the_glass() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let bus = SimBus::new(&devices);
+    let mut shell = Shell::new(bus, DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+    devices[0].borrow_mut().set_range_mm(100);
+    devices[0].borrow_mut().set_return_rate(4 * MCPS);
+    devices[0].borrow_mut().set_crosstalk_rate(MCPS);
+    assert_eq!(
+        shell.execute(Command::Read(Measurement::Range), &mut delay),
+        Ok(Response::RangeMm(80))
+    );
+
+    let response = shell.execute(Command::CalibrateCrosstalk(100), &mut delay);
+    let Ok(Response::Crosstalk {
+        rate,
+        before,
+        after,
+        ..
+    }) = response
+    else {
+        panic!("{:?}", response);
+    };
+    assert_eq!(rate, MCPS);
+    assert_eq!((before.mean_mm, before.min_mm, before.max_mm), (80, 80, 80));
+    assert_eq!(before.mean_rate, 5 * MCPS);
+    assert_eq!((after.mean_mm, after.min_mm, after.max_mm), (100, 100, 100));
+    let register = devices[0]
+        .borrow()
+        .register_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE);
+    assert_eq!(register, MCPS);
+    assert_eq!(shell.calibration().crosstalk_rate, Some(MCPS));
+
+    // A fresh sensor gets it back from the stored calibration
+    devices[0].borrow_mut().set_powered(false);
+    devices[0].borrow_mut().set_powered(true);
+    let mut shell = Shell::new(bus, DEFAULT_ADDRESS, Settings::default());
+    shell
+        .apply_calibration(Calibration {
+            offset_mm: None,
+            crosstalk_rate: Some(rate),
+        })
+        .unwrap();
+    assert_eq!(
+        shell.execute(Command::Read(Measurement::Range), &mut delay),
+        Ok(Response::RangeMm(100))
+    );
+}
+
+#[test]
+fn failed_crosstalk_calibration_keeps_the_previous_rate() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut shell = Shell::new(SimBus::new(&devices), DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+    let previous = Calibration {
+        offset_mm: None,
+        crosstalk_rate: Some(MCPS / 2),
+    };
+    shell.apply_calibration(previous).unwrap();
+    devices[0].borrow_mut().set_range_error(11);
+
+    assert_eq!(
+        shell.execute(Command::CalibrateCrosstalk(100), &mut delay),
+        Err(ShellError::Range(11))
+    );
+    let register = devices[0]
+        .borrow()
+        .register_u16(SYSRANGE__CROSSTALK_COMPENSATION_RATE);
+    assert_eq!(register, MCPS / 2);
+    assert_eq!(shell.calibration(), &previous);
+}
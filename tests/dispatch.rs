@@ -0,0 +1,130 @@
+//! Two simulated sensors sharing the `EXTI9_5` vector. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::dispatch::*;
+use vl6180x_stm32f401_examples::handlers::InterruptPin;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sensor_array::{Sensor, SensorArray, SensorSpec};
+use vl6180x_stm32f401_examples::sim::{
+    Edge, SimBus, SimDelay, SimInterruptPin, SimLed, SimVl6180x, SimXshutPin,
+};
+
+const ADDRESSES: [u8; 2] = [0x30, 0x31];
+
+/// PB5 and PB6.
+const DISPATCHER: Dispatcher<2> = Dispatcher::new([Route::new(5, 0), Route::new(6, 1)]);
+
+type SimSensor<'a> = Sensor<vl6180x::ReadyMode, SimBus<'a>, SimXshutPin<'a>, SimInterruptPin<'a>>;
+
+fn sensors(devices: &[RefCell<SimVl6180x>; 2]) -> Vec<SimSensor<'_>> {
+    let specs = devices.iter().zip(ADDRESSES).map(|(device, address)| {
+        let mut config = vl6180x::Config::new();
+        config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+        SensorSpec {
+            x_shutdown_pin: SimXshutPin::new(device),
+            interrupt_pin: SimInterruptPin::new(device, Edge::Rising),
+            config,
+            address,
+        }
+    });
+    let bus = SimBus::new(devices);
+    let array = SensorArray::<_, _, _, 2>::new(specs, || bus, &mut SimDelay::new());
+    let mut sensors: Vec<_> = array.expect("array").into_sensors().into_iter().collect();
+    // The driver set GPIO1 up while the pins weren't looking
+    for sensor in &mut sensors {
+        assert!(!sensor.interrupt_pin.sample());
+    }
+    sensors
+}
+
+#[test]
+fn services_only_the_pending_sensor() {
+    let devices: [RefCell<SimVl6180x>; 2] = Default::default();
+    let mut sensors = sensors(&devices);
+    let mut led = SimLed::new();
+
+    // Sensor 1 measures and interrupts; sensor 0 has a result waiting too,
+    // but its line hasn't fired
+    write_u8(
+        &mut SimBus::new(&devices),
+        ADDRESSES[1],
+        SYSRANGE__START,
+        START_STOP | MODE_CONTINUOUS,
+    )
+    .unwrap();
+    devices[1].borrow_mut().set_range_mm(42);
+    assert!(sensors[1].interrupt_pin.tick());
+    devices[0]
+        .borrow_mut()
+        .set_register(RESULT__INTERRUPT_STATUS_GPIO, INT_NEW_SAMPLE_READY);
+
+    let mut readings = Vec::new();
+    let serviced = DISPATCHER.dispatch(
+        ExtiVector::Exti9_5,
+        &mut sensors,
+        &mut led,
+        |index, handled| {
+            assert!(handled.cleared.is_ok());
+            readings.push((index, handled.reading.ok()));
+        },
+    );
+    assert_eq!(serviced, 1);
+    assert_eq!(readings, [(1, Some(42))]);
+    assert_eq!(led.flashes(), 1);
+
+    assert!(!sensors[1].interrupt_pin.check_interrupt());
+    assert_eq!(devices[1].borrow().interrupt_status(), 0);
+    assert_eq!(devices[0].borrow().interrupt_status(), INT_NEW_SAMPLE_READY);
+
+    // Nothing left for the vector
+    let serviced = DISPATCHER.dispatch(ExtiVector::Exti9_5, &mut sensors, &mut led, |_, _| {
+        panic!("serviced twice")
+    });
+    assert_eq!(serviced, 0);
+}
+
+#[test]
+fn services_both_when_both_are_pending() {
+    let devices: [RefCell<SimVl6180x>; 2] = Default::default();
+    let mut sensors = sensors(&devices);
+    let mut led = SimLed::new();
+    for (device, address) in devices.iter().zip(ADDRESSES) {
+        device.borrow_mut().set_range_mm(address as u16);
+        write_u8(
+            &mut SimBus::new(&devices),
+            address,
+            SYSRANGE__START,
+            START_STOP | MODE_CONTINUOUS,
+        )
+        .unwrap();
+    }
+    for sensor in &mut sensors {
+        assert!(sensor.interrupt_pin.sample());
+    }
+
+    // Another vector leaves them alone
+    let serviced = DISPATCHER.dispatch(ExtiVector::Exti0, &mut sensors, &mut led, |_, _| ());
+    assert_eq!(serviced, 0);
+    assert!(sensors
+        .iter()
+        .all(|sensor| sensor.interrupt_pin.check_interrupt()));
+
+    let mut readings = Vec::new();
+    let serviced = DISPATCHER.dispatch(
+        ExtiVector::Exti9_5,
+        &mut sensors,
+        &mut led,
+        |index, handled| {
+            readings.push((index, handled.reading.ok()));
+        },
+    );
+    assert_eq!(serviced, 2);
+    assert_eq!(readings, [(0, Some(0x30)), (1, Some(0x31))]);
+    assert!(devices
+        .iter()
+        .all(|device| device.borrow().interrupt_status() == 0));
+}
@@ -0,0 +1,107 @@
+//! The vl6180x driver itself against the simulated sensor, so that the model
+//! is held to what the real driver writes and reads, and the bus model's own
+//! checks. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::WriteRead;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimError, SimVl6180x};
+
+#[test]
+fn default_config_polls_single_ranges() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut tof = vl6180x::VL6180X::new(SimBus::new(&devices)).expect("init");
+
+    for mm in [20, 105, 200] {
+        devices[0].borrow_mut().set_range_mm(mm);
+        assert_eq!(tof.poll_range_mm_single_blocking().expect("poll"), mm);
+        // Each read clears its interrupt, or the next would return at once
+        assert_eq!(range_int_status(devices[0].borrow().interrupt_status()), 0);
+    }
+    assert!(!devices[0].borrow().is_range_continuous());
+}
+
+#[test]
+fn with_config_writes_the_config() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_range_high_interrupt_threshold(150);
+    config.set_range_low_interrupt_threshold(30);
+    let mut tof = vl6180x::VL6180X::with_config(SimBus::new(&devices), &config).expect("init");
+
+    {
+        let sensor = devices[0].borrow();
+        assert_eq!(sensor.register(SYSTEM__FRESH_OUT_OF_RESET), 0);
+        assert_eq!(sensor.register(SYSRANGE__THRESH_HIGH), 150);
+        assert_eq!(sensor.register(SYSRANGE__THRESH_LOW), 30);
+    }
+
+    devices[0].borrow_mut().set_range_mm(64);
+    assert_eq!(tof.poll_range_mm_single_blocking().expect("poll"), 64);
+}
+
+#[test]
+fn dynamic_mode_moves_the_address() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let bus = SimBus::new(&devices);
+    let mut tof = vl6180x::VL6180X::new(bus)
+        .expect("init")
+        .into_dynamic_mode();
+
+    tof.try_change_i2c_address(0x30).expect("change address");
+    assert_eq!(bus.responders(DEFAULT_ADDRESS), 0);
+    assert_eq!(bus.responders(0x30), 1);
+    assert_eq!(devices[0].borrow().address(), 0x30);
+}
+
+/// A transfer running off the end of the register map is refused whole: the
+/// write half of a write-read isn't applied when the read half doesn't fit.
+#[test]
+fn rejects_spans_past_the_register_map() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut bus = SimBus::new(&devices);
+    let mut buffer = [0; 2];
+
+    assert_eq!(
+        bus.write_read(DEFAULT_ADDRESS, &[0x02, 0xFF, 0xAA], &mut buffer),
+        Err(SimError::InvalidRegister(0x2FF))
+    );
+    assert_eq!(devices[0].borrow().register(0x2FF), 0);
+
+    assert_eq!(
+        bus.write_read(DEFAULT_ADDRESS, &[0xFF, 0xFF, 0xAA], &mut buffer[..1]),
+        Err(SimError::InvalidRegister(0xFFFF))
+    );
+    assert_eq!(
+        bus.write_read(DEFAULT_ADDRESS, &[0x02, 0xFF], &mut buffer[..1]),
+        Ok(())
+    );
+}
+
+/// A range past the top of the model, pushed further by the part's offset,
+/// reads as an overflow rather than wrapping around.
+#[test]
+fn far_ranges_overflow_the_reading() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut bus = SimBus::new(&devices);
+    devices[0].borrow_mut().set_range_mm(u16::MAX);
+    devices[0].borrow_mut().set_part_offset_mm(i16::MAX);
+    write_u8(
+        &mut bus,
+        DEFAULT_ADDRESS,
+        SYSRANGE__PART_TO_PART_RANGE_OFFSET,
+        0x7F,
+    )
+    .unwrap();
+
+    write_u8(&mut bus, DEFAULT_ADDRESS, SYSRANGE__START, START_STOP).unwrap();
+    assert_eq!(
+        read_u8(&mut bus, DEFAULT_ADDRESS, RESULT__RANGE_VAL),
+        Ok(255)
+    );
+    let status = read_u8(&mut bus, DEFAULT_ADDRESS, RESULT__RANGE_STATUS).unwrap();
+    assert_eq!(range_error_code(status), 15);
+}
@@ -0,0 +1,160 @@
+//! Gesture recognition from synthetic timelines and from the readings in
+//! `gestures/`. Run with `cargo test-sim`.
+
+use vl6180x_stm32f401_examples::gesture::*;
+
+/// How often each sensor ranges in the synthetic timelines.
+const PERIOD_MS: u32 = 30;
+
+const SESSION: &str = include_str!("gestures/session.txt");
+
+/// Readings of both sensors for `duration_ms` from `start_ms`, the right one
+/// half a period behind the left, near at `mm` while `covered` says so.
+fn timeline(
+    start_ms: u32,
+    duration_ms: u32,
+    mm: u16,
+    covered: impl Fn(Side, u32) -> bool,
+) -> Vec<(Side, Option<u16>, u32)> {
+    let mut readings = Vec::new();
+    for n in 0..duration_ms / PERIOD_MS {
+        for (side, offset) in [(Side::Left, 0), (Side::Right, PERIOD_MS / 2)] {
+            let at = start_ms.wrapping_add(n * PERIOD_MS + offset);
+            readings.push((side, covered(side, at).then_some(mm), at));
+        }
+    }
+    readings
+}
+
+/// Covered from `from` to `to`, relative to `origin`.
+fn between(origin: u32, at: u32, (from, to): (u32, u32)) -> bool {
+    let offset = at.wrapping_sub(origin);
+    offset >= from && offset < to
+}
+
+fn recognise(
+    recogniser: &mut GestureRecogniser,
+    readings: &[(Side, Option<u16>, u32)],
+) -> Vec<Gesture> {
+    readings
+        .iter()
+        .filter_map(|&(side, mm, at)| recogniser.update(side, mm, at))
+        .collect()
+}
+
+fn sweep(start_ms: u32, left: (u32, u32), right: (u32, u32)) -> Vec<Gesture> {
+    let readings = timeline(start_ms, 2000, 60, |side, at| {
+        let span = match side {
+            Side::Left => left,
+            Side::Right => right,
+        };
+        between(start_ms, at, span)
+    });
+    recognise(
+        &mut GestureRecogniser::new(GestureConfig::default()),
+        &readings,
+    )
+}
+
+#[test]
+fn swipes_in_both_directions() {
+    assert_eq!(
+        sweep(0, (100, 250), (200, 350)),
+        [Gesture::SwipeLeftToRight]
+    );
+    assert_eq!(
+        sweep(0, (200, 350), (100, 250)),
+        [Gesture::SwipeRightToLeft]
+    );
+}
+
+#[test]
+fn taps_over_one_or_both_sensors() {
+    assert_eq!(sweep(0, (100, 300), (110, 290)), [Gesture::Tap]);
+    assert_eq!(sweep(0, (100, 300), (2000, 2000)), [Gesture::Tap]);
+}
+
+#[test]
+fn hovers_once_per_stroke() {
+    let mut recogniser = GestureRecogniser::new(GestureConfig::default());
+    let readings = timeline(0, 3000, 80, |_, at| between(0, at, (100, 2500)));
+    assert_eq!(
+        recognise(&mut recogniser, &readings),
+        [Gesture::Hover { range_mm: 80 }]
+    );
+}
+
+#[test]
+fn hover_reports_the_closer_side() {
+    let mut recogniser = GestureRecogniser::new(GestureConfig::default());
+    let mut gestures = Vec::new();
+    for n in 0..40 {
+        let at = n * PERIOD_MS;
+        gestures.extend(recogniser.update(Side::Left, Some(90), at));
+        gestures.extend(recogniser.update(Side::Right, Some(70), at + PERIOD_MS / 2));
+    }
+    assert_eq!(gestures, [Gesture::Hover { range_mm: 70 }]);
+}
+
+#[test]
+fn ignores_noise_and_strokes_too_slow_to_be_anything() {
+    // One near reading
+    assert_eq!(sweep(0, (100, 110), (2000, 2000)), []);
+    // A swipe taking 750 ms, over the limit but not yet a hover
+    assert_eq!(sweep(0, (100, 500), (440, 850)), []);
+    // Far readings never start a stroke
+    let mut recogniser = GestureRecogniser::new(GestureConfig::default());
+    let readings = timeline(0, 2000, 180, |_, _| true);
+    assert_eq!(recognise(&mut recogniser, &readings), []);
+}
+
+#[test]
+fn survives_the_clock_wrapping() {
+    let start = u32::MAX - 220;
+    assert_eq!(
+        sweep(start, (100, 250), (200, 350)),
+        [Gesture::SwipeLeftToRight]
+    );
+    assert_eq!(sweep(start, (100, 300), (110, 290)), [Gesture::Tap]);
+}
+
+#[test]
+fn recognises_the_session() {
+    let mut recogniser = GestureRecogniser::new(GestureConfig::default());
+    let mut expected = Vec::new();
+    let mut recognised: Vec<Vec<Gesture>> = Vec::new();
+    for line in SESSION.lines() {
+        if let Some(name) = line.strip_prefix("# expect: ") {
+            expected.push(name);
+            recognised.push(Vec::new());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let at: u32 = words.next().unwrap().parse().unwrap();
+        let side = match words.next().unwrap() {
+            "left" => Side::Left,
+            "right" => Side::Right,
+            other => panic!("side {other}"),
+        };
+        let mm = words.next().unwrap().parse().ok();
+        if let Some(gesture) = recogniser.update(side, mm, at) {
+            recognised.last_mut().unwrap().push(gesture);
+        }
+    }
+
+    assert_eq!(recognised.len(), expected.len());
+    for (name, gestures) in expected.iter().zip(&recognised) {
+        let matches = match (*name, gestures.as_slice()) {
+            ("none", []) => true,
+            ("tap", [Gesture::Tap]) => true,
+            ("swipe-left-to-right", [Gesture::SwipeLeftToRight]) => true,
+            ("swipe-right-to-left", [Gesture::SwipeRightToLeft]) => true,
+            ("hover", [Gesture::Hover { range_mm }]) => (60..=80).contains(range_mm),
+            _ => false,
+        };
+        assert!(matches, "expected {name}, got {gestures:?}");
+    }
+}
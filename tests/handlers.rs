@@ -0,0 +1,244 @@
+//! The interrupt handlers against a simulated sensor, its GPIO1 line and the
+//! LED. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x::{AmbientInterruptMode, RangeInterruptMode};
+use vl6180x_stm32f401_examples::handlers::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{Edge, SimBus, SimInterruptPin, SimLed, SimVl6180x};
+
+type Sensor<'a> = vl6180x::VL6180X<vl6180x::ReadyMode, SimBus<'a>>;
+
+fn sensor<'a>(devices: &'a [RefCell<SimVl6180x>], config: &vl6180x::Config) -> Sensor<'a> {
+    vl6180x::VL6180X::with_config(SimBus::new(devices), config).expect("init")
+}
+
+/// Starts continuous measurements with `start` one of the start registers.
+fn start(devices: &[RefCell<SimVl6180x>], start: u16) {
+    write_u8(
+        &mut SimBus::new(devices),
+        DEFAULT_ADDRESS,
+        start,
+        START_STOP | MODE_CONTINUOUS,
+    )
+    .unwrap();
+}
+
+/// A [`SimLed`] that notes the sensor's interrupt status whenever it is
+/// switched, which shows it goes on and off around the read and before the
+/// sensor interrupt is cleared.
+struct WatchedLed<'a> {
+    led: SimLed,
+    device: &'a RefCell<SimVl6180x>,
+    seen: Vec<(bool, u8)>,
+}
+
+impl<'a> WatchedLed<'a> {
+    fn new(device: &'a RefCell<SimVl6180x>) -> Self {
+        Self {
+            led: SimLed::new(),
+            device,
+            seen: Vec::new(),
+        }
+    }
+}
+
+impl StatusLed for WatchedLed<'_> {
+    fn on(&mut self) {
+        self.led.on();
+        self.seen
+            .push((true, self.device.borrow().interrupt_status()));
+    }
+
+    fn off(&mut self) {
+        self.led.off();
+        self.seen
+            .push((false, self.device.borrow().interrupt_status()));
+    }
+}
+
+#[test]
+fn range_level_high() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_range_interrupt_mode(RangeInterruptMode::LevelHigh);
+    config.set_range_high_interrupt_threshold(100);
+    let mut tof = sensor(&devices, &config);
+    let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+    let mut led = WatchedLed::new(&devices[0]);
+    start(&devices, SYSRANGE__START);
+
+    devices[0].borrow_mut().set_range_mm(80);
+    assert!(!pin.tick());
+    devices[0].borrow_mut().set_range_mm(150);
+    assert!(pin.tick());
+
+    let handled = range_interrupt(&mut tof, &mut pin, &mut led, Clear::Range);
+    assert_eq!(handled.reading.ok(), Some(150));
+    assert!(handled.cleared.is_ok());
+    assert_eq!(led.seen, [(true, INT_LEVEL_HIGH), (false, INT_LEVEL_HIGH)]);
+    assert!(!led.led.is_on());
+    assert!(!pin.check_interrupt());
+    assert_eq!(devices[0].borrow().interrupt_status(), 0);
+
+    // Cleared, the line drops and the next sample above raises it again
+    assert!(!pin.sample());
+    assert!(!pin.is_high());
+    assert!(pin.tick());
+    assert_eq!(pin.interrupts(), 2);
+}
+
+#[test]
+fn range_level_low_left_set() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_range_interrupt_mode(RangeInterruptMode::LevelLow);
+    config.set_range_low_interrupt_threshold(30);
+    let mut tof = sensor(&devices, &config);
+    let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+    let mut led = SimLed::new();
+    devices[0].borrow_mut().set_range_mm(50);
+    start(&devices, SYSRANGE__START);
+    assert!(!pin.sample());
+
+    devices[0].borrow_mut().set_range_mm(20);
+    assert!(pin.tick());
+    let handled = range_interrupt(&mut tof, &mut pin, &mut led, Clear::None);
+    assert_eq!(handled.reading.ok(), Some(20));
+    assert!(!pin.check_interrupt());
+    assert_eq!(led.flashes(), 1);
+
+    // Without clearing, GPIO1 stays asserted and no further edge comes
+    assert_eq!(devices[0].borrow().interrupt_status(), INT_LEVEL_LOW);
+    assert!(!pin.tick());
+    assert_eq!(pin.interrupts(), 1);
+}
+
+#[test]
+fn range_out_of_window() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_range_interrupt_mode(RangeInterruptMode::OutOfWindow);
+    config.set_range_low_interrupt_threshold(30);
+    config.set_range_high_interrupt_threshold(100);
+    let mut tof = sensor(&devices, &config);
+    let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+    let mut led = SimLed::new();
+    devices[0].borrow_mut().set_range_mm(50);
+    start(&devices, SYSRANGE__START);
+
+    let mut readings = Vec::new();
+    for mm in [50, 20, 60, 120, 90] {
+        devices[0].borrow_mut().set_range_mm(mm);
+        if pin.tick() {
+            let handled = range_interrupt(&mut tof, &mut pin, &mut led, Clear::All);
+            assert!(handled.cleared.is_ok());
+            readings.push(handled.reading.ok());
+            assert!(!pin.sample());
+        }
+    }
+    assert_eq!(readings, [Some(20), Some(120)]);
+    assert_eq!(led.flashes(), 2);
+    assert!(!led.is_on());
+}
+
+#[test]
+fn ambient_raw_level_high() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_ambient_interrupt_mode(AmbientInterruptMode::LevelHigh);
+    config.set_ambient_high_interrupt_threshold(70);
+    let mut tof = sensor(&devices, &config);
+    let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+    let mut led = WatchedLed::new(&devices[0]);
+    start(&devices, SYSALS__START);
+
+    devices[0].borrow_mut().set_ambient_raw(40);
+    assert!(!pin.tick());
+    devices[0].borrow_mut().set_ambient_raw(90);
+    assert!(pin.tick());
+
+    let handled = ambient_raw_interrupt(&mut tof, &mut pin, &mut led, Clear::Ambient);
+    assert_eq!(handled.reading.ok(), Some(90));
+    assert!(handled.cleared.is_ok());
+    let status = INT_LEVEL_HIGH << 3;
+    assert_eq!(led.seen, [(true, status), (false, status)]);
+    assert!(!pin.check_interrupt());
+    assert_eq!(devices[0].borrow().interrupt_status(), 0);
+}
+
+/// With a range and an ambient interrupt both set, each mode clears only
+/// its own.
+#[test]
+fn each_clear_mode_clears_its_interrupt() {
+    let both = INT_NEW_SAMPLE_READY | (INT_NEW_SAMPLE_READY << 3);
+    let cases = [
+        (Clear::None, both),
+        (Clear::Range, INT_NEW_SAMPLE_READY << 3),
+        (Clear::Ambient, INT_NEW_SAMPLE_READY),
+        (Clear::All, 0),
+    ];
+    for (mode, left) in cases {
+        let devices = [RefCell::new(SimVl6180x::new())];
+        let mut config = vl6180x::Config::new();
+        config.set_range_interrupt_mode(RangeInterruptMode::NewSampleReady);
+        config.set_ambient_interrupt_mode(AmbientInterruptMode::NewSampleReady);
+        let mut tof = sensor(&devices, &config);
+        let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+        start(&devices, SYSRANGE__START);
+        start(&devices, SYSALS__START);
+        pin.tick();
+        assert_eq!(devices[0].borrow().interrupt_status(), both);
+
+        let handled = range_interrupt(&mut tof, &mut pin, &mut SimLed::new(), mode);
+        assert!(handled.cleared.is_ok());
+        assert_eq!(devices[0].borrow().interrupt_status(), left, "{:?}", mode);
+        assert!(!pin.check_interrupt());
+    }
+}
+
+#[test]
+fn interleaved_reads_what_the_status_reports() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut config = vl6180x::Config::new();
+    config.set_range_interrupt_mode(RangeInterruptMode::NewSampleReady);
+    config.set_ambient_interrupt_mode(AmbientInterruptMode::LevelHigh);
+    config.set_ambient_high_interrupt_threshold(70);
+    let mut tof = sensor(&devices, &config);
+    let mut pin = SimInterruptPin::new(&devices[0], Edge::Rising);
+    let mut led = SimLed::new();
+    write_u8(
+        &mut SimBus::new(&devices),
+        DEFAULT_ADDRESS,
+        INTERLEAVED_MODE__ENABLE,
+        0x01,
+    )
+    .unwrap();
+    start(&devices, SYSALS__START);
+    devices[0].borrow_mut().set_range_mm(60);
+
+    // Dark: only the range reports an event
+    devices[0].borrow_mut().set_ambient_raw(10);
+    assert!(pin.tick());
+    let handled = interleaved_interrupt(&mut tof, &mut pin, &mut led, Clear::All);
+    let reading = handled.reading.ok().unwrap();
+    assert_eq!(reading.range_mm.map(Result::ok), Some(Some(60)));
+    assert!(reading.ambient_lux.is_none());
+    assert_eq!(devices[0].borrow().interrupt_status(), 0);
+    assert!(!pin.sample());
+
+    // Bright: both do
+    devices[0].borrow_mut().set_ambient_raw(100);
+    assert!(pin.tick());
+    let handled = interleaved_interrupt(&mut tof, &mut pin, &mut led, Clear::All);
+    let reading = handled.reading.ok().unwrap();
+    assert_eq!(reading.range_mm.map(Result::ok), Some(Some(60)));
+    assert!(matches!(reading.ambient_lux, Some(Ok(_))));
+    assert!(handled.cleared.is_ok());
+    assert!(!pin.check_interrupt());
+    assert_eq!(devices[0].borrow().interrupt_status(), 0);
+    assert_eq!(led.flashes(), 2);
+}
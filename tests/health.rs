@@ -0,0 +1,226 @@
+//! The health supervisor against a simulated sensor that wedges. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::health::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimClock, SimError, SimVl6180x, SimXshutPin};
+use vl6180x_stm32f401_examples::timeout::{ReadError, TimedReader};
+
+const ADDRESS: u8 = 0x30;
+
+const CONFIG: HealthConfig = HealthConfig {
+    stale_ms: 1_000,
+    max_i2c_errors: 3,
+    max_range_errors: 2,
+    backoff_initial_ms: 500,
+    backoff_max_ms: 4_000,
+};
+
+/// Range high threshold the sensor is set up with, not the power-on 0xFF.
+const THRESH_HIGH: u8 = 200;
+
+type Sensor<'a> = DynamicSensor<SimBus<'a>, SimXshutPin<'a>>;
+
+type Error = vl6180x::Error<SimError>;
+
+/// The example's sensor, through the driver, on `devices` with its XSHUT
+/// wired to the first of them.
+fn sensor(devices: &[RefCell<SimVl6180x>]) -> Sensor<'_> {
+    let mut config = vl6180x::Config::new();
+    config.set_range_interrupt_mode(vl6180x::RangeInterruptMode::NewSampleReady);
+    config.set_range_high_interrupt_threshold(THRESH_HIGH);
+    let mut sensor = DynamicSensor {
+        vl6180x: vl6180x::VL6180X::with_config(SimBus::new(devices), &config)
+            .expect("init")
+            .into_dynamic_mode(),
+        x_shutdown_pin: SimXshutPin::new(&devices[0]),
+        address: ADDRESS,
+        continuous: Continuous::Range,
+    };
+    sensor.power_cycle().expect("power cycle");
+    sensor
+}
+
+/// Samples every 100 ms for `ms`, the way the example's main loop does:
+/// a timed read per sample, the outcome recorded, then supervision.
+fn run(
+    monitor: &mut HealthMonitor,
+    devices: &[RefCell<SimVl6180x>; 1],
+    sensor: &mut Sensor,
+    from_ms: u32,
+    ms: u32,
+) -> Vec<(u32, Recovery<Error>)> {
+    let clock = SimClock::new(0, 0);
+    let mut reader = TimedReader::new(SimBus::new(devices), ADDRESS, &clock);
+    reader.set_timeout_ms(0);
+    let mut recoveries = Vec::new();
+    for now_ms in (from_ms..from_ms + ms).step_by(100) {
+        devices[0].borrow_mut().tick();
+        match reader.read_range_mm() {
+            Ok(_) => monitor.record_sample(now_ms),
+            Err(ReadError::Range(code)) => monitor.record_range_error(code, now_ms),
+            Err(ReadError::I2c(_)) => monitor.record_i2c_error(),
+            Err(ReadError::Timeout) => (),
+        }
+        if let Some(recovery) = monitor.supervise(sensor, now_ms) {
+            recoveries.push((now_ms, recovery));
+        }
+    }
+    recoveries
+}
+
+#[test]
+fn a_healthy_sensor_is_left_alone() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut sensor = sensor(&devices);
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    devices[0].borrow_mut().set_range_mm(100);
+
+    assert!(run(&mut monitor, &devices, &mut sensor, 0, 5_000).is_empty());
+    assert_eq!(monitor.counters().samples, 50);
+    assert_eq!(monitor.counters().power_cycles, 0);
+}
+
+#[test]
+fn a_stalled_sensor_is_power_cycled_back() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut sensor = sensor(&devices);
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    run(&mut monitor, &devices, &mut sensor, 0, 500);
+
+    assert_eq!(
+        devices[0].borrow().register(SYSRANGE__THRESH_HIGH),
+        THRESH_HIGH
+    );
+
+    // Wedges with its settings scrambled
+    devices[0].borrow_mut().set_stalled(true);
+    devices[0]
+        .borrow_mut()
+        .set_register(SYSRANGE__THRESH_HIGH, 0x42);
+    let recoveries = run(&mut monitor, &devices, &mut sensor, 500, 1_000);
+    assert_eq!(recoveries.len(), 1);
+    let (at_ms, recovery) = &recoveries[0];
+    assert_eq!(*at_ms, 1_400);
+    assert_eq!(recovery.fault, Fault::Stale { silent_ms: 1_000 });
+    assert_eq!((recovery.attempt, recovery.backoff_ms), (1, 500));
+    devices[0].borrow_mut().set_stalled(false);
+
+    // Back on its address with its config and continuous mode, and sampling
+    let device = devices[0].borrow();
+    assert_eq!(device.address(), ADDRESS);
+    assert_eq!(device.register(SYSRANGE__THRESH_HIGH), THRESH_HIGH);
+    assert!(device.is_range_continuous());
+    drop(device);
+    assert!(run(&mut monitor, &devices, &mut sensor, 1_500, 3_000).is_empty());
+    assert_eq!(monitor.fault(4_500), None);
+    assert_eq!(monitor.counters().power_cycles, 1);
+}
+
+#[test]
+fn a_dead_sensor_is_retried_with_backoff() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut sensor = sensor(&devices);
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    // Stays wedged through every power cycle
+    devices[0].borrow_mut().set_stalled(true);
+
+    let recoveries = run(&mut monitor, &devices, &mut sensor, 0, 20_000);
+    let attempts: Vec<(u32, u32, u32)> = recoveries
+        .iter()
+        .map(|(at_ms, recovery)| (*at_ms, recovery.attempt, recovery.backoff_ms))
+        .collect();
+    assert_eq!(
+        attempts,
+        [
+            (1_000, 1, 500),
+            (2_000, 2, 1_000),
+            (3_000, 3, 2_000),
+            (5_000, 4, 4_000),
+            (9_000, 5, 4_000),
+            (13_000, 6, 4_000),
+            (17_000, 7, 4_000),
+        ]
+    );
+
+    // Once it works again the backoff starts over
+    devices[0].borrow_mut().set_stalled(false);
+    run(&mut monitor, &devices, &mut sensor, 20_000, 1_000);
+    devices[0].borrow_mut().set_stalled(true);
+    let again = run(&mut monitor, &devices, &mut sensor, 21_000, 2_000);
+    assert_eq!(again[0].1.attempt, 1);
+    assert_eq!(again[0].1.backoff_ms, 500);
+}
+
+#[test]
+fn i2c_and_system_errors_are_faults() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut sensor = sensor(&devices);
+
+    // A sensor that fell off its address fails every read
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    devices[0]
+        .borrow_mut()
+        .set_register(I2C_SLAVE__DEVICE_ADDRESS, DEFAULT_ADDRESS);
+    let recoveries = run(&mut monitor, &devices, &mut sensor, 0, 300);
+    assert_eq!(recoveries.len(), 1);
+    assert_eq!(recoveries[0].1.fault, Fault::I2cErrors(3));
+    assert!(recoveries[0].1.result.is_ok());
+    assert_eq!(devices[0].borrow().address(), ADDRESS);
+
+    // VCSEL failures count, nothing in range doesn't
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    devices[0].borrow_mut().set_range_error(6);
+    assert!(run(&mut monitor, &devices, &mut sensor, 0, 2_000).is_empty());
+    devices[0].borrow_mut().set_range_error(2);
+    let recoveries = run(&mut monitor, &devices, &mut sensor, 2_000, 200);
+    assert_eq!(
+        recoveries[0].1.fault,
+        Fault::RangeErrors { code: 2, count: 2 }
+    );
+    assert_eq!(monitor.counters().range_errors, 22);
+}
+
+#[test]
+fn failed_power_cycles_are_counted() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    sensor(&devices);
+    let mut monitor = HealthMonitor::new(CONFIG, 0);
+    // A second device turns up on the default address once the sensor is
+    // set up, so it can't be set up again
+    let bystander = [
+        RefCell::new(SimVl6180x::new()),
+        RefCell::new(SimVl6180x::new()),
+    ];
+    bystander[1].borrow_mut().set_powered(false);
+    let mut broken = sensor(&bystander);
+    bystander[1].borrow_mut().set_powered(true);
+    devices[0].borrow_mut().set_stalled(true);
+    let recoveries = run(&mut monitor, &devices, &mut broken, 0, 1_100);
+    assert!(matches!(
+        recoveries[0].1.result,
+        Err(vl6180x::Error::BusError(SimError::AddressCollision(
+            DEFAULT_ADDRESS
+        )))
+    ));
+    let counters = *monitor.counters();
+    assert_eq!(
+        (counters.power_cycles, counters.failed_power_cycles),
+        (1, 1)
+    );
+    assert_eq!(
+        counters.to_string(),
+        "samples 1, i2c errors 0, range errors 0, faults 1, power cycles 1 (1 failed)"
+    );
+}
+
+#[test]
+fn staleness_survives_the_clock_wrapping() {
+    let monitor = HealthMonitor::new(CONFIG, u32::MAX - 400);
+    assert_eq!(monitor.fault(500), None);
+    assert_eq!(monitor.fault(599), Some(Fault::Stale { silent_ms: 1_000 }));
+}
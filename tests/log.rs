@@ -0,0 +1,61 @@
+//! Line formatting and the in-memory `log-capture` backend. Run with
+//! `cargo test-sim`.
+
+#![cfg(all(feature = "sim", feature = "log-capture"))]
+
+use vl6180x_stm32f401_examples::log::{Backend, Capture, Channel, LINE_CAPACITY};
+use vl6180x_stm32f401_examples::{log, log_to};
+
+#[test]
+fn formats_one_line() {
+    let line = log::format_line(format_args!("Range {}mm from tof_{}", 42, 1));
+    assert_eq!(line.as_str(), "Range 42mm from tof_1\n");
+    assert_eq!(log::format_line(format_args!("")).as_str(), "\n");
+}
+
+#[test]
+fn truncates_at_line_capacity() {
+    let long = "x".repeat(2 * LINE_CAPACITY);
+    let line = log::format_line(format_args!("{}", long));
+    assert_eq!(line.len(), LINE_CAPACITY);
+    assert_eq!(line[..LINE_CAPACITY - 1], long[..LINE_CAPACITY - 1]);
+    assert!(line.ends_with('\n'));
+
+    // Only whole characters are kept
+    let wide = "é".repeat(LINE_CAPACITY);
+    let line = log::format_line(format_args!("{}", wide));
+    assert_eq!(line.len(), LINE_CAPACITY - 1);
+    assert!(line.ends_with("é\n"));
+}
+
+#[test]
+fn drops_lines_once_a_capture_is_full() {
+    let mut capture = Capture::new();
+    let line = log::format_line(format_args!("{}", "y".repeat(99)));
+    for _ in 0..log::CAPTURE_CAPACITY / line.len() + 1 {
+        capture.write_line(Channel::Data, &line);
+    }
+    let data = capture.take(Channel::Data);
+    assert_eq!(data.len(), log::CAPTURE_CAPACITY / 100 * 100);
+    assert!(data.lines().all(|captured| captured.len() == 99));
+    assert_eq!(capture.take(Channel::Log).as_str(), "");
+}
+
+/// The only test logging through the global backend, since the tests of a
+/// file run in parallel.
+#[test]
+fn channels_are_captured_apart_until_taken() {
+    assert_eq!(log::backend_name(), "capture");
+
+    log!("Range {}mm", 42);
+    log_to!(Channel::Data, "42");
+    log!("Range {}mm", 43);
+    assert_eq!(log::take(Channel::Log).as_str(), "Range 42mm\nRange 43mm\n");
+    assert_eq!(log::take(Channel::Data).as_str(), "42\n");
+
+    // Taking drains the buffer
+    assert_eq!(log::take(Channel::Log).as_str(), "");
+    log!("again");
+    assert_eq!(log::take(Channel::Log).as_str(), "again\n");
+    assert_eq!(log::take(Channel::Data).as_str(), "");
+}
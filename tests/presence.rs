@@ -0,0 +1,168 @@
+//! The presence detector against scripted distance sequences. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::presence::*;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimVl6180x};
+
+const CONFIG: PresenceConfig = PresenceConfig {
+    enter_mm: 50,
+    exit_mm: 80,
+    enter_dwell_ms: 300,
+    exit_dwell_ms: 500,
+};
+
+/// One reading every 100 ms.
+const PERIOD_MS: u32 = 100;
+
+/// Feeds `readings` 100 ms apart and collects every event.
+fn script(detector: &mut PresenceDetector, readings: &[Option<u16>]) -> Vec<(PresenceEvent, u32)> {
+    let mut events = Vec::new();
+    for (n, &reading) in readings.iter().enumerate() {
+        detector.update(reading, n as u32 * PERIOD_MS, |event, at_ms| {
+            events.push((event, at_ms))
+        });
+    }
+    events
+}
+
+fn mm(readings: &[u16]) -> Vec<Option<u16>> {
+    readings.iter().copied().map(Some).collect()
+}
+
+#[test]
+fn arrives_and_leaves_after_the_dwell_times() {
+    let mut detector = PresenceDetector::new(CONFIG);
+    let readings = mm(&[200, 40, 40, 40, 40, 40, 120, 120, 120, 120, 120, 120]);
+    assert_eq!(
+        script(&mut detector, &readings),
+        [
+            (PresenceEvent::Approached, 100),
+            (PresenceEvent::Arrived, 400),
+            (PresenceEvent::Withdrew, 600),
+            (PresenceEvent::Left, 1100),
+        ]
+    );
+    assert_eq!(detector.state(), PresenceState::Absent);
+}
+
+#[test]
+fn hovering_at_a_threshold_does_not_flicker() {
+    let mut detector = PresenceDetector::new(CONFIG);
+    // Jitter across the enter threshold, then drift out to the exit one
+    let readings = mm(&[49, 52, 48, 55, 47, 51, 60, 72, 79, 75, 80, 66]);
+    let events = script(&mut detector, &readings);
+    assert_eq!(
+        events,
+        [
+            (PresenceEvent::Approached, 0),
+            (PresenceEvent::Arrived, 300)
+        ]
+    );
+    assert!(detector.is_present());
+    assert_eq!(detector.state(), PresenceState::Present);
+}
+
+#[test]
+fn short_visits_and_absences_are_ignored() {
+    let mut detector = PresenceDetector::new(CONFIG);
+    // Passes by for 200 ms, then stays
+    let readings = mm(&[40, 40, 200, 40, 40, 40, 40]);
+    assert_eq!(
+        script(&mut detector, &readings),
+        [
+            (PresenceEvent::Approached, 0),
+            (PresenceEvent::Passed, 200),
+            (PresenceEvent::Approached, 300),
+            (PresenceEvent::Arrived, 600),
+        ]
+    );
+
+    // Gone for 300 ms, then back
+    let mut events = Vec::new();
+    for (n, reading) in [None, None, None, Some(30), Some(30)]
+        .into_iter()
+        .enumerate()
+    {
+        let now_ms = 700 + n as u32 * PERIOD_MS;
+        detector.update(reading, now_ms, |event, _| events.push(event));
+    }
+    assert_eq!(events, [PresenceEvent::Withdrew, PresenceEvent::Returned]);
+    assert_eq!(detector.state(), PresenceState::Present);
+}
+
+#[test]
+fn zero_dwell_changes_at_once() {
+    let mut detector = PresenceDetector::new(PresenceConfig {
+        enter_dwell_ms: 0,
+        exit_dwell_ms: 0,
+        ..CONFIG
+    });
+    let events = script(&mut detector, &[Some(10), None]);
+    assert_eq!(
+        events,
+        [
+            (PresenceEvent::Approached, 0),
+            (PresenceEvent::Arrived, 0),
+            (PresenceEvent::Withdrew, 100),
+            (PresenceEvent::Left, 100),
+        ]
+    );
+}
+
+#[test]
+fn dwell_survives_the_clock_wrapping() {
+    let mut detector = PresenceDetector::new(CONFIG);
+    let start = u32::MAX - 150;
+    let mut events = Vec::new();
+    for n in 0..4 {
+        let now_ms = start.wrapping_add(n * PERIOD_MS);
+        detector.update(Some(20), now_ms, |event, _| events.push(event));
+    }
+    assert_eq!(events, [PresenceEvent::Approached, PresenceEvent::Arrived]);
+}
+
+#[test]
+#[should_panic(expected = "presence thresholds")]
+fn rejects_exit_below_enter() {
+    PresenceDetector::new(PresenceConfig {
+        exit_mm: 40,
+        ..CONFIG
+    });
+}
+
+#[test]
+fn follows_a_scripted_sensor() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut i2c = SimBus::new(&devices);
+    let mut detector = PresenceDetector::new(CONFIG);
+    let mut sensor = devices[0].borrow_mut();
+    sensor.queue_range_mm(&[150, 45, 45, 52, 45, 45, 90, 90, 90, 90, 90, 90]);
+    sensor.set_range_mm(90);
+    drop(sensor);
+
+    write_u8(
+        &mut i2c,
+        DEFAULT_ADDRESS,
+        SYSRANGE__START,
+        START_STOP | MODE_CONTINUOUS,
+    )
+    .unwrap();
+    let mut present = Vec::new();
+    for n in 0..12 {
+        if n > 0 {
+            devices[0].borrow_mut().tick();
+        }
+        let raw = read_u8(&mut i2c, DEFAULT_ADDRESS, RESULT__RANGE_VAL).unwrap();
+        detector.update(Some(raw as u16), n * PERIOD_MS, |_, _| ());
+        present.push(detector.is_present());
+    }
+    let arrived = present.iter().position(|&p| p);
+    let left = present.iter().rposition(|&p| p).map(|n| n + 1);
+    assert_eq!(arrived, Some(4));
+    assert_eq!(left, Some(11));
+}
@@ -0,0 +1,177 @@
+//! Bringing several simulated sensors up on one bus. Run with
+//! `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::OutputPin;
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sensor_array::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimDelay, SimVl6180x, SimXshutPin};
+
+/// XSHUT of one sensor that checks, as the sensor is woken, that the bus is
+/// ready for it: the sensors woken before it have moved off the default
+/// address and the ones after it are still powered down.
+struct CheckedXshut<'a> {
+    pin: SimXshutPin<'a>,
+    bus: SimBus<'a>,
+    index: usize,
+    woken: &'a RefCell<Vec<usize>>,
+}
+
+impl OutputPin for CheckedXshut<'_> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.pin.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        assert_eq!(self.bus.responders(DEFAULT_ADDRESS), 0);
+        let devices = self.bus.devices();
+        assert!(devices[self.index..]
+            .iter()
+            .all(|device| !device.borrow().is_powered()));
+        self.woken.borrow_mut().push(self.index);
+        self.pin.set_high()
+    }
+}
+
+fn specs<'a>(
+    devices: &'a [RefCell<SimVl6180x>],
+    addresses: &[u8],
+    woken: &'a RefCell<Vec<usize>>,
+) -> Vec<SensorSpec<CheckedXshut<'a>, ()>> {
+    addresses
+        .iter()
+        .enumerate()
+        .map(|(index, &address)| SensorSpec {
+            x_shutdown_pin: CheckedXshut {
+                pin: SimXshutPin::new(&devices[index]),
+                bus: SimBus::new(devices),
+                index,
+                woken,
+            },
+            interrupt_pin: (),
+            config: vl6180x::Config::new(),
+            address,
+        })
+        .collect()
+}
+
+#[test]
+fn wakes_and_moves_the_sensors_one_by_one() {
+    let devices: [RefCell<SimVl6180x>; 3] = Default::default();
+    let woken = RefCell::new(Vec::new());
+    let bus = SimBus::new(&devices);
+    let mut delay = SimDelay::new();
+
+    let array = SensorArray::<_, _, _, 3>::new(
+        specs(&devices, &[0x30, 0x31, 0x32], &woken),
+        || bus,
+        &mut delay,
+    )
+    .expect("array");
+
+    assert_eq!(*woken.borrow(), [0, 1, 2]);
+    assert_eq!(array.sensors().len(), 3);
+    for (device, address) in devices.iter().zip([0x30, 0x31, 0x32]) {
+        assert_eq!(device.borrow().address(), address);
+        assert_eq!(bus.responders(address), 1);
+    }
+    assert_eq!(bus.responders(DEFAULT_ADDRESS), 0);
+    // Each sensor had its boot time
+    assert_eq!(delay.elapsed_ms(), 3 * 2);
+}
+
+#[test]
+fn rejects_bad_addresses_before_touching_the_sensors() {
+    let cases: [&[u8]; 5] = [
+        &[0x30, 0x07],
+        &[0x78],
+        &[0x80],
+        &[0x30, DEFAULT_ADDRESS],
+        &[0x30, 0x31, 0x30],
+    ];
+    for addresses in cases {
+        let devices: [RefCell<SimVl6180x>; 3] = Default::default();
+        let woken = RefCell::new(Vec::new());
+        let result = SensorArray::<_, _, _, 3>::new(
+            specs(&devices, addresses, &woken),
+            || SimBus::new(&devices),
+            &mut SimDelay::new(),
+        );
+        let bad = *addresses.last().unwrap();
+        assert!(
+            matches!(result, Err(SensorArrayError::InvalidAddress(address)) if address == bad),
+            "{:?}",
+            addresses
+        );
+        assert!(devices.iter().all(|device| device.borrow().is_powered()));
+        assert!(woken.borrow().is_empty());
+    }
+}
+
+#[test]
+fn rejects_more_sensors_than_it_holds() {
+    let devices: [RefCell<SimVl6180x>; 3] = Default::default();
+    let woken = RefCell::new(Vec::new());
+    let result = SensorArray::<_, _, _, 2>::new(
+        specs(&devices, &[0x30, 0x31, 0x32], &woken),
+        || SimBus::new(&devices),
+        &mut SimDelay::new(),
+    );
+    assert!(matches!(result, Err(SensorArrayError::TooManySensors)));
+}
+
+/// Another device already on the address the second sensor is moved to:
+/// the two collide, so the sensor can't be found there.
+#[test]
+fn a_sensor_missing_at_its_new_address() {
+    let devices: [RefCell<SimVl6180x>; 3] = Default::default();
+    devices[2]
+        .borrow_mut()
+        .set_register(I2C_SLAVE__DEVICE_ADDRESS, 0x31);
+    let woken = RefCell::new(Vec::new());
+    let result = SensorArray::<_, _, _, 2>::new(
+        specs(&devices[..2], &[0x30, 0x31], &woken),
+        || SimBus::new(&devices),
+        &mut SimDelay::new(),
+    );
+    assert!(matches!(
+        result,
+        Err(SensorArrayError::NoResponse {
+            index: 1,
+            address: 0x31
+        })
+    ));
+    assert_eq!(devices[0].borrow().address(), 0x30);
+}
+
+/// A sensor whose XSHUT isn't wired stays on at the default address.
+#[test]
+fn a_device_left_on_the_default_address() {
+    let devices: [RefCell<SimVl6180x>; 2] = Default::default();
+    let woken = RefCell::new(Vec::new());
+
+    // Found by the final check when there is nothing to bring up
+    let result = SensorArray::<_, _, _, 1>::new(
+        specs(&devices[..0], &[], &woken),
+        || SimBus::new(&devices[1..]),
+        &mut SimDelay::new(),
+    );
+    assert!(matches!(result, Err(SensorArrayError::DefaultAddressInUse)));
+
+    // Otherwise it collides with the first sensor woken
+    let result = SensorArray::<_, _, _, 1>::new(
+        specs(&devices[..1], &[0x30], &woken),
+        || SimBus::new(&devices),
+        &mut SimDelay::new(),
+    );
+    assert!(matches!(
+        result,
+        Err(SensorArrayError::Sensor { index: 0, .. })
+    ));
+}
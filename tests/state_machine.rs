@@ -0,0 +1,229 @@
+//! Transition and action ordering of the state machine and the dynamic mode
+//! suite table. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use vl6180x_stm32f401_examples::button::ButtonEvent;
+use vl6180x_stm32f401_examples::state_machine::*;
+use vl6180x_stm32f401_examples::suite::{
+    SuiteAction, DYNAMIC_SUITE, DYNAMIC_SUITE_START, END_TEXT, WELCOME_TEXT,
+};
+
+/// Writes down every action, failing the ones it is told to.
+struct Recorder<A> {
+    ran: Vec<A>,
+    fail_on: Option<A>,
+}
+
+impl<A> Default for Recorder<A> {
+    fn default() -> Self {
+        Self {
+            ran: Vec::new(),
+            fail_on: None,
+        }
+    }
+}
+
+impl<A: Copy + PartialEq> Actions<A> for Recorder<A> {
+    type Error = A;
+
+    fn run(&mut self, action: &A) -> Result<(), A> {
+        if self.fail_on == Some(*action) {
+            return Err(*action);
+        }
+        self.ran.push(*action);
+        Ok(())
+    }
+}
+
+impl<A> Recorder<A> {
+    fn take(&mut self) -> Vec<A> {
+        core::mem::take(&mut self.ran)
+    }
+}
+
+const TABLE: [State<&str>; 3] = [
+    State::new("a")
+        .on_enter(&["enter a", "enter a 2"])
+        .on_tick(&["tick a"])
+        .on_exit(&["exit a", "exit a 2"]),
+    State::new("b").on_enter(&["enter b"]).on_exit(&["exit b"]),
+    State::new("c").on_tick(&["tick c"]),
+];
+
+#[test]
+fn exit_actions_run_before_enter_actions() {
+    let mut machine = StateMachine::new(&TABLE);
+    let mut recorder = Recorder::default();
+    machine.start(&mut recorder).unwrap();
+    machine.tick(&mut recorder).unwrap();
+    machine.tick(&mut recorder).unwrap();
+    assert_eq!(
+        recorder.take(),
+        ["enter a", "enter a 2", "tick a", "tick a"]
+    );
+
+    assert_eq!(machine.handle(Transition::Next, &mut recorder), Ok(true));
+    assert_eq!(machine.current().name, "b");
+    // A state without tick actions does nothing while in it
+    machine.tick(&mut recorder).unwrap();
+    assert_eq!(recorder.take(), ["exit a", "exit a 2", "enter b"]);
+}
+
+#[test]
+fn next_and_previous_wrap_around() {
+    let mut machine = StateMachine::new(&TABLE);
+    let mut recorder = Recorder::default();
+    let mut visit = |machine: &mut StateMachine<&str>, transition| {
+        machine.handle(transition, &mut recorder).unwrap();
+        machine.current().name
+    };
+    assert_eq!(visit(&mut machine, Transition::Previous), "c");
+    assert_eq!(visit(&mut machine, Transition::Next), "a");
+    assert_eq!(visit(&mut machine, Transition::To(2)), "c");
+    assert_eq!(visit(&mut machine, Transition::Next), "a");
+    assert_eq!(visit(&mut machine, Transition::To(1)), "b");
+    assert_eq!(visit(&mut machine, Transition::First), "a");
+}
+
+#[test]
+fn starts_at_any_state() {
+    let mut machine = StateMachine::new(&TABLE);
+    let mut recorder = Recorder::default();
+    machine.start_at(1, &mut recorder).unwrap();
+    assert_eq!(machine.current().name, "b");
+    assert_eq!(recorder.take(), ["enter b"]);
+    machine.handle(Transition::Previous, &mut recorder).unwrap();
+    assert_eq!(machine.current().name, "a");
+}
+
+#[test]
+#[should_panic]
+fn cannot_start_past_the_table() {
+    let _ = StateMachine::new(&TABLE).start_at(3, &mut Recorder::default());
+}
+
+#[test]
+fn going_nowhere_or_to_the_same_state() {
+    let mut machine = StateMachine::new(&TABLE);
+    let mut recorder = Recorder::default();
+    assert_eq!(machine.handle(Transition::To(3), &mut recorder), Ok(false));
+    assert!(recorder.take().is_empty());
+
+    // Restarting the first state leaves and enters it again
+    assert_eq!(machine.handle(Transition::First, &mut recorder), Ok(true));
+    assert_eq!(
+        recorder.take(),
+        ["exit a", "exit a 2", "enter a", "enter a 2"]
+    );
+}
+
+#[test]
+fn failed_actions_stop_the_rest() {
+    let mut machine = StateMachine::new(&TABLE);
+    let mut recorder = Recorder {
+        fail_on: Some("exit a 2"),
+        ..Recorder::default()
+    };
+    // Couldn't leave, so it is still in a
+    assert_eq!(
+        machine.handle(Transition::Next, &mut recorder),
+        Err("exit a 2")
+    );
+    assert_eq!(machine.index(), 0);
+    assert_eq!(recorder.take(), ["exit a"]);
+
+    // Couldn't finish entering, but it has left b all the same
+    recorder.fail_on = Some("enter a");
+    machine
+        .handle(Transition::To(1), &mut Recorder::default())
+        .unwrap();
+    assert_eq!(machine.handle(Transition::Next, &mut recorder), Ok(true));
+    assert_eq!(
+        machine.handle(Transition::Next, &mut recorder),
+        Err("enter a")
+    );
+    assert_eq!(machine.index(), 0);
+    assert_eq!(recorder.take(), ["exit b"]);
+}
+
+#[test]
+fn button_and_console_inputs() {
+    assert_eq!(
+        Transition::from_button(ButtonEvent::ShortPress),
+        Transition::Next
+    );
+    assert_eq!(
+        Transition::from_button(ButtonEvent::DoublePress),
+        Transition::Previous
+    );
+    assert_eq!(
+        Transition::from_button(ButtonEvent::LongPress),
+        Transition::First
+    );
+    let keys: Vec<_> = b"n pr4x"
+        .iter()
+        .map(|&key| Transition::from_key(key))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            Some(Transition::Next),
+            Some(Transition::Next),
+            Some(Transition::Previous),
+            Some(Transition::First),
+            Some(Transition::To(4)),
+            None,
+        ]
+    );
+}
+
+#[test]
+fn the_dynamic_suite_leaves_nothing_running() {
+    use SuiteAction::*;
+
+    // Like the example before it was a table, it starts out polling single
+    // ranges and presses cycle on from there
+    let mut machine = StateMachine::new(&DYNAMIC_SUITE);
+    let mut recorder = Recorder::default();
+    machine
+        .start_at(DYNAMIC_SUITE_START, &mut recorder)
+        .unwrap();
+    assert_eq!(machine.current().name, "Range Single Poll");
+    machine.tick(&mut recorder).unwrap();
+    let mut ran = recorder.take();
+    for _ in 0..DYNAMIC_SUITE.len() {
+        machine.handle(Transition::Next, &mut recorder).unwrap();
+        machine.tick(&mut recorder).unwrap();
+        ran.extend(recorder.take());
+    }
+    assert_eq!(
+        ran,
+        [
+            PollRange,
+            StartAmbientContinuous,
+            ReadAmbient,
+            DelayMs(500),
+            StopAmbientContinuous,
+            PollAmbient,
+            Print(END_TEXT),
+            Print(WELCOME_TEXT),
+            StartRangeContinuous,
+            ReadRange,
+            StopRangeContinuous,
+            PollRange,
+        ]
+    );
+
+    // Jumping out of a continuous state still stops it first
+    machine.handle(Transition::To(3), &mut recorder).unwrap();
+    machine.handle(Transition::First, &mut recorder).unwrap();
+    assert_eq!(
+        recorder.take(),
+        [
+            StartAmbientContinuous,
+            StopAmbientContinuous,
+            Print(WELCOME_TEXT)
+        ]
+    );
+}
@@ -0,0 +1,60 @@
+//! Filing failed reads under their range error code and logging the
+//! reports, against a simulated sensor and the `log-capture` backend. Run
+//! with `cargo test-sim`.
+
+#![cfg(all(feature = "sim", feature = "log-capture"))]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::log::{self, Channel};
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::sim::{SimBus, SimVl6180x};
+use vl6180x_stm32f401_examples::stats::*;
+
+#[test]
+fn failed_reads_are_filed_under_their_code() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut bus = SimBus::new(&devices);
+    let mut reporter = range_reporter(0);
+    let stats = reporter.stats_mut();
+
+    // Range ignored
+    devices[0]
+        .borrow_mut()
+        .set_register(RESULT__RANGE_STATUS, 11 << 4 | 0x01);
+    record_failed_read(stats, &mut bus, DEFAULT_ADDRESS);
+    // No error code, or no sensor to ask
+    devices[0]
+        .borrow_mut()
+        .set_register(RESULT__RANGE_STATUS, 0x01);
+    record_failed_read(stats, &mut bus, DEFAULT_ADDRESS);
+    record_failed_read(stats, &mut bus, 0x30);
+
+    assert_eq!(stats.errors().code(11), 1);
+    assert_eq!(stats.errors().other(), 2);
+    assert_eq!(stats.count(), 0);
+}
+
+/// The only test logging, through the global backend.
+#[test]
+fn reports_are_logged_once_due() {
+    let mut reporter = range_reporter(1_000);
+    reporter.stats_mut().record(50);
+    reporter.stats_mut().record(52);
+    reporter.stats_mut().record_error(6);
+
+    log_report(&mut reporter, 1_000 + REPORT_PERIOD_MS - 1);
+    assert_eq!(log::take(Channel::Log).as_str(), "");
+
+    log_report(&mut reporter, 1_000 + REPORT_PERIOD_MS);
+    let logged = log::take(Channel::Log);
+    let lines: Vec<_> = logged.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "Range stats: n 2, min 50, max 52, mean 51.00, std dev 1.41, errors 1"
+    );
+    assert!(lines[1].starts_with("Range histogram: "));
+    assert_eq!(lines[2], "Range errors: 6:1");
+    assert_eq!(reporter.stats().count(), 0);
+}
@@ -0,0 +1,201 @@
+//! Timeout-bounded reads against a simulated sensor, including one that
+//! never finishes a measurement. Run with `cargo test-sim`.
+
+#![cfg(feature = "sim")]
+
+use core::cell::RefCell;
+
+use vl6180x_stm32f401_examples::protocol::{ErrorKind, Message};
+use vl6180x_stm32f401_examples::registers::*;
+use vl6180x_stm32f401_examples::shell::{
+    Command, Measurement, Response, Settings, Shell, ShellError, READ_ATTEMPTS,
+};
+use vl6180x_stm32f401_examples::sim::{SimBus, SimClock, SimDelay, SimError, SimVl6180x};
+use vl6180x_stm32f401_examples::stream;
+use vl6180x_stm32f401_examples::timeout::*;
+
+#[test]
+fn single_shots_read_like_the_driver() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0].borrow_mut().set_range_mm(120);
+    devices[0].borrow_mut().set_ambient_raw(625);
+    let clock = SimClock::new(0, 1);
+    let mut reader = TimedReader::new(SimBus::new(&devices), DEFAULT_ADDRESS, &clock);
+
+    assert_eq!(reader.poll_range_mm_single(), Ok(120));
+    // Gain 1.01 (level 6) for 1 ms, as out of reset in the sim
+    devices[0]
+        .borrow_mut()
+        .set_register(SYSALS__ANALOGUE_GAIN, ALS_GAIN_BASE | 6);
+    let lux = reader.poll_ambient_lux_single().unwrap();
+    assert!((lux - 0.32 * 625.0 * 100.0 / 1.01).abs() < 1.0, "{}", lux);
+    // Both interrupts are cleared and the config is left as it was
+    let sensor = devices[0].borrow();
+    assert_eq!(sensor.interrupt_status(), 0);
+    assert_eq!(sensor.register(SYSTEM__INTERRUPT_CONFIG_GPIO), 0);
+}
+
+#[test]
+fn continuous_reads_wait_for_the_next_sample() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0]
+        .borrow_mut()
+        .set_register(SYSTEM__INTERRUPT_CONFIG_GPIO, INT_NEW_SAMPLE_READY);
+    devices[0].borrow_mut().queue_range_mm(&[50, 60]);
+    let clock = SimClock::new(0, 1);
+    let mut reader = TimedReader::new(SimBus::new(&devices), DEFAULT_ADDRESS, &clock);
+    let mut i2c = SimBus::new(&devices);
+    write_u8(
+        &mut i2c,
+        DEFAULT_ADDRESS,
+        SYSRANGE__START,
+        START_STOP | MODE_CONTINUOUS,
+    )
+    .unwrap();
+
+    assert_eq!(reader.read_range_mm(), Ok(50));
+    // Nothing new until the next sample
+    reader.set_timeout_ms(5);
+    assert_eq!(reader.read_range_mm(), Err(ReadError::Timeout));
+    devices[0].borrow_mut().tick();
+    assert_eq!(reader.read_range_mm(), Ok(60));
+}
+
+#[test]
+fn a_stalled_sensor_times_out() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0].borrow_mut().set_stalled(true);
+    let clock = SimClock::new(0, 1);
+    let mut reader = TimedReader::new(SimBus::new(&devices), DEFAULT_ADDRESS, &clock);
+
+    assert_eq!(reader.poll_range_mm_single(), Err(ReadError::Timeout));
+    let waited_ms = clock.now_ms();
+    assert!(
+        (DEFAULT_TIMEOUT_MS..DEFAULT_TIMEOUT_MS + 5).contains(&waited_ms),
+        "{}",
+        waited_ms
+    );
+    assert_eq!(reader.poll_ambient_lux_single(), Err(ReadError::Timeout));
+    assert_eq!(reader.read_ambient_lux(), Err(ReadError::Timeout));
+
+    // The interrupt config is put back, and the sensor works once it recovers
+    devices[0].borrow_mut().set_stalled(false);
+    devices[0].borrow_mut().set_range_mm(80);
+    assert_eq!(
+        devices[0].borrow().register(SYSTEM__INTERRUPT_CONFIG_GPIO),
+        0
+    );
+    assert_eq!(reader.poll_range_mm_single(), Ok(80));
+}
+
+/// The shell's single shots poll the same way, counting attempts on its
+/// delay instead of a clock.
+#[test]
+fn a_stalled_sensor_times_the_shell_out() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0].borrow_mut().set_stalled(true);
+    let mut shell = Shell::new(SimBus::new(&devices), DEFAULT_ADDRESS, Settings::default());
+    let mut delay = SimDelay::new();
+
+    let read = Command::Read(Measurement::Range);
+    assert_eq!(shell.execute(read, &mut delay), Err(ShellError::Timeout));
+    assert_eq!(delay.elapsed_ms(), READ_ATTEMPTS as u32 - 1);
+    assert_eq!(
+        shell.execute(Command::CalibrateOffset(50), &mut delay),
+        Err(ShellError::Timeout)
+    );
+
+    devices[0].borrow_mut().set_stalled(false);
+    devices[0].borrow_mut().set_range_mm(80);
+    assert_eq!(
+        devices[0].borrow().register(SYSTEM__INTERRUPT_CONFIG_GPIO),
+        0
+    );
+    assert_eq!(shell.execute(read, &mut delay), Ok(Response::RangeMm(80)));
+}
+
+#[test]
+fn timeouts_survive_the_clock_wrapping() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0].borrow_mut().set_stalled(true);
+    let clock = SimClock::new(u32::MAX - 10, 1);
+    let mut reader = TimedReader::new(SimBus::new(&devices), DEFAULT_ADDRESS, &clock);
+    reader.set_timeout_ms(50);
+
+    assert_eq!(reader.read_range_mm(), Err(ReadError::Timeout));
+    assert!((39..45).contains(&clock.now_ms()), "{}", clock.now_ms());
+}
+
+#[test]
+fn range_errors_and_missing_sensors_are_reported() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    devices[0].borrow_mut().set_range_error(6);
+    let clock = SimClock::new(0, 1);
+    let mut reader = TimedReader::new(SimBus::new(&devices), DEFAULT_ADDRESS, &clock);
+    assert_eq!(reader.poll_range_mm_single(), Err(ReadError::Range(6)));
+
+    reader.set_address(0x30);
+    assert_eq!(
+        reader.poll_range_mm_single(),
+        Err(ReadError::I2c(SimError::Nack(0x30)))
+    );
+}
+
+/// A read that failed elsewhere, e.g. in the driver, and the stream's
+/// error for it.
+#[test]
+fn failed_reads_are_told_apart() {
+    let devices = [RefCell::new(SimVl6180x::new())];
+    let mut bus = SimBus::new(&devices);
+    devices[0]
+        .borrow_mut()
+        .set_register(RESULT__RANGE_STATUS, 11 << 4 | 0x01);
+    let error = failed_range_read(&mut bus, DEFAULT_ADDRESS, "driver");
+    assert_eq!(error, ReadError::Range(11));
+    assert_eq!(
+        stream::error_message(2, &error),
+        Message::Error {
+            sensor: 2,
+            kind: ErrorKind::Range,
+            detail: 11,
+        }
+    );
+
+    let error = failed_range_read(&mut bus, 0x30, "driver");
+    assert_eq!(error, ReadError::I2c("driver"));
+    assert_eq!(
+        stream::error_message(2, &error),
+        Message::Error {
+            sensor: 2,
+            kind: ErrorKind::Bus,
+            detail: 0,
+        }
+    );
+    assert_eq!(
+        stream::error_message::<()>(2, &ReadError::Timeout),
+        Message::Error {
+            sensor: 2,
+            kind: ErrorKind::Timeout,
+            detail: 0,
+        }
+    );
+}
+
+#[test]
+fn deadlines_count_in_ticks() {
+    struct Cycles(core::cell::Cell<u32>);
+    impl TimeSource for Cycles {
+        fn ticks(&self) -> u32 {
+            self.0.get()
+        }
+        fn ticks_per_ms(&self) -> u32 {
+            48_000
+        }
+    }
+    let cycles = Cycles(core::cell::Cell::new(u32::MAX - 1_000));
+    let deadline = Deadline::after_ms(&cycles, 2);
+    cycles.0.set(cycles.0.get().wrapping_add(95_999));
+    assert!(!deadline.has_passed(&cycles));
+    cycles.0.set(cycles.0.get().wrapping_add(1));
+    assert!(deadline.has_passed(&cycles));
+}
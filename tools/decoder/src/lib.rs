@@ -0,0 +1,254 @@
+//! Decodes the firmware's framed measurement stream (see `vl6180x-protocol`)
+//! into text, CSV or JSON lines.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use vl6180x_protocol::frame::Decoder;
+use vl6180x_protocol::{DecodeError, Envelope, ErrorKind, Message, Reading};
+
+/// How decoded messages are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One human readable line per message.
+    Text,
+    /// Comma separated, one column per field, with a header row.
+    Csv,
+    /// One JSON object per line, holding only the fields the message has.
+    JsonLines,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "json-lines" => Ok(Format::JsonLines),
+            other => Err(format!(
+                "unknown format `{other}`, expected text, csv or jsonl"
+            )),
+        }
+    }
+}
+
+const CSV_HEADER: &str =
+    "sequence,message,sensor,range_mm,ambient_raw,ambient_lux,line,error,detail,sensors,errors";
+
+/// What was seen while decoding a stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Frames decoded and printed.
+    pub messages: u64,
+    /// Frames dropped because they didn't decode.
+    pub bad_frames: u64,
+    /// Messages missing according to gaps in the sequence numbers.
+    pub missed: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages, {} bad frames, {} missed",
+            self.messages, self.bad_frames, self.missed
+        )
+    }
+}
+
+/// Writes decoded messages in one [`Format`] and keeps [`Stats`].
+pub struct Printer<W> {
+    out: W,
+    format: Format,
+    stats: Stats,
+    last_sequence: Option<u16>,
+    header_written: bool,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Self {
+            out,
+            format,
+            stats: Stats::default(),
+            last_sequence: None,
+            header_written: false,
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Prints one decoded message.
+    pub fn message(&mut self, envelope: &Envelope) -> io::Result<()> {
+        if let Some(last) = self.last_sequence {
+            let gap = envelope.sequence.wrapping_sub(last).wrapping_sub(1);
+            self.stats.missed += gap as u64;
+        }
+        self.last_sequence = Some(envelope.sequence);
+        self.stats.messages += 1;
+
+        match self.format {
+            Format::Text => writeln!(self.out, "{}", Text(envelope)),
+            Format::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "{CSV_HEADER}")?;
+                    self.header_written = true;
+                }
+                writeln!(self.out, "{}", Csv(envelope))
+            }
+            Format::JsonLines => writeln!(self.out, "{}", JsonLine(envelope)),
+        }
+    }
+
+    /// Counts a frame that didn't decode.
+    pub fn bad_frame(&mut self) {
+        self.stats.bad_frames += 1;
+    }
+}
+
+/// Decodes everything `input` yields until it ends, printing each message
+/// with `printer` and handing frames that fail to decode to `on_error`.
+///
+/// A serial port that times out is read again; one whose other end hangs up
+/// (e.g. the writer side of a pseudo-terminal closing) ends the stream.
+pub fn decode_stream<R, W>(
+    mut input: R,
+    printer: &mut Printer<W>,
+    mut on_error: impl FnMut(DecodeError),
+) -> io::Result<Stats>
+where
+    R: Read,
+    W: Write,
+{
+    let mut decoder = Decoder::new();
+    let mut buf = [0; 256];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => match e.kind() {
+                io::ErrorKind::Interrupted | io::ErrorKind::TimedOut => continue,
+                io::ErrorKind::BrokenPipe => break,
+                _ => return Err(e),
+            },
+        };
+        for &byte in &buf[..n] {
+            match decoder.push(byte) {
+                Some(Ok(envelope)) => printer.message(&envelope)?,
+                Some(Err(error)) => {
+                    printer.bad_frame();
+                    on_error(error);
+                }
+                None => (),
+            }
+        }
+        printer.out.flush()?;
+    }
+    Ok(printer.stats())
+}
+
+fn error_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Bus => "bus",
+        ErrorKind::Range => "range",
+        ErrorKind::Ambient => "ambient",
+        ErrorKind::Timeout => "timeout",
+    }
+}
+
+struct Text<'a>(&'a Envelope);
+
+impl fmt::Display for Text<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:<5} ", self.0.sequence)?;
+        match self.0.message {
+            Message::Measurement { sensor, reading } => match reading {
+                Reading::RangeMm(mm) => write!(f, "sensor {sensor} range {mm} mm"),
+                Reading::AmbientRaw(raw) => write!(f, "sensor {sensor} ambient {raw}"),
+                Reading::AmbientLux(lux) => write!(f, "sensor {sensor} ambient {lux} lux"),
+            },
+            Message::Interrupt { sensor, line } => {
+                write!(f, "sensor {sensor} interrupt on EXTI{line}")
+            }
+            Message::Error {
+                sensor,
+                kind,
+                detail,
+            } => write!(f, "sensor {sensor} {} error {detail}", error_name(kind)),
+            Message::Status { sensors, errors } => {
+                write!(f, "status: {sensors} sensors, {errors} errors")
+            }
+        }
+    }
+}
+
+struct Csv<'a>(&'a Envelope);
+
+impl fmt::Display for Csv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},", self.0.sequence)?;
+        // Columns after `sequence`, see CSV_HEADER.
+        match self.0.message {
+            Message::Measurement { sensor, reading } => match reading {
+                Reading::RangeMm(mm) => write!(f, "measurement,{sensor},{mm},,,,,,,"),
+                Reading::AmbientRaw(raw) => write!(f, "measurement,{sensor},,{raw},,,,,,"),
+                Reading::AmbientLux(lux) => write!(f, "measurement,{sensor},,,{lux},,,,,"),
+            },
+            Message::Interrupt { sensor, line } => write!(f, "interrupt,{sensor},,,,{line},,,,"),
+            Message::Error {
+                sensor,
+                kind,
+                detail,
+            } => write!(f, "error,{sensor},,,,,{},{detail},,", error_name(kind)),
+            Message::Status { sensors, errors } => write!(f, "status,,,,,,,,{sensors},{errors}"),
+        }
+    }
+}
+
+struct JsonLine<'a>(&'a Envelope);
+
+impl fmt::Display for JsonLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{\"sequence\":{},", self.0.sequence)?;
+        match self.0.message {
+            Message::Measurement { sensor, reading } => {
+                write!(f, "\"message\":\"measurement\",\"sensor\":{sensor},")?;
+                match reading {
+                    Reading::RangeMm(mm) => write!(f, "\"range_mm\":{mm}"),
+                    Reading::AmbientRaw(raw) => write!(f, "\"ambient_raw\":{raw}"),
+                    // JSON has no NaN or infinity.
+                    Reading::AmbientLux(lux) if !lux.is_finite() => {
+                        write!(f, "\"ambient_lux\":null")
+                    }
+                    Reading::AmbientLux(lux) => write!(f, "\"ambient_lux\":{lux}"),
+                }
+            }
+            Message::Interrupt { sensor, line } => write!(
+                f,
+                "\"message\":\"interrupt\",\"sensor\":{sensor},\"line\":{line}"
+            ),
+            Message::Error {
+                sensor,
+                kind,
+                detail,
+            } => write!(
+                f,
+                "\"message\":\"error\",\"sensor\":{sensor},\"error\":\"{}\",\"detail\":{detail}",
+                error_name(kind)
+            ),
+            Message::Status { sensors, errors } => write!(
+                f,
+                "\"message\":\"status\",\"sensors\":{sensors},\"errors\":{errors}"
+            ),
+        }?;
+        write!(f, "}}")
+    }
+}
@@ -0,0 +1,122 @@
+//! Reads the firmware measurement stream from a serial port or a capture file
+//! and prints it as text, CSV or JSON lines.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use vl6180x_decoder::{decode_stream, Format, Printer};
+
+const USAGE: &str = "\
+Usage: vl6180x-decoder [OPTIONS] <CAPTURE_FILE>
+       vl6180x-decoder [OPTIONS] --serial <DEVICE>
+
+Decodes the framed measurement stream sent by the firmware. A CAPTURE_FILE of
+`-` reads standard input.
+
+Options:
+  --serial <DEVICE>  Read from a serial port instead of a capture file
+  --baud <BAUD>      Serial baud rate [default: 115200]
+  --format <FORMAT>  text, csv or jsonl [default: text]
+  --output <FILE>    Write to FILE instead of standard output
+  -h, --help         Print this help";
+
+const DEFAULT_BAUD: u32 = 115_200;
+
+enum Source {
+    Capture(String),
+    Serial { device: String, baud: u32 },
+}
+
+struct Args {
+    source: Source,
+    format: Format,
+    output: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut capture = None;
+    let mut serial = None;
+    let mut baud = DEFAULT_BAUD;
+    let mut format = Format::Text;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--serial" => serial = Some(value("--serial")?),
+            "--baud" => {
+                baud = value("--baud")?
+                    .parse()
+                    .map_err(|e| format!("invalid --baud: {e}"))?
+            }
+            "--format" => format = value("--format")?.parse()?,
+            "--output" => output = Some(value("--output")?),
+            other if other.starts_with("--") => return Err(format!("unknown option `{other}`")),
+            _ if capture.is_some() => return Err(format!("unexpected argument `{arg}`")),
+            _ => capture = Some(arg),
+        }
+    }
+
+    let source = match (capture, serial) {
+        (Some(path), None) => Source::Capture(path),
+        (None, Some(device)) => Source::Serial { device, baud },
+        (Some(_), Some(_)) => return Err("give either a capture file or --serial, not both".into()),
+        (None, None) => return Err("no capture file or --serial device given".into()),
+    };
+    Ok(Some(Args {
+        source,
+        format,
+        output,
+    }))
+}
+
+fn open(source: &Source) -> io::Result<Box<dyn Read>> {
+    Ok(match source {
+        Source::Capture(path) if path == "-" => Box::new(io::stdin()),
+        Source::Capture(path) => Box::new(File::open(path)?),
+        Source::Serial { device, baud } => Box::new(
+            serialport::new(device, *baud)
+                .timeout(Duration::from_millis(500))
+                .open()?,
+        ),
+    })
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let input = open(&args.source)?;
+    let output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    let mut printer = Printer::new(output, args.format);
+    let stats = decode_stream(input, &mut printer, |error| {
+        eprintln!("warning: dropped frame: {error:?}")
+    })?;
+    printer.into_inner().flush()?;
+    eprintln!("{stats}");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
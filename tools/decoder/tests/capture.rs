@@ -0,0 +1,88 @@
+//! Decodes a recorded session, which starts mid-frame and contains a corrupted
+//! frame and a gap in the sequence numbers, and compares against golden output.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use vl6180x_decoder::{decode_stream, Format, Printer, Stats};
+use vl6180x_protocol::DecodeError;
+
+fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn decode(format: Format) -> (String, Stats, Vec<DecodeError>) {
+    let capture = fs::read(data("session.bin")).unwrap();
+    let mut printer = Printer::new(Vec::new(), format);
+    let mut errors = Vec::new();
+    let stats = decode_stream(capture.as_slice(), &mut printer, |e| errors.push(e)).unwrap();
+    (
+        String::from_utf8(printer.into_inner()).unwrap(),
+        stats,
+        errors,
+    )
+}
+
+#[test]
+fn session_stats() {
+    let (_, stats, errors) = decode(Format::Text);
+    assert_eq!(
+        stats,
+        Stats {
+            messages: 9,
+            bad_frames: 2,
+            missed: 3,
+        }
+    );
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(errors[1], DecodeError::Crc { .. }));
+}
+
+#[test]
+fn session_as_text() {
+    let (text, _, _) = decode(Format::Text);
+    assert_eq!(text, fs::read_to_string(data("session.txt")).unwrap());
+}
+
+#[test]
+fn session_as_csv() {
+    let (csv, _, _) = decode(Format::Csv);
+    assert_eq!(csv, fs::read_to_string(data("session.csv")).unwrap());
+}
+
+#[test]
+fn session_as_json_lines() {
+    let (jsonl, _, _) = decode(Format::JsonLines);
+    assert_eq!(jsonl, fs::read_to_string(data("session.jsonl")).unwrap());
+}
+
+#[test]
+fn cli_decodes_capture_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_vl6180x-decoder"))
+        .arg("--format")
+        .arg("csv")
+        .arg(data("session.bin"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        fs::read_to_string(data("session.csv")).unwrap()
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("9 messages, 2 bad frames, 3 missed\n"));
+}
+
+#[test]
+fn cli_rejects_bad_arguments() {
+    let output = Command::new(env!("CARGO_BIN_EXE_vl6180x-decoder"))
+        .arg("--format")
+        .arg("xml")
+        .arg(data("session.bin"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,77 @@
+//! Streams frames through a pseudo-terminal, standing in for the board's
+//! serial port.
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serialport::{SerialPort, TTYPort};
+use vl6180x_decoder::{decode_stream, Format, Printer, Stats};
+use vl6180x_protocol::{frame, Envelope, Message, Reading};
+
+/// Counts what the decoder has read, so the writer knows when it may hang up
+/// without the pty discarding unread input.
+struct Counted<R> {
+    inner: R,
+    read: Arc<AtomicUsize>,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n, Ordering::SeqCst);
+        Ok(n)
+    }
+}
+
+#[test]
+fn decodes_from_pty_until_hangup() {
+    let (mut board, mut host) = TTYPort::pair().unwrap();
+    host.set_timeout(Duration::from_millis(100)).unwrap();
+    let read = Arc::new(AtomicUsize::new(0));
+    let input = Counted {
+        inner: host,
+        read: read.clone(),
+    };
+
+    let writer = thread::spawn(move || {
+        let mut written = 0;
+        for sequence in 0..50u16 {
+            let envelope = Envelope {
+                sequence,
+                message: Message::Measurement {
+                    sensor: 0,
+                    reading: Reading::RangeMm(sequence * 2),
+                },
+            };
+            let mut buf = [0; frame::MAX_FRAME_LEN];
+            let len = frame::encode(&envelope, &mut buf).unwrap();
+            board.write_all(&buf[..len]).unwrap();
+            written += len;
+        }
+        while read.load(Ordering::SeqCst) < written {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // Dropping the board end hangs up the host end.
+    });
+
+    let mut printer = Printer::new(Vec::new(), Format::JsonLines);
+    let stats = decode_stream(input, &mut printer, |e| panic!("bad frame: {e:?}")).unwrap();
+    writer.join().unwrap();
+
+    assert_eq!(
+        stats,
+        Stats {
+            messages: 50,
+            bad_frames: 0,
+            missed: 0,
+        }
+    );
+    let output = String::from_utf8(printer.into_inner()).unwrap();
+    assert_eq!(
+        output.lines().last(),
+        Some(r#"{"sequence":49,"message":"measurement","sensor":0,"range_mm":98}"#)
+    );
+}